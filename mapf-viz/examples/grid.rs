@@ -969,24 +969,16 @@ impl App {
                     start,
                     goal,
                     yaw,
-                    ..ctx.agent
-                }
-            }
-            None => {
-                Agent {
-                    start,
-                    goal,
-                    yaw,
-                    radius: default_radius(),
-                    speed: default_speed(),
-                    spin: default_spin(),
+                    ..ctx.agent.clone()
                 }
             }
+            None => Agent::new(start, goal).with_yaw(yaw),
         };
 
+        let radius = agent.radius;
         self.canvas.program.layers.2.agents.insert(name.clone(), AgentContext::new(agent));
         self.canvas.program.layers.2.selected_agent = Some(name);
-        self.canvas.program.layers.1.set_robot_radius(agent.radius as f32);
+        self.canvas.program.layers.1.set_robot_radius(radius as f32);
         self.update_all_endpoints();
         self.canvas.cache.clear();
         self.generate_plan();
@@ -1009,6 +1001,8 @@ impl App {
         Scenario {
             agents: self.canvas.program.layers.2.agents.iter().map(|(n, a)| (n.clone(), a.agent.clone())).collect(),
             obstacles: self.canvas.program.layers.3.obstacles.iter().map(|obs| Obstacle::new(obs.0, &obs.1, cell_size)).collect(),
+            zones: Vec::new(),
+            capacity_regions: Vec::new(),
             occupancy: serialize_grid(self.canvas.program.layers.1.grid()),
             cell_size,
             camera_bounds,
@@ -1047,7 +1041,7 @@ impl App {
             }
 
             let r = ctx.agent.radius;
-            let extrapolator = DifferentialDriveLineFollow::new(ctx.agent.speed, ctx.agent.spin).expect("Bad speeds");
+            let extrapolator = ctx.agent.make_extrapolator().expect("Bad speeds");
             let profile = CircularProfile::new(r, 0.0, 0.0).expect("Bad profile sizes");
 
             let shared_accessibility = Arc::new(accessibility.clone());
@@ -1176,6 +1170,12 @@ impl App {
                         self.negotiation_history.sort_unstable_by_key(|n| n.id);
                         self.name_map = name_map;
                     }
+                    NegotiationError::DeadlinesMissed { agents, nodes, name_map } => {
+                        println!("Unable to meet the time windows of {agents:?}");
+                        self.negotiation_history = nodes;
+                        self.negotiation_history.sort_unstable_by_key(|n| n.id);
+                        self.name_map = name_map;
+                    }
                     err => println!("Error while planning: {err:?}"),
                 };
 
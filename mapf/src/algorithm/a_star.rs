@@ -17,7 +17,8 @@
 
 use crate::{
     algorithm::{
-        tree::*, Algorithm, Coherent, MinimumCostBound, Path, QueueLength, SearchStatus, Solvable,
+        tree::*, Algorithm, Coherent, MemoryFootprint, MinimumCostBound, ObservableMemory, Path,
+        QueueLength, SearchEvent, SearchObserver, SearchStatus, Solvable,
     },
     domain::{
         Activity, Closable, CloseResult, ClosedSet, Configurable, Connectable, Domain, Informed,
@@ -25,6 +26,7 @@ use crate::{
     },
     error::{Anyhow, ThisError},
 };
+use serde::{Deserialize, Serialize};
 use std::ops::Add;
 
 /// The AStar algorithm can be used on domains that implement the following traits:
@@ -69,6 +71,20 @@ where
     }
 }
 
+impl<Closed, State, Action, Cost> MemoryFootprint for Memory<Closed, State, Action, Cost>
+where
+    Cost: Clone + Add<Cost, Output = Cost>,
+{
+    fn memory_footprint(&self) -> usize {
+        self.0.memory_footprint()
+    }
+}
+
+impl<Closed, State, Action, Cost> ObservableMemory for Memory<Closed, State, Action, Cost> {
+    type State = State;
+    type Cost = Cost;
+}
+
 #[derive(ThisError, Debug)]
 pub enum AStarSearchError<D> {
     #[error("An error occurred in the algorithm:\n{0}")]
@@ -143,12 +159,13 @@ where
     }
 
     #[inline]
-    fn choose_top<Goal>(
+    fn choose_top<Goal, Obs: SearchObserver<<Self as Algorithm>::Memory>>(
         domain: &D,
         closed_set: &mut D::ClosedSet<usize>,
         queue: &mut TreeFrontierQueue<D::Cost>,
         arena: &Vec<Node<D::State, D::ActivityAction, D::Cost>>,
         goal: &Goal,
+        observer: &mut Obs,
     ) -> Result<
         Flow<(usize, Node<D::State, D::ActivityAction, D::Cost>), D>,
         AStarSearchError<D::Error>,
@@ -163,10 +180,20 @@ where
         };
 
         let top = arena.get_node(top_id).map_err(Self::algo_err)?;
+        observer.observe(SearchEvent::Pop {
+            node: top_id,
+            state: &top.state,
+            cost: &top.cost,
+        });
         if domain
             .is_satisfied(&top.state, goal)
             .map_err(Self::domain_err)?
         {
+            observer.observe(SearchEvent::Solution {
+                node: top_id,
+                state: &top.state,
+                cost: &top.cost,
+            });
             let solution = arena.retrace(top_id).map_err(Self::algo_err)?;
             return Ok(Flow::Return(SearchStatus::Solved(solution)));
         }
@@ -177,6 +204,12 @@ where
                 // The state we are attempting to expand has already been closed
                 // in the past by a lower cost node, so we will not expand from
                 // this top node. Instead we will finish this iteration.
+                observer.observe(SearchEvent::CloseRejected {
+                    node: top_id,
+                    prior: *prior,
+                    state: &top.state,
+                    cost: &top.cost,
+                });
                 return Ok(Flow::Return(SearchStatus::Incomplete));
             }
 
@@ -189,12 +222,13 @@ where
     }
 
     #[inline]
-    fn expand_from_parent<Goal>(
+    fn expand_from_parent<Goal, Obs: SearchObserver<<Self as Algorithm>::Memory>>(
         domain: &D,
         memory: &mut <Self as Algorithm>::Memory,
         parent_id: usize,
         parent: &Node<D::State, D::ActivityAction, D::Cost>,
         goal: &Goal,
+        observer: &mut Obs,
     ) -> Result<(), AStarSearchError<D::Error>>
     where
         D: Activity<D::State>,
@@ -204,44 +238,34 @@ where
         D::InformedError: Into<D::Error>,
     {
         for next in domain.choices(parent.state.clone()) {
-            let (action, child_state) = next.map_err(Self::domain_err)?;
-            Self::make_child_node(
-                domain,
-                memory,
-                parent_id,
-                &parent.state,
-                &parent.cost,
-                action.into(),
-                child_state,
-                goal,
-            )?
+            let next = next.map_err(Self::domain_err)?;
+            Self::make_child_node(domain, memory, parent_id, parent, next, goal, observer)?
         }
 
         Ok(())
     }
 
     #[inline]
-    fn make_child_node<Goal>(
+    fn make_child_node<Goal, Obs: SearchObserver<<Self as Algorithm>::Memory>>(
         domain: &D,
         memory: &mut <Self as Algorithm>::Memory,
         parent_id: usize,
-        parent_state: &D::State,
-        parent_cost: &D::Cost,
-        action: D::ActivityAction,
-        child_state: D::State,
+        parent: &Node<D::State, D::ActivityAction, D::Cost>,
+        (action, child_state): (D::ActivityAction, D::State),
         goal: &Goal,
+        observer: &mut Obs,
     ) -> Result<(), AStarSearchError<D::Error>>
     where
         D: Informed<D::State, Goal, CostEstimate = D::Cost>,
         D::InformedError: Into<D::Error>,
     {
         let cost = match domain
-            .cost(parent_state, &action, &child_state)
+            .cost(&parent.state, &action, &child_state)
             .map_err(Self::domain_err)?
         {
             Some(c) => c,
             None => return Ok(()),
-        } + parent_cost.clone();
+        } + parent.cost.clone();
 
         let remaining_cost_estimate = match domain
             .estimate_remaining_cost(&child_state, goal)
@@ -251,7 +275,7 @@ where
             None => return Ok(()),
         };
 
-        memory
+        let pushed = memory
             .0
             .push_node(Node {
                 state: child_state,
@@ -261,6 +285,16 @@ where
             })
             .map_err(Self::algo_err)?;
 
+        if let Some(node_id) = pushed {
+            let node = memory.0.arena.get_node(node_id).map_err(Self::algo_err)?;
+            observer.observe(SearchEvent::Push {
+                node: node_id,
+                parent: Some(parent_id),
+                state: &node.state,
+                cost: &node.cost,
+            });
+        }
+
         Ok(())
     }
 }
@@ -318,6 +352,15 @@ where
         &self,
         memory: &mut Self::Memory,
         goal: &Goal,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step_observed(memory, goal, &mut ())
+    }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        observer: &mut Obs,
     ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
         let (top_id, top) = match AStar::<D>::choose_top(
             &self.0,
@@ -325,12 +368,13 @@ where
             &mut memory.0.queue,
             &memory.0.arena,
             goal,
+            observer,
         )? {
             Flow::Proceed(r) => r,
             Flow::Return(r) => return Ok(r),
         };
 
-        AStar::<D>::expand_from_parent(&self.0, memory, top_id, &top, goal)?;
+        AStar::<D>::expand_from_parent(&self.0, memory, top_id, &top, goal, observer)?;
 
         Ok(SearchStatus::Incomplete)
     }
@@ -400,6 +444,15 @@ where
         &self,
         memory: &mut Self::Memory,
         goal: &Goal,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step_observed(memory, goal, &mut ())
+    }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        observer: &mut Obs,
     ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
         let (top_id, top) = match AStar::<D>::choose_top(
             &self.0,
@@ -407,26 +460,18 @@ where
             &mut memory.0.queue,
             &memory.0.arena,
             goal,
+            observer,
         )? {
             Flow::Proceed(r) => r,
             Flow::Return(r) => return Ok(r),
         };
 
-        AStar::<D>::expand_from_parent(&self.0, memory, top_id, &top, goal)?;
+        AStar::<D>::expand_from_parent(&self.0, memory, top_id, &top, goal, observer)?;
 
         // Attempt to connect the top to the goal
         for connection in self.0.connect(top.state.clone(), goal) {
-            let (action, child_state) = connection.map_err(AStar::<D>::domain_err)?;
-            AStar::<D>::make_child_node(
-                &self.0,
-                memory,
-                top_id,
-                &top.state,
-                &top.cost,
-                action,
-                child_state,
-                goal,
-            )?;
+            let connection = connection.map_err(AStar::<D>::domain_err)?;
+            AStar::<D>::make_child_node(&self.0, memory, top_id, &top, connection, goal, observer)?;
         }
 
         Ok(SearchStatus::Incomplete)
@@ -443,7 +488,7 @@ impl<D: Configurable> Configurable for AStarConnect<D> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node<State, Action, Cost> {
     state: State,
     cost: Cost,
@@ -452,6 +497,20 @@ pub struct Node<State, Action, Cost> {
 }
 
 impl<State, Action, Cost> Node<State, Action, Cost> {
+    pub fn new(
+        state: State,
+        cost: Cost,
+        remaining_cost_estimate: Cost,
+        parent: Option<(usize, Action)>,
+    ) -> Self {
+        Self {
+            state,
+            cost,
+            remaining_cost_estimate,
+            parent,
+        }
+    }
+
     pub fn cost(&self) -> &Cost {
         &self.cost
     }
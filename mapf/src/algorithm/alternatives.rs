@@ -0,0 +1,1300 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::{
+        a_star::{AStarSearchError, Node},
+        path::DomainPath,
+        tree::*,
+        Algorithm, Coherent, MemoryFootprint, ObservableMemory, Path, QueueLength, SearchEvent,
+        SearchObserver, SearchStatus, Solvable,
+    },
+    domain::{
+        Activity, Closable, CloseResult, ClosedSet, Configurable, Connectable, Domain, Informed,
+        Initializable, Keyed, Keyring, Satisfiable, Weighted,
+    },
+    error::Anyhow,
+};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    ops::Add,
+};
+
+/// Find the `k` cheapest loopless paths from the start to the goal using Yen's
+/// algorithm. The cheapest path is found first. Every later path is found by
+/// taking a previously found path, keeping a prefix of it, and searching for a
+/// detour from the end of that prefix which avoids the keys of the prefix and
+/// the transitions that earlier paths took from the same place.
+///
+/// The solution contains the paths in order of increasing cost. If fewer than
+/// `k` paths exist then all of the paths that exist will be given.
+///
+/// Two paths are considered the same if they visit the same sequence of keys,
+/// so the domain must implement [`Keyring`]. Each detour is an A* search that
+/// attempts to connect to the goal the same way that [`AStarConnect`] does, so
+/// the domain must implement the following traits:
+/// * [`Initializable`]
+/// * [`Keyring`]
+/// * [`Closable`]
+/// * [`Activity`]
+/// * [`Weighted`]
+/// * [`Informed`]
+/// * [`Satisfiable`]
+/// * [`Connectable`] as `Connectable<D::State, D::ActivityAction, Goal>`
+///
+/// [`AStarConnect`]: crate::algorithm::AStarConnect
+#[derive(Debug, Clone)]
+pub struct KShortestPaths<D> {
+    pub domain: D,
+    /// How many paths should be found
+    pub k: usize,
+}
+
+impl<D> KShortestPaths<D> {
+    pub fn new(domain: D, k: usize) -> Self {
+        Self { domain, k }
+    }
+}
+
+/// Find up to `k` alternative paths from the start to the goal that avoid
+/// sharing transitions with each other, using the penalty method. Each round
+/// searches for the cheapest path while every transition that was taken by a
+/// path of an earlier round costs an additional `penalty` for each time it was
+/// taken. This pushes each round away from the routes that were already found,
+/// but still allows them to be reused when no reasonable alternative exists.
+///
+/// The paths in the solution report their actual cost, without any penalties.
+/// The first path is always the cheapest one. Rounds that produce a path which
+/// was already found are not counted, but at most `max_rounds` rounds will be
+/// run before the search gives back the paths that it has.
+///
+/// The domain must implement the same traits as [`KShortestPaths`].
+#[derive(Debug, Clone)]
+pub struct DiversePaths<D, Cost> {
+    pub domain: D,
+    /// How many paths should be found
+    pub k: usize,
+    /// The cost that gets added to a transition each time a path uses it
+    pub penalty: Cost,
+    /// The most rounds of searching that will be done
+    pub max_rounds: usize,
+}
+
+impl<D, Cost> DiversePaths<D, Cost> {
+    /// Make a new search for diverse paths. At most `3*k` rounds of searching
+    /// will be done. Use [`Self::with_max_rounds`] to change that.
+    pub fn new(domain: D, k: usize, penalty: Cost) -> Self {
+        Self {
+            domain,
+            k,
+            penalty,
+            max_rounds: 3 * k,
+        }
+    }
+
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+}
+
+/// A path along with the key and accumulated cost of each of its states.
+#[derive(Debug, Clone)]
+struct Route<Key, State, Action, Cost> {
+    path: Path<State, Action, Cost>,
+    keys: Vec<Key>,
+    costs: Vec<Cost>,
+}
+
+impl<Key, State, Action, Cost> Route<Key, State, Action, Cost>
+where
+    Key: Clone,
+    State: Clone,
+    Action: Clone,
+    Cost: Clone + Add<Output = Cost>,
+{
+    fn begin<D>(domain: &D, state: State, cost: Cost) -> Self
+    where
+        D: Keyring<State, Key = Key>,
+    {
+        Self {
+            keys: vec![key_of(domain, &state)],
+            costs: vec![cost.clone()],
+            path: Path {
+                initial_state: state,
+                sequence: Vec::new(),
+                total_cost: cost,
+            },
+        }
+    }
+
+    /// Extend the route with a sequence of actions, weighing each one with the
+    /// domain. Gives back None if the domain refuses to weigh one of them.
+    fn extend<D>(
+        mut self,
+        domain: &D,
+        sequence: Vec<(Action, State)>,
+    ) -> Result<Option<Self>, D::Error>
+    where
+        D: Domain + Keyring<State, Key = Key> + Weighted<State, Action, Cost = Cost>,
+        D::WeightedError: Into<D::Error>,
+    {
+        for (action, state) in sequence {
+            let cost = match domain
+                .cost(self.path.final_state(), &action, &state)
+                .map_err(Into::into)?
+            {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+
+            let total_cost = self.path.total_cost.clone() + cost;
+            self.keys.push(key_of(domain, &state));
+            self.costs.push(total_cost.clone());
+            self.path.sequence.push((action, state));
+            self.path.total_cost = total_cost;
+        }
+
+        Ok(Some(self))
+    }
+
+    /// The part of this route that ends at the state with the given index.
+    fn prefix(&self, index: usize) -> Self {
+        Self {
+            path: Path {
+                initial_state: self.path.initial_state.clone(),
+                sequence: self.path.sequence[..index].to_vec(),
+                total_cost: self.costs[index].clone(),
+            },
+            keys: self.keys[..=index].to_vec(),
+            costs: self.costs[..=index].to_vec(),
+        }
+    }
+
+    fn state(&self, index: usize) -> &State {
+        match index {
+            0 => &self.path.initial_state,
+            i => &self.path.sequence[i - 1].1,
+        }
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Key, Key)> + '_ {
+        self.keys
+            .iter()
+            .zip(self.keys.iter().skip(1))
+            .map(|(from, to)| (from.clone(), to.clone()))
+    }
+}
+
+fn key_of<D: Keyring<State>, State>(domain: &D, state: &State) -> D::Key
+where
+    D::Key: Clone,
+{
+    let key_ref = domain.key_for(state);
+    let key: &D::Key = key_ref.borrow();
+    key.clone()
+}
+
+fn is_new<Key: PartialEq, State, Action, Cost>(
+    route: &Route<Key, State, Action, Cost>,
+    among: &[Route<Key, State, Action, Cost>],
+) -> bool {
+    among.iter().all(|other| other.keys != route.keys)
+}
+
+/// An A* search for a path that is not allowed to go everywhere or that pays
+/// extra to go some places.
+#[derive(Debug)]
+struct Detour<Closed, Key, State, Action, Cost> {
+    tree: Tree<Closed, Node<State, Action, Cost>, Cost>,
+    /// The route leading up to the first state of the detour. When this is
+    /// None the detour begins from the initial states of the search.
+    root: Option<Route<Key, State, Action, Cost>>,
+    /// Keys that the detour may not pass through
+    avoid_keys: HashSet<Key>,
+    /// Transitions from one key to another that the detour may not take
+    avoid_edges: HashSet<(Key, Key)>,
+    /// Additional cost for taking a transition from one key to another
+    surcharge: HashMap<(Key, Key), Cost>,
+}
+
+impl<Closed, Key, State, Action, Cost> Detour<Closed, Key, State, Action, Cost>
+where
+    Cost: Ord + Clone + Add<Output = Cost>,
+{
+    fn new(closed_set: Closed, root: Option<Route<Key, State, Action, Cost>>) -> Self {
+        Self {
+            tree: Tree::new(closed_set),
+            root,
+            avoid_keys: Default::default(),
+            avoid_edges: Default::default(),
+            surcharge: Default::default(),
+        }
+    }
+}
+
+type DomainRoute<D> =
+    Route<
+        <D as Keyed>::Key,
+        <D as Domain>::State,
+        <D as Activity<<D as Domain>::State>>::ActivityAction,
+        <D as Weighted<
+            <D as Domain>::State,
+            <D as Activity<<D as Domain>::State>>::ActivityAction,
+        >>::Cost,
+    >;
+
+type DomainDetour<D> =
+    Detour<
+        <D as Closable<<D as Domain>::State>>::ClosedSet<usize>,
+        <D as Keyed>::Key,
+        <D as Domain>::State,
+        <D as Activity<<D as Domain>::State>>::ActivityAction,
+        <D as Weighted<
+            <D as Domain>::State,
+            <D as Activity<<D as Domain>::State>>::ActivityAction,
+        >>::Cost,
+    >;
+
+#[derive(Debug)]
+pub struct Memory<Closed, Key, State, Action, Cost> {
+    /// Paths that have been accepted, in order of increasing cost
+    found: Vec<Route<Key, State, Action, Cost>>,
+    /// Paths that may be accepted once the paths before them are found
+    candidates: Vec<Route<Key, State, Action, Cost>>,
+    detour: Option<Detour<Closed, Key, State, Action, Cost>>,
+    /// Index of the next state along the most recently found path where a
+    /// detour should branch off
+    next_spur: usize,
+}
+
+impl<Closed, Key, State, Action, Cost> Memory<Closed, Key, State, Action, Cost> {
+    /// The paths that have been found so far, in order of increasing cost.
+    pub fn found(&self) -> impl Iterator<Item = &Path<State, Action, Cost>> {
+        self.found.iter().map(|route| &route.path)
+    }
+}
+
+#[derive(Debug)]
+pub struct DiverseMemory<Closed, Key, State, Action, Cost> {
+    found: Vec<Route<Key, State, Action, Cost>>,
+    /// The initial states of the search and their initial costs
+    starts: Vec<(State, Cost)>,
+    /// The accumulated penalty of each transition that has been used
+    surcharge: HashMap<(Key, Key), Cost>,
+    detour: Option<Detour<Closed, Key, State, Action, Cost>>,
+    rounds: usize,
+}
+
+impl<Closed, Key, State, Action, Cost> DiverseMemory<Closed, Key, State, Action, Cost> {
+    /// The paths that have been found so far.
+    pub fn found(&self) -> impl Iterator<Item = &Path<State, Action, Cost>> {
+        self.found.iter().map(|route| &route.path)
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> QueueLength for Memory<Closed, Key, State, Action, Cost> {
+    fn queue_length(&self) -> usize {
+        self.detour
+            .as_ref()
+            .map(|d| d.tree.queue.len())
+            .unwrap_or(0)
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> QueueLength
+    for DiverseMemory<Closed, Key, State, Action, Cost>
+{
+    fn queue_length(&self) -> usize {
+        self.detour
+            .as_ref()
+            .map(|d| d.tree.queue.len())
+            .unwrap_or(0)
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> MemoryFootprint for Memory<Closed, Key, State, Action, Cost>
+where
+    Cost: Clone + Add<Cost, Output = Cost>,
+{
+    fn memory_footprint(&self) -> usize {
+        let route = std::mem::size_of::<(Action, State)>()
+            + std::mem::size_of::<Key>()
+            + std::mem::size_of::<Cost>();
+        let routes: usize = self
+            .found
+            .iter()
+            .chain(self.candidates.iter())
+            .map(|r| r.keys.len() * route)
+            .sum();
+
+        std::mem::size_of::<Self>()
+            + routes
+            + self
+                .detour
+                .as_ref()
+                .map(|d| d.tree.memory_footprint())
+                .unwrap_or(0)
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> MemoryFootprint
+    for DiverseMemory<Closed, Key, State, Action, Cost>
+where
+    Cost: Clone + Add<Cost, Output = Cost>,
+{
+    fn memory_footprint(&self) -> usize {
+        let route = std::mem::size_of::<(Action, State)>()
+            + std::mem::size_of::<Key>()
+            + std::mem::size_of::<Cost>();
+        let routes: usize = self.found.iter().map(|r| r.keys.len() * route).sum();
+
+        std::mem::size_of::<Self>()
+            + routes
+            + self.surcharge.len() * std::mem::size_of::<((Key, Key), Cost)>()
+            + self
+                .detour
+                .as_ref()
+                .map(|d| d.tree.memory_footprint())
+                .unwrap_or(0)
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> ObservableMemory
+    for Memory<Closed, Key, State, Action, Cost>
+{
+    type State = State;
+    type Cost = Cost;
+}
+
+impl<Closed, Key, State, Action, Cost> ObservableMemory
+    for DiverseMemory<Closed, Key, State, Action, Cost>
+{
+    type State = State;
+    type Cost = Cost;
+}
+
+impl<D> KShortestPaths<D>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>,
+    D::Key: Clone,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::WeightedError: Into<D::Error>,
+{
+    fn domain_err(err: impl Into<D::Error>) -> AStarSearchError<D::Error> {
+        AStarSearchError::Domain(err.into())
+    }
+
+    fn algo_err(err: TreeError) -> AStarSearchError<D::Error> {
+        AStarSearchError::Algorithm(err)
+    }
+
+    /// Put a state into a detour as one of the places that it may begin from.
+    fn seed<Goal>(
+        domain: &D,
+        detour: &mut DomainDetour<D>,
+        state: D::State,
+        cost: D::Cost,
+        goal: &Goal,
+    ) -> Result<(), AStarSearchError<D::Error>>
+    where
+        D: Informed<D::State, Goal, CostEstimate = D::Cost>,
+        D::InformedError: Into<D::Error>,
+    {
+        let remaining_cost_estimate = match domain
+            .estimate_remaining_cost(&state, goal)
+            .map_err(Self::domain_err)?
+        {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        detour
+            .tree
+            .push_node(Node::new(state, cost, remaining_cost_estimate, None))
+            .map_err(Self::algo_err)?;
+
+        Ok(())
+    }
+
+    fn step_detour<Goal, Mem, Obs>(
+        domain: &D,
+        detour: &mut DomainDetour<D>,
+        goal: &Goal,
+        observer: &mut Obs,
+    ) -> Result<SearchStatus<DomainPath<D>>, AStarSearchError<D::Error>>
+    where
+        D: Informed<D::State, Goal, CostEstimate = D::Cost>
+            + Satisfiable<D::State, Goal>
+            + Connectable<D::State, D::ActivityAction, Goal>,
+        D::ActivityError: Into<D::Error>,
+        D::InformedError: Into<D::Error>,
+        D::SatisfactionError: Into<D::Error>,
+        D::ConnectionError: Into<D::Error>,
+        Mem: ObservableMemory<State = D::State, Cost = D::Cost>,
+        Obs: SearchObserver<Mem>,
+    {
+        let top_id = match detour.tree.queue.pop() {
+            Some(top) => top.0.node_id,
+            None => return Ok(SearchStatus::Impossible),
+        };
+
+        let top = detour
+            .tree
+            .arena
+            .get_node(top_id)
+            .map_err(Self::algo_err)?
+            .clone();
+        observer.observe(SearchEvent::Pop {
+            node: top_id,
+            state: top.state(),
+            cost: top.cost(),
+        });
+
+        if domain
+            .is_satisfied(top.state(), goal)
+            .map_err(Self::domain_err)?
+        {
+            observer.observe(SearchEvent::Solution {
+                node: top_id,
+                state: top.state(),
+                cost: top.cost(),
+            });
+            let path = detour.tree.arena.retrace(top_id).map_err(Self::algo_err)?;
+            return Ok(SearchStatus::Solved(path));
+        }
+
+        if let CloseResult::Rejected { prior, .. } =
+            detour.tree.closed_set.close(top.state(), top_id)
+        {
+            let prior_node = detour.tree.arena.get_node(*prior).map_err(Self::algo_err)?;
+            if prior_node.cost() <= top.cost() {
+                observer.observe(SearchEvent::CloseRejected {
+                    node: top_id,
+                    prior: *prior,
+                    state: top.state(),
+                    cost: top.cost(),
+                });
+                return Ok(SearchStatus::Incomplete);
+            }
+
+            *prior = top_id;
+        }
+
+        for next in domain.choices(top.state().clone()) {
+            let next = next.map_err(Self::domain_err)?;
+            Self::push_child(domain, detour, top_id, &top, next, goal, observer)?;
+        }
+
+        for connection in domain.connect(top.state().clone(), goal) {
+            let connection = connection.map_err(Self::domain_err)?;
+            Self::push_child(domain, detour, top_id, &top, connection, goal, observer)?;
+        }
+
+        Ok(SearchStatus::Incomplete)
+    }
+
+    fn push_child<Goal, Mem, Obs>(
+        domain: &D,
+        detour: &mut DomainDetour<D>,
+        parent_id: usize,
+        parent: &Node<D::State, D::ActivityAction, D::Cost>,
+        (action, child_state): (D::ActivityAction, D::State),
+        goal: &Goal,
+        observer: &mut Obs,
+    ) -> Result<(), AStarSearchError<D::Error>>
+    where
+        D: Informed<D::State, Goal, CostEstimate = D::Cost>,
+        D::InformedError: Into<D::Error>,
+        Mem: ObservableMemory<State = D::State, Cost = D::Cost>,
+        Obs: SearchObserver<Mem>,
+    {
+        let child_key = key_of(domain, &child_state);
+        if detour.avoid_keys.contains(&child_key) {
+            return Ok(());
+        }
+
+        let edge = (key_of(domain, parent.state()), child_key);
+        if detour.avoid_edges.contains(&edge) {
+            return Ok(());
+        }
+
+        let cost = match domain
+            .cost(parent.state(), &action, &child_state)
+            .map_err(Self::domain_err)?
+        {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let cost = match detour.surcharge.get(&edge) {
+            Some(extra) => cost + extra.clone(),
+            None => cost,
+        } + parent.cost().clone();
+
+        let remaining_cost_estimate = match domain
+            .estimate_remaining_cost(&child_state, goal)
+            .map_err(Self::domain_err)?
+        {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        let pushed = detour
+            .tree
+            .push_node(Node::new(
+                child_state,
+                cost,
+                remaining_cost_estimate,
+                Some((parent_id, action)),
+            ))
+            .map_err(Self::algo_err)?;
+
+        if let Some(node_id) = pushed {
+            let node = detour
+                .tree
+                .arena
+                .get_node(node_id)
+                .map_err(Self::algo_err)?;
+            observer.observe(SearchEvent::Push {
+                node: node_id,
+                parent: Some(parent_id),
+                state: node.state(),
+                cost: node.cost(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Begin a detour from the state at `spur` along the most recently found
+    /// path.
+    fn branch<Goal>(
+        &self,
+        memory: &mut <Self as Algorithm>::Memory,
+        spur: usize,
+        goal: &Goal,
+    ) -> Result<(), AStarSearchError<D::Error>>
+    where
+        D: Informed<D::State, Goal, CostEstimate = D::Cost>,
+        D::InformedError: Into<D::Error>,
+    {
+        let last = match memory.found.last() {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+
+        let root = last.prefix(spur);
+        let mut detour = Detour::new(self.domain.new_closed_set(), None);
+        detour.avoid_keys.extend(root.keys[..spur].iter().cloned());
+        for other in &memory.found {
+            if other.keys.len() > spur + 1 && other.keys[..=spur] == root.keys[..] {
+                detour
+                    .avoid_edges
+                    .insert((other.keys[spur].clone(), other.keys[spur + 1].clone()));
+            }
+        }
+
+        Self::seed(
+            &self.domain,
+            &mut detour,
+            last.state(spur).clone(),
+            last.costs[spur].clone(),
+            goal,
+        )?;
+        detour.root = Some(root);
+        memory.detour = Some(detour);
+        Ok(())
+    }
+
+    /// Weigh a path from its very beginning to get its keys and actual costs.
+    fn measure(
+        domain: &D,
+        path: Path<D::State, D::ActivityAction, D::Cost>,
+    ) -> Result<Option<DomainRoute<D>>, AStarSearchError<D::Error>> {
+        let initial_cost = match domain
+            .initial_cost(&path.initial_state)
+            .map_err(Self::domain_err)?
+        {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        Route::begin(domain, path.initial_state, initial_cost)
+            .extend(domain, path.sequence)
+            .map_err(AStarSearchError::Domain)
+    }
+
+    fn solution(found: &[DomainRoute<D>]) -> Vec<Path<D::State, D::ActivityAction, D::Cost>> {
+        found.iter().map(|route| route.path.clone()).collect()
+    }
+}
+
+impl<D> Algorithm for KShortestPaths<D>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>,
+{
+    type Memory = Memory<D::ClosedSet<usize>, D::Key, D::State, D::ActivityAction, D::Cost>;
+}
+
+impl<D, Start, Goal> Coherent<Start, Goal> for KShortestPaths<D>
+where
+    D: Domain
+        + Initializable<Start, Goal, D::State>
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>,
+    D::Key: Clone,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::InitialError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    D::InformedError: Into<D::Error>,
+{
+    type InitError = AStarSearchError<D::Error>;
+
+    fn initialize(&self, start: Start, goal: &Goal) -> Result<Self::Memory, Self::InitError> {
+        let mut detour = Detour::new(self.domain.new_closed_set(), None);
+        for state in self.domain.initialize(start, goal) {
+            let state = state.map_err(Self::domain_err)?;
+            let cost = match self.domain.initial_cost(&state).map_err(Self::domain_err)? {
+                Some(c) => c,
+                None => continue,
+            };
+            Self::seed(&self.domain, &mut detour, state, cost, goal)?;
+        }
+
+        Ok(Memory {
+            found: Vec::new(),
+            candidates: Vec::new(),
+            detour: Some(detour),
+            next_spur: 0,
+        })
+    }
+}
+
+impl<D, Goal> Solvable<Goal> for KShortestPaths<D>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>
+        + Satisfiable<D::State, Goal>
+        + Connectable<D::State, D::ActivityAction, Goal>,
+    D::Key: Clone,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::SatisfactionError: Into<D::Error>,
+    D::ActivityError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    D::InformedError: Into<D::Error>,
+    D::ConnectionError: Into<D::Error>,
+{
+    type Solution = Vec<Path<D::State, D::ActivityAction, D::Cost>>;
+    type StepError = AStarSearchError<D::Error>;
+
+    fn step(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step_observed(memory, goal, &mut ())
+    }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        observer: &mut Obs,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        if memory.found.len() >= self.k {
+            return Ok(SearchStatus::Solved(Self::solution(&memory.found)));
+        }
+
+        if let Some(detour) = &mut memory.detour {
+            let path = match Self::step_detour(&self.domain, detour, goal, observer)? {
+                SearchStatus::Incomplete => return Ok(SearchStatus::Incomplete),
+                SearchStatus::Impossible => None,
+                SearchStatus::Solved(path) => Some(path),
+            };
+
+            let root = memory.detour.take().and_then(|d| d.root);
+            let route = match (path, root) {
+                (Some(path), Some(root)) => root
+                    .extend(&self.domain, path.sequence)
+                    .map_err(AStarSearchError::Domain)?,
+                (Some(path), None) => Self::measure(&self.domain, path)?,
+                (None, _) => None,
+            };
+
+            if memory.found.is_empty() {
+                // This was the search for the very first path
+                match route {
+                    Some(route) => memory.found.push(route),
+                    None => return Ok(SearchStatus::Impossible),
+                }
+            } else if let Some(route) = route {
+                if is_new(&route, &memory.found) && is_new(&route, &memory.candidates) {
+                    memory.candidates.push(route);
+                }
+            }
+
+            return Ok(SearchStatus::Incomplete);
+        }
+
+        let last_len = match memory.found.last() {
+            Some(last) => last.keys.len(),
+            None => return Ok(SearchStatus::Impossible),
+        };
+
+        // There is nowhere to branch off from the final state of a path
+        if memory.next_spur + 1 < last_len {
+            let spur = memory.next_spur;
+            memory.next_spur += 1;
+            self.branch(memory, spur, goal)?;
+            return Ok(SearchStatus::Incomplete);
+        }
+
+        let best = memory
+            .candidates
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.path.total_cost.cmp(&b.path.total_cost))
+            .map(|(i, _)| i);
+
+        match best {
+            Some(best) => {
+                let route = memory.candidates.remove(best);
+                memory.found.push(route);
+                memory.next_spur = 0;
+                Ok(SearchStatus::Incomplete)
+            }
+            None => {
+                // Every alternative has been exhausted
+                Ok(SearchStatus::Solved(Self::solution(&memory.found)))
+            }
+        }
+    }
+}
+
+impl<D: Configurable> Configurable for KShortestPaths<D> {
+    type Configuration = D::Configuration;
+    fn configure<F>(self, f: F) -> Result<Self, Anyhow>
+    where
+        F: FnOnce(Self::Configuration) -> Result<Self::Configuration, Anyhow>,
+    {
+        Ok(KShortestPaths {
+            domain: self.domain.configure(f)?,
+            k: self.k,
+        })
+    }
+}
+
+impl<D, Cost> Algorithm for DiversePaths<D, Cost>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>,
+{
+    type Memory = DiverseMemory<D::ClosedSet<usize>, D::Key, D::State, D::ActivityAction, D::Cost>;
+}
+
+impl<D, Cost, Start, Goal> Coherent<Start, Goal> for DiversePaths<D, Cost>
+where
+    D: Domain
+        + Initializable<Start, Goal, D::State>
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>,
+    D::Key: Clone,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::InitialError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    D::InformedError: Into<D::Error>,
+{
+    type InitError = AStarSearchError<D::Error>;
+
+    fn initialize(&self, start: Start, goal: &Goal) -> Result<Self::Memory, Self::InitError> {
+        let mut starts = Vec::new();
+        for state in self.domain.initialize(start, goal) {
+            let state = state.map_err(KShortestPaths::<D>::domain_err)?;
+            let cost = match self
+                .domain
+                .initial_cost(&state)
+                .map_err(KShortestPaths::<D>::domain_err)?
+            {
+                Some(c) => c,
+                None => continue,
+            };
+            starts.push((state, cost));
+        }
+
+        Ok(DiverseMemory {
+            found: Vec::new(),
+            starts,
+            surcharge: HashMap::new(),
+            detour: None,
+            rounds: 0,
+        })
+    }
+}
+
+impl<D, Goal> Solvable<Goal> for DiversePaths<D, D::Cost>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>
+        + Satisfiable<D::State, Goal>
+        + Connectable<D::State, D::ActivityAction, Goal>,
+    D::Key: Clone,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::SatisfactionError: Into<D::Error>,
+    D::ActivityError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    D::InformedError: Into<D::Error>,
+    D::ConnectionError: Into<D::Error>,
+{
+    type Solution = Vec<Path<D::State, D::ActivityAction, D::Cost>>;
+    type StepError = AStarSearchError<D::Error>;
+
+    fn step(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step_observed(memory, goal, &mut ())
+    }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        observer: &mut Obs,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        if memory.found.len() >= self.k {
+            return Ok(SearchStatus::Solved(KShortestPaths::<D>::solution(
+                &memory.found,
+            )));
+        }
+
+        let detour = match &mut memory.detour {
+            Some(detour) => detour,
+            None => {
+                if memory.rounds >= self.max_rounds {
+                    return Ok(SearchStatus::Solved(KShortestPaths::<D>::solution(
+                        &memory.found,
+                    )));
+                }
+
+                let mut detour = Detour::new(self.domain.new_closed_set(), None);
+                detour.surcharge = memory.surcharge.clone();
+                for (state, cost) in &memory.starts {
+                    KShortestPaths::<D>::seed(
+                        &self.domain,
+                        &mut detour,
+                        state.clone(),
+                        cost.clone(),
+                        goal,
+                    )?;
+                }
+
+                memory.rounds += 1;
+                memory.detour = Some(detour);
+                return Ok(SearchStatus::Incomplete);
+            }
+        };
+
+        let path = match KShortestPaths::<D>::step_detour(&self.domain, detour, goal, observer)? {
+            SearchStatus::Incomplete => return Ok(SearchStatus::Incomplete),
+            SearchStatus::Impossible => {
+                // Penalties never forbid anything, so if one round finds no
+                // path then no round ever will.
+                memory.detour = None;
+                if memory.found.is_empty() {
+                    return Ok(SearchStatus::Impossible);
+                }
+
+                return Ok(SearchStatus::Solved(KShortestPaths::<D>::solution(
+                    &memory.found,
+                )));
+            }
+            SearchStatus::Solved(path) => path,
+        };
+        memory.detour = None;
+
+        // Measure the path again without any penalties to learn its actual cost
+        let route = match KShortestPaths::<D>::measure(&self.domain, path)? {
+            Some(route) => route,
+            None => return Ok(SearchStatus::Incomplete),
+        };
+
+        for edge in route.edges() {
+            let penalty = match memory.surcharge.remove(&edge) {
+                Some(prior) => prior + self.penalty.clone(),
+                None => self.penalty.clone(),
+            };
+            memory.surcharge.insert(edge, penalty);
+        }
+
+        if is_new(&route, &memory.found) {
+            memory.found.push(route);
+        }
+
+        Ok(SearchStatus::Incomplete)
+    }
+}
+
+impl<D: Configurable, Cost> Configurable for DiversePaths<D, Cost> {
+    type Configuration = D::Configuration;
+    fn configure<F>(self, f: F) -> Result<Self, Anyhow>
+    where
+        F: FnOnce(Self::Configuration) -> Result<Self::Configuration, Anyhow>,
+    {
+        Ok(DiversePaths {
+            domain: self.domain.configure(f)?,
+            k: self.k,
+            penalty: self.penalty,
+            max_rounds: self.max_rounds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::{
+            observer::{SearchCounters, SearchEventKind, SearchTrace},
+            AStar, AStarConnect,
+        },
+        domain::Cost,
+        graph::{SharedGraph, SimpleGraph},
+        motion::{r2::*, se2::DifferentialDriveLineFollow, SpeedLimit, Timed},
+        templates::InformedSearch,
+        Planner,
+    };
+    use approx::assert_relative_eq;
+    use std::collections::HashSet;
+
+    fn make_lattice() -> SimpleGraph<Position, SpeedLimit> {
+        /*
+         * 0-----1-----2
+         * |     |     |
+         * 3-----4-----5
+         * |     |     |
+         * 6-----7-----8
+         */
+
+        let s = SpeedLimit(None);
+        let mut edges = Vec::new();
+        for (a, b) in [
+            (0, 1),
+            (1, 2),
+            (3, 4),
+            (4, 5),
+            (6, 7),
+            (7, 8),
+            (0, 3),
+            (3, 6),
+            (1, 4),
+            (4, 7),
+            (2, 5),
+            (5, 8),
+        ] {
+            edges.push((a, b, s));
+            edges.push((b, a, s));
+        }
+
+        SimpleGraph::from_iters(
+            (0..9).map(|i| Position::new((i % 3) as f64, -((i / 3) as f64))),
+            edges,
+        )
+    }
+
+    fn make_branchless_graph() -> SimpleGraph<Position, SpeedLimit> {
+        /*
+         * 0-----1-----2-----3
+         *           /       |
+         *         /         |
+         *       4-----5     6
+         *             |
+         *             |
+         *             7-----8
+         */
+
+        let s = SpeedLimit(None);
+        SimpleGraph::from_iters(
+            [
+                Position::new(0.0, 0.0),  // 0
+                Position::new(1.0, 0.0),  // 1
+                Position::new(2.0, 0.0),  // 2
+                Position::new(3.0, 0.0),  // 3
+                Position::new(1.0, -1.0), // 4
+                Position::new(2.0, -1.0), // 5
+                Position::new(3.0, -1.0), // 6
+                Position::new(2.0, -2.0), // 7
+                Position::new(3.0, -2.0), // 8
+            ],
+            [
+                (0, 1, s),
+                (1, 0, s),
+                (1, 2, s),
+                (2, 1, s),
+                (2, 3, s),
+                (3, 2, s),
+                (2, 4, s),
+                (4, 2, s),
+                (3, 6, s),
+                (6, 3, s),
+                (4, 5, s),
+                (5, 4, s),
+                (5, 7, s),
+                (7, 5, s),
+                (7, 8, s),
+                (8, 7, s),
+            ],
+        )
+    }
+
+    fn vertices_r2(path: &Path<StateR2<usize>, impl Clone, Cost<f64>>) -> Vec<usize> {
+        [path.initial_state.key]
+            .into_iter()
+            .chain(path.sequence.iter().map(|(_, s)| s.key))
+            .collect()
+    }
+
+    #[test]
+    fn test_k_shortest_r2() {
+        let domain = InformedSearch::new_r2(
+            SharedGraph::new(make_lattice()),
+            LineFollow::new(2.0).unwrap(),
+        );
+        let expected = Planner::new(AStar(domain.clone()))
+            .plan(0usize, 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        let paths = Planner::new(KShortestPaths::new(domain, 8))
+            .plan(0usize, 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        assert_eq!(paths.len(), 8);
+        assert_relative_eq!(paths[0].total_cost.0, expected.total_cost.0, epsilon = 1e-6);
+
+        // There are six ways to cross the lattice using four edges. Every
+        // other loopless way needs six edges.
+        for (i, path) in paths.iter().enumerate() {
+            let edges = if i < 6 { 4.0 } else { 6.0 };
+            assert_relative_eq!(path.total_cost.0, edges * 0.5, epsilon = 1e-6);
+        }
+
+        let mut unique = HashSet::new();
+        for path in &paths {
+            let vertices = vertices_r2(path);
+            assert_eq!(vertices.first(), Some(&0));
+            assert_eq!(vertices.last(), Some(&8));
+            assert_eq!(
+                vertices.iter().collect::<HashSet<_>>().len(),
+                vertices.len()
+            );
+            assert!(unique.insert(vertices));
+
+            let duration = (path.final_state().time() - path.initial_state.time()).as_secs_f64();
+            assert_relative_eq!(duration, path.total_cost.0, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_k_shortest_runs_out_of_paths() {
+        let domain = InformedSearch::new_r2(
+            SharedGraph::new(make_branchless_graph()),
+            LineFollow::new(2.0).unwrap(),
+        );
+
+        let paths = Planner::new(KShortestPaths::new(domain.clone(), 3))
+            .plan(0usize, 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        assert_eq!(paths.len(), 1);
+
+        let paths = Planner::new(DiversePaths::new(domain, 3, Cost(1.0)))
+            .plan(0usize, 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        assert_eq!(paths.len(), 1);
+    }
+
+    #[test]
+    fn test_alternatives_are_observed() {
+        let domain = InformedSearch::new_r2(
+            SharedGraph::new(make_lattice()),
+            LineFollow::new(2.0).unwrap(),
+        );
+
+        let mut search = Planner::new(KShortestPaths::new(domain.clone(), 3))
+            .plan(0usize, 8usize)
+            .unwrap()
+            .with_observer(SearchCounters::default());
+        let paths = search.solve().unwrap().solution().unwrap();
+        let counters = *search.observer();
+        // Every path that was found reached the goal in one of the detours
+        assert!(counters.solutions >= paths.len());
+        assert!(counters.popped > 0);
+        assert!(counters.pushed > 0);
+
+        let mut search = Planner::new(DiversePaths::new(domain, 3, Cost(1.0)))
+            .plan(0usize, 8usize)
+            .unwrap()
+            .with_observer(SearchTrace::new(1000));
+        let paths = search.solve().unwrap().solution().unwrap();
+        let trace = search.observer();
+        let solutions = trace
+            .events()
+            .filter(|e| e.kind == SearchEventKind::Solution)
+            .count();
+        assert!(solutions >= paths.len());
+        assert_eq!(
+            trace.events().last().unwrap().kind,
+            SearchEventKind::Solution
+        );
+    }
+
+    #[test]
+    fn test_diverse_r2() {
+        let domain = InformedSearch::new_r2(
+            SharedGraph::new(make_lattice()),
+            LineFollow::new(2.0).unwrap(),
+        );
+
+        let paths = Planner::new(DiversePaths::new(domain, 3, Cost(1.0)))
+            .plan(0usize, 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        assert_eq!(paths.len(), 3);
+        assert_relative_eq!(paths[0].total_cost.0, 2.0, epsilon = 1e-6);
+
+        // A second path that shares no edges with the first one is just as
+        // short, so the penalty must steer the second round onto it.
+        let edges = |path: &Path<StateR2<usize>, _, Cost<f64>>| {
+            let vertices = vertices_r2(path);
+            vertices
+                .windows(2)
+                .map(|w| (w[0], w[1]))
+                .collect::<HashSet<_>>()
+        };
+        assert!(edges(&paths[0]).is_disjoint(&edges(&paths[1])));
+        assert_relative_eq!(paths[1].total_cost.0, 2.0, epsilon = 1e-6);
+
+        for path in &paths {
+            // The reported costs must not include any penalties
+            let duration = (path.final_state().time() - path.initial_state.time()).as_secs_f64();
+            assert_relative_eq!(duration, path.total_cost.0, epsilon = 1e-6);
+            assert_eq!(path.final_state().key, 8);
+        }
+    }
+
+    #[test]
+    fn test_k_shortest_se2() {
+        let domain = InformedSearch::new_se2(
+            SharedGraph::new(make_lattice()),
+            DifferentialDriveLineFollow::new(2.0, 1.0).unwrap(),
+        );
+        let expected = Planner::new(AStarConnect(domain.clone()))
+            .plan((0usize, 0_f64), 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        let paths = Planner::new(KShortestPaths::new(domain.clone(), 3))
+            .plan((0usize, 0_f64), 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        assert_eq!(paths.len(), 3);
+        assert_relative_eq!(paths[0].total_cost.0, expected.total_cost.0, epsilon = 1e-6);
+        for (previous, next) in paths.iter().zip(paths.iter().skip(1)) {
+            assert!(previous.total_cost <= next.total_cost);
+        }
+        for path in &paths {
+            assert_eq!(path.initial_state.key.vertex, 0);
+            assert_eq!(path.final_state().key.vertex, 8);
+        }
+
+        let paths = Planner::new(DiversePaths::new(domain, 3, Cost(1.0)))
+            .plan((0usize, 0_f64), 8usize)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        assert_eq!(paths.len(), 3);
+        assert_relative_eq!(paths[0].total_cost.0, expected.total_cost.0, epsilon = 1e-6);
+        for path in &paths {
+            assert_eq!(path.final_state().key.vertex, 8);
+        }
+    }
+}
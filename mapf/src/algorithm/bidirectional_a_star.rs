@@ -0,0 +1,901 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::{
+        a_star::{AStarSearchError, Node},
+        path::DomainPath,
+        tree::*,
+        Algorithm, Coherent, MemoryFootprint, MinimumCostBound, ObservableMemory, Path,
+        QueueLength, SearchEvent, SearchObserver, SearchStatus, Solvable,
+    },
+    domain::{
+        Activity, Backtrack, Closable, CloseResult, ClosedSet, ClosedStatusForKey, Configurable,
+        Domain, Informed, Initializable, Keyed, Keyring, Reversible, Weighted,
+    },
+    error::Anyhow,
+    motion::Timed,
+};
+use std::{borrow::Borrow, ops::Add};
+
+/// Bidirectional A* grows one search tree forward from the start and another
+/// backward from the goal using the [`Reversible`] counterpart of the domain.
+/// Whenever a node reaches a key that was already closed by the other tree, the
+/// two trees have met. The roots of both trees are closed as soon as they are
+/// pushed, so a tree that reaches the root of the other tree meets it even if
+/// the other tree never expanded anything. The search finishes once the cheapest meeting can no
+/// longer be beaten by anything left in either queue, and the backward half of
+/// the path is turned around with [`Backtrack`].
+///
+/// The forward tree is informed by [`Informed`] towards the goal while the
+/// backward tree is informed towards the keys of the start states, so the
+/// domain must implement `Informed<D::State, D::Key>` as well.
+///
+/// The domain must implement the following traits:
+/// * [`Reversible`]
+/// * [`Backtrack`]
+/// * [`Keyring`]
+/// * [`Initializable`] as both `Initializable<Start, Goal, D::State>` and
+///   `Initializable<Goal, Start, D::State>`
+/// * [`Closable`] with a closed set that implements [`ClosedStatusForKey`]
+/// * [`Activity`]
+/// * [`Weighted`]
+/// * [`Informed`]
+///
+/// The states must be [`Timed`] so the backward half of the path can be shifted
+/// to begin when the forward half ends.
+#[derive(Debug, Clone)]
+pub struct BidirectionalAStar<D> {
+    forward: D,
+    backward: D,
+}
+
+impl<D: Reversible> BidirectionalAStar<D> {
+    pub fn new(domain: D) -> Result<Self, D::ReversalError> {
+        Ok(Self {
+            backward: domain.reversed()?,
+            forward: domain,
+        })
+    }
+}
+
+impl<D> BidirectionalAStar<D> {
+    pub fn forward(&self) -> &D {
+        &self.forward
+    }
+
+    pub fn backward(&self) -> &D {
+        &self.backward
+    }
+}
+
+type HalfTree<Closed, State, Action, Cost> = Tree<Closed, Node<State, Action, Cost>, Cost>;
+
+/// A node of one tree that meets a node closed by the other tree, given as
+/// `(cost, this_node, other_node)`.
+type Contact<Cost> = (Cost, usize, usize);
+
+#[derive(Debug)]
+pub struct Memory<Closed, Key, State, Action, Cost> {
+    pub forward: HalfTree<Closed, State, Action, Cost>,
+    pub backward: HalfTree<Closed, State, Action, Cost>,
+    /// The backward tree is informed towards these keys
+    start_keys: Vec<Key>,
+    best: Option<Meeting<Cost>>,
+}
+
+/// A node in the forward tree and a node in the backward tree which share a key
+#[derive(Debug, Clone)]
+struct Meeting<Cost> {
+    cost: Cost,
+    forward: usize,
+    backward: usize,
+}
+
+impl<Closed, Key, State, Action, Cost> QueueLength for Memory<Closed, Key, State, Action, Cost>
+where
+    Cost: Clone + Add<Cost, Output = Cost>,
+{
+    fn queue_length(&self) -> usize {
+        self.forward.queue_length() + self.backward.queue_length()
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> MinimumCostBound for Memory<Closed, Key, State, Action, Cost>
+where
+    Cost: Clone + Ord + Add<Cost, Output = Cost>,
+{
+    type Cost = Cost;
+    fn minimum_cost_bound(&self) -> Option<Self::Cost> {
+        // Each queue gives a lower bound independently, so take the tighter one
+        match (
+            self.forward.minimum_cost_bound(),
+            self.backward.minimum_cost_bound(),
+        ) {
+            (Some(f), Some(b)) => Some(f.max(b)),
+            (f, b) => f.or(b),
+        }
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> MemoryFootprint for Memory<Closed, Key, State, Action, Cost>
+where
+    Cost: Clone + Add<Cost, Output = Cost>,
+{
+    fn memory_footprint(&self) -> usize {
+        self.forward.memory_footprint() + self.backward.memory_footprint()
+    }
+}
+
+impl<Closed, Key, State, Action, Cost> ObservableMemory
+    for Memory<Closed, Key, State, Action, Cost>
+{
+    type State = State;
+    type Cost = Cost;
+}
+
+impl<D> BidirectionalAStar<D>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Key: Clone,
+    D::WeightedError: Into<D::Error>,
+    D::ActivityError: Into<D::Error>,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::ClosedSet<usize>: ClosedStatusForKey<D::Key, usize>,
+{
+    fn domain_err(err: impl Into<D::Error>) -> AStarSearchError<D::Error> {
+        AStarSearchError::Domain(err.into())
+    }
+
+    fn algo_err(err: TreeError) -> AStarSearchError<D::Error> {
+        AStarSearchError::Algorithm(err)
+    }
+
+    /// Push a root node into one of the trees and close it right away
+    fn push_root(
+        domain: &D,
+        tree: &mut HalfTree<D::ClosedSet<usize>, D::State, D::ActivityAction, D::Cost>,
+        state: D::State,
+        estimate: impl Fn(&D::State) -> Result<Option<D::Cost>, AStarSearchError<D::Error>>,
+    ) -> Result<(), AStarSearchError<D::Error>> {
+        let cost = match domain.initial_cost(&state).map_err(Self::domain_err)? {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let remaining_cost_estimate = match estimate(&state)? {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        let node_id = match tree
+            .push_node(Node::new(state, cost, remaining_cost_estimate, None))
+            .map_err(Self::algo_err)?
+        {
+            Some(node_id) => node_id,
+            None => return Ok(()),
+        };
+
+        let node = tree.arena.get_node(node_id).map_err(Self::algo_err)?;
+        if let CloseResult::Rejected { prior, .. } = tree.closed_set.close(node.state(), node_id) {
+            // Another root has the same key, so keep whichever is cheaper
+            let prior_node = tree.arena.get_node(*prior).map_err(Self::algo_err)?;
+            if node.cost() < prior_node.cost() {
+                *prior = node_id;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Expand the top node of one tree. If any node of this tree meets a node
+    /// that was closed by the other tree, the cheapest meeting is returned as
+    /// `(cost, this_node, other_node)`.
+    fn step_half<Obs: SearchObserver<<Self as Algorithm>::Memory>>(
+        domain: &D,
+        tree: &mut HalfTree<D::ClosedSet<usize>, D::State, D::ActivityAction, D::Cost>,
+        other: &HalfTree<D::ClosedSet<usize>, D::State, D::ActivityAction, D::Cost>,
+        estimate: impl Fn(&D::State) -> Result<Option<D::Cost>, AStarSearchError<D::Error>>,
+        observer: &mut Obs,
+    ) -> Result<Option<Contact<D::Cost>>, AStarSearchError<D::Error>> {
+        let top_id = match tree.queue.pop() {
+            Some(top) => top.0.node_id,
+            None => return Ok(None),
+        };
+
+        let top = tree.arena.get_node(top_id).map_err(Self::algo_err)?.clone();
+        observer.observe(SearchEvent::Pop {
+            node: top_id,
+            state: top.state(),
+            cost: top.cost(),
+        });
+
+        if let CloseResult::Rejected { prior, .. } = tree.closed_set.close(top.state(), top_id) {
+            let prior_node = tree.arena.get_node(*prior).map_err(Self::algo_err)?;
+            // Roots were already closed by themselves when they were pushed
+            if *prior != top_id && prior_node.cost() <= top.cost() {
+                observer.observe(SearchEvent::CloseRejected {
+                    node: top_id,
+                    prior: *prior,
+                    state: top.state(),
+                    cost: top.cost(),
+                });
+                return Ok(None);
+            }
+
+            *prior = top_id;
+        }
+
+        let mut meeting = None;
+        Self::meet(domain, other, top_id, &top, &mut meeting)?;
+
+        for next in domain.choices(top.state().clone()) {
+            let (action, child_state) = next.map_err(Self::domain_err)?;
+            let cost = match domain
+                .cost(top.state(), &action, &child_state)
+                .map_err(Self::domain_err)?
+            {
+                Some(c) => c,
+                None => continue,
+            } + top.cost().clone();
+
+            let remaining_cost_estimate = match estimate(&child_state)? {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let pushed = tree
+                .push_node(Node::new(
+                    child_state,
+                    cost,
+                    remaining_cost_estimate,
+                    Some((top_id, action)),
+                ))
+                .map_err(Self::algo_err)?;
+
+            if let Some(node_id) = pushed {
+                let node = tree.arena.get_node(node_id).map_err(Self::algo_err)?;
+                observer.observe(SearchEvent::Push {
+                    node: node_id,
+                    parent: Some(top_id),
+                    state: node.state(),
+                    cost: node.cost(),
+                });
+                Self::meet(domain, other, node_id, node, &mut meeting)?;
+            }
+        }
+
+        Ok(meeting)
+    }
+
+    /// Check whether a node has the same key as a node that was closed by the
+    /// other tree.
+    fn meet(
+        domain: &D,
+        other: &HalfTree<D::ClosedSet<usize>, D::State, D::ActivityAction, D::Cost>,
+        node_id: usize,
+        node: &Node<D::State, D::ActivityAction, D::Cost>,
+        meeting: &mut Option<(D::Cost, usize, usize)>,
+    ) -> Result<(), AStarSearchError<D::Error>> {
+        let key = domain.key_for(node.state());
+        if let Some(other_id) = other.closed_set.status_for_key(key.borrow()).closed() {
+            let other_node = other.arena.get_node(*other_id).map_err(Self::algo_err)?;
+            let cost = node.cost().clone() + other_node.cost().clone();
+            if meeting.as_ref().is_none_or(|(best, _, _)| cost < *best) {
+                *meeting = Some((cost, node_id, *other_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Join the forward half of the path with the backtracked backward half.
+    fn join(
+        &self,
+        memory: &<Self as Algorithm>::Memory,
+        meeting: &Meeting<D::Cost>,
+    ) -> Result<DomainPath<D>, AStarSearchError<D::Error>>
+    where
+        D: Backtrack<D::State, D::ActivityAction>,
+        D::BacktrackError: Into<D::Error>,
+        D::State: Timed,
+    {
+        let forward = memory
+            .forward
+            .arena
+            .retrace(meeting.forward)
+            .map_err(Self::algo_err)?;
+        let backward = memory
+            .backward
+            .arena
+            .retrace(meeting.backward)
+            .map_err(Self::algo_err)?;
+
+        let (meet_forward_state, goal_forward_state) = self
+            .backward
+            .flip_endpoints(&backward.initial_state, backward.final_state())
+            .map_err(Self::domain_err)?;
+
+        // The backward half would begin at the time that the backward search
+        // began, so shift it to begin where the forward half ends.
+        let delta_t = forward.final_state().time() - meet_forward_state.time();
+        let mut parent_forward_state = goal_forward_state.time_shifted_by(delta_t);
+        let mut parent_reverse_state = backward.initial_state;
+
+        let mut tail = Vec::new();
+        for (reverse_action, child_reverse_state) in backward.sequence {
+            let (forward_action, child_forward_state) = self
+                .backward
+                .backtrack(
+                    &parent_forward_state,
+                    &parent_reverse_state,
+                    &reverse_action,
+                    &child_reverse_state,
+                )
+                .map_err(Self::domain_err)?;
+
+            tail.push((forward_action, parent_forward_state));
+            parent_forward_state = child_forward_state;
+            parent_reverse_state = child_reverse_state;
+        }
+        tail.reverse();
+
+        let mut sequence = forward.sequence;
+        sequence.extend(tail);
+        Ok(Path {
+            initial_state: forward.initial_state,
+            sequence,
+            total_cost: forward.total_cost + backward.total_cost,
+        })
+    }
+}
+
+impl<D> Algorithm for BidirectionalAStar<D>
+where
+    D: Domain
+        + Keyed
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>,
+{
+    type Memory = Memory<D::ClosedSet<usize>, D::Key, D::State, D::ActivityAction, D::Cost>;
+}
+
+impl<D, Start, Goal> Coherent<Start, Goal> for BidirectionalAStar<D>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Initializable<Start, Goal, D::State>
+        + Initializable<Goal, Start, D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>
+        + Informed<D::State, D::Key, CostEstimate = D::Cost>,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Key: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::ClosedSet<usize>: ClosedStatusForKey<D::Key, usize>,
+    <D as Initializable<Start, Goal, D::State>>::InitialError: Into<D::Error>,
+    <D as Initializable<Goal, Start, D::State>>::InitialError: Into<D::Error>,
+    <D as Informed<D::State, Goal>>::InformedError: Into<D::Error>,
+    <D as Informed<D::State, D::Key>>::InformedError: Into<D::Error>,
+    D::ActivityError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    Start: Clone,
+    Goal: Clone,
+{
+    type InitError = AStarSearchError<D::Error>;
+
+    fn initialize(&self, start: Start, goal: &Goal) -> Result<Self::Memory, Self::InitError> {
+        let mut memory = Memory {
+            forward: Tree::new(self.forward.new_closed_set()),
+            backward: Tree::new(self.backward.new_closed_set()),
+            start_keys: Vec::new(),
+            best: None,
+        };
+
+        let forward_estimate = |state: &D::State| {
+            <D as Informed<D::State, Goal>>::estimate_remaining_cost(&self.forward, state, goal)
+                .map_err(Self::domain_err)
+        };
+        let initial_states = <D as Initializable<Start, Goal, D::State>>::initialize(
+            &self.forward,
+            start.clone(),
+            goal,
+        );
+        for state in initial_states {
+            let state = state.map_err(Self::domain_err)?;
+            {
+                let key_ref = self.forward.key_for(&state);
+                let key: &D::Key = key_ref.borrow();
+                memory.start_keys.push(key.clone());
+            }
+            Self::push_root(&self.forward, &mut memory.forward, state, forward_estimate)?;
+        }
+
+        let start_keys = &memory.start_keys;
+        let backward_estimate = |state: &D::State| {
+            estimate_to_any(&self.backward, state, start_keys).map_err(Self::domain_err)
+        };
+        let final_states = <D as Initializable<Goal, Start, D::State>>::initialize(
+            &self.backward,
+            goal.clone(),
+            &start,
+        );
+        for state in final_states {
+            let state = state.map_err(Self::domain_err)?;
+            Self::push_root(
+                &self.backward,
+                &mut memory.backward,
+                state,
+                backward_estimate,
+            )?;
+        }
+
+        Ok(memory)
+    }
+}
+
+/// Estimate the cost of reaching whichever of the keys is cheapest to reach.
+fn estimate_to_any<D>(
+    domain: &D,
+    state: &D::State,
+    keys: &[D::Key],
+) -> Result<Option<D::Cost>, D::InformedError>
+where
+    D: Domain + Keyed + Weighted<D::State, D::ActivityAction> + Activity<D::State>,
+    D: Informed<D::State, D::Key, CostEstimate = D::Cost>,
+    D::Cost: Ord,
+{
+    let mut best: Option<D::Cost> = None;
+    for key in keys {
+        if let Some(estimate) = domain.estimate_remaining_cost(state, key)? {
+            if best.as_ref().is_none_or(|b| estimate < *b) {
+                best = Some(estimate);
+            }
+        }
+    }
+
+    Ok(best)
+}
+
+impl<D, Goal> Solvable<Goal> for BidirectionalAStar<D>
+where
+    D: Domain
+        + Keyring<D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>
+        + Informed<D::State, D::Key, CostEstimate = D::Cost>
+        + Backtrack<D::State, D::ActivityAction>,
+    D::State: Clone + Timed,
+    D::ActivityAction: Clone,
+    D::Key: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::ClosedSet<usize>: ClosedStatusForKey<D::Key, usize>,
+    <D as Informed<D::State, Goal>>::InformedError: Into<D::Error>,
+    <D as Informed<D::State, D::Key>>::InformedError: Into<D::Error>,
+    D::ActivityError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    D::BacktrackError: Into<D::Error>,
+{
+    type Solution = Path<D::State, D::ActivityAction, D::Cost>;
+    type StepError = AStarSearchError<D::Error>;
+
+    fn step(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step_observed(memory, goal, &mut ())
+    }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        observer: &mut Obs,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        let forward_bound = memory.forward.minimum_cost_bound();
+        let backward_bound = memory.backward.minimum_cost_bound();
+        if let Some(best) = &memory.best {
+            // Any path that could still be found must pass through both
+            // queues, so it cannot cost less than either of their bounds. A
+            // tree that has run out of nodes can still be met by the other
+            // tree, so keep going until the remaining bound passes the best.
+            let finished = match (&forward_bound, &backward_bound) {
+                (Some(f), Some(b)) => best.cost <= *f || best.cost <= *b,
+                (Some(bound), None) | (None, Some(bound)) => best.cost <= *bound,
+                (None, None) => true,
+            };
+
+            if finished {
+                let solution = self.join(memory, best)?;
+                let node = memory
+                    .forward
+                    .arena
+                    .get_node(best.forward)
+                    .map_err(Self::algo_err)?;
+                observer.observe(SearchEvent::Solution {
+                    node: best.forward,
+                    state: node.state(),
+                    cost: &solution.total_cost,
+                });
+                return Ok(SearchStatus::Solved(solution));
+            }
+        } else if forward_bound.is_none() && backward_bound.is_none() {
+            // Both trees have run out of nodes without ever meeting
+            return Ok(SearchStatus::Impossible);
+        }
+
+        // Expand whichever tree has the smaller frontier, as long as it still
+        // has nodes left.
+        let expand_forward = match (&forward_bound, &backward_bound) {
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            _ => memory.forward.queue.len() <= memory.backward.queue.len(),
+        };
+
+        let meeting = if expand_forward {
+            let estimate = |state: &D::State| {
+                <D as Informed<D::State, Goal>>::estimate_remaining_cost(&self.forward, state, goal)
+                    .map_err(Self::domain_err)
+            };
+            Self::step_half(
+                &self.forward,
+                &mut memory.forward,
+                &memory.backward,
+                estimate,
+                observer,
+            )?
+            .map(|(cost, forward, backward)| Meeting {
+                cost,
+                forward,
+                backward,
+            })
+        } else {
+            let start_keys = &memory.start_keys;
+            let estimate = |state: &D::State| {
+                estimate_to_any(&self.backward, state, start_keys).map_err(Self::domain_err)
+            };
+            Self::step_half(
+                &self.backward,
+                &mut memory.backward,
+                &memory.forward,
+                estimate,
+                observer,
+            )?
+            .map(|(cost, backward, forward)| Meeting {
+                cost,
+                forward,
+                backward,
+            })
+        };
+
+        if let Some(meeting) = meeting {
+            if memory
+                .best
+                .as_ref()
+                .is_none_or(|best| meeting.cost < best.cost)
+            {
+                memory.best = Some(meeting);
+            }
+        }
+
+        Ok(SearchStatus::Incomplete)
+    }
+}
+
+impl<D: Configurable + Reversible> Configurable for BidirectionalAStar<D>
+where
+    D::ReversalError: Into<Anyhow>,
+{
+    type Configuration = D::Configuration;
+    fn configure<F>(self, f: F) -> Result<Self, Anyhow>
+    where
+        F: FnOnce(Self::Configuration) -> Result<Self::Configuration, Anyhow>,
+    {
+        Self::new(self.forward.configure(f)?).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::AStar,
+        domain::{Cost, KeyedCloser},
+        error::NoError,
+        graph::{SharedGraph, SimpleGraph},
+        motion::{
+            r2::*,
+            se2::{
+                DifferentialDriveLineFollow, DiscreteSpaceTimeSE2, GoalSE2, InitializeSE2,
+                Orientation, SatisfySE2, StartSE2,
+            },
+            SpeedLimit, TimePoint, TravelTimeCost,
+        },
+        templates::{GraphMotion, InformedSearch},
+        Planner,
+    };
+    use approx::assert_relative_eq;
+    use std::sync::Arc;
+
+    fn make_test_graph() -> SimpleGraph<Position, SpeedLimit> {
+        /*
+         * 0-----1-----2-----3
+         *           /       |
+         *         /         |
+         *       4-----5     6
+         *             |
+         *             |
+         *             7-----8
+         */
+
+        let s = SpeedLimit(None);
+        SimpleGraph::from_iters(
+            [
+                Position::new(0.0, 0.0),  // 0
+                Position::new(1.0, 0.0),  // 1
+                Position::new(2.0, 0.0),  // 2
+                Position::new(3.0, 0.0),  // 3
+                Position::new(1.0, -1.0), // 4
+                Position::new(2.0, -1.0), // 5
+                Position::new(3.0, -1.0), // 6
+                Position::new(2.0, -2.0), // 7
+                Position::new(3.0, -2.0), // 8
+            ],
+            [
+                (0, 1, s),
+                (1, 0, s),
+                (1, 2, s),
+                (2, 1, s),
+                (2, 3, s),
+                (3, 2, s),
+                (2, 4, s),
+                (4, 2, s),
+                (3, 6, s),
+                (6, 3, s),
+                (4, 5, s),
+                (5, 4, s),
+                (5, 7, s),
+                (7, 5, s),
+                (7, 8, s),
+                (8, 7, s),
+            ],
+        )
+    }
+
+    fn make_directed_chain() -> SimpleGraph<Position, SpeedLimit> {
+        /*
+         * 0---->1---->2---->3
+         */
+
+        let s = SpeedLimit(None);
+        SimpleGraph::from_iters(
+            [
+                Position::new(0.0, 0.0), // 0
+                Position::new(1.0, 0.0), // 1
+                Position::new(2.0, 0.0), // 2
+                Position::new(3.0, 0.0), // 3
+            ],
+            [(0, 1, s), (1, 2, s), (2, 3, s)],
+        )
+    }
+
+    #[test]
+    fn test_bidirectional_matches_a_star_r2() {
+        for (graph, vertex_count) in [(make_test_graph(), 9), (make_directed_chain(), 4)] {
+            let domain =
+                InformedSearch::new_r2(SharedGraph::new(graph), LineFollow::new(2.0).unwrap());
+            let a_star = Planner::new(Arc::new(AStar(domain.clone())));
+            let bidirectional = Planner::new(Arc::new(BidirectionalAStar::new(domain).unwrap()));
+
+            for start in 0..vertex_count {
+                for goal in 0..vertex_count {
+                    let expected = a_star.plan(start, goal).unwrap().solve().unwrap();
+                    let solution = bidirectional.plan(start, goal).unwrap().solve().unwrap();
+                    match (expected.solution(), solution.solution()) {
+                        (Some(expected), Some(solution)) => {
+                            assert_relative_eq!(
+                                solution.total_cost.0,
+                                expected.total_cost.0,
+                                epsilon = 1e-6
+                            );
+                            assert_eq!(solution.initial_state.key, start);
+                            assert_eq!(solution.final_state().key, goal);
+
+                            // The joined path must move forward in time without
+                            // any gap where the two halves meet.
+                            let mut previous = solution.initial_state.time();
+                            for (_, state) in &solution.sequence {
+                                assert!(previous <= state.time());
+                                previous = state.time();
+                            }
+                            let duration = (solution.final_state().time()
+                                - solution.initial_state.time())
+                            .as_secs_f64();
+                            assert_relative_eq!(duration, solution.total_cost.0, epsilon = 1e-6);
+                        }
+                        (None, None) => {
+                            // Going backwards along the chain is impossible
+                            assert!(goal < start);
+                        }
+                        (expected, solution) => panic!(
+                            "Mismatch from {start} to {goal}: expected {expected:?}, \
+                            found {solution:?}",
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_same_start_and_goal() {
+        let domain = InformedSearch::new_r2(
+            SharedGraph::new(make_test_graph()),
+            LineFollow::new(2.0).unwrap(),
+        );
+        let bidirectional = Planner::new(BidirectionalAStar::new(domain).unwrap());
+
+        for vertex in 0..9 {
+            let solution = bidirectional
+                .plan(vertex, vertex)
+                .unwrap()
+                .solve()
+                .unwrap()
+                .solution()
+                .unwrap();
+            assert!(solution.sequence.is_empty());
+            assert_eq!(solution.initial_state.key, vertex);
+            assert_relative_eq!(solution.total_cost.0, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_reports_impossible() {
+        /*
+         * 0-----1     2-----3
+         */
+
+        let s = SpeedLimit(None);
+        let graph = SimpleGraph::from_iters(
+            [
+                Position::new(0.0, 0.0), // 0
+                Position::new(1.0, 0.0), // 1
+                Position::new(3.0, 0.0), // 2
+                Position::new(4.0, 0.0), // 3
+            ],
+            [(0, 1, s), (1, 0, s), (2, 3, s), (3, 2, s)],
+        );
+
+        let domain = InformedSearch::new_r2(SharedGraph::new(graph), LineFollow::new(2.0).unwrap());
+        let bidirectional = Planner::new(BidirectionalAStar::new(domain).unwrap());
+
+        // Both trees run out of nodes without ever meeting
+        let mut search = bidirectional.plan(0usize, 3usize).unwrap();
+        assert!(search.solve().unwrap().impossible());
+        let memory = search.memory();
+        assert_eq!(memory.queue_length(), 0);
+        assert!(memory.forward.closed_set.status_for_key(&1).is_closed());
+        assert!(memory.backward.closed_set.status_for_key(&2).is_closed());
+    }
+
+    /// A heuristic that never informs the search, so it can be used with any
+    /// domain, including reversed ones.
+    #[derive(Clone, Copy)]
+    struct NoHeuristic;
+
+    impl<State, Goal> Informed<State, Goal> for NoHeuristic {
+        type CostEstimate = Cost<f64>;
+        type InformedError = NoError;
+        fn estimate_remaining_cost(
+            &self,
+            _: &State,
+            _: &Goal,
+        ) -> Result<Option<Self::CostEstimate>, Self::InformedError> {
+            Ok(Some(Cost(0.0)))
+        }
+    }
+
+    impl Reversible for NoHeuristic {
+        type ReversalError = NoError;
+        fn reversed(&self) -> Result<Self, Self::ReversalError> {
+            Ok(*self)
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_se2() {
+        let graph = SharedGraph::new(make_test_graph());
+        let motion = DifferentialDriveLineFollow::new(2.0, 1.0).unwrap();
+        let domain = InformedSearch::new(
+            GraphMotion {
+                space: DiscreteSpaceTimeSE2::<usize, 100>::new(),
+                graph: graph.clone(),
+                extrapolator: motion,
+            },
+            TravelTimeCost(1.0),
+            NoHeuristic,
+            KeyedCloser(DiscreteSpaceTimeSE2::<usize, 100>::new()),
+        )
+        .with_initializer(InitializeSE2(graph));
+
+        let a_star = Planner::new(AStar(
+            domain.clone().with_satisfier(SatisfySE2::from(motion)),
+        ));
+        let bidirectional = Planner::new(BidirectionalAStar::new(domain).unwrap());
+
+        let start = StartSE2 {
+            time: TimePoint::zero(),
+            key: 0usize,
+            orientation: Orientation::from_angle(0.0),
+        };
+        let goal = StartSE2 {
+            time: TimePoint::zero(),
+            key: 8usize,
+            orientation: Orientation::from_angle(0.0),
+        };
+
+        let solution = bidirectional
+            .plan(start, goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        let expected = a_star
+            .plan(
+                start,
+                GoalSE2::new(8usize).with_orientation(Some(goal.orientation)),
+            )
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        assert_relative_eq!(solution.total_cost.0, expected.total_cost.0, epsilon = 1e-6);
+
+        assert_eq!(solution.initial_state.key.vertex, 0);
+        let arrival = solution.final_state();
+        assert_eq!(arrival.key.vertex, 8);
+        assert_relative_eq!(
+            arrival.waypoint.position.rotation.angle(),
+            0.0,
+            epsilon = 1e-6
+        );
+
+        // The turns of the backward half must be backtracked into forward
+        // turns that line up with the forward half.
+        let mut previous = solution.initial_state.time();
+        for (_, state) in &solution.sequence {
+            assert!(previous <= state.time());
+            previous = state.time();
+        }
+        let duration = (arrival.time() - solution.initial_state.time()).as_secs_f64();
+        assert_relative_eq!(duration, solution.total_cost.0, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,588 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::{
+        a_star::{self, AStar, AStarConnect},
+        tree::{Tree, TreeNode, TreeQueueTicket},
+        Algorithm,
+    },
+    domain::{Activity, Closable, ClosedSet, Domain, Fingerprinted, Weighted},
+    error::ThisError,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{cmp::Reverse, ops::Add, path::Path, sync::Arc};
+
+/// The format version of [`Checkpoint`]. This is increased whenever the
+/// content of a checkpoint changes in a way that older checkpoints can no
+/// longer be restored.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// A saved snapshot of some search progress.
+///
+/// The fingerprint identifies the configuration of the domain that the
+/// snapshot was taken with. A checkpoint will only be restored for a
+/// configuration with the same fingerprint, because the node IDs and costs
+/// inside of it are meaningless for any other configuration. The fingerprint
+/// is calculated by the [`Fingerprinted`] domain of the search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint<T> {
+    pub version: u32,
+    pub fingerprint: u64,
+    pub content: T,
+}
+
+/// The fields of a checkpoint that can be read before its content is parsed.
+#[derive(Deserialize)]
+struct CheckpointHeader {
+    version: u32,
+}
+
+impl<T> Checkpoint<T> {
+    pub fn new(fingerprint: u64, content: T) -> Self {
+        Self {
+            version: CHECKPOINT_VERSION,
+            fingerprint,
+            content,
+        }
+    }
+
+    /// Check that this checkpoint can be restored for a domain configuration
+    /// with the given fingerprint.
+    pub fn validate(&self, fingerprint: u64) -> Result<(), CheckpointError> {
+        if self.version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::Version {
+                found: self.version,
+                expected: CHECKPOINT_VERSION,
+            });
+        }
+
+        if self.fingerprint != fingerprint {
+            return Err(CheckpointError::Fingerprint {
+                found: self.fingerprint,
+                expected: fingerprint,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, CheckpointError>
+    where
+        T: Serialize,
+    {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Parse a checkpoint. The version is checked before the content is
+    /// parsed so that checkpoints from an incompatible version of the library
+    /// are reported as such instead of as a format error.
+    pub fn from_json(text: &str) -> Result<Self, CheckpointError>
+    where
+        T: DeserializeOwned,
+    {
+        let header: CheckpointHeader = serde_json::from_str(text)?;
+        if header.version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::Version {
+                found: header.version,
+                expected: CHECKPOINT_VERSION,
+            });
+        }
+
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Write this checkpoint to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError>
+    where
+        T: Serialize,
+    {
+        // Write to a temporary file first so that a crash while saving does
+        // not destroy the previous checkpoint.
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        std::fs::write(&temp, self.to_json()?)?;
+        std::fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    /// Read a checkpoint from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CheckpointError>
+    where
+        T: DeserializeOwned,
+    {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum CheckpointError {
+    #[error("Unable to access the checkpoint file:\n{0}")]
+    Io(#[from] std::io::Error),
+    #[error("The checkpoint is not formatted correctly:\n{0}")]
+    Format(#[from] serde_json::Error),
+    #[error("The checkpoint has version [{found}] but version [{expected}] is required")]
+    Version { found: u32, expected: u32 },
+    #[error(
+        "The checkpoint was created for a different domain configuration \
+    (fingerprint [{found:016x}] instead of [{expected:016x}])"
+    )]
+    Fingerprint { found: u64, expected: u64 },
+    #[error("A node [{0}] is referenced but does not exist in the checkpoint")]
+    BrokenReference(usize),
+    #[error("The content of the checkpoint is invalid: {0}")]
+    Invalid(String),
+}
+
+/// Calculate a fingerprint for a domain configuration. The fingerprint is
+/// stable across machines and runs of the program, so it can be saved with a
+/// [`Checkpoint`].
+///
+/// Maps are sorted by key before the fingerprint is calculated, so the order of
+/// entries in a [`HashMap`](std::collections::HashMap) does not matter.
+pub fn fingerprint<T: Serialize + ?Sized>(configuration: &T) -> Result<u64, CheckpointError> {
+    // Converting to a value sorts the entries of every map
+    let value = serde_json::to_value(configuration)?;
+    let bytes = serde_json::to_vec(&value)?;
+
+    // 64-bit FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    Ok(hash)
+}
+
+/// The serializable content of a [`Tree`]. The closed set and the queue are
+/// saved as node IDs and rebuilt from the nodes when the tree is restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeCheckpoint<Node> {
+    pub arena: Vec<Node>,
+    /// Nodes that were waiting in the queue
+    pub queue: Vec<usize>,
+    /// Nodes that closed their states
+    pub closed: Vec<usize>,
+}
+
+impl<Closed, Node: TreeNode> Tree<Closed, Node, Node::Cost> {
+    /// Take a snapshot of this tree that can be saved in a [`Checkpoint`].
+    pub fn checkpoint(&self) -> TreeCheckpoint<Node>
+    where
+        Closed: ClosedSet<Node::State, usize>,
+        Node: Clone,
+    {
+        let mut queue: Vec<usize> = self.queue.iter().map(|t| t.0.node_id).collect();
+        queue.sort_unstable();
+        let mut closed: Vec<usize> = self.closed_set.iter_closed().into_iter().copied().collect();
+        closed.sort_unstable();
+
+        TreeCheckpoint {
+            arena: self.arena.clone(),
+            queue,
+            closed,
+        }
+    }
+
+    /// Rebuild a tree from a snapshot. The closed set that is given will be
+    /// filled in with the closed nodes of the snapshot.
+    pub fn restore(
+        mut closed_set: Closed,
+        checkpoint: TreeCheckpoint<Node>,
+    ) -> Result<Self, CheckpointError>
+    where
+        Closed: ClosedSet<Node::State, usize>,
+        Node::Cost: Ord,
+    {
+        let TreeCheckpoint {
+            arena,
+            queue,
+            closed,
+        } = checkpoint;
+
+        let get = |id: usize| arena.get(id).ok_or(CheckpointError::BrokenReference(id));
+        for node in &arena {
+            if let Some((parent, _)) = node.parent() {
+                get(parent)?;
+            }
+        }
+
+        for id in closed {
+            closed_set.replace(get(id)?.state(), id);
+        }
+
+        let queue = queue
+            .into_iter()
+            .map(|node_id| {
+                let node = get(node_id)?;
+                Ok(Reverse(TreeQueueTicket {
+                    evaluation: node.queue_evaluation(),
+                    bias: node.queue_bias(),
+                    node_id,
+                }))
+            })
+            .collect::<Result<_, CheckpointError>>()?;
+
+        Ok(Self {
+            closed_set,
+            queue,
+            arena,
+        })
+    }
+}
+
+/// Algorithms whose memory can be saved into a [`Checkpoint`] and restored
+/// later so the search can continue where it left off.
+pub trait Checkpointable: Algorithm {
+    /// The serializable content of the algorithm's memory
+    type Snapshot;
+
+    /// The fingerprint of the domain configuration that is being searched
+    fn fingerprint(&self) -> Result<u64, CheckpointError>;
+
+    fn snapshot(&self, memory: &Self::Memory) -> Self::Snapshot;
+
+    fn restore(&self, snapshot: Self::Snapshot) -> Result<Self::Memory, CheckpointError>;
+}
+
+/// The snapshot of an A* search
+pub type AStarSnapshot<State, Action, Cost> = TreeCheckpoint<a_star::Node<State, Action, Cost>>;
+
+impl<D> Checkpointable for AStar<D>
+where
+    D: Domain
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Fingerprinted,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+{
+    type Snapshot = AStarSnapshot<D::State, D::ActivityAction, D::Cost>;
+
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        self.0.fingerprint()
+    }
+
+    fn snapshot(&self, memory: &Self::Memory) -> Self::Snapshot {
+        memory.0.checkpoint()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) -> Result<Self::Memory, CheckpointError> {
+        Ok(a_star::Memory(Tree::restore(
+            self.0.new_closed_set(),
+            snapshot,
+        )?))
+    }
+}
+
+impl<D> Checkpointable for AStarConnect<D>
+where
+    D: Domain
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Fingerprinted,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+{
+    type Snapshot = AStarSnapshot<D::State, D::ActivityAction, D::Cost>;
+
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        self.0.fingerprint()
+    }
+
+    fn snapshot(&self, memory: &Self::Memory) -> Self::Snapshot {
+        memory.0.checkpoint()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) -> Result<Self::Memory, CheckpointError> {
+        Ok(a_star::Memory(Tree::restore(
+            self.0.new_closed_set(),
+            snapshot,
+        )?))
+    }
+}
+
+impl<Algo: Checkpointable> Checkpointable for Arc<Algo> {
+    type Snapshot = Algo::Snapshot;
+
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        self.as_ref().fingerprint()
+    }
+
+    fn snapshot(&self, memory: &Self::Memory) -> Self::Snapshot {
+        self.as_ref().snapshot(memory)
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) -> Result<Self::Memory, CheckpointError> {
+        self.as_ref().restore(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{Cost, Informed, KeyedClosedSet, SelfKeyring},
+        error::NoError,
+        planner::halt::StepLimit,
+        templates::InformedSearch,
+        Planner,
+    };
+
+    type Cell = (i64, i64);
+
+    /// Move in the four cardinal directions inside of a square box
+    struct GridMoves(i64);
+    impl Domain for GridMoves {
+        type State = Cell;
+        type Error = NoError;
+    }
+
+    impl Activity<Cell> for GridMoves {
+        type ActivityAction = ();
+        type ActivityError = NoError;
+        type Choices<'a> = Vec<Result<((), Cell), NoError>>
+        where
+            Self: 'a,
+            Self::ActivityAction: 'a,
+            Self::ActivityError: 'a,
+            Cell: 'a;
+
+        fn choices<'a>(&'a self, (x, y): Cell) -> Self::Choices<'a>
+        where
+            Self: 'a,
+            Self::ActivityAction: 'a,
+            Self::ActivityError: 'a,
+            Cell: 'a,
+        {
+            [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                .into_iter()
+                .map(|(dx, dy)| (x + dx, y + dy))
+                .filter(|(x, y)| (0..self.0).contains(x) && (0..self.0).contains(y))
+                .map(|cell| Ok(((), cell)))
+                .collect()
+        }
+    }
+
+    impl Fingerprinted for GridMoves {
+        fn fingerprint(&self) -> Result<u64, CheckpointError> {
+            fingerprint(&("grid", self.0))
+        }
+    }
+
+    struct UnitCost;
+    impl Weighted<Cell, ()> for UnitCost {
+        type Cost = i64;
+        type WeightedError = NoError;
+        fn cost(&self, _: &Cell, _: &(), _: &Cell) -> Result<Option<i64>, NoError> {
+            Ok(Some(1))
+        }
+
+        fn initial_cost(&self, _: &Cell) -> Result<Option<i64>, NoError> {
+            Ok(Some(0))
+        }
+    }
+
+    impl Fingerprinted for UnitCost {
+        fn fingerprint(&self) -> Result<u64, CheckpointError> {
+            fingerprint("unit_cost")
+        }
+    }
+
+    struct Manhattan;
+    impl Informed<Cell, Cell> for Manhattan {
+        type CostEstimate = i64;
+        type InformedError = NoError;
+        fn estimate_remaining_cost(&self, from: &Cell, to: &Cell) -> Result<Option<i64>, NoError> {
+            Ok(Some((from.0 - to.0).abs() + (from.1 - to.1).abs()))
+        }
+    }
+
+    impl Fingerprinted for Manhattan {
+        fn fingerprint(&self) -> Result<u64, CheckpointError> {
+            fingerprint("manhattan")
+        }
+    }
+
+    struct GridCloser;
+    impl Closable<Cell> for GridCloser {
+        type ClosedSet<T> = KeyedClosedSet<SelfKeyring<Cell>, T>;
+        fn new_closed_set<T>(&self) -> Self::ClosedSet<T> {
+            KeyedClosedSet::new(SelfKeyring::new())
+        }
+    }
+
+    impl Fingerprinted for GridCloser {
+        fn fingerprint(&self) -> Result<u64, CheckpointError> {
+            fingerprint("keyed")
+        }
+    }
+
+    /// Charge an uneven amount for entering each cell so that the costs
+    /// cannot be written exactly with a few decimal digits.
+    struct UnevenCost;
+    impl Weighted<Cell, ()> for UnevenCost {
+        type Cost = Cost<f64>;
+        type WeightedError = NoError;
+        fn cost(&self, _: &Cell, _: &(), (x, y): &Cell) -> Result<Option<Cost<f64>>, NoError> {
+            Ok(Some(Cost(1.0 + *x as f64 / 3.0 + (*y as f64).sqrt() / 7.0)))
+        }
+
+        fn initial_cost(&self, _: &Cell) -> Result<Option<Cost<f64>>, NoError> {
+            Ok(Some(Cost(0.0)))
+        }
+    }
+
+    impl Fingerprinted for UnevenCost {
+        fn fingerprint(&self) -> Result<u64, CheckpointError> {
+            fingerprint("uneven_cost")
+        }
+    }
+
+    struct FloatManhattan;
+    impl Informed<Cell, Cell> for FloatManhattan {
+        type CostEstimate = Cost<f64>;
+        type InformedError = NoError;
+        fn estimate_remaining_cost(
+            &self,
+            from: &Cell,
+            to: &Cell,
+        ) -> Result<Option<Cost<f64>>, NoError> {
+            Ok(Some(Cost(
+                ((from.0 - to.0).abs() + (from.1 - to.1).abs()) as f64,
+            )))
+        }
+    }
+
+    impl Fingerprinted for FloatManhattan {
+        fn fingerprint(&self) -> Result<u64, CheckpointError> {
+            fingerprint("float_manhattan")
+        }
+    }
+
+    type GridSearch = InformedSearch<GridMoves, UnitCost, Manhattan, GridCloser, (), (), ()>;
+    type UnevenGridSearch =
+        InformedSearch<GridMoves, UnevenCost, FloatManhattan, GridCloser, (), (), ()>;
+
+    fn make_planner(size: i64) -> Planner<Arc<AStar<GridSearch>>> {
+        Planner::new(Arc::new(AStar(InformedSearch::new(
+            GridMoves(size),
+            UnitCost,
+            Manhattan,
+            GridCloser,
+        ))))
+    }
+
+    #[test]
+    fn test_resume_a_star() {
+        let planner = make_planner(20);
+        let (start, goal): (Cell, Cell) = ((0, 0), (13, 17));
+
+        let mut search = planner
+            .plan_with_halting(start, goal, StepLimit::new(Some(10)))
+            .unwrap();
+        assert!(search.solve().unwrap().incomplete());
+
+        let path = std::env::temp_dir().join(format!(
+            "mapf_test_resume_a_star_{}.json",
+            std::process::id()
+        ));
+        search.checkpoint().unwrap().save(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+
+        let resumed = planner
+            .resume(Checkpoint::load(&path).unwrap(), goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let uninterrupted = planner
+            .plan(start, goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        assert_eq!(resumed.total_cost, uninterrupted.total_cost);
+        assert_eq!(resumed.initial_state, start);
+        assert_eq!(resumed.sequence.last().unwrap().1, goal);
+
+        // A larger grid changes the domain, so the checkpoint does not fit it.
+        assert!(matches!(
+            make_planner(30).resume(Checkpoint::from_json(&text).unwrap(), goal),
+            Err(CheckpointError::Fingerprint { .. }),
+        ));
+
+        let old_version = text.replacen(
+            &format!("\"version\":{CHECKPOINT_VERSION}"),
+            "\"version\":0",
+            1,
+        );
+        assert!(matches!(
+            Checkpoint::<AStarSnapshot<Cell, (), i64>>::from_json(&old_version),
+            Err(CheckpointError::Version { found: 0, .. }),
+        ));
+    }
+    #[test]
+    fn test_resume_float_costs_from_json() {
+        let planner = Planner::new(Arc::new(AStar(UnevenGridSearch::new(
+            GridMoves(12),
+            UnevenCost,
+            FloatManhattan,
+            GridCloser,
+        ))));
+        let (start, goal): (Cell, Cell) = ((0, 0), (9, 11));
+        let uninterrupted = planner
+            .plan(start, goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        // The costs in the checkpoint pass through text, so they must come
+        // back exactly for the resumed search to match the uninterrupted one.
+        for steps in [1, 5, 20, 60] {
+            let mut search = planner
+                .plan_with_halting(start, goal, StepLimit::new(Some(steps)))
+                .unwrap();
+            assert!(search.solve().unwrap().incomplete());
+            let text = search.checkpoint().unwrap().to_json().unwrap();
+            let checkpoint = Checkpoint::from_json(&text).unwrap();
+            assert_eq!(checkpoint.to_json().unwrap(), text);
+
+            let resumed = planner
+                .resume(checkpoint, goal)
+                .unwrap()
+                .solve()
+                .unwrap()
+                .solution()
+                .unwrap();
+            assert_eq!(resumed.total_cost.0, uninterrupted.total_cost.0);
+            assert_eq!(resumed.sequence, uninterrupted.sequence);
+        }
+    }
+}
@@ -16,7 +16,10 @@
 */
 
 use crate::{
-    algorithm::{tree::*, Algorithm, Coherent, Path, SearchStatus, Solvable},
+    algorithm::{
+        tree::*, Algorithm, Coherent, ObservableMemory, Path, SearchEvent, SearchObserver,
+        SearchStatus, Solvable,
+    },
     domain::{
         Activity, ArrivalKeyring, Closable, CloseResult, ClosedSet, ClosedStatus,
         ClosedStatusForKey, Configurable, Connectable, Domain, Initializable, Keyed, Keyring,
@@ -91,6 +94,30 @@ where
     fn algo_err(err: impl Into<DijkstraImplError>) -> DijkstraSearchError<D::Error> {
         DijkstraSearchError::Algorithm(err.into())
     }
+
+    fn observe_push<Obs: SearchObserver<Memory<D>>>(
+        cache: &CachedTree<D>,
+        pushed: Option<usize>,
+        parent_id: usize,
+        observer: &mut Obs,
+    ) -> Result<(), DijkstraSearchError<D::Error>> {
+        if let Some(node_id) = pushed {
+            let node = cache
+                .tree
+                .arena
+                .get(node_id)
+                .ok_or(TreeError::BrokenReference(node_id))
+                .map_err(Self::algo_err)?;
+            observer.observe(SearchEvent::Push {
+                node: node_id,
+                parent: Some(parent_id),
+                state: &node.state,
+                cost: &node.cost,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl<D, Start, Goal> Coherent<Start, Goal> for Dijkstra<D>
@@ -251,9 +278,18 @@ where
     type StepError = DijkstraSearchError<D::Error>;
 
     fn step(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step_observed(memory, goal, &mut ())
+    }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
         &self,
         memory: &mut Self::Memory,
         _: &Goal,
+        observer: &mut Obs,
     ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
         if memory.exhausted {
             if let Some((s, _)) = memory.best_solution {
@@ -343,6 +379,11 @@ where
                         .get_node(top_id)
                         .map_err(Self::algo_err)?
                         .clone();
+                    observer.observe(SearchEvent::Pop {
+                        node: top_id,
+                        state: &top.state,
+                        cost: &top.cost,
+                    });
                     if let CloseResult::Rejected { prior, .. } =
                         cache.tree.closed_set.close(&top.state, top_id)
                     {
@@ -352,6 +393,12 @@ where
                             // The state we are attempting to expand has already been
                             // closed in the past by a lower cost node, so we will not
                             // expand from this top node.
+                            observer.observe(SearchEvent::CloseRejected {
+                                node: top_id,
+                                prior: *prior,
+                                state: &top.state,
+                                cost: &top.cost,
+                            });
                             continue;
                         }
 
@@ -372,7 +419,7 @@ where
                             None => continue,
                         } + top.cost.clone();
 
-                        cache
+                        let pushed = cache
                             .tree
                             .push_node(Node {
                                 state: child_state,
@@ -381,6 +428,7 @@ where
                                 decisive: true,
                             })
                             .map_err(Self::algo_err)?;
+                        Self::observe_push(cache, pushed, top_id, observer)?;
                     }
 
                     let top_key_ref = self.domain.key_for(top.state());
@@ -388,6 +436,11 @@ where
                     for goal_key in &memory.goal_keys {
                         if *top_key == *goal_key {
                             // We have found a solution.
+                            observer.observe(SearchEvent::Solution {
+                                node: top_id,
+                                state: &top.state,
+                                cost: &top.cost,
+                            });
                             let path = cache.tree.arena.retrace(top_id).map_err(Self::algo_err)?;
                             tree_solution = Some(path);
                             break 'grow;
@@ -405,7 +458,7 @@ where
                                     None => continue,
                                 } + top.cost.clone();
 
-                                cache
+                                let pushed = cache
                                     .tree
                                     .push_node(Node {
                                         state: child_state,
@@ -414,6 +467,7 @@ where
                                         decisive: false,
                                     })
                                     .map_err(Self::algo_err)?;
+                                Self::observe_push(cache, pushed, top_id, observer)?;
                             }
                         }
                     }
@@ -623,6 +677,18 @@ where
     }
 }
 
+impl<D> ObservableMemory for Memory<D>
+where
+    D: Domain
+        + Keyed
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Closable<D::State>,
+{
+    type State = D::State;
+    type Cost = D::Cost;
+}
+
 pub struct TreeMemory<D>
 where
     D: Domain
@@ -691,3 +757,90 @@ impl<State, Action, Cost: Clone> TreeNode for Node<State, Action, Cost> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::observer::{SearchCounters, SearchEventKind, SearchTrace},
+        domain::KeyedCloser,
+        graph::{SharedGraph, SimpleGraph},
+        motion::{se2::*, TravelTimeCost},
+        templates::{GraphMotion, LazyGraphMotion, UninformedSearch},
+        Planner,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_observed_dijkstra() {
+        /*
+         * 0-----1-----2
+         *       |     |
+         *       3-----4
+         */
+        let graph = SharedGraph::new(SimpleGraph::from_iters(
+            [
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 0.0),
+                Point::new(2.0, 0.0),
+                Point::new(1.0, -1.0),
+                Point::new(2.0, -1.0),
+            ],
+            [
+                (0, 1, ()),
+                (1, 0, ()),
+                (1, 2, ()),
+                (2, 1, ()),
+                (1, 3, ()),
+                (3, 1, ()),
+                (2, 4, ()),
+                (4, 2, ()),
+                (3, 4, ()),
+                (4, 3, ()),
+            ],
+        ));
+
+        let extrapolator = DifferentialDriveLineFollow::new(1.0, 1.0).unwrap();
+        let motion = GraphMotion {
+            space: DiscreteSpaceTimeSE2::<usize, 100>::new(),
+            graph: graph.clone(),
+            extrapolator,
+        };
+
+        let planner = Planner::new(Arc::new(Dijkstra::new(
+            UninformedSearch::new_uninformed(
+                motion.clone(),
+                TravelTimeCost(1.0),
+                KeyedCloser(DiscreteSpaceTimeSE2::new()),
+            )
+            .with_initializer(PreferentialStarburstSE2::for_start(graph.clone()))
+            .with_satisfier(PreferentialStarburstSE2::for_goal(graph).unwrap())
+            .with_connector(LazyGraphMotion {
+                motion,
+                keyring: (),
+                chain: MergeIntoGoal(extrapolator),
+            }),
+        )));
+
+        let goal = KeySE2::new(4, 0.0);
+        let mut search = planner
+            .plan(0, goal)
+            .unwrap()
+            .with_observer(SearchTrace::new(1000));
+        assert!(search.solve().unwrap().solved());
+        let trace = search.observer();
+        assert_eq!(trace.events().next().unwrap().kind, SearchEventKind::Pop);
+        assert!(trace.events().any(|e| e.kind == SearchEventKind::Solution));
+
+        // The second search finds the solution in the cached tree, so nothing
+        // gets popped or pushed.
+        let mut search = planner
+            .plan(0, goal)
+            .unwrap()
+            .with_observer(SearchCounters::default());
+        assert!(search.solve().unwrap().solved());
+        let counters = *search.observer();
+        assert_eq!(counters.popped, 0);
+        assert_eq!(counters.pushed, 0);
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::{
+        a_star,
+        tree::{Tree, TreeNode},
+    },
+    domain::{ClosedSet, ClosedStatus},
+    util::escape_dot,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fmt::Debug, fmt::Write};
+
+/// What has happened to a node of a search tree by the time it was exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeNodeStatus {
+    /// The node is still waiting in the search queue.
+    Queued,
+    /// The node closed its state, so it was expanded.
+    Closed,
+    /// The node was taken out of the queue but not expanded because its state
+    /// was already closed by the prior node.
+    Rejected { prior: usize },
+    /// The node was taken out of the queue without closing its state, e.g.
+    /// because it reached the goal.
+    Popped,
+}
+
+/// One node of an [`ExportedTree`]. The state and costs are recorded with their
+/// [`Debug`] format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedTreeNode {
+    pub id: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    pub state: String,
+    pub cost: String,
+    /// For A* this is the remaining cost estimate of the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heuristic: Option<String>,
+    pub status: TreeNodeStatus,
+}
+
+/// A snapshot of a search tree that can be written to Graphviz DOT or JSON so
+/// the search can be analyzed offline.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportedTree {
+    pub nodes: Vec<ExportedTreeNode>,
+}
+
+impl ExportedTree {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Write the tree in the Graphviz DOT format. Edges point from parent to
+    /// child, and rejected nodes get a dashed edge to the node that closed
+    /// their state before them.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph search_tree {\n    node [shape=box];\n");
+        for node in &self.nodes {
+            let style = match node.status {
+                TreeNodeStatus::Queued => "dotted",
+                TreeNodeStatus::Closed => "solid",
+                TreeNodeStatus::Rejected { .. } => "dashed",
+                TreeNodeStatus::Popped => "bold",
+            };
+            let mut label = format!("#{}\n{}\ncost: {}", node.id, node.state, node.cost);
+            if let Some(h) = &node.heuristic {
+                write!(label, "\nh: {h}").ok();
+            }
+            writeln!(
+                dot,
+                "    n{} [label=\"{}\", style={style}];",
+                node.id,
+                escape_dot(&label),
+            )
+            .ok();
+        }
+
+        for node in &self.nodes {
+            if let Some(parent) = node.parent {
+                writeln!(dot, "    n{parent} -> n{};", node.id).ok();
+            }
+
+            if let TreeNodeStatus::Rejected { prior } = node.status {
+                writeln!(
+                    dot,
+                    "    n{} -> n{prior} [style=dashed, constraint=false];",
+                    node.id
+                )
+                .ok();
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl<Closed, Node: TreeNode> Tree<Closed, Node, Node::Cost> {
+    /// Take a snapshot of this tree for exporting.
+    pub fn export(&self) -> ExportedTree
+    where
+        Closed: ClosedSet<Node::State, usize>,
+        Node::State: Debug,
+        Node::Cost: Debug,
+    {
+        let queued: HashSet<usize> = self.queue.iter().map(|t| t.0.node_id).collect();
+        let nodes = self
+            .arena
+            .iter()
+            .enumerate()
+            .map(|(id, node)| {
+                let status = if queued.contains(&id) {
+                    TreeNodeStatus::Queued
+                } else {
+                    match self.closed_set.status(node.state()) {
+                        ClosedStatus::Closed(prior) if *prior == id => TreeNodeStatus::Closed,
+                        ClosedStatus::Closed(prior) => TreeNodeStatus::Rejected { prior: *prior },
+                        ClosedStatus::Open => TreeNodeStatus::Popped,
+                    }
+                };
+
+                ExportedTreeNode {
+                    id,
+                    parent: node.parent().map(|(parent, _)| parent),
+                    state: format!("{:?}", node.state()),
+                    cost: format!("{:?}", node.cost()),
+                    heuristic: node.queue_bias().map(|h| format!("{h:?}")),
+                    status,
+                }
+            })
+            .collect();
+
+        ExportedTree { nodes }
+    }
+}
+
+impl<Closed, State, Action, Cost> a_star::Memory<Closed, State, Action, Cost>
+where
+    Closed: ClosedSet<State, usize>,
+    State: Debug,
+    Cost: Debug + Clone + std::ops::Add<Cost, Output = Cost>,
+{
+    /// Take a snapshot of the search tree for exporting.
+    pub fn export(&self) -> ExportedTree {
+        self.0.export()
+    }
+}
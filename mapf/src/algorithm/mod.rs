@@ -18,14 +18,34 @@
 pub mod a_star;
 pub use a_star::{AStar, AStarConnect};
 
+pub mod alternatives;
+pub use alternatives::{DiversePaths, KShortestPaths};
+
+pub mod bidirectional_a_star;
+pub use bidirectional_a_star::BidirectionalAStar;
+
 pub mod dijkstra;
 pub use dijkstra::{BackwardDijkstra, Dijkstra};
 
+pub mod pibt;
+pub use pibt::Pibt;
+
+pub mod sma_star;
+pub use sma_star::SmaStar;
+
 pub mod tree;
 
 pub mod path;
 pub use path::Path;
 
+pub mod observer;
+pub use observer::{ObservableMemory, SearchEvent, SearchObserver};
+
+pub mod export;
+
+pub mod checkpoint;
+pub use checkpoint::{Checkpoint, CheckpointError, Checkpointable};
+
 // TODO(@mxgrey): Consider whether this should be in the planner::search module
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SearchStatus<Solution> {
@@ -111,6 +131,19 @@ pub trait Solvable<Goal>: Algorithm + Sized {
         memory: &mut Self::Memory,
         goal: &Goal,
     ) -> Result<SearchStatus<Self::Solution>, Self::StepError>;
+
+    /// Take a step in the search algorithm while telling the observer about
+    /// the [`SearchEvent`]s that happen. Algorithms that do not report events
+    /// can rely on this default implementation, which never calls the
+    /// observer.
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        _observer: &mut Obs,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step(memory, goal)
+    }
 }
 
 // Implement the Algorithm traits for Arc<Algo> so that planners can always
@@ -139,6 +172,15 @@ impl<Goal, Algo: Solvable<Goal>> Solvable<Goal> for Arc<Algo> {
     ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
         self.as_ref().step(memory, goal)
     }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        observer: &mut Obs,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.as_ref().step_observed(memory, goal, observer)
+    }
 }
 
 /// The [`QueueLength`] trait can be implemented by `Algorithm::Memory` types to
@@ -163,3 +205,16 @@ pub trait MinimumCostBound {
     type Cost;
     fn minimum_cost_bound(&self) -> Option<Self::Cost>;
 }
+
+/// The `MemoryFootprint` trait can be implemented by `Algorithm::Memory` types
+/// to estimate how many bytes the search is currently occupying. This may be
+/// used to halt search efforts before they exhaust the available RAM.
+///
+/// The estimate is an approximation that only accounts for the containers
+/// owned by the memory itself. Containers that cannot be inspected, such as
+/// closed sets, are charged for the most entries they could hold, so their
+/// share is an upper bound. Any heap allocations held inside of states,
+/// actions, or costs are not counted at all.
+pub trait MemoryFootprint {
+    fn memory_footprint(&self) -> usize;
+}
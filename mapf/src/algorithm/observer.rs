@@ -0,0 +1,351 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt::Debug};
+
+/// The `ObservableMemory` trait is implemented by `Algorithm::Memory` types
+/// whose algorithms report [`SearchEvent`]s. It tells observers what type of
+/// state and cost the events will refer to.
+pub trait ObservableMemory {
+    type State;
+    type Cost;
+}
+
+/// Something that happened to a node while a search algorithm was stepping.
+///
+/// Node IDs refer to the arena of the tree that the node belongs to. For
+/// algorithms that grow several trees, such as [`Dijkstra`](super::Dijkstra),
+/// the same ID may show up for nodes of different trees.
+#[derive(Debug)]
+pub enum SearchEvent<'a, State, Cost> {
+    /// A node was added to the search queue.
+    Push {
+        node: usize,
+        parent: Option<usize>,
+        state: &'a State,
+        cost: &'a Cost,
+    },
+    /// A node was taken off of the search queue to be considered for
+    /// expansion.
+    Pop {
+        node: usize,
+        state: &'a State,
+        cost: &'a Cost,
+    },
+    /// A node that was popped will not be expanded because its state was
+    /// already closed by a prior node whose cost is no higher.
+    CloseRejected {
+        node: usize,
+        prior: usize,
+        state: &'a State,
+        cost: &'a Cost,
+    },
+    /// A node was found to reach the goal.
+    Solution {
+        node: usize,
+        state: &'a State,
+        cost: &'a Cost,
+    },
+}
+
+impl<'a, State, Cost> SearchEvent<'a, State, Cost> {
+    pub fn kind(&self) -> SearchEventKind {
+        match self {
+            Self::Push { .. } => SearchEventKind::Push,
+            Self::Pop { .. } => SearchEventKind::Pop,
+            Self::CloseRejected { .. } => SearchEventKind::CloseRejected,
+            Self::Solution { .. } => SearchEventKind::Solution,
+        }
+    }
+
+    /// The ID of the node that the event happened to.
+    pub fn node(&self) -> usize {
+        match self {
+            Self::Push { node, .. }
+            | Self::Pop { node, .. }
+            | Self::CloseRejected { node, .. }
+            | Self::Solution { node, .. } => *node,
+        }
+    }
+
+    /// The node that this event is related to: the parent of a pushed node or
+    /// the prior node that closed the state of a rejected node.
+    pub fn related(&self) -> Option<usize> {
+        match self {
+            Self::Push { parent, .. } => *parent,
+            Self::CloseRejected { prior, .. } => Some(*prior),
+            Self::Pop { .. } | Self::Solution { .. } => None,
+        }
+    }
+
+    pub fn state(&self) -> &'a State {
+        match self {
+            Self::Push { state, .. }
+            | Self::Pop { state, .. }
+            | Self::CloseRejected { state, .. }
+            | Self::Solution { state, .. } => state,
+        }
+    }
+
+    pub fn cost(&self) -> &'a Cost {
+        match self {
+            Self::Push { cost, .. }
+            | Self::Pop { cost, .. }
+            | Self::CloseRejected { cost, .. }
+            | Self::Solution { cost, .. } => cost,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SearchEventKind {
+    Push,
+    Pop,
+    CloseRejected,
+    Solution,
+}
+
+/// A trait for watching the progress of a search. The observer of a
+/// [`Search`](crate::planner::Search) is told about every [`SearchEvent`]
+/// that happens while the algorithm is stepping.
+///
+/// Algorithms that do not report events will simply never call the observer.
+pub trait SearchObserver<Mem> {
+    fn observe(&mut self, event: SearchEvent<'_, Mem::State, Mem::Cost>)
+    where
+        Mem: ObservableMemory;
+}
+
+/// If an empty tuple is given for the observer then nothing is observed. The
+/// calls to it will be optimized away, so searches that are not observed do not
+/// pay anything for this feature.
+impl<Mem> SearchObserver<Mem> for () {
+    #[inline(always)]
+    fn observe(&mut self, _: SearchEvent<'_, Mem::State, Mem::Cost>)
+    where
+        Mem: ObservableMemory,
+    {
+        // Do nothing
+    }
+}
+
+/// Count how many times each kind of event has happened.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchCounters {
+    pub pushed: usize,
+    pub popped: usize,
+    pub close_rejected: usize,
+    pub solutions: usize,
+}
+
+impl<Mem> SearchObserver<Mem> for SearchCounters {
+    fn observe(&mut self, event: SearchEvent<'_, Mem::State, Mem::Cost>)
+    where
+        Mem: ObservableMemory,
+    {
+        match event.kind() {
+            SearchEventKind::Push => self.pushed += 1,
+            SearchEventKind::Pop => self.popped += 1,
+            SearchEventKind::CloseRejected => self.close_rejected += 1,
+            SearchEventKind::Solution => self.solutions += 1,
+        }
+    }
+}
+
+/// An event that has been copied out of the search so it can be kept in a
+/// [`SearchTrace`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracedEvent<State, Cost> {
+    pub kind: SearchEventKind,
+    pub node: usize,
+    pub related: Option<usize>,
+    pub state: State,
+    pub cost: Cost,
+}
+
+/// Keep the most recent events of a search. Once the capacity is reached, the
+/// oldest event is dropped each time a new one arrives.
+#[derive(Debug, Clone)]
+pub struct SearchTrace<State, Cost> {
+    capacity: usize,
+    events: VecDeque<TracedEvent<State, Cost>>,
+}
+
+impl<State, Cost> SearchTrace<State, Cost> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Iterate over the events that are being kept, from oldest to newest.
+    pub fn events(&self) -> impl Iterator<Item = &TracedEvent<State, Cost>> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl<Mem, State, Cost> SearchObserver<Mem> for SearchTrace<State, Cost>
+where
+    Mem: ObservableMemory<State = State, Cost = Cost>,
+    State: Clone,
+    Cost: Clone,
+{
+    fn observe(&mut self, event: SearchEvent<'_, Mem::State, Mem::Cost>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(TracedEvent {
+            kind: event.kind(),
+            node: event.node(),
+            related: event.related(),
+            state: event.state().clone(),
+            cost: event.cost().clone(),
+        });
+    }
+}
+
+/// An event of a [`SearchEventLog`]. The state and cost are recorded with
+/// their [`Debug`] format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    pub kind: SearchEventKind,
+    pub node: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub related: Option<usize>,
+    pub state: String,
+    pub cost: String,
+}
+
+/// Record every event of a search in a form that can be serialized, e.g. to
+/// be saved and inspected after the search is finished.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchEventLog {
+    pub events: Vec<LoggedEvent>,
+}
+
+impl SearchEventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+}
+
+impl<Mem> SearchObserver<Mem> for SearchEventLog
+where
+    Mem: ObservableMemory,
+    Mem::State: Debug,
+    Mem::Cost: Debug,
+{
+    fn observe(&mut self, event: SearchEvent<'_, Mem::State, Mem::Cost>) {
+        self.events.push(LoggedEvent {
+            kind: event.kind(),
+            node: event.node(),
+            related: event.related(),
+            state: format!("{:?}", event.state()),
+            cost: format!("{:?}", event.cost()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestMemory;
+    impl ObservableMemory for TestMemory {
+        type State = u32;
+        type Cost = u32;
+    }
+
+    fn send<O: SearchObserver<TestMemory>>(observer: &mut O) {
+        for node in 0..5 {
+            observer.observe(SearchEvent::Push {
+                node,
+                parent: None,
+                state: &10,
+                cost: &1,
+            });
+        }
+        observer.observe(SearchEvent::Pop {
+            node: 3,
+            state: &10,
+            cost: &1,
+        });
+        observer.observe(SearchEvent::CloseRejected {
+            node: 4,
+            prior: 3,
+            state: &10,
+            cost: &1,
+        });
+    }
+
+    #[test]
+    fn test_builtin_observers() {
+        let mut counters = SearchCounters::default();
+        send(&mut counters);
+        assert_eq!(
+            counters,
+            SearchCounters {
+                pushed: 5,
+                popped: 1,
+                close_rejected: 1,
+                solutions: 0,
+            }
+        );
+
+        let mut trace = SearchTrace::new(3);
+        send(&mut trace);
+        let kept: Vec<_> = trace.events().map(|e| (e.kind, e.node)).collect();
+        assert_eq!(
+            kept,
+            [
+                (SearchEventKind::Push, 4),
+                (SearchEventKind::Pop, 3),
+                (SearchEventKind::CloseRejected, 4),
+            ]
+        );
+
+        let mut log = SearchEventLog::new();
+        send(&mut log);
+        assert_eq!(log.events.len(), 7);
+        assert_eq!(log.events[6].related, Some(3));
+        let reloaded: SearchEventLog = serde_yaml::from_str(&log.to_yaml().unwrap()).unwrap();
+        assert_eq!(reloaded, log);
+    }
+}
@@ -16,7 +16,7 @@
 */
 
 use crate::{
-    domain::{Backtrack, Domain},
+    domain::{Activity, Backtrack, Domain, Weighted},
     error::ThisError,
     motion::{Duration, IntegrateWaypoints, TimePoint, Timed, Trajectory, Waypoint},
 };
@@ -29,6 +29,17 @@ pub struct Path<State, Action, Cost> {
     pub total_cost: Cost,
 }
 
+/// The kind of [`Path`] that a search over domain `D` produces.
+pub(crate) type DomainPath<D> =
+    Path<
+        <D as Domain>::State,
+        <D as Activity<<D as Domain>::State>>::ActivityAction,
+        <D as Weighted<
+            <D as Domain>::State,
+            <D as Activity<<D as Domain>::State>>::ActivityAction,
+        >>::Cost,
+    >;
+
 impl<S, A, C> Path<S, A, C> {
     pub fn backtrack<ReverseDomain>(
         self,
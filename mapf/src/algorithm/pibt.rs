@@ -0,0 +1,487 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    domain::{Cost, Key, Reversible},
+    error::ThisError,
+    graph::{Edge, Graph},
+    motion::{
+        r2::{Positioned, WaypointR2},
+        Duration, TimePoint, Trajectory,
+    },
+};
+use std::{
+    borrow::Borrow,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+};
+
+#[derive(Debug, ThisError)]
+pub enum PibtError<R> {
+    #[error("Unable to reverse the graph:\n{0:?}")]
+    Reversal(R),
+    #[error("Vertex {0:?} does not exist in the graph")]
+    MissingVertex(String),
+    #[error("Agents {0} and {1} start on the same vertex")]
+    SharedStart(usize, usize),
+    #[error("There is no agent with index {0}")]
+    UnknownAgent(usize),
+}
+
+#[derive(Debug, Clone)]
+struct PibtAgent<K> {
+    current: K,
+    goal: K,
+    /// Travel distance from each vertex to the goal
+    distances: Arc<HashMap<K, f64>>,
+    priority: f64,
+    /// Unique fractional part of the priority that breaks ties between agents
+    tie_breaker: f64,
+    /// The vertex that the agent was at after each step
+    history: Vec<K>,
+}
+
+/// The distance from every vertex that can reach a goal to that goal.
+type DistanceMap<K> = Arc<HashMap<K, f64>>;
+
+/// Priority Inheritance with Backtracking.
+///
+/// Every call to [`Pibt::step`] moves all agents one synchronous step along
+/// the edges of the graph (or keeps them in place). Agents choose their next
+/// vertex in order of priority, preferring vertices that are closer to their
+/// goals. When an agent wants a vertex that is occupied by a lower priority
+/// agent, that agent inherits the priority and has to move out of the way
+/// first, backtracking to the next choice if it cannot. This never produces
+/// two agents on the same vertex or two agents swapping vertices.
+///
+/// By default an agent's priority grows with every step that it is away from
+/// its goal and resets when it arrives. On graphs where every pair of adjacent
+/// vertices lies on a simple cycle, this guarantees that every agent reaches its
+/// goal at some point, though not that they all rest at their goals together.
+/// There is no such guarantee on other graphs: on trees or in dead ends, agents
+/// may push each other back and forth forever. Priorities can also be changed
+/// at any time with [`Pibt::set_priority`].
+pub struct Pibt<G: Graph> {
+    graph: G,
+    reversed: G,
+    agents: Vec<PibtAgent<G::Key>>,
+    distance_cache: HashMap<G::Key, DistanceMap<G::Key>>,
+    step_duration: Duration,
+    steps: usize,
+    rng: u64,
+}
+
+impl<G> Pibt<G>
+where
+    G: Graph + Reversible,
+    G::Key: Key + Clone,
+    G::Vertex: Positioned,
+{
+    /// Create a new PIBT planner. Each agent is given as a (start, goal) pair.
+    /// Each step takes the same amount of time, so the step duration should
+    /// be long enough for the agents to traverse any edge of the graph.
+    pub fn new(
+        graph: G,
+        agents: impl IntoIterator<Item = (G::Key, G::Key)>,
+        step_duration: Duration,
+    ) -> Result<Self, PibtError<G::ReversalError>> {
+        let reversed = graph.reversed().map_err(PibtError::Reversal)?;
+        let mut pibt = Self {
+            graph,
+            reversed,
+            agents: Vec::new(),
+            distance_cache: HashMap::new(),
+            step_duration,
+            steps: 0,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        };
+
+        let mut starts: HashMap<G::Key, usize> = HashMap::new();
+        for (start, goal) in agents {
+            let i = pibt.agents.len();
+            if pibt.graph.vertex(&start).is_none() {
+                return Err(PibtError::MissingVertex(format!("{start:?}")));
+            }
+
+            if let Some(other) = starts.insert(start.clone(), i) {
+                return Err(PibtError::SharedStart(other, i));
+            }
+
+            let distances = pibt.distances_to(&goal)?;
+            let tie_breaker = (i as f64 + 1.0) / (i as f64 + 2.0) / 2.0;
+            pibt.agents.push(PibtAgent {
+                current: start.clone(),
+                goal,
+                distances,
+                priority: tie_breaker,
+                tie_breaker,
+                history: vec![start],
+            });
+        }
+
+        Ok(pibt)
+    }
+
+    /// Change the seed used to break ties between vertices that are equally
+    /// close to the goal of an agent.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        // The state must never be zero
+        self.rng = seed.max(1);
+        self
+    }
+
+    pub fn graph(&self) -> &G {
+        &self.graph
+    }
+
+    pub fn agent_count(&self) -> usize {
+        self.agents.len()
+    }
+
+    /// How many steps have been taken so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// The vertex that each agent is currently at
+    pub fn positions(&self) -> impl Iterator<Item = &G::Key> {
+        self.agents.iter().map(|a| &a.current)
+    }
+
+    pub fn priority(&self, agent: usize) -> Option<f64> {
+        self.agents.get(agent).map(|a| a.priority)
+    }
+
+    /// Override the priority of an agent. Agents with a higher priority get to
+    /// choose their next vertex first. The priority will keep changing by the
+    /// default rule after every step.
+    pub fn set_priority(
+        &mut self,
+        agent: usize,
+        priority: f64,
+    ) -> Result<(), PibtError<G::ReversalError>> {
+        self.agents
+            .get_mut(agent)
+            .ok_or(PibtError::UnknownAgent(agent))?
+            .priority = priority;
+        Ok(())
+    }
+
+    /// Give an agent a new goal, e.g. when it has finished a task.
+    pub fn set_goal(
+        &mut self,
+        agent: usize,
+        goal: G::Key,
+    ) -> Result<(), PibtError<G::ReversalError>> {
+        if agent >= self.agents.len() {
+            return Err(PibtError::UnknownAgent(agent));
+        }
+
+        let distances = self.distances_to(&goal)?;
+        let agent = &mut self.agents[agent];
+        agent.goal = goal;
+        agent.distances = distances;
+        Ok(())
+    }
+
+    /// Check whether every agent is at its goal.
+    pub fn all_arrived(&self) -> bool {
+        self.agents.iter().all(|a| a.current == a.goal)
+    }
+
+    /// Keep stepping until every agent is at its goal or the step limit is
+    /// reached. Returns true if every agent arrived.
+    pub fn solve(&mut self, max_steps: usize) -> bool {
+        while !self.all_arrived() {
+            if self.steps >= max_steps {
+                return false;
+            }
+            self.step();
+        }
+
+        true
+    }
+
+    /// Advance every agent by one synchronous step.
+    pub fn step(&mut self) {
+        let mut order: Vec<usize> = (0..self.agents.len()).collect();
+        order.sort_by(|a, b| {
+            self.agents[*b]
+                .priority
+                .total_cmp(&self.agents[*a].priority)
+        });
+
+        let occupied_now: HashMap<G::Key, usize> = self
+            .agents
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (a.current.clone(), i))
+            .collect();
+        let mut occupied_next: HashMap<G::Key, usize> = HashMap::new();
+        let mut next: Vec<Option<G::Key>> = vec![None; self.agents.len()];
+
+        for i in order {
+            if next[i].is_none() {
+                self.choose(i, None, &occupied_now, &mut occupied_next, &mut next);
+            }
+        }
+
+        for (agent, next) in self.agents.iter_mut().zip(next) {
+            // Every agent is given a next vertex by the loop above
+            let next = next.unwrap();
+            agent.current = next.clone();
+            agent.history.push(next);
+            if agent.current == agent.goal {
+                agent.priority = agent.tie_breaker;
+            } else {
+                agent.priority += 1.0;
+            }
+        }
+
+        self.steps += 1;
+    }
+
+    /// Choose the next vertex of agent `i`. If `parent` is given then agent
+    /// `i` is being pushed out of the way by that agent. Returns false if the
+    /// agent could not move anywhere, in which case it stays in place.
+    fn choose(
+        &mut self,
+        i: usize,
+        parent: Option<usize>,
+        occupied_now: &HashMap<G::Key, usize>,
+        occupied_next: &mut HashMap<G::Key, usize>,
+        next: &mut Vec<Option<G::Key>>,
+    ) -> bool {
+        let current = self.agents[i].current.clone();
+        let mut candidates: Vec<G::Key> = self
+            .graph
+            .edges_from_vertex(&current)
+            .into_iter()
+            .map(|e| e.to_vertex().clone())
+            .collect();
+        candidates.push(current.clone());
+
+        // Shuffle before sorting so that ties between equally good vertices
+        // are broken randomly, but reproducibly.
+        for k in (1..candidates.len()).rev() {
+            let j = (self.next_random() % (k as u64 + 1)) as usize;
+            candidates.swap(k, j);
+        }
+        let distances = self.agents[i].distances.clone();
+        let distance = |k: &G::Key| distances.get(k).copied().unwrap_or(f64::INFINITY);
+        candidates.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+
+        for v in candidates {
+            if occupied_next.contains_key(&v) {
+                continue;
+            }
+
+            if let Some(parent) = parent {
+                // Moving into the vertex of the parent would swap places with it
+                if self.agents[parent].current == v {
+                    continue;
+                }
+            }
+
+            occupied_next.insert(v.clone(), i);
+            next[i] = Some(v.clone());
+
+            if let Some(&other) = occupied_now.get(&v) {
+                if other != i
+                    && next[other].is_none()
+                    && !self.choose(other, Some(i), occupied_now, occupied_next, next)
+                {
+                    continue;
+                }
+            }
+
+            return true;
+        }
+
+        occupied_next.insert(current.clone(), i);
+        next[i] = Some(current);
+        false
+    }
+
+    /// Get the trajectory of each agent through all the steps taken so far.
+    /// Each agent holds its final position indefinitely.
+    pub fn trajectories(&self) -> Vec<Trajectory<WaypointR2>> {
+        self.agents
+            .iter()
+            .map(|agent| {
+                let mut waypoints: Vec<WaypointR2> = Vec::new();
+                for (step, key) in agent.history.iter().enumerate() {
+                    // Unwrap is safe because agents only visit vertices that
+                    // exist in the graph.
+                    let vertex = self.graph.vertex(key).unwrap();
+                    let vertex: &G::Vertex = vertex.borrow();
+                    let p = vertex.point();
+                    let time = TimePoint::zero()
+                        + Duration::from_secs_f64(self.step_duration.as_secs_f64() * step as f64);
+                    waypoints.push(WaypointR2::new(time, p.x, p.y));
+                }
+
+                let trajectory = if waypoints.len() < 2 {
+                    let wp = waypoints[0];
+                    Trajectory::hold(wp, wp.time + self.step_duration)
+                } else {
+                    Trajectory::from_iter(waypoints)
+                };
+
+                // Unwrap is safe because every waypoint has a distinct time
+                trajectory.unwrap().with_indefinite_finish_time(true)
+            })
+            .collect()
+    }
+
+    fn distances_to(
+        &mut self,
+        goal: &G::Key,
+    ) -> Result<DistanceMap<G::Key>, PibtError<G::ReversalError>> {
+        if let Some(distances) = self.distance_cache.get(goal) {
+            return Ok(distances.clone());
+        }
+
+        if self.reversed.vertex(goal).is_none() {
+            return Err(PibtError::MissingVertex(format!("{goal:?}")));
+        }
+
+        // Dijkstra over the reversed graph gives the distance from every
+        // vertex to the goal.
+        let mut distances: HashMap<G::Key, f64> = HashMap::new();
+        let mut closed: HashSet<G::Key> = HashSet::new();
+        let mut arena: Vec<G::Key> = vec![goal.clone()];
+        let mut queue = BinaryHeap::new();
+        distances.insert(goal.clone(), 0.0);
+        queue.push(Reverse((Cost(0.0), 0)));
+        while let Some(Reverse((Cost(distance), index))) = queue.pop() {
+            let key = arena[index].clone();
+            if !closed.insert(key.clone()) {
+                continue;
+            }
+
+            for edge in self.reversed.edges_from_vertex(&key) {
+                let to = edge.to_vertex();
+                if closed.contains(to) {
+                    continue;
+                }
+
+                let (Some(v0), Some(v1)) = (self.reversed.vertex(&key), self.reversed.vertex(to))
+                else {
+                    continue;
+                };
+                let (v0, v1): (&G::Vertex, &G::Vertex) = (v0.borrow(), v1.borrow());
+                let next_distance = distance + (v1.point() - v0.point()).norm();
+                if distances
+                    .get(to)
+                    .map(|d| next_distance < *d)
+                    .unwrap_or(true)
+                {
+                    distances.insert(to.clone(), next_distance);
+                    queue.push(Reverse((Cost(next_distance), arena.len())));
+                    arena.push(to.clone());
+                }
+            }
+        }
+
+        let distances = Arc::new(distances);
+        self.distance_cache.insert(goal.clone(), distances.clone());
+        Ok(distances)
+    }
+
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{graph::SimpleGraph, motion::se2::Point};
+
+    #[test]
+    fn test_pibt_swaps_through_a_side_branch() {
+        // A corridor 0-1-2-3 with a side branch at vertex 1
+        let graph = SimpleGraph::from_iters(
+            [
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 0.0),
+                Point::new(2.0, 0.0),
+                Point::new(3.0, 0.0),
+                Point::new(1.0, 1.0),
+            ],
+            [
+                (0, 1, ()),
+                (1, 0, ()),
+                (1, 2, ()),
+                (2, 1, ()),
+                (2, 3, ()),
+                (3, 2, ()),
+                (1, 4, ()),
+                (4, 1, ()),
+            ],
+        );
+
+        let mut pibt = Pibt::new(graph, [(0, 3), (3, 0)], Duration::from_secs(1)).unwrap();
+        assert!(pibt.solve(50));
+
+        let trajectories = pibt.trajectories();
+        assert_eq!(trajectories.len(), 2);
+        for trajectory in &trajectories {
+            assert_eq!(trajectory.len(), pibt.steps() + 1);
+        }
+
+        // No two agents are ever at the same vertex or swap vertices.
+        let a = &pibt.agents[0].history;
+        let b = &pibt.agents[1].history;
+        for t in 0..a.len() {
+            assert_ne!(a[t], b[t]);
+            if t > 0 {
+                assert!(!(a[t] == b[t - 1] && b[t] == a[t - 1]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pibt_dynamic_priority_and_goals() {
+        let graph = SimpleGraph::from_iters(
+            [
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 0.0),
+                Point::new(2.0, 0.0),
+            ],
+            [(0, 1, ()), (1, 0, ()), (1, 2, ()), (2, 1, ())],
+        );
+
+        let mut pibt = Pibt::new(graph, [(0, 2)], Duration::from_secs(1)).unwrap();
+        pibt.set_priority(0, 10.0).unwrap();
+        assert_eq!(pibt.priority(0), Some(10.0));
+        assert!(pibt.solve(10));
+        assert_eq!(pibt.steps(), 2);
+
+        pibt.set_goal(0, 0).unwrap();
+        assert!(!pibt.all_arrived());
+        assert!(pibt.solve(10));
+        assert_eq!(pibt.positions().next(), Some(&0));
+    }
+}
@@ -0,0 +1,955 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::{
+        a_star::AStarSearchError, tree::TreeError, Algorithm, Coherent, MemoryFootprint,
+        MinimumCostBound, ObservableMemory, Path, QueueLength, SearchEvent, SearchObserver,
+        SearchStatus, Solvable,
+    },
+    domain::{
+        Activity, Closable, ClosedSet, Configurable, Domain, Informed, Initializable, Satisfiable,
+        Weighted,
+    },
+    error::Anyhow,
+};
+use std::{cmp::Ordering, collections::BTreeSet, mem::size_of, ops::Add};
+
+/// Simplified Memory-bounded A*.
+///
+/// This behaves like [`AStar`](super::AStar) except that the search tree will
+/// never hold more than `node_limit` nodes. When the tree is full, the leaf
+/// with the worst evaluation is pruned and its evaluation is remembered by its
+/// parent. The parent goes back into the queue so the pruned branch will be
+/// regenerated if it ever becomes the most promising option again.
+///
+/// The search is complete as long as the path to the shallowest solution fits
+/// within the node limit alongside the dead ends found on the way, and the
+/// first solution that it finds is optimal among all solutions that fit.
+/// Branches that cannot fit are dropped.
+///
+/// Regenerating a pruned branch relies on [`Activity::choices`] giving back
+/// the same choices in the same order each time it is called for a state.
+///
+/// The closed set is used to skip nodes whose state was already reached at a
+/// lower cost, or at an equal cost by a node that is still in the tree. It
+/// keeps a small record for each node in the tree, and the record is dropped
+/// when its node is pruned, since the parent of a pruned branch remembers its
+/// evaluation. Dead ends leave their record behind so they are not explored
+/// again, and each of those records takes the place of a node in the tree for
+/// the rest of the search. The closed set is therefore limited by `node_limit`
+/// as well.
+///
+/// The SmaStar algorithm can be used on domains that implement the following
+/// traits:
+/// * [`Initializable`]
+/// * [`Closable`]
+/// * [`Activity`]
+/// * [`Weighted`]
+/// * [`Informed`]
+/// * [`Satisfiable`]
+///
+/// The following templates implement these traits:
+/// * [`InformedSearch`](crate::templates::InformedSearch)
+#[derive(Debug, Clone)]
+pub struct SmaStar<D> {
+    pub domain: D,
+    /// The maximum number of nodes that the search tree may hold at once.
+    pub node_limit: usize,
+}
+
+impl<D> SmaStar<D> {
+    pub fn new(domain: D, node_limit: usize) -> Self {
+        Self { domain, node_limit }
+    }
+
+    /// Choose the node limit so that the [`MemoryFootprint`] of the search,
+    /// including its closed set, is estimated to stay within the given number
+    /// of bytes.
+    pub fn with_byte_limit(domain: D, bytes: usize) -> Self
+    where
+        D: Domain + Closable<D::State> + Activity<D::State> + Weighted<D::State, D::ActivityAction>,
+    {
+        type SmaMemory<D> = <SmaStar<D> as Algorithm>::Memory;
+        let per_node = SmaMemory::<D>::bytes_per_node();
+        let fixed = size_of::<SmaMemory<D>>();
+        Self::new(domain, bytes.saturating_sub(fixed) / per_node)
+    }
+}
+
+#[derive(Debug)]
+pub struct Memory<Closed, State, Action, Cost> {
+    closed_set: Closed,
+    arena: Vec<Option<Node<State, Action, Cost>>>,
+    /// Slots in the arena that were freed by pruning and can be reused.
+    vacant: Vec<usize>,
+    queue: BTreeSet<Ticket<Cost>>,
+    /// How many dead ends have left their records in the closed set.
+    dead: usize,
+    node_limit: usize,
+    live: usize,
+    /// Gives each inserted node a unique identity, since arena slots get reused
+    serial: usize,
+}
+
+impl<Closed, State, Action, Cost> Memory<Closed, State, Action, Cost> {
+    fn new(closed_set: Closed, node_limit: usize) -> Self {
+        Self {
+            closed_set,
+            arena: Vec::new(),
+            vacant: Vec::new(),
+            queue: BTreeSet::new(),
+            dead: 0,
+            node_limit,
+            live: 0,
+            serial: 0,
+        }
+    }
+
+    /// How many nodes the search tree is currently holding.
+    pub fn live_nodes(&self) -> usize {
+        self.live
+    }
+
+    pub fn get_node(&self, node_id: usize) -> Result<&Node<State, Action, Cost>, TreeError> {
+        self.arena
+            .get(node_id)
+            .and_then(|n| n.as_ref())
+            .ok_or(TreeError::BrokenReference(node_id))
+    }
+
+    fn get_node_mut(
+        &mut self,
+        node_id: usize,
+    ) -> Result<&mut Node<State, Action, Cost>, TreeError> {
+        self.arena
+            .get_mut(node_id)
+            .and_then(|n| n.as_mut())
+            .ok_or(TreeError::BrokenReference(node_id))
+    }
+
+    fn bytes_per_node() -> usize {
+        // Every node occupies an arena slot, might have a ticket in the queue,
+        // is listed among the children of its parent, might leave a vacant
+        // slot behind, and might have a record in the closed set.
+        size_of::<Option<Node<State, Action, Cost>>>()
+            + size_of::<Ticket<Cost>>()
+            + 2 * size_of::<usize>()
+            + Self::bytes_per_record()
+    }
+
+    fn bytes_per_record() -> usize {
+        size_of::<State>() + size_of::<Record<Cost>>()
+    }
+
+    fn insert(&mut self, mut node: Node<State, Action, Cost>) -> usize {
+        self.live += 1;
+        self.serial += 1;
+        node.serial = self.serial;
+        if let Some(node_id) = self.vacant.pop() {
+            self.arena[node_id] = Some(node);
+            node_id
+        } else {
+            if self.arena.len() == self.arena.capacity() {
+                self.arena
+                    .reserve_exact(growth(self.arena.len(), self.node_limit));
+            }
+            self.arena.push(Some(node));
+            self.arena.len() - 1
+        }
+    }
+
+    fn retrace(&self, node_id: usize) -> Result<Path<State, Action, Cost>, TreeError>
+    where
+        State: Clone,
+        Action: Clone,
+        Cost: Clone,
+    {
+        let total_cost = self.get_node(node_id)?.cost.clone();
+        let mut sequence = Vec::new();
+        let mut current = self.get_node(node_id)?;
+        while let Some((parent_id, action)) = &current.parent {
+            sequence.push((action.clone(), current.state.clone()));
+            current = self.get_node(*parent_id)?;
+        }
+
+        sequence.reverse();
+        Ok(Path {
+            initial_state: current.state.clone(),
+            sequence,
+            total_cost,
+        })
+    }
+}
+
+impl<Closed, State, Action, Cost: Ord + Clone> Memory<Closed, State, Action, Cost> {
+    /// Decide whether a newly generated node is worth adding to the tree.
+    fn visit(&self, state: &State, cost: &Cost) -> bool
+    where
+        Closed: ClosedSet<State, Record<Cost>>,
+    {
+        if let Some(prior) = self.closed_set.status(state).closed() {
+            if prior.cost < *cost {
+                return false;
+            }
+
+            if prior.cost == *cost {
+                let prior_is_live = self
+                    .arena
+                    .get(prior.node_id)
+                    .and_then(|n| n.as_ref())
+                    .is_some_and(|n| n.serial == prior.serial);
+                if prior_is_live {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn record(&mut self, node_id: usize) -> Result<(), TreeError>
+    where
+        Closed: ClosedSet<State, Record<Cost>>,
+    {
+        let node = self
+            .arena
+            .get(node_id)
+            .and_then(|n| n.as_ref())
+            .ok_or(TreeError::BrokenReference(node_id))?;
+        self.closed_set.replace(
+            &node.state,
+            Record {
+                cost: node.cost.clone(),
+                node_id,
+                serial: node.serial,
+            },
+        );
+        Ok(())
+    }
+
+    fn enqueue(&mut self, node_id: usize, evaluation: Cost) -> Result<(), TreeError> {
+        self.dequeue(node_id)?;
+        let node = self.get_node_mut(node_id)?;
+        node.queued = Some(evaluation.clone());
+        let depth = node.depth;
+        self.queue.insert(Ticket {
+            evaluation,
+            depth,
+            node_id,
+        });
+        Ok(())
+    }
+
+    fn dequeue(&mut self, node_id: usize) -> Result<(), TreeError> {
+        let node = self.get_node_mut(node_id)?;
+        if let Some(evaluation) = node.queued.take() {
+            let depth = node.depth;
+            self.queue.remove(&Ticket {
+                evaluation,
+                depth,
+                node_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Take a node out of the tree and detach it from its parent. Returns the
+    /// node and whether the closed set still holds its record, which is left
+    /// in place.
+    fn remove(&mut self, node_id: usize) -> Result<(Node<State, Action, Cost>, bool), TreeError>
+    where
+        Closed: ClosedSet<State, Record<Cost>>,
+    {
+        self.dequeue(node_id)?;
+        let node = self
+            .arena
+            .get_mut(node_id)
+            .and_then(|n| n.take())
+            .ok_or(TreeError::BrokenReference(node_id))?;
+        if self.vacant.len() == self.vacant.capacity() {
+            self.vacant
+                .reserve_exact(growth(self.vacant.len(), self.node_limit));
+        }
+        self.vacant.push(node_id);
+        self.live -= 1;
+
+        if let Some((parent_id, _)) = &node.parent {
+            self.get_node_mut(*parent_id)?
+                .children
+                .retain(|c| *c != node_id);
+        }
+
+        let recorded = self
+            .closed_set
+            .status(&node.state)
+            .closed()
+            .is_some_and(|r| r.node_id == node_id && r.serial == node.serial);
+        Ok((node, recorded))
+    }
+
+    /// Find the leaf with the worst evaluation that is allowed to be pruned.
+    /// Root nodes cannot be pruned because there would be nothing to remember
+    /// them by.
+    fn worst_leaf(&self, protected: usize) -> Option<&Ticket<Cost>> {
+        self.queue.iter().rev().find(|ticket| {
+            ticket.node_id != protected
+                && self
+                    .get_node(ticket.node_id)
+                    .is_ok_and(|n| n.children.is_empty() && n.parent.is_some())
+        })
+    }
+
+    /// Lower the evaluation that a node remembers for its forgotten children.
+    fn remember(&mut self, node_id: usize, evaluation: Cost) -> Result<(), TreeError> {
+        let node = self.get_node_mut(node_id)?;
+        node.forgotten = Some(match node.forgotten.take() {
+            Some(prior) => prior.min(evaluation),
+            None => evaluation,
+        });
+        Ok(())
+    }
+
+    /// Prune a leaf and send its parent back into the queue so that the leaf
+    /// can be regenerated later. The record of the leaf is dropped since the
+    /// parent remembers its evaluation.
+    fn forget(&mut self, node_id: usize) -> Result<(), TreeError>
+    where
+        Closed: ClosedSet<State, Record<Cost>>,
+    {
+        let evaluation = match &self.get_node(node_id)?.queued {
+            Some(evaluation) => evaluation.clone(),
+            None => return Err(TreeError::BrokenReference(node_id)),
+        };
+        let (node, recorded) = self.remove(node_id)?;
+        if recorded {
+            self.closed_set.remove(&node.state);
+        }
+
+        if let Some((parent_id, _)) = node.parent {
+            self.remember(parent_id, evaluation)?;
+            let forgotten = self.get_node(parent_id)?.forgotten.clone();
+            if let Some(forgotten) = forgotten {
+                self.enqueue(parent_id, forgotten)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make room for a new child of the protected node. Returns false if the
+    /// child should not be added to the tree.
+    fn make_room(&mut self, protected: usize, evaluation: &Cost) -> Result<bool, TreeError>
+    where
+        Closed: ClosedSet<State, Record<Cost>>,
+    {
+        if self.live + self.dead < self.node_limit {
+            return Ok(true);
+        }
+
+        let worst = match self.worst_leaf(protected) {
+            Some(worst) => worst.clone(),
+            // Nothing can be pruned, so the child is too deep to ever fit
+            // within the node limit.
+            None => return Ok(false),
+        };
+
+        if worst.evaluation < *evaluation {
+            // The new child would be the worst leaf in the tree, so remember it
+            // instead of adding it.
+            self.remember(protected, evaluation.clone())?;
+            return Ok(false);
+        }
+
+        self.forget(worst.node_id)?;
+        Ok(true)
+    }
+
+    /// Remove nodes that have been fully expanded without leaving any children
+    /// or forgotten branches behind, since nothing can be found below them.
+    /// Their parents are told not to regenerate them, and their records stay
+    /// in the closed set.
+    fn discard_dead(&mut self, node_id: usize) -> Result<(), TreeError>
+    where
+        Closed: ClosedSet<State, Record<Cost>>,
+    {
+        let mut next = Some(node_id);
+        while let Some(node_id) = next {
+            let node = self.get_node(node_id)?;
+            if !node.expanded || !node.children.is_empty() || node.forgotten.is_some() {
+                break;
+            }
+
+            let (node, recorded) = self.remove(node_id)?;
+            next = match node.parent {
+                Some((parent_id, _)) => {
+                    self.get_node_mut(parent_id)?.exhausted.push(node.choice);
+                    Some(parent_id)
+                }
+                None => None,
+            };
+
+            if recorded {
+                self.dead += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<Closed, State, Action, Cost> QueueLength for Memory<Closed, State, Action, Cost> {
+    fn queue_length(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+impl<Closed, State, Action, Cost: Ord + Clone> MinimumCostBound for Memory<Closed, State, Action, Cost> {
+    type Cost = Cost;
+    fn minimum_cost_bound(&self) -> Option<Self::Cost> {
+        self.queue.first().map(|t| t.evaluation.clone())
+    }
+}
+
+impl<Closed, State, Action, Cost> MemoryFootprint for Memory<Closed, State, Action, Cost> {
+    fn memory_footprint(&self) -> usize {
+        // The closed set holds at most one record per node in the tree or dead
+        // end, and each record needs to identify the state it closed.
+        size_of::<Self>()
+            + (self.live + self.dead) * Self::bytes_per_record()
+            + self.arena.capacity() * size_of::<Option<Node<State, Action, Cost>>>()
+            + self.vacant.capacity() * size_of::<usize>()
+            + self.queue.len() * size_of::<Ticket<Cost>>()
+            + self.live * size_of::<usize>()
+    }
+}
+
+/// How much to grow a full container by. This grows the way [`Vec::push`]
+/// would but never beyond the node limit, so the capacity of the container
+/// stays within the memory that the node limit allows for.
+fn growth(len: usize, node_limit: usize) -> usize {
+    len.max(4).min(node_limit.saturating_sub(len)).max(1)
+}
+
+impl<Closed, State, Action, Cost> ObservableMemory for Memory<Closed, State, Action, Cost> {
+    type State = State;
+    type Cost = Cost;
+}
+
+#[derive(Debug, Clone)]
+pub struct Node<State, Action, Cost> {
+    state: State,
+    cost: Cost,
+    /// The cost plus remaining cost estimate, raised to be no lower than the
+    /// evaluation of the parent.
+    evaluation: Cost,
+    parent: Option<(usize, Action)>,
+    /// Which of the parent's choices produced this node
+    choice: usize,
+    depth: usize,
+    children: Vec<usize>,
+    /// Choices that were found to lead nowhere within the node limit
+    exhausted: Vec<usize>,
+    /// The lowest evaluation among children that have been pruned
+    forgotten: Option<Cost>,
+    expanded: bool,
+    /// The evaluation of this node's ticket if it is in the queue
+    queued: Option<Cost>,
+    serial: usize,
+}
+
+impl<State, Action, Cost> Node<State, Action, Cost> {
+    fn new(
+        state: State,
+        cost: Cost,
+        evaluation: Cost,
+        parent: Option<(usize, Action)>,
+        choice: usize,
+        depth: usize,
+    ) -> Self {
+        Self {
+            state,
+            cost,
+            evaluation,
+            parent,
+            choice,
+            depth,
+            children: Vec::new(),
+            exhausted: Vec::new(),
+            forgotten: None,
+            expanded: false,
+            queued: None,
+            serial: 0,
+        }
+    }
+}
+
+impl<State, Action, Cost> Node<State, Action, Cost> {
+    pub fn cost(&self) -> &Cost {
+        &self.cost
+    }
+
+    pub fn evaluation(&self) -> &Cost {
+        &self.evaluation
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+}
+
+/// What the closed set remembers about the node that reached a state.
+#[derive(Debug, Clone)]
+pub struct Record<Cost> {
+    cost: Cost,
+    node_id: usize,
+    serial: usize,
+}
+
+/// Lower evaluations come first. Ties are broken in favor of deeper nodes so
+/// that the back of the queue holds the shallowest of the worst nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Ticket<Cost> {
+    evaluation: Cost,
+    depth: usize,
+    node_id: usize,
+}
+
+impl<Cost: Ord> PartialOrd for Ticket<Cost> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Cost: Ord> Ord for Ticket<Cost> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.evaluation
+            .cmp(&other.evaluation)
+            .then_with(|| other.depth.cmp(&self.depth))
+            .then_with(|| self.node_id.cmp(&other.node_id))
+    }
+}
+
+impl<D> SmaStar<D> {
+    fn domain_err(err: impl Into<D::Error>) -> AStarSearchError<D::Error>
+    where
+        D: Domain,
+    {
+        AStarSearchError::Domain(err.into())
+    }
+
+    fn algo_err(err: TreeError) -> AStarSearchError<D::Error>
+    where
+        D: Domain,
+    {
+        AStarSearchError::Algorithm(err)
+    }
+}
+
+impl<D> Algorithm for SmaStar<D>
+where
+    D: Domain + Closable<D::State> + Activity<D::State> + Weighted<D::State, D::ActivityAction>,
+{
+    type Memory = Memory<D::ClosedSet<Record<D::Cost>>, D::State, D::ActivityAction, D::Cost>;
+}
+
+impl<D, Start, Goal> Coherent<Start, Goal> for SmaStar<D>
+where
+    D: Domain
+        + Initializable<Start, Goal, D::State>
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::InitialError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    D::InformedError: Into<D::Error>,
+{
+    type InitError = AStarSearchError<D::Error>;
+
+    fn initialize(&self, start: Start, goal: &Goal) -> Result<Self::Memory, Self::InitError> {
+        let mut memory = Memory::new(self.domain.new_closed_set(), self.node_limit);
+        for (choice, state) in self.domain.initialize(start, goal).into_iter().enumerate() {
+            let state = state.map_err(Self::domain_err)?;
+            let cost = match self.domain.initial_cost(&state).map_err(Self::domain_err)? {
+                Some(c) => c,
+                None => continue,
+            };
+            let remaining_cost_estimate = match self
+                .domain
+                .estimate_remaining_cost(&state, goal)
+                .map_err(Self::domain_err)?
+            {
+                Some(c) => c,
+                None => continue,
+            };
+
+            if !memory.visit(&state, &cost) {
+                continue;
+            }
+
+            let evaluation = cost.clone() + remaining_cost_estimate;
+            let node_id =
+                memory.insert(Node::new(state, cost, evaluation.clone(), None, choice, 0));
+            memory.record(node_id).map_err(Self::algo_err)?;
+            memory
+                .enqueue(node_id, evaluation)
+                .map_err(Self::algo_err)?;
+        }
+
+        Ok(memory)
+    }
+}
+
+impl<D, Goal> Solvable<Goal> for SmaStar<D>
+where
+    D: Domain
+        + Closable<D::State>
+        + Activity<D::State>
+        + Weighted<D::State, D::ActivityAction>
+        + Informed<D::State, Goal, CostEstimate = D::Cost>
+        + Satisfiable<D::State, Goal>,
+    D::State: Clone,
+    D::ActivityAction: Clone,
+    D::Cost: Ord + Add<Output = D::Cost> + Clone,
+    D::SatisfactionError: Into<D::Error>,
+    D::ActivityError: Into<D::Error>,
+    D::WeightedError: Into<D::Error>,
+    D::InformedError: Into<D::Error>,
+{
+    type Solution = Path<D::State, D::ActivityAction, D::Cost>;
+    type StepError = AStarSearchError<D::Error>;
+
+    fn step(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        self.step_observed(memory, goal, &mut ())
+    }
+
+    fn step_observed<Obs: SearchObserver<Self::Memory>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &Goal,
+        observer: &mut Obs,
+    ) -> Result<SearchStatus<Self::Solution>, Self::StepError> {
+        let Ticket {
+            evaluation: bound,
+            node_id: top_id,
+            ..
+        } = match memory.queue.pop_first() {
+            Some(top) => top,
+            None => return Ok(SearchStatus::Impossible),
+        };
+
+        let top = memory.get_node_mut(top_id).map_err(Self::algo_err)?;
+        top.queued = None;
+        observer.observe(SearchEvent::Pop {
+            node: top_id,
+            state: &top.state,
+            cost: &top.cost,
+        });
+
+        // A node that was already expanded has already been checked against
+        // the goal. It is back in the queue to regenerate forgotten children.
+        if !top.expanded
+            && self
+                .domain
+                .is_satisfied(&top.state, goal)
+                .map_err(Self::domain_err)?
+        {
+            observer.observe(SearchEvent::Solution {
+                node: top_id,
+                state: &top.state,
+                cost: &top.cost,
+            });
+            let solution = memory.retrace(top_id).map_err(Self::algo_err)?;
+            return Ok(SearchStatus::Solved(solution));
+        }
+
+        // Every child that is not currently in the tree will be generated, so
+        // nothing is forgotten anymore.
+        top.forgotten = None;
+        let parent_state = top.state.clone();
+        let parent_cost = top.cost.clone();
+        let depth = top.depth + 1;
+        let mut skip_choices = top.exhausted.clone();
+        for child in top.children.clone() {
+            skip_choices.push(memory.get_node(child).map_err(Self::algo_err)?.choice);
+        }
+
+        for (choice, next) in self
+            .domain
+            .choices(parent_state.clone())
+            .into_iter()
+            .enumerate()
+        {
+            let (action, child_state) = next.map_err(Self::domain_err)?;
+            if skip_choices.contains(&choice) {
+                continue;
+            }
+
+            let cost = match self
+                .domain
+                .cost(&parent_state, &action, &child_state)
+                .map_err(Self::domain_err)?
+            {
+                Some(c) => c,
+                None => continue,
+            } + parent_cost.clone();
+
+            let remaining_cost_estimate = match self
+                .domain
+                .estimate_remaining_cost(&child_state, goal)
+                .map_err(Self::domain_err)?
+            {
+                Some(c) => c,
+                None => continue,
+            };
+
+            // The path to a node needs one tree node for each of its states, so
+            // a node at the deepest level that fits can only be useful if it
+            // reaches the goal.
+            if depth + 1 >= self.node_limit
+                && (depth >= self.node_limit
+                    || !self
+                        .domain
+                        .is_satisfied(&child_state, goal)
+                        .map_err(Self::domain_err)?)
+            {
+                continue;
+            }
+
+            if !memory.visit(&child_state, &cost) {
+                continue;
+            }
+
+            let evaluation = (cost.clone() + remaining_cost_estimate).max(bound.clone());
+            if !memory
+                .make_room(top_id, &evaluation)
+                .map_err(Self::algo_err)?
+            {
+                continue;
+            }
+
+            let node_id = memory.insert(Node::new(
+                child_state,
+                cost,
+                evaluation.clone(),
+                Some((top_id, action)),
+                choice,
+                depth,
+            ));
+            memory.record(node_id).map_err(Self::algo_err)?;
+            memory
+                .get_node_mut(top_id)
+                .map_err(Self::algo_err)?
+                .children
+                .push(node_id);
+            memory
+                .enqueue(node_id, evaluation)
+                .map_err(Self::algo_err)?;
+
+            let node = memory.get_node(node_id).map_err(Self::algo_err)?;
+            observer.observe(SearchEvent::Push {
+                node: node_id,
+                parent: Some(top_id),
+                state: &node.state,
+                cost: &node.cost,
+            });
+        }
+
+        let top = memory.get_node_mut(top_id).map_err(Self::algo_err)?;
+        top.expanded = true;
+        if let Some(forgotten) = top.forgotten.clone() {
+            memory.enqueue(top_id, forgotten).map_err(Self::algo_err)?;
+        } else {
+            memory.discard_dead(top_id).map_err(Self::algo_err)?;
+        }
+
+        Ok(SearchStatus::Incomplete)
+    }
+}
+
+impl<D: Configurable> Configurable for SmaStar<D> {
+    type Configuration = D::Configuration;
+    fn configure<F>(self, f: F) -> Result<Self, Anyhow>
+    where
+        F: FnOnce(Self::Configuration) -> Result<Self::Configuration, Anyhow>,
+    {
+        Ok(SmaStar {
+            domain: self.domain.configure(f)?,
+            node_limit: self.node_limit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::AStar,
+        domain::{Closable, KeyedClosedSet, SelfKeyring},
+        error::NoError,
+        planner::halt::StepLimit,
+        templates::InformedSearch,
+        Planner,
+    };
+    use std::sync::Arc;
+
+    type Cell = (i64, i64);
+
+    /// Move in the four cardinal directions inside of an 8x8 box that has a
+    /// wall at x=3 with a single gap at the top.
+    struct WalledGrid;
+    impl Domain for WalledGrid {
+        type State = Cell;
+        type Error = NoError;
+    }
+
+    impl Activity<Cell> for WalledGrid {
+        type ActivityAction = ();
+        type ActivityError = NoError;
+        type Choices<'a> = Vec<Result<((), Cell), NoError>>
+        where
+            Self: 'a,
+            Self::ActivityAction: 'a,
+            Self::ActivityError: 'a,
+            Cell: 'a;
+
+        fn choices<'a>(&'a self, (x, y): Cell) -> Self::Choices<'a>
+        where
+            Self: 'a,
+            Self::ActivityAction: 'a,
+            Self::ActivityError: 'a,
+            Cell: 'a,
+        {
+            [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                .into_iter()
+                .map(|(dx, dy)| (x + dx, y + dy))
+                .filter(|(x, y)| (0..8).contains(x) && (0..8).contains(y))
+                .filter(|(x, y)| *x != 3 || *y == 7)
+                .map(|cell| Ok(((), cell)))
+                .collect()
+        }
+    }
+
+    struct UnitCost;
+    impl Weighted<Cell, ()> for UnitCost {
+        type Cost = i64;
+        type WeightedError = NoError;
+        fn cost(&self, _: &Cell, _: &(), _: &Cell) -> Result<Option<i64>, NoError> {
+            Ok(Some(1))
+        }
+
+        fn initial_cost(&self, _: &Cell) -> Result<Option<i64>, NoError> {
+            Ok(Some(0))
+        }
+    }
+
+    struct Manhattan;
+    impl Informed<Cell, Cell> for Manhattan {
+        type CostEstimate = i64;
+        type InformedError = NoError;
+        fn estimate_remaining_cost(&self, from: &Cell, to: &Cell) -> Result<Option<i64>, NoError> {
+            Ok(Some((from.0 - to.0).abs() + (from.1 - to.1).abs()))
+        }
+    }
+
+    struct GridCloser;
+    impl Closable<Cell> for GridCloser {
+        type ClosedSet<T> = KeyedClosedSet<SelfKeyring<Cell>, T>;
+        fn new_closed_set<T>(&self) -> Self::ClosedSet<T> {
+            KeyedClosedSet::new(SelfKeyring::new())
+        }
+    }
+
+    #[test]
+    fn test_sma_star_stays_within_bound() {
+        let (start, goal): (Cell, Cell) = ((0, 0), (7, 0));
+        let domain = || InformedSearch::new(WalledGrid, UnitCost, Manhattan, GridCloser);
+
+        let expected = Planner::new(Arc::new(AStar(domain())))
+            .plan(start, goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        let node_limit = 24;
+        let sma = SmaStar::new(domain(), node_limit);
+        let mut memory = sma.initialize(start, &goal).unwrap();
+        let mut solution = None;
+        for _ in 0..100_000 {
+            let status = sma.step(&mut memory, &goal).unwrap();
+            assert!(memory.live_nodes() <= node_limit);
+            match status {
+                SearchStatus::Solved(path) => {
+                    solution = Some(path);
+                    break;
+                }
+                status => assert!(status.incomplete()),
+            }
+        }
+
+        let solution = solution.unwrap();
+        assert_eq!(solution.total_cost, expected.total_cost);
+        assert_eq!(solution.initial_state, start);
+        assert_eq!(solution.sequence.last().unwrap().1, goal);
+    }
+
+    #[test]
+    fn test_sma_star_stays_within_byte_limit() {
+        let (start, goal): (Cell, Cell) = ((0, 0), (7, 0));
+        let domain = InformedSearch::new(WalledGrid, UnitCost, Manhattan, GridCloser);
+        type SmaMemory = Memory<KeyedClosedSet<SelfKeyring<Cell>, Record<i64>>, Cell, (), i64>;
+        let bytes = size_of::<SmaMemory>() + 30 * SmaMemory::bytes_per_node();
+        let sma = SmaStar::with_byte_limit(domain, bytes);
+        assert_eq!(sma.node_limit, 30);
+
+        let mut memory = sma.initialize(start, &goal).unwrap();
+        let mut solved = false;
+        for _ in 0..100_000 {
+            let status = sma.step(&mut memory, &goal).unwrap();
+            assert!(memory.memory_footprint() <= bytes);
+            if status.solved() {
+                solved = true;
+                break;
+            }
+            assert!(status.incomplete());
+        }
+        assert!(solved);
+    }
+
+    #[test]
+    fn test_sma_star_too_deep() {
+        // The shortest path visits 22 cells, so it cannot fit within 21 nodes.
+        let (start, goal): (Cell, Cell) = ((0, 0), (7, 0));
+        let sma = SmaStar::new(
+            InformedSearch::new(WalledGrid, UnitCost, Manhattan, GridCloser),
+            21,
+        );
+
+        let status = Planner::new(Arc::new(sma))
+            .plan_with_halting(start, goal, StepLimit::new(Some(100_000)))
+            .unwrap()
+            .solve()
+            .unwrap();
+        assert!(status.impossible());
+    }
+}
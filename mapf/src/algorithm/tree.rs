@@ -16,13 +16,14 @@
 */
 
 use crate::{
-    algorithm::{MinimumCostBound, Path, QueueLength},
+    algorithm::{MemoryFootprint, MinimumCostBound, Path, QueueLength},
     domain::{ClosedSet, ClosedStatus},
     error::ThisError,
 };
 use std::{
     cmp::{Ordering, Reverse},
     collections::BinaryHeap,
+    mem::size_of,
 };
 
 /// A data structure for storing, managing, and growing a tree of nodes.
@@ -51,7 +52,10 @@ impl<Closed, Node: TreeNode> Tree<Closed, Node, Node::Cost> {
         }
     }
 
-    pub fn push_node(&mut self, node: Node) -> Result<(), TreeError>
+    /// Push a node into the tree. This gives back the ID of the node if it was
+    /// pushed, or None if its state was already closed by a node with a lower
+    /// cost.
+    pub fn push_node(&mut self, node: Node) -> Result<Option<usize>, TreeError>
     where
         Node: TreeNode,
         Closed: ClosedSet<Node::State, usize>,
@@ -62,7 +66,7 @@ impl<Closed, Node: TreeNode> Tree<Closed, Node, Node::Cost> {
                 if prior.cost() <= node.cost() {
                     // The state is already closed with a lower-cost node, so
                     // we should not push this new node.
-                    return Ok(());
+                    return Ok(None);
                 }
             } else {
                 // The closed set is referencing a node that does not exist in
@@ -80,7 +84,7 @@ impl<Closed, Node: TreeNode> Tree<Closed, Node, Node::Cost> {
             bias,
             evaluation,
         }));
-        Ok(())
+        Ok(Some(node_id))
     }
 }
 
@@ -236,3 +240,16 @@ where
         self.queue.peek().map(|n| n.0.evaluation.clone())
     }
 }
+
+impl<Closed, Node: TreeNode> MemoryFootprint for Tree<Closed, Node, Node::Cost> {
+    fn memory_footprint(&self) -> usize {
+        // We cannot see inside of the closed set, so charge it for the most
+        // entries it could hold: one per node in the arena, each identifying
+        // the state that it closed.
+        let closed_entry = size_of::<Node::State>() + size_of::<usize>();
+        size_of::<Self>()
+            + self.arena.capacity() * size_of::<Node>()
+            + self.queue.capacity() * size_of::<Reverse<TreeQueueTicket<Node::Cost>>>()
+            + self.arena.len() * closed_entry
+    }
+}
@@ -20,7 +20,8 @@ use super::{
     ClosedStatusForKey, TimeVariantKeyedCloser,
 };
 use crate::{
-    domain::{Keyed, Keyring, Reversible},
+    algorithm::checkpoint::CheckpointError,
+    domain::{Fingerprinted, Keyed, Keyring, Reversible},
     error::NoError,
 };
 use std::{
@@ -33,6 +34,12 @@ use std::{
 #[derive(Debug, Clone)]
 pub struct KeyedCloser<Ring>(pub Ring);
 
+impl<Ring: Fingerprinted> Fingerprinted for KeyedCloser<Ring> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        self.0.fingerprint()
+    }
+}
+
 impl<Ring: Clone> Reversible for KeyedCloser<Ring> {
     type ReversalError = NoError;
     fn reversed(&self) -> Result<Self, Self::ReversalError>
@@ -121,6 +128,11 @@ where
         self.container.get(key.borrow()).into()
     }
 
+    fn remove(&mut self, state: &State) -> Option<T> {
+        let key = self.keyring.key_for(state);
+        self.container.remove(key.borrow())
+    }
+
     type ClosedSetIter<'a> = impl Iterator<Item=&'a T> + 'a
     where
         Self: 'a,
@@ -109,6 +109,9 @@ pub trait ClosedSet<State, T> {
     /// Get the status of the specified state.
     fn status<'a>(&'a self, state: &State) -> ClosedStatus<'a, T>;
 
+    /// Reopen a state. If the state was closed, its value will be returned.
+    fn remove(&mut self, state: &State) -> Option<T>;
+
     type ClosedSetIter<'a>: IntoIterator<Item = &'a T> + 'a
     where
         Self: 'a,
@@ -141,6 +141,12 @@ where
         self.container.get(key).into()
     }
 
+    fn remove(&mut self, state: &State) -> Option<T> {
+        let key_ref = self.keyring.key_for(state);
+        let key = key_ref.borrow().as_ref()?;
+        self.container.remove(key)
+    }
+
     type ClosedSetIter<'a> = impl Iterator<Item=&'a T> + 'a
     where
         Self: 'a,
@@ -172,6 +172,19 @@ where
             .into()
     }
 
+    fn remove(&mut self, state: &State) -> Option<T> {
+        let key_ref = self.keyring.key_for(state);
+        let key = key_ref.borrow();
+        let time_key = state.time().nanos_since_zero / self.time_thresh;
+
+        let times = self.container.get_mut(key)?;
+        let value = times.remove(&time_key);
+        if times.is_empty() {
+            self.container.remove(key);
+        }
+        value
+    }
+
     type ClosedSetIter<'a> = impl Iterator<Item=&'a T> + 'a
     where
         Self: 'a,
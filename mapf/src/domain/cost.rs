@@ -17,6 +17,7 @@
 
 use float_ord::FloatOrd;
 use num::traits::Zero;
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd},
     hash::{Hash, Hasher},
@@ -25,7 +26,8 @@ use std::{
 
 /// Use Cost(f64) or Cost(f32) to use floating point values as planner costs,
 /// giving them the traits of total ordering, full equivalence, and hashability.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Cost<Num>(pub Num);
 
 macro_rules! cost_impl {
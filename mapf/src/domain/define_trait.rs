@@ -16,6 +16,7 @@
 */
 
 use super::*;
+use crate::algorithm::checkpoint::{fingerprint, CheckpointError};
 
 use anyhow::Error as AnyError;
 
@@ -58,6 +59,13 @@ impl<State, Error> Default for DefineTrait<State, Error> {
         Self::new()
     }
 }
+
+impl<State, Error> Fingerprinted for DefineTrait<State, Error> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&())
+    }
+}
+
 /// Incorporates a property into a domain. Use `.with(~)` on a domain to create
 /// this struct.
 #[derive(Debug, Clone)]
@@ -70,6 +78,12 @@ impl<Base: Domain, Prop> Domain for Incorporated<Base, Prop> {
     type Error = Base::Error;
 }
 
+impl<Base: Fingerprinted, Prop: Fingerprinted> Fingerprinted for Incorporated<Base, Prop> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[self.base.fingerprint()?, self.prop.fingerprint()?])
+    }
+}
+
 pub trait Incorporate {
     /// Provides the `.with(~)` function for domains so you can incorporate new
     /// properties into them.
@@ -109,6 +123,12 @@ impl<Base: Domain, Prop> Domain for Chained<Base, Prop> {
     type Error = Base::Error;
 }
 
+impl<Base: Fingerprinted, Prop: Fingerprinted> Fingerprinted for Chained<Base, Prop> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[self.base.fingerprint()?, self.prop.fingerprint()?])
+    }
+}
+
 pub trait Chain {
     /// Chains the trait implementation of a property with the trait
     /// implementation of a different property that already existed in the
@@ -141,6 +161,12 @@ impl<Base: Domain, Prop> Domain for Mapped<Base, Prop> {
     type Error = Base::Error;
 }
 
+impl<Base: Fingerprinted, Prop: Fingerprinted> Fingerprinted for Mapped<Base, Prop> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[self.base.fingerprint()?, self.prop.fingerprint()?])
+    }
+}
+
 pub trait Map {
     /// Maps (modifies) some trait(s) of a domain based on the traits of the
     /// provided property. This can be used to apply constraints to a domain
@@ -175,6 +201,21 @@ impl<Base: Domain, Lifter, Prop> Domain for Lifted<Base, Lifter, Prop> {
     type Error = Base::Error;
 }
 
+impl<Base, Lifter, Prop> Fingerprinted for Lifted<Base, Lifter, Prop>
+where
+    Base: Fingerprinted,
+    Lifter: Fingerprinted,
+    Prop: Fingerprinted,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[
+            self.base.fingerprint()?,
+            self.lifter.fingerprint()?,
+            self.prop.fingerprint()?,
+        ])
+    }
+}
+
 pub trait Lift {
     /// Lifts from the domain of property into the base domain using Lifter.
     ///
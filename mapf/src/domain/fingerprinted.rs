@@ -0,0 +1,35 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::algorithm::checkpoint::{fingerprint, CheckpointError};
+
+/// Describe the configuration of a domain so that a search over it can be
+/// saved into a [`Checkpoint`](crate::algorithm::Checkpoint) and resumed later.
+pub trait Fingerprinted {
+    /// Calculate a fingerprint of the configuration of this domain. A
+    /// checkpoint is only resumed by a domain with the same fingerprint, so
+    /// everything that changes the states, costs, or choices of the search
+    /// should go into it. Use [`fingerprint`] to calculate one from a
+    /// serializable description of the configuration.
+    fn fingerprint(&self) -> Result<u64, CheckpointError>;
+}
+
+impl Fingerprinted for () {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&())
+    }
+}
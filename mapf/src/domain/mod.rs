@@ -38,6 +38,7 @@ pub mod cost;
 pub mod define_trait;
 pub mod domain_map;
 pub mod extrapolator;
+pub mod fingerprinted;
 pub mod informed;
 pub mod initializable;
 pub mod keyed;
@@ -58,6 +59,7 @@ pub use cost::Cost;
 pub use define_trait::*;
 pub use domain_map::*;
 pub use extrapolator::*;
+pub use fingerprinted::*;
 pub use informed::*;
 pub use initializable::*;
 pub use keyed::*;
@@ -15,7 +15,11 @@
  *
 */
 
-use crate::error::NoError;
+use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::Fingerprinted,
+    error::NoError,
+};
 
 pub trait StateSubspace {
     type ProjectedState;
@@ -95,6 +99,11 @@ impl<ProjectedState> StateInto<ProjectedState> {
 impl<ProjectedState> StateSubspace for StateInto<ProjectedState> {
     type ProjectedState = ProjectedState;
 }
+impl<ProjectedState> Fingerprinted for StateInto<ProjectedState> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&())
+    }
+}
 impl<State: Clone + Into<ProjectedState>, ProjectedState> ProjectState<State>
     for StateInto<ProjectedState>
 {
@@ -23,6 +23,9 @@ pub use simple::SimpleGraph;
 pub mod shared_graph;
 pub use shared_graph::SharedGraph;
 
+pub mod multi_level;
+pub use multi_level::{LevelKey, MultiLevelGraph};
+
 use std::borrow::Borrow;
 
 pub trait Edge<Key, Attributes> {
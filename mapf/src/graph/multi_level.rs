@@ -0,0 +1,999 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    domain::{Key, Reversible},
+    error::ThisError,
+    graph::{Edge, Graph},
+    motion::{
+        se2::WaypointSE2, CapacityZone, CircularProfile, DynamicCircularObstacle, KeyLevels,
+        Occupancy, Reservation, SpeedLimiter, TimePoint, Trajectory,
+    },
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Key for a vertex inside of a [`MultiLevelGraph`]. The `level` indicates
+/// which per-level graph the vertex belongs to and `key` identifies the vertex
+/// inside of that graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LevelKey<K> {
+    pub level: usize,
+    pub key: K,
+}
+
+impl<K> LevelKey<K> {
+    pub fn new(level: usize, key: K) -> Self {
+        Self { level, key }
+    }
+}
+
+impl<K: 'static> LevelKey<K> {
+    /// Tell a [`crate::motion::CcbsEnvironment`] which level each key is on,
+    /// so that obstacles on one level do not block the other levels.
+    pub fn key_levels() -> KeyLevels<LevelKey<K>> {
+        KeyLevels::new(|key: &LevelKey<K>| key.level)
+    }
+}
+
+impl<K> From<(usize, K)> for LevelKey<K> {
+    fn from((level, key): (usize, K)) -> Self {
+        Self { level, key }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransitionKind {
+    Lift,
+    Stairs,
+}
+
+/// Describes how long it takes to travel through a transition once the agent
+/// has boarded it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TravelTimeModel {
+    /// Time in seconds that is spent on every trip regardless of how many
+    /// levels are crossed, e.g. doors opening and closing.
+    pub fixed: f64,
+    /// Time in seconds that is spent for each level that gets crossed.
+    pub per_level: f64,
+}
+
+impl TravelTimeModel {
+    pub fn new(fixed: f64, per_level: f64) -> Self {
+        Self { fixed, per_level }
+    }
+
+    /// How long (in seconds) a trip across `levels` levels will take.
+    pub fn travel_time(&self, levels: usize) -> f64 {
+        self.fixed + self.per_level * levels as f64
+    }
+}
+
+/// The properties of a lift or stairway that joins two levels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransitionAttributes {
+    pub kind: TransitionKind,
+    /// Identifies the physical resource (e.g. the lift car) that this
+    /// transition uses. Transitions that share a resource also share its
+    /// capacity, e.g. all the floor-to-floor transitions of one lift.
+    pub resource: usize,
+    /// How many agents can use the resource at the same time.
+    pub capacity: usize,
+    /// Time in seconds that it takes for an agent to board the transition.
+    pub boarding_time: f64,
+    pub travel_time: TravelTimeModel,
+    /// How many levels are crossed by the transition. This is filled in by
+    /// [`MultiLevelGraph::add_transition`].
+    levels_crossed: usize,
+}
+
+impl TransitionAttributes {
+    pub fn lift(
+        resource: usize,
+        capacity: usize,
+        boarding_time: f64,
+        travel_time: TravelTimeModel,
+    ) -> Self {
+        Self {
+            kind: TransitionKind::Lift,
+            resource,
+            capacity,
+            boarding_time,
+            travel_time,
+            levels_crossed: 0,
+        }
+    }
+
+    pub fn stairs(resource: usize, capacity: usize, travel_time: TravelTimeModel) -> Self {
+        Self {
+            kind: TransitionKind::Stairs,
+            resource,
+            capacity,
+            boarding_time: 0.0,
+            travel_time,
+            levels_crossed: 0,
+        }
+    }
+
+    /// The total time in seconds that an agent will spend in the transition,
+    /// including boarding.
+    pub fn duration(&self) -> f64 {
+        self.boarding_time + self.travel_time.travel_time(self.levels_crossed)
+    }
+
+    pub fn levels_crossed(&self) -> usize {
+        self.levels_crossed
+    }
+}
+
+/// Edge attributes of a [`MultiLevelGraph`]. Edges either stay within one
+/// level, in which case they carry the attributes of the per-level graph, or
+/// they cross between levels through a transition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelEdgeAttributes<E> {
+    Level(E),
+    Transition(TransitionAttributes),
+}
+
+impl<E: SpeedLimiter> SpeedLimiter for LevelEdgeAttributes<E> {
+    fn speed_limit(&self) -> Option<f64> {
+        match self {
+            Self::Level(e) => e.speed_limit(),
+            Self::Transition(_) => None,
+        }
+    }
+
+    fn traversal_delay(&self) -> Option<f64> {
+        match self {
+            Self::Level(e) => e.traversal_delay(),
+            Self::Transition(t) => Some(t.duration()),
+        }
+    }
+
+    fn extra_distance(&self) -> Option<f64> {
+        match self {
+            Self::Level(e) => e.extra_distance(),
+            Self::Transition(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelEdge<K, E> {
+    from: LevelKey<K>,
+    to: LevelKey<K>,
+    attributes: LevelEdgeAttributes<E>,
+}
+
+impl<K, E> Edge<LevelKey<K>, LevelEdgeAttributes<E>> for LevelEdge<K, E> {
+    fn from_vertex(&self) -> &LevelKey<K> {
+        &self.from
+    }
+
+    fn to_vertex(&self) -> &LevelKey<K> {
+        &self.to
+    }
+
+    fn attributes(&self) -> &LevelEdgeAttributes<E> {
+        &self.attributes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition<K> {
+    pub from: LevelKey<K>,
+    pub to: LevelKey<K>,
+    pub attributes: TransitionAttributes,
+}
+
+/// A graph that spans several levels, e.g. the floors of a building. Each
+/// level has its own graph, and the levels are joined together by lift and
+/// stair transitions.
+///
+/// The vertices of each level keep the positions of their own level, so the
+/// start and end vertex of a lift transition should normally share the same
+/// position in the plane. Riding the transition is expressed as a
+/// [`SpeedLimiter::traversal_delay`] so that planners hold in place for the
+/// boarding and travel time of the transition.
+#[derive(Debug, Clone)]
+pub struct MultiLevelGraph<G: Graph> {
+    levels: Vec<G>,
+    transitions: Vec<Transition<G::Key>>,
+    transitions_from: HashMap<LevelKey<G::Key>, Vec<usize>>,
+}
+
+impl<G: Graph> MultiLevelGraph<G>
+where
+    G::Key: Key + Clone,
+{
+    pub fn new(levels: Vec<G>) -> Self {
+        Self {
+            levels,
+            transitions: Vec::new(),
+            transitions_from: HashMap::new(),
+        }
+    }
+
+    /// Add a one-way transition between two vertices. Call this twice with
+    /// the endpoints flipped to allow travel in both directions.
+    pub fn add_transition(
+        &mut self,
+        from: LevelKey<G::Key>,
+        to: LevelKey<G::Key>,
+        mut attributes: TransitionAttributes,
+    ) -> Result<usize, MultiLevelGraphError<G::Key>> {
+        for endpoint in [&from, &to] {
+            let level = self
+                .levels
+                .get(endpoint.level)
+                .ok_or_else(|| MultiLevelGraphError::MissingLevel(endpoint.level))?;
+            if level.vertex(&endpoint.key).is_none() {
+                return Err(MultiLevelGraphError::MissingVertex(endpoint.clone()));
+            }
+        }
+
+        if attributes.capacity == 0 {
+            return Err(MultiLevelGraphError::ZeroCapacity(attributes.resource));
+        }
+
+        attributes.levels_crossed = from.level.abs_diff(to.level);
+        let index = self.transitions.len();
+        self.transitions_from
+            .entry(from.clone())
+            .or_default()
+            .push(index);
+        self.transitions.push(Transition {
+            from,
+            to,
+            attributes,
+        });
+        Ok(index)
+    }
+
+    pub fn with_transition(
+        mut self,
+        from: LevelKey<G::Key>,
+        to: LevelKey<G::Key>,
+        attributes: TransitionAttributes,
+    ) -> Result<Self, MultiLevelGraphError<G::Key>> {
+        self.add_transition(from, to, attributes)?;
+        Ok(self)
+    }
+
+    pub fn levels(&self) -> &[G] {
+        &self.levels
+    }
+
+    pub fn level(&self, level: usize) -> Option<&G> {
+        self.levels.get(level)
+    }
+
+    pub fn level_mut(&mut self, level: usize) -> Option<&mut G> {
+        self.levels.get_mut(level)
+    }
+
+    pub fn transitions(&self) -> &[Transition<G::Key>] {
+        &self.transitions
+    }
+
+    /// Find the transition that goes directly between two vertices, if one
+    /// exists.
+    pub fn transition_between(
+        &self,
+        from: &LevelKey<G::Key>,
+        to: &LevelKey<G::Key>,
+    ) -> Option<&Transition<G::Key>> {
+        self.transitions_from
+            .get(from)?
+            .iter()
+            .filter_map(|i| self.transitions.get(*i))
+            .find(|t| t.to == *to)
+    }
+
+    /// Get the transition resources of the graph in ascending order. The
+    /// index of a resource in this list is the index of its zone in
+    /// [`Self::capacity_zones`].
+    pub fn resources(&self) -> Vec<usize> {
+        let mut resources: Vec<_> = self
+            .transitions
+            .iter()
+            .map(|t| t.attributes.resource)
+            .collect();
+        resources.sort();
+        resources.dedup();
+        resources
+    }
+
+    /// Make a capacity zone for each transition resource, covering the
+    /// boarding and exit vertices of all its transitions on every level. Give
+    /// these to [`crate::motion::CcbsEnvironment::with_capacity_zones`] so the
+    /// planner will not board a lift while other agents have filled it.
+    pub fn capacity_zones(&self) -> Vec<CapacityZone<LevelKey<G::Key>>> {
+        let mut zones = BTreeMap::new();
+        for t in &self.transitions {
+            let (capacity, keys) = zones
+                .entry(t.attributes.resource)
+                .or_insert_with(|| (t.attributes.capacity, Vec::new()));
+            *capacity = (*capacity).min(t.attributes.capacity);
+            keys.push(t.from.clone());
+            keys.push(t.to.clone());
+        }
+
+        zones
+            .into_values()
+            .map(|(capacity, keys)| CapacityZone::new(capacity, keys))
+            .collect()
+    }
+}
+
+impl<G> Graph for MultiLevelGraph<G>
+where
+    G: Graph,
+    G::Key: Key + Clone,
+    G::EdgeAttributes: Clone,
+{
+    type Key = LevelKey<G::Key>;
+    type Vertex = G::Vertex;
+    type EdgeAttributes = LevelEdgeAttributes<G::EdgeAttributes>;
+
+    type VertexRef<'a>
+        = G::VertexRef<'a>
+    where
+        G: 'a;
+
+    type Edge<'a>
+        = LevelEdge<G::Key, G::EdgeAttributes>
+    where
+        G: 'a;
+
+    type EdgeIter<'a>
+        = impl Iterator<Item = LevelEdge<G::Key, G::EdgeAttributes>> + 'a
+    where
+        G: 'a;
+
+    fn vertex<'a>(&'a self, key: &Self::Key) -> Option<Self::VertexRef<'a>> {
+        self.levels.get(key.level)?.vertex(&key.key)
+    }
+
+    fn edges_from_vertex<'a>(&'a self, key: &Self::Key) -> Self::EdgeIter<'a>
+    where
+        Self: 'a,
+        Self::Vertex: 'a,
+        Self::Key: 'a,
+        Self::EdgeAttributes: 'a,
+    {
+        let between_levels = self
+            .transitions_from
+            .get(key)
+            .into_iter()
+            .flat_map(|indices| indices.iter())
+            .filter_map(|i| self.transitions.get(*i))
+            .map(|t| LevelEdge {
+                from: t.from.clone(),
+                to: t.to.clone(),
+                attributes: LevelEdgeAttributes::Transition(t.attributes),
+            });
+
+        let level = key.level;
+        let key = key.key.clone();
+        let within_level = self
+            .levels
+            .get(level)
+            .into_iter()
+            .flat_map(move |g| g.edges_from_vertex(&key))
+            .map(move |edge| LevelEdge {
+                from: LevelKey::new(level, edge.from_vertex().clone()),
+                to: LevelKey::new(level, edge.to_vertex().clone()),
+                attributes: LevelEdgeAttributes::Level(edge.attributes().clone()),
+            });
+
+        within_level.chain(between_levels)
+    }
+
+    type LazyEdgeIter<'a>
+        = impl Iterator<Item = LevelEdge<G::Key, G::EdgeAttributes>> + 'a
+    where
+        G: 'a;
+
+    fn lazy_edges_between<'a>(
+        &'a self,
+        from_key: &Self::Key,
+        to_key: &Self::Key,
+    ) -> Self::LazyEdgeIter<'a>
+    where
+        Self: 'a,
+        Self::Vertex: 'a,
+        Self::Key: 'a,
+        Self::EdgeAttributes: 'a,
+    {
+        // Lazy edges never cross between levels.
+        let level = from_key.level;
+        let (from_key, to_key) = (from_key.key.clone(), to_key.clone());
+        self.levels
+            .get(level)
+            .filter(|_| to_key.level == level)
+            .into_iter()
+            .flat_map(move |g| g.lazy_edges_between(&from_key, &to_key.key))
+            .map(move |edge| LevelEdge {
+                from: LevelKey::new(level, edge.from_vertex().clone()),
+                to: LevelKey::new(level, edge.to_vertex().clone()),
+                attributes: LevelEdgeAttributes::Level(edge.attributes().clone()),
+            })
+    }
+}
+
+impl<G> Reversible for MultiLevelGraph<G>
+where
+    G: Graph + Reversible,
+    G::Key: Key + Clone,
+{
+    type ReversalError = G::ReversalError;
+    fn reversed(&self) -> Result<Self, Self::ReversalError> {
+        let levels = self
+            .levels
+            .iter()
+            .map(|g| g.reversed())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut reversed = Self::new(levels);
+        for t in &self.transitions {
+            let index = reversed.transitions.len();
+            reversed
+                .transitions_from
+                .entry(t.to.clone())
+                .or_default()
+                .push(index);
+            reversed.transitions.push(Transition {
+                from: t.to.clone(),
+                to: t.from.clone(),
+                attributes: t.attributes,
+            });
+        }
+
+        Ok(reversed)
+    }
+}
+
+#[derive(ThisError, Debug, Clone, PartialEq, Eq)]
+pub enum MultiLevelGraphError<K> {
+    #[error("The graph does not have a level with index {0}")]
+    MissingLevel(usize),
+    #[error("The graph does not have a vertex for key {0:?}")]
+    MissingVertex(LevelKey<K>),
+    #[error("Transition resource {0} was given a capacity of zero")]
+    ZeroCapacity(usize),
+}
+
+/// A reservation of a transition resource by one agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiftReservation {
+    pub agent: usize,
+    pub start: TimePoint,
+    pub finish: TimePoint,
+}
+
+/// Tracks how the lifts and stairways of a [`MultiLevelGraph`] are occupied
+/// over time. Each transition resource can only hold as many agents as its
+/// capacity at once, so planners should treat the resource as blocked while
+/// it is full. See [`LiftOccupancy::capacity_reservations`] for how to give
+/// the reservations to a planner.
+#[derive(Debug, Clone, Default)]
+pub struct LiftOccupancy {
+    capacity: HashMap<usize, usize>,
+    reservations: HashMap<usize, Vec<LiftReservation>>,
+}
+
+impl LiftOccupancy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an occupancy tracker for all the transition resources of a
+    /// graph.
+    pub fn for_graph<G: Graph>(graph: &MultiLevelGraph<G>) -> Self
+    where
+        G::Key: Key + Clone,
+    {
+        let mut occupancy = Self::new();
+        for t in graph.transitions() {
+            occupancy
+                .capacity
+                .insert(t.attributes.resource, t.attributes.capacity);
+        }
+        occupancy
+    }
+
+    pub fn capacity(&self, resource: usize) -> Option<usize> {
+        self.capacity.get(&resource).copied()
+    }
+
+    pub fn reservations(&self, resource: usize) -> &[LiftReservation] {
+        self.reservations
+            .get(&resource)
+            .map(|r| r.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// How many agents are using the resource at the given time.
+    pub fn occupancy_at(&self, resource: usize, time: TimePoint) -> usize {
+        self.reservations(resource)
+            .iter()
+            .filter(|r| r.start <= time && time < r.finish)
+            .count()
+    }
+
+    /// Reserve a resource for an agent. This will fail without changing
+    /// anything if the reservation would exceed the capacity of the resource
+    /// at any moment.
+    pub fn reserve(
+        &mut self,
+        resource: usize,
+        reservation: LiftReservation,
+    ) -> Result<(), LiftOccupancyError> {
+        let capacity = self
+            .capacity(resource)
+            .ok_or(LiftOccupancyError::UnknownResource(resource))?;
+
+        if reservation.finish <= reservation.start {
+            return Err(LiftOccupancyError::EmptyInterval);
+        }
+
+        // The occupancy only changes when a reservation starts, so it is
+        // enough to check the start times that overlap the new reservation.
+        let existing = self.reservations(resource);
+        let overlapping: Vec<_> = existing
+            .iter()
+            .filter(|r| r.start < reservation.finish && reservation.start < r.finish)
+            .collect();
+        for t in std::iter::once(reservation.start).chain(
+            overlapping
+                .iter()
+                .map(|r| r.start)
+                .filter(|t| reservation.start <= *t),
+        ) {
+            let count = overlapping
+                .iter()
+                .filter(|r| r.start <= t && t < r.finish)
+                .count();
+            if count + 1 > capacity {
+                return Err(LiftOccupancyError::CapacityExceeded {
+                    resource,
+                    time: t,
+                    capacity,
+                });
+            }
+        }
+
+        self.reservations
+            .entry(resource)
+            .or_default()
+            .push(reservation);
+        Ok(())
+    }
+
+    /// Reserve every transition that appears in a sequence of visited
+    /// vertices. Each element of `visits` is a vertex that the agent arrived
+    /// at and the time when it arrived. A transition is reserved from the
+    /// time the agent reached its start vertex until the agent reached its
+    /// end vertex.
+    pub fn reserve_path<G: Graph>(
+        &mut self,
+        graph: &MultiLevelGraph<G>,
+        agent: usize,
+        visits: impl IntoIterator<Item = (LevelKey<G::Key>, TimePoint)>,
+    ) -> Result<(), LiftOccupancyError>
+    where
+        G::Key: Key + Clone,
+    {
+        let mut previous: Option<(LevelKey<G::Key>, TimePoint)> = None;
+        for (key, time) in visits {
+            if let Some((prev_key, prev_time)) = previous {
+                if prev_key.level != key.level {
+                    if let Some(t) = graph.transition_between(&prev_key, &key) {
+                        self.reserve(
+                            t.attributes.resource,
+                            LiftReservation {
+                                agent,
+                                start: prev_time,
+                                finish: time,
+                            },
+                        )?;
+                    }
+                }
+            }
+            previous = Some((key, time));
+        }
+
+        Ok(())
+    }
+
+    /// Remove all reservations that belong to an agent.
+    pub fn release(&mut self, agent: usize) {
+        for reservations in self.reservations.values_mut() {
+            reservations.retain(|r| r.agent != agent);
+        }
+    }
+
+    /// Get the time intervals where a resource is full. A planner must not
+    /// board the resource during these intervals.
+    pub fn full_intervals(&self, resource: usize) -> Vec<(TimePoint, TimePoint)> {
+        let capacity = match self.capacity(resource) {
+            Some(c) => c,
+            None => return Vec::new(),
+        };
+
+        let mut events: Vec<(TimePoint, i64)> = self
+            .reservations(resource)
+            .iter()
+            .flat_map(|r| [(r.start, 1), (r.finish, -1)])
+            .collect();
+        // Process departures before arrivals at the same time point.
+        events.sort_by_key(|(t, delta)| (*t, *delta));
+
+        let mut intervals = Vec::new();
+        let mut count = 0;
+        let mut full_since = None;
+        for (t, delta) in events {
+            count += delta;
+            if count >= capacity as i64 {
+                if full_since.is_none() {
+                    full_since = Some(t);
+                }
+            } else if let Some(since) = full_since.take() {
+                if since < t {
+                    intervals.push((since, t));
+                }
+            }
+        }
+
+        intervals
+    }
+
+    /// Turn the reservations into reservations of the capacity zones made by
+    /// [`MultiLevelGraph::capacity_zones`]. Give these to
+    /// [`crate::motion::CcbsEnvironment::reserve`] so that planners wait for
+    /// room in a lift that other agents have already reserved.
+    pub fn capacity_reservations<G: Graph>(&self, graph: &MultiLevelGraph<G>) -> Vec<Reservation>
+    where
+        G::Key: Key + Clone,
+    {
+        graph
+            .resources()
+            .into_iter()
+            .enumerate()
+            .flat_map(|(zone, resource)| {
+                self.reservations(resource)
+                    .iter()
+                    .map(move |r| Reservation {
+                        zone,
+                        agent: r.agent,
+                        occupancy: Occupancy::new(r.start, Some(r.finish)),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Make obstacles for an agent that moves through a multi-level graph, one for
+/// each stretch of time that the agent spends on a level. Each element of
+/// `waypoints` is a waypoint of the agent's trajectory along with the level
+/// that the agent is on when it reaches the waypoint.
+///
+/// While the agent rides a transition it is on both levels, so the obstacle of
+/// each level covers the whole ride.
+pub fn level_obstacles(
+    profile: CircularProfile,
+    waypoints: impl IntoIterator<Item = (usize, WaypointSE2)>,
+) -> Vec<DynamicCircularObstacle<WaypointSE2>> {
+    let mut stretches: Vec<(usize, Vec<WaypointSE2>)> = Vec::new();
+    for (level, wp) in waypoints {
+        match stretches.last_mut() {
+            Some((last_level, stretch)) if *last_level == level => {
+                stretch.push(wp);
+            }
+            Some((_, stretch)) => {
+                let boarded = *stretch.last().unwrap();
+                let mut held = boarded;
+                held.time = wp.time;
+                stretch.push(held);
+
+                let mut boarding = wp;
+                boarding.time = boarded.time;
+                stretches.push((level, vec![boarding, wp]));
+            }
+            None => {
+                stretches.push((level, vec![wp]));
+            }
+        }
+    }
+
+    stretches
+        .into_iter()
+        .filter_map(|(level, stretch)| {
+            let trajectory = Trajectory::from_iter(stretch).ok()?;
+            Some(
+                DynamicCircularObstacle::new(profile)
+                    .with_level(Some(level))
+                    .with_trajectory(Some(trajectory)),
+            )
+        })
+        .collect()
+}
+
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiftOccupancyError {
+    #[error("Transition resource {0} is not known")]
+    UnknownResource(usize),
+    #[error("A reservation must finish after it starts")]
+    EmptyInterval,
+    #[error("Transition resource {resource} would exceed its capacity of {capacity} at {time:?}")]
+    CapacityExceeded {
+        resource: usize,
+        time: TimePoint,
+        capacity: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::AStarConnect,
+        graph::{SharedGraph, SimpleGraph},
+        motion::{
+            se2::{DifferentialDriveLineFollow, GoalSE2, Point},
+            CcbsEnvironment, DynamicEnvironment, TravelEffortCost,
+        },
+        premade::SippSE2,
+        Planner,
+    };
+    use approx::assert_relative_eq;
+    use std::sync::Arc;
+
+    fn make_two_level_graph() -> MultiLevelGraph<SimpleGraph<Point, ()>> {
+        let level = SimpleGraph::from_iters(
+            [Point::new(0.0, 0.0), Point::new(1.0, 0.0)],
+            [(0, 1, ()), (1, 0, ())],
+        );
+
+        let lift = TransitionAttributes::lift(0, 1, 5.0, TravelTimeModel::new(2.0, 3.0));
+        MultiLevelGraph::new(vec![level.clone(), level])
+            .with_transition(LevelKey::new(0, 1), LevelKey::new(1, 1), lift)
+            .unwrap()
+            .with_transition(LevelKey::new(1, 1), LevelKey::new(0, 1), lift)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_multi_level_edges() {
+        let graph = make_two_level_graph();
+        let edges: Vec<_> = graph.edges_from_vertex(&LevelKey::new(0, 1)).collect();
+        assert_eq!(edges.len(), 2);
+        assert!(edges.iter().any(|e| *e.to_vertex() == LevelKey::new(0, 0)));
+        let lift = edges
+            .iter()
+            .find(|e| *e.to_vertex() == LevelKey::new(1, 1))
+            .unwrap();
+        assert_eq!(lift.attributes().traversal_delay(), Some(5.0 + 2.0 + 3.0));
+
+        let reversed = graph.reversed().unwrap();
+        assert!(reversed
+            .transition_between(&LevelKey::new(1, 1), &LevelKey::new(0, 1))
+            .is_some());
+
+        let mut broken = make_two_level_graph();
+        let lift = TransitionAttributes::lift(1, 1, 0.0, TravelTimeModel::new(0.0, 1.0));
+        assert_eq!(
+            broken.add_transition(LevelKey::new(0, 0), LevelKey::new(2, 0), lift),
+            Err(MultiLevelGraphError::MissingLevel(2)),
+        );
+    }
+
+    #[test]
+    fn test_lift_occupancy() {
+        let graph = make_two_level_graph();
+        let mut occupancy = LiftOccupancy::for_graph(&graph);
+        let t = |s: f64| TimePoint::from_secs_f64(s);
+
+        occupancy
+            .reserve_path(
+                &graph,
+                0,
+                [
+                    (LevelKey::new(0, 0), t(0.0)),
+                    (LevelKey::new(0, 1), t(1.0)),
+                    (LevelKey::new(1, 1), t(11.0)),
+                ],
+            )
+            .unwrap();
+        assert_eq!(occupancy.occupancy_at(0, t(5.0)), 1);
+        assert_eq!(occupancy.full_intervals(0), vec![(t(1.0), t(11.0))]);
+
+        let conflict = occupancy.reserve(
+            0,
+            LiftReservation {
+                agent: 1,
+                start: t(10.0),
+                finish: t(20.0),
+            },
+        );
+        assert!(matches!(
+            conflict,
+            Err(LiftOccupancyError::CapacityExceeded { .. })
+        ));
+
+        occupancy
+            .reserve(
+                0,
+                LiftReservation {
+                    agent: 1,
+                    start: t(11.0),
+                    finish: t(20.0),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(occupancy.full_intervals(0).len(), 2);
+
+        // Both levels of the lift belong to the zone of its resource
+        let zones = graph.capacity_zones();
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].capacity(), 1);
+        assert!(zones[0].contains(&LevelKey::new(0, 1)));
+        assert!(zones[0].contains(&LevelKey::new(1, 1)));
+        assert!(!zones[0].contains(&LevelKey::new(0, 0)));
+
+        let reservations = occupancy.capacity_reservations(&graph);
+        assert_eq!(reservations.len(), 2);
+        assert!(reservations.iter().all(|r| r.zone == 0));
+
+        occupancy.release(0);
+        assert_eq!(occupancy.occupancy_at(0, t(5.0)), 0);
+    }
+
+    fn plan_arrival(
+        environment: CcbsEnvironment<WaypointSE2, LevelKey<usize>>,
+        goal: LevelKey<usize>,
+    ) -> f64 {
+        let graph = SharedGraph::new(make_two_level_graph());
+        let domain = SippSE2::new_sipp_se2(
+            graph.clone(),
+            graph,
+            DifferentialDriveLineFollow::new(1.0, 1.0).unwrap(),
+            Arc::new(environment),
+            TravelEffortCost::default(),
+        )
+        .unwrap();
+
+        let solution = Planner::new(AStarConnect(domain))
+            .plan((LevelKey::new(0, 0), 0.0), GoalSE2::new(goal))
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_eq!(final_state.key.vertex, goal);
+        final_state.waypoint.time.as_secs_f64()
+    }
+
+    fn make_profile() -> CircularProfile {
+        CircularProfile::new(0.1, 0.25, 1.0).unwrap()
+    }
+
+    #[test]
+    fn test_sipp_se2_across_levels() {
+        let environment = CcbsEnvironment::new(Arc::new(DynamicEnvironment::new(make_profile())));
+        let arrival = plan_arrival(environment, LevelKey::new(1, 0));
+
+        // Drive 1m to the lift, ride it for 10s, turn around, and drive 1m
+        // back out of it on the other level.
+        let expected_arrival = 1.0 + 10.0 + std::f64::consts::PI + 1.0;
+        assert_relative_eq!(arrival, expected_arrival, max_relative = 0.01);
+    }
+
+    #[test]
+    fn test_sipp_se2_waits_for_full_lift() {
+        let graph = make_two_level_graph();
+        let mut occupancy = LiftOccupancy::for_graph(&graph);
+        occupancy
+            .reserve(
+                0,
+                LiftReservation {
+                    agent: 7,
+                    start: TimePoint::from_secs_f64(0.0),
+                    finish: TimePoint::from_secs_f64(20.0),
+                },
+            )
+            .unwrap();
+
+        let mut environment =
+            CcbsEnvironment::new(Arc::new(DynamicEnvironment::new(make_profile())))
+                .with_capacity_zones(graph.capacity_zones());
+        for reservation in occupancy.capacity_reservations(&graph) {
+            environment.reserve(reservation);
+        }
+
+        // The lift is full until another agent leaves it, so the agent cannot
+        // board it before then.
+        let arrival = plan_arrival(environment, LevelKey::new(1, 0));
+        assert!(arrival >= 20.0 + 10.0 + std::f64::consts::PI + 1.0 - 1e-3);
+    }
+
+    #[test]
+    fn test_obstacles_only_block_their_level() {
+        let profile = make_profile();
+        let parked = level_obstacles(
+            profile,
+            [
+                (1, WaypointSE2::new_f64(0.0, 1.0, 0.0, 0.0)),
+                (1, WaypointSE2::new_f64(100.0, 1.0, 0.0, 0.0)),
+            ],
+        );
+        assert_eq!(parked.len(), 1);
+        assert_eq!(parked[0].level(), Some(1));
+
+        let mut base = DynamicEnvironment::new(profile);
+        for obstacle in parked {
+            base.obstacles.push(obstacle);
+        }
+        let base = Arc::new(base);
+
+        // An agent parked on the upper level does not block the same spot on
+        // the lower level.
+        let environment =
+            CcbsEnvironment::new(base.clone()).with_key_levels(LevelKey::key_levels());
+        assert_relative_eq!(
+            plan_arrival(environment, LevelKey::new(0, 1)),
+            1.0,
+            max_relative = 0.01
+        );
+
+        // Without knowing the levels of the keys, every obstacle blocks every
+        // level.
+        let environment = CcbsEnvironment::new(base);
+        assert!(plan_arrival(environment, LevelKey::new(0, 1)) > 99.0);
+    }
+
+    #[test]
+    fn test_level_obstacles_cover_rides() {
+        let profile = make_profile();
+        let obstacles = level_obstacles(
+            profile,
+            [
+                (0, WaypointSE2::new_f64(0.0, 0.0, 0.0, 0.0)),
+                (0, WaypointSE2::new_f64(1.0, 1.0, 0.0, 0.0)),
+                (1, WaypointSE2::new_f64(11.0, 1.0, 0.0, 0.0)),
+                (1, WaypointSE2::new_f64(12.0, 0.0, 0.0, 0.0)),
+            ],
+        );
+        assert_eq!(obstacles.len(), 2);
+
+        let span = |obstacle: &DynamicCircularObstacle<WaypointSE2>| {
+            let trajectory = obstacle.trajectory().unwrap();
+            (
+                trajectory.initial_motion_time().as_secs_f64(),
+                trajectory.finish_motion_time().as_secs_f64(),
+            )
+        };
+        assert_eq!(obstacles[0].level(), Some(0));
+        assert_eq!(span(&obstacles[0]), (0.0, 11.0));
+        assert_eq!(obstacles[1].level(), Some(1));
+        assert_eq!(span(&obstacles[1]), (1.0, 12.0));
+    }
+}
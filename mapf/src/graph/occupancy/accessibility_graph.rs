@@ -16,7 +16,8 @@
 */
 
 use crate::{
-    domain::Reversible,
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{Fingerprinted, Reversible},
     error::NoError,
     graph::{
         occupancy::{Cell, Grid},
@@ -120,6 +121,16 @@ impl<G: Grid> Reversible for AccessibilityGraph<G> {
     }
 }
 
+impl<G: Grid + Fingerprinted> Fingerprinted for AccessibilityGraph<G> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        // The accessibility of each cell follows from the grid and the radius
+        fingerprint(&(
+            self.accessibility.grid.fingerprint()?,
+            self.accessibility.agent_radius,
+        ))
+    }
+}
+
 bitfield! {
     #[derive(Clone, Copy, PartialEq, Eq)]
     pub struct CellDirections(u8);
@@ -168,8 +179,8 @@ impl IntoIterator for CellDirections {
 }
 
 pub struct CellDirectionsIter {
-    // NOTE: We need to allow next_dir to increment beyond 255 so we don't get
-    // an integer overflow while iterating
+    // NOTE: next_dir goes one past the last direction (7) when iteration is
+    // finished, so it must never be used to read a bit after that.
     next_dir: u16,
     directions: CellDirections,
     /// Do we want to iterate on directions that are accessible (true) or
@@ -180,13 +191,13 @@ pub struct CellDirectionsIter {
 impl Iterator for CellDirectionsIter {
     type Item = [i64; 2];
     fn next(&mut self) -> Option<Self::Item> {
-        if self.next_dir > u8::MAX as u16 {
+        if self.next_dir > 7 {
             return None;
         }
 
         while self.directions.bit(self.next_dir as usize) != self.accessibility {
             self.next_dir += 1;
-            if self.next_dir > u8::MAX as u16 {
+            if self.next_dir > 7 {
                 return None;
             }
         }
@@ -425,3 +436,24 @@ impl<G: Grid> Accessibility<G> {
         (agent_radius / cell_size + 0.5).ceil() as i64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_directions_iter_stops_after_last_direction() {
+        assert_eq!(CellDirections::all().iter().count(), 8);
+        assert_eq!(CellDirections::all().iter_inaccessible().count(), 0);
+
+        // Iteration must end cleanly when the last direction is the only one
+        // that matches, without reading any bits past it.
+        let mut directions = CellDirections(0);
+        directions.set_northwest(true);
+        let mut iter = directions.iter();
+        assert_eq!(iter.next(), Some([-1, 1]));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+        assert_eq!(directions.iter_inaccessible().count(), 7);
+    }
+}
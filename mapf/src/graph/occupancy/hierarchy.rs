@@ -0,0 +1,1103 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    domain::{Cost, Reversible},
+    error::NoError,
+    graph::{
+        occupancy::{Accessibility, AccessibilityGraph, Cell, Grid},
+        Edge, Graph,
+    },
+    motion::{r2::Point, SpeedLimiter},
+};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+};
+
+/// Index of a cluster within a [`Hierarchy`]. Cluster `(x, y)` contains every
+/// cell whose indices divided by the cluster size give `(x, y)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClusterKey {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl ClusterKey {
+    pub fn new(x: i64, y: i64) -> Self {
+        Self { x, y }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HierarchySettings {
+    /// How many cells wide and tall each cluster is.
+    pub cluster_size: i64,
+    /// Contiguous openings between two clusters are split into segments of at
+    /// most this many cells, and each segment gets one entrance in its middle.
+    ///
+    /// The default of 1 places an entrance on every crossing, including the
+    /// diagonal steps across a border and across the corner of a cluster, so
+    /// the cost of every abstract path is a lower bound on the true cost and
+    /// the [`HierarchyGraph`] is an admissible heuristic. Larger values give a
+    /// sparser abstract graph, but a path through a wide opening is forced to
+    /// detour through the middle of its segment, so estimates can overshoot
+    /// the true cost by up to the width of a segment at each crossing.
+    pub entrance_width: usize,
+}
+
+impl Default for HierarchySettings {
+    fn default() -> Self {
+        Self {
+            cluster_size: 10,
+            entrance_width: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ClusterData {
+    entrances: HashSet<Cell>,
+    /// Shortest path cost within the cluster from each entrance to each other
+    /// entrance that it can reach without leaving the cluster.
+    intra: HashMap<Cell, Vec<(Cell, f64)>>,
+}
+
+/// A path found by [`Hierarchy::find_path`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HierarchicalPath {
+    /// The cells that the path passes through, including the start and goal.
+    pub cells: Vec<Cell>,
+    /// The length of the path.
+    pub cost: f64,
+}
+
+/// Hierarchical Path-Finding A* (HPA*) abstraction over an [`Accessibility`]
+/// grid. The grid is divided into square clusters, entrances are placed where
+/// agents can cross between neighboring clusters, and the cost of travelling
+/// between the entrances of each cluster is precomputed. Searching the abstract
+/// graph of entrances is much cheaper than searching every cell of a large map.
+///
+/// Only the clusters inside of the bounds given to [`Hierarchy::new`] are
+/// covered by the hierarchy. Cells outside of those bounds are treated as
+/// unreachable.
+pub struct Hierarchy<G: Grid> {
+    accessibility: Arc<Accessibility<G>>,
+    settings: HierarchySettings,
+    bounds: [ClusterKey; 2],
+    clusters: HashMap<ClusterKey, ClusterData>,
+    /// The cell pairs that cross each border, keyed by the lesser of the two
+    /// clusters followed by the greater one. Clusters that only meet at a
+    /// corner also have a border.
+    borders: HashMap<(ClusterKey, ClusterKey), Vec<(Cell, Cell)>>,
+    inter: HashMap<Cell, Vec<(Cell, f64)>>,
+}
+
+impl<G: Grid> Clone for Hierarchy<G> {
+    fn clone(&self) -> Self {
+        Self {
+            accessibility: self.accessibility.clone(),
+            settings: self.settings,
+            bounds: self.bounds,
+            clusters: self.clusters.clone(),
+            borders: self.borders.clone(),
+            inter: self.inter.clone(),
+        }
+    }
+}
+
+impl<G: Grid> Hierarchy<G> {
+    /// Build a hierarchy over the region between the `bounds` cells
+    /// (inclusive). The region is expanded to the clusters that contain them.
+    pub fn new(
+        accessibility: Arc<Accessibility<G>>,
+        settings: HierarchySettings,
+        bounds: [Cell; 2],
+    ) -> Self {
+        let settings = HierarchySettings {
+            cluster_size: settings.cluster_size.max(1),
+            entrance_width: settings.entrance_width.max(1),
+        };
+        let [a, b] = bounds;
+        let min = Cell::new(a.x.min(b.x), a.y.min(b.y));
+        let max = Cell::new(a.x.max(b.x), a.y.max(b.y));
+        let mut hierarchy = Self {
+            accessibility,
+            settings,
+            bounds: [ClusterKey::new(0, 0); 2],
+            clusters: HashMap::new(),
+            borders: HashMap::new(),
+            inter: HashMap::new(),
+        };
+        hierarchy.bounds = [hierarchy.cluster_of(&min), hierarchy.cluster_of(&max)];
+
+        let all_clusters: Vec<_> = hierarchy.iter_clusters().collect();
+        for k in &all_clusters {
+            for n in Self::neighbors(k) {
+                if *k < n && hierarchy.in_bounds(&n) {
+                    hierarchy.build_border(*k, n);
+                }
+            }
+        }
+
+        for k in &all_clusters {
+            hierarchy.build_cluster(*k);
+        }
+
+        hierarchy
+    }
+
+    pub fn accessibility(&self) -> &Arc<Accessibility<G>> {
+        &self.accessibility
+    }
+
+    pub fn settings(&self) -> &HierarchySettings {
+        &self.settings
+    }
+
+    /// Get the cluster that a cell belongs to.
+    pub fn cluster_of(&self, cell: &Cell) -> ClusterKey {
+        ClusterKey::new(
+            cell.x.div_euclid(self.settings.cluster_size),
+            cell.y.div_euclid(self.settings.cluster_size),
+        )
+    }
+
+    /// Check if a cluster is covered by this hierarchy.
+    pub fn in_bounds(&self, cluster: &ClusterKey) -> bool {
+        let [min, max] = &self.bounds;
+        min.x <= cluster.x && cluster.x <= max.x && min.y <= cluster.y && cluster.y <= max.y
+    }
+
+    pub fn is_entrance(&self, cell: &Cell) -> bool {
+        self.clusters
+            .get(&self.cluster_of(cell))
+            .filter(|c| c.entrances.contains(cell))
+            .is_some()
+    }
+
+    /// Iterate over the entrances of a cluster.
+    pub fn entrances<'a>(&'a self, cluster: &ClusterKey) -> impl Iterator<Item = Cell> + 'a {
+        self.clusters
+            .get(cluster)
+            .into_iter()
+            .flat_map(|c| c.entrances.iter().copied())
+    }
+
+    /// Get the edges of the abstract graph that leave an entrance, along with
+    /// the cost of each edge. This will be empty for cells that are not
+    /// entrances.
+    pub fn abstract_edges<'a>(&'a self, from: &Cell) -> impl Iterator<Item = (Cell, f64)> + 'a {
+        let intra = self
+            .clusters
+            .get(&self.cluster_of(from))
+            .and_then(|c| c.intra.get(from));
+        let inter = self.inter.get(from);
+        intra
+            .into_iter()
+            .chain(inter)
+            .flat_map(|edges| edges.iter().copied())
+    }
+
+    fn iter_clusters(&self) -> impl Iterator<Item = ClusterKey> {
+        let [min, max] = self.bounds;
+        (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| ClusterKey::new(x, y)))
+    }
+
+    /// Get the clusters that share a side or a corner with a cluster.
+    fn neighbors(cluster: &ClusterKey) -> [ClusterKey; 8] {
+        let ClusterKey { x, y } = *cluster;
+        [
+            ClusterKey::new(x - 1, y - 1),
+            ClusterKey::new(x - 1, y),
+            ClusterKey::new(x - 1, y + 1),
+            ClusterKey::new(x, y - 1),
+            ClusterKey::new(x, y + 1),
+            ClusterKey::new(x + 1, y - 1),
+            ClusterKey::new(x + 1, y),
+            ClusterKey::new(x + 1, y + 1),
+        ]
+    }
+
+    /// Get the key of the border between two neighboring clusters.
+    fn border_key(a: ClusterKey, b: ClusterKey) -> (ClusterKey, ClusterKey) {
+        (a.min(b), a.max(b))
+    }
+
+    fn cluster_contains(&self, cluster: &ClusterKey, cell: &Cell) -> bool {
+        self.cluster_of(cell) == *cluster
+    }
+
+    fn graph(&self) -> AccessibilityGraph<G> {
+        AccessibilityGraph::new(self.accessibility.clone())
+    }
+
+    fn step_cost(&self, from: &Cell, to: &Cell) -> f64 {
+        let (dx, dy) = *to - *from;
+        self.accessibility.grid().cell_size() * ((dx * dx + dy * dy) as f64).sqrt()
+    }
+
+    fn can_cross(&self, from: &Cell, to: &Cell) -> bool {
+        let graph = self.graph();
+        graph.vertex(to).is_some()
+            && graph
+                .edges_from_vertex(from)
+                .into_iter()
+                .any(|(_, next)| next == *to)
+    }
+
+    /// Get the lines of cell pairs that step from cluster `a` into cluster
+    /// `b`, which must be a greater neighbor of `a`. A side is crossed by a
+    /// line of straight steps and by a line of diagonal steps in each
+    /// direction, while a corner is crossed by a single diagonal step.
+    fn border_crossings(&self, a: ClusterKey, b: ClusterKey) -> Vec<Vec<(Cell, Cell)>> {
+        let size = self.settings.cluster_size;
+        let lines = |cells: &dyn Fn(i64, i64) -> (Cell, Cell), range: std::ops::Range<i64>| {
+            [0, 1, -1]
+                .into_iter()
+                .map(|shift| {
+                    range
+                        .clone()
+                        .map(|i| cells(i, shift))
+                        .filter(|(_, to)| self.cluster_contains(&b, to))
+                        .collect()
+                })
+                .collect()
+        };
+
+        match (b.x - a.x, b.y - a.y) {
+            (1, 0) => {
+                let x = b.x * size;
+                lines(
+                    &|y, dy| (Cell::new(x - 1, y), Cell::new(x, y + dy)),
+                    a.y * size..(a.y + 1) * size,
+                )
+            }
+            (0, 1) => {
+                let y = b.y * size;
+                lines(
+                    &|x, dx| (Cell::new(x, y - 1), Cell::new(x + dx, y)),
+                    a.x * size..(a.x + 1) * size,
+                )
+            }
+            (1, 1) => vec![vec![(
+                Cell::new(b.x * size - 1, b.y * size - 1),
+                Cell::new(b.x * size, b.y * size),
+            )]],
+            (1, -1) => vec![vec![(
+                Cell::new(b.x * size - 1, a.y * size),
+                Cell::new(b.x * size, a.y * size - 1),
+            )]],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Find the entrances along the border between `a` and `b` and connect
+    /// them in the abstract graph. The cluster `b` must be a greater neighbor
+    /// of `a`. Returns true if the entrances of the border changed.
+    fn build_border(&mut self, a: ClusterKey, b: ClusterKey) -> bool {
+        let mut entrances = Vec::new();
+        for line in self.border_crossings(a, b) {
+            let mut run: Vec<(Cell, Cell)> = Vec::new();
+            for (from, to) in line {
+                if self.can_cross(&from, &to) && self.can_cross(&to, &from) {
+                    run.push((from, to));
+                    continue;
+                }
+
+                Self::place_entrances(&run, self.settings.entrance_width, &mut entrances);
+                run.clear();
+            }
+            Self::place_entrances(&run, self.settings.entrance_width, &mut entrances);
+        }
+
+        if self.borders.get(&(a, b)) == Some(&entrances) {
+            return false;
+        }
+
+        for (from, to) in self.borders.remove(&(a, b)).into_iter().flatten() {
+            self.unlink(&from, &to);
+            self.unlink(&to, &from);
+        }
+
+        for (from, to) in &entrances {
+            let cost = self.step_cost(from, to);
+            self.inter.entry(*from).or_default().push((*to, cost));
+            self.inter.entry(*to).or_default().push((*from, cost));
+        }
+        self.borders.insert((a, b), entrances);
+        true
+    }
+
+    /// Remove the abstract edge that crosses a border from one cell to another.
+    fn unlink(&mut self, from: &Cell, to: &Cell) {
+        if let Some(edges) = self.inter.get_mut(from) {
+            edges.retain(|(next, _)| next != to);
+            if edges.is_empty() {
+                self.inter.remove(from);
+            }
+        }
+    }
+
+    fn place_entrances(
+        run: &[(Cell, Cell)],
+        entrance_width: usize,
+        entrances: &mut Vec<(Cell, Cell)>,
+    ) {
+        for segment in run.chunks(entrance_width) {
+            entrances.push(segment[segment.len() / 2]);
+        }
+    }
+
+    fn build_cluster(&mut self, cluster: ClusterKey) {
+        let mut data = ClusterData::default();
+        for neighbor in Self::neighbors(&cluster) {
+            let border = Self::border_key(cluster, neighbor);
+            for (a, b) in self.borders.get(&border).into_iter().flatten() {
+                if self.cluster_contains(&cluster, a) {
+                    data.entrances.insert(*a);
+                } else {
+                    data.entrances.insert(*b);
+                }
+            }
+        }
+
+        for entrance in &data.entrances {
+            let reached = self.search_in_cluster(*entrance, &cluster, None);
+            let edges = data
+                .entrances
+                .iter()
+                .filter(|other| *other != entrance)
+                .filter_map(|other| reached.get(other).map(|(cost, _)| (*other, *cost)))
+                .collect();
+            data.intra.insert(*entrance, edges);
+        }
+
+        self.clusters.insert(cluster, data);
+    }
+
+    /// Dijkstra search over the cells of one cluster. Returns the cost to
+    /// reach each cell and the cell that it was reached from. If `stop_at` is
+    /// given, the search ends as soon as that cell is reached.
+    fn search_in_cluster(
+        &self,
+        from: Cell,
+        cluster: &ClusterKey,
+        stop_at: Option<Cell>,
+    ) -> HashMap<Cell, (f64, Option<Cell>)> {
+        let graph = self.graph();
+        let mut reached: HashMap<Cell, (f64, Option<Cell>)> = HashMap::new();
+        let mut closed: HashSet<Cell> = HashSet::new();
+        let mut queue = BinaryHeap::new();
+        reached.insert(from, (0.0, None));
+        queue.push(Reverse((Cost(0.0), <(i64, i64)>::from(from))));
+
+        while let Some(Reverse((Cost(cost), cell))) = queue.pop() {
+            let cell = Cell::from(cell);
+            if !closed.insert(cell) {
+                continue;
+            }
+
+            if Some(cell) == stop_at {
+                break;
+            }
+
+            for (_, next) in graph.edges_from_vertex(&cell) {
+                if !self.cluster_contains(cluster, &next) || closed.contains(&next) {
+                    continue;
+                }
+
+                if graph.vertex(&next).is_none() {
+                    continue;
+                }
+
+                let next_cost = cost + self.step_cost(&cell, &next);
+                let improved = reached
+                    .get(&next)
+                    .map(|(c, _)| next_cost < *c)
+                    .unwrap_or(true);
+                if improved {
+                    reached.insert(next, (next_cost, Some(cell)));
+                    queue.push(Reverse((Cost(next_cost), next.into())));
+                }
+            }
+        }
+
+        reached
+    }
+
+    fn refine_in_cluster(&self, from: Cell, to: Cell, cells: &mut Vec<Cell>) -> Option<()> {
+        let cluster = self.cluster_of(&from);
+        let reached = self.search_in_cluster(from, &cluster, Some(to));
+        let mut segment = Vec::new();
+        let mut current = to;
+        while current != from {
+            segment.push(current);
+            current = reached.get(&current)?.1?;
+        }
+        cells.extend(segment.into_iter().rev());
+        Some(())
+    }
+
+    /// Find a path between two cells by first searching the abstract graph
+    /// and then refining each abstract edge into the cells that it passes
+    /// through. Returns None if no path exists inside the bounds of the
+    /// hierarchy.
+    pub fn find_path(&self, start: Cell, goal: Cell) -> Option<HierarchicalPath> {
+        let start_cluster = self.cluster_of(&start);
+        let goal_cluster = self.cluster_of(&goal);
+        if !self.in_bounds(&start_cluster) || !self.in_bounds(&goal_cluster) {
+            return None;
+        }
+
+        let graph = self.graph();
+        if graph.vertex(&start).is_none() || graph.vertex(&goal).is_none() {
+            return None;
+        }
+
+        if start == goal {
+            return Some(HierarchicalPath {
+                cells: vec![start],
+                cost: 0.0,
+            });
+        }
+
+        // Temporarily connect the start and goal to the entrances of their
+        // clusters. Accessibility is symmetric, so costs found by searching
+        // out from the goal are also the costs of arriving at the goal.
+        let from_start = self.search_in_cluster(start, &start_cluster, None);
+        let start_links: Vec<(Cell, f64)> = self
+            .entrances(&start_cluster)
+            .chain([goal].into_iter().filter(|_| start_cluster == goal_cluster))
+            .filter_map(|e| from_start.get(&e).map(|(c, _)| (e, *c)))
+            .collect();
+
+        let to_goal = self.search_in_cluster(goal, &goal_cluster, None);
+        let goal_links: HashMap<Cell, f64> = self
+            .entrances(&goal_cluster)
+            .filter_map(|e| to_goal.get(&e).map(|(c, _)| (e, *c)))
+            .collect();
+
+        let goal_p = goal.center_point(self.accessibility.grid().cell_size());
+        let h = |cell: &Cell| -> f64 {
+            (cell.center_point(self.accessibility.grid().cell_size()) - goal_p).norm()
+        };
+
+        let mut costs: HashMap<Cell, f64> = HashMap::new();
+        let mut parents: HashMap<Cell, Cell> = HashMap::new();
+        let mut closed: HashSet<Cell> = HashSet::new();
+        let mut queue = BinaryHeap::new();
+        costs.insert(start, 0.0);
+        queue.push(Reverse((Cost(h(&start)), <(i64, i64)>::from(start))));
+
+        let mut found = None;
+        while let Some(Reverse((_, cell))) = queue.pop() {
+            let cell = Cell::from(cell);
+            if !closed.insert(cell) {
+                continue;
+            }
+
+            let cost = *costs.get(&cell)?;
+            if cell == goal {
+                found = Some(cost);
+                break;
+            }
+
+            let neighbors: Vec<(Cell, f64)> = if cell == start {
+                // A start that is itself an entrance can also leave its
+                // cluster through the abstract graph.
+                start_links
+                    .iter()
+                    .copied()
+                    .chain(self.abstract_edges(&start))
+                    .collect()
+            } else {
+                self.abstract_edges(&cell)
+                    .chain(goal_links.get(&cell).map(|c| (goal, *c)))
+                    .collect()
+            };
+
+            for (next, step) in neighbors {
+                if closed.contains(&next) {
+                    continue;
+                }
+
+                let next_cost = cost + step;
+                if costs.get(&next).map(|c| next_cost < *c).unwrap_or(true) {
+                    costs.insert(next, next_cost);
+                    parents.insert(next, cell);
+                    queue.push(Reverse((Cost(next_cost + h(&next)), next.into())));
+                }
+            }
+        }
+
+        let cost = found?;
+        let mut abstract_path = vec![goal];
+        let mut current = goal;
+        while let Some(parent) = parents.get(&current) {
+            abstract_path.push(*parent);
+            current = *parent;
+        }
+        abstract_path.reverse();
+
+        let mut cells = vec![start];
+        for pair in abstract_path.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if self.cluster_of(&from) == self.cluster_of(&to) {
+                self.refine_in_cluster(from, to, &mut cells)?;
+            } else {
+                cells.push(to);
+            }
+        }
+
+        Some(HierarchicalPath { cells, cost })
+    }
+}
+
+impl<G: Grid + Clone> Hierarchy<G> {
+    /// Change the occupancy of some cells and rebuild only the clusters whose
+    /// entrances or internal costs could have been affected, along with the
+    /// abstract edges across their borders. Returns false if none of the cells
+    /// actually changed.
+    pub fn change_cells(&mut self, changes: HashMap<Cell, bool>) -> bool {
+        let changed: Vec<Cell> = changes.keys().copied().collect();
+        if !Arc::make_mut(&mut self.accessibility).change_cells(changes) {
+            return false;
+        }
+
+        // A cell change affects the accessibility of every cell within the
+        // agent radius of it, plus the adjacency of their neighbors.
+        let reach = (self.accessibility.agent_radius() / self.accessibility.grid().cell_size())
+            .ceil() as i64
+            + 1;
+        let mut touched: HashSet<ClusterKey> = HashSet::new();
+        for cell in changed {
+            let lower = self.cluster_of(&cell.shifted(-reach, -reach));
+            let upper = self.cluster_of(&cell.shifted(reach, reach));
+            for x in lower.x..=upper.x {
+                for y in lower.y..=upper.y {
+                    let k = ClusterKey::new(x, y);
+                    if self.in_bounds(&k) {
+                        touched.insert(k);
+                    }
+                }
+            }
+        }
+
+        // A neighbor of a touched cluster only needs to be rebuilt if the
+        // entrances on its border have changed.
+        let mut rebuild = touched.clone();
+        for k in &touched {
+            for n in Self::neighbors(k) {
+                if !self.in_bounds(&n) {
+                    continue;
+                }
+
+                let (a, b) = Self::border_key(*k, n);
+                if self.build_border(a, b) {
+                    rebuild.insert(n);
+                }
+            }
+        }
+
+        for k in rebuild {
+            self.build_cluster(k);
+        }
+
+        true
+    }
+}
+
+/// Attributes of an edge in a [`HierarchyGraph`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HierarchyEdgeAttributes {
+    /// How much longer the path of an abstract edge through its cluster is
+    /// than the straight line between its ends.
+    pub extra_distance: f64,
+}
+
+impl SpeedLimiter for HierarchyEdgeAttributes {
+    fn speed_limit(&self) -> Option<f64> {
+        None
+    }
+
+    fn extra_distance(&self) -> Option<f64> {
+        Some(self.extra_distance)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HierarchyEdge {
+    from: Cell,
+    to: Cell,
+    attributes: HierarchyEdgeAttributes,
+}
+
+impl HierarchyEdge {
+    fn straight(from: Cell, to: Cell) -> Self {
+        Self {
+            from,
+            to,
+            attributes: HierarchyEdgeAttributes {
+                extra_distance: 0.0,
+            },
+        }
+    }
+}
+
+impl Edge<Cell, HierarchyEdgeAttributes> for HierarchyEdge {
+    fn from_vertex(&self) -> &Cell {
+        &self.from
+    }
+
+    fn to_vertex(&self) -> &Cell {
+        &self.to
+    }
+
+    fn attributes(&self) -> &HierarchyEdgeAttributes {
+        &self.attributes
+    }
+}
+
+/// A [`Graph`] view of a [`Hierarchy`] which can be used as the heuristic
+/// graph of [`crate::premade::SippSE2`]. Entrances are connected to each other
+/// through the abstract graph, while any other cell is connected to the
+/// entrances of its own cluster and, through lazy edges, to the other cells
+/// of its cluster.
+///
+/// Abstract edges carry the cost of their path through the cluster as an
+/// [`HierarchyEdgeAttributes::extra_distance`], and every other edge is a
+/// straight line. With the default [`HierarchySettings::entrance_width`] the
+/// heuristic is admissible. Wider entrances add detours that planners may
+/// follow into somewhat suboptimal paths.
+pub struct HierarchyGraph<G: Grid> {
+    hierarchy: Arc<Hierarchy<G>>,
+}
+
+impl<G: Grid> HierarchyGraph<G> {
+    pub fn new(hierarchy: Arc<Hierarchy<G>>) -> Self {
+        Self { hierarchy }
+    }
+
+    pub fn hierarchy(&self) -> &Arc<Hierarchy<G>> {
+        &self.hierarchy
+    }
+}
+
+impl<G: Grid> Clone for HierarchyGraph<G> {
+    fn clone(&self) -> Self {
+        Self {
+            hierarchy: self.hierarchy.clone(),
+        }
+    }
+}
+
+impl<G: Grid> Graph for HierarchyGraph<G> {
+    type Key = Cell;
+    type Vertex = Point;
+    type EdgeAttributes = HierarchyEdgeAttributes;
+
+    type VertexRef<'a>
+        = Point
+    where
+        G: 'a;
+    type Edge<'a>
+        = HierarchyEdge
+    where
+        G: 'a;
+    type EdgeIter<'a>
+        = std::vec::IntoIter<HierarchyEdge>
+    where
+        G: 'a;
+
+    fn vertex(&self, key: &Cell) -> Option<Point> {
+        let hierarchy = &self.hierarchy;
+        if !hierarchy.in_bounds(&hierarchy.cluster_of(key)) {
+            return None;
+        }
+
+        if hierarchy.accessibility.is_inaccessible(key) {
+            return None;
+        }
+
+        Some(key.center_point(hierarchy.accessibility.grid().cell_size()))
+    }
+
+    fn edges_from_vertex<'a>(&'a self, key: &Self::Key) -> Self::EdgeIter<'a>
+    where
+        Self: 'a,
+        Self::Vertex: 'a,
+        Self::Key: 'a,
+        Self::EdgeAttributes: 'a,
+    {
+        if self.vertex(key).is_none() {
+            return Vec::new().into_iter();
+        }
+
+        let from = *key;
+        let hierarchy = &self.hierarchy;
+        let edges: Vec<HierarchyEdge> = if hierarchy.is_entrance(key) {
+            hierarchy
+                .abstract_edges(key)
+                .map(|(to, cost)| HierarchyEdge {
+                    from,
+                    to,
+                    attributes: HierarchyEdgeAttributes {
+                        extra_distance: (cost - hierarchy.step_cost(&from, &to)).max(0.0),
+                    },
+                })
+                .collect()
+        } else {
+            hierarchy
+                .entrances(&hierarchy.cluster_of(key))
+                .map(|to| HierarchyEdge::straight(from, to))
+                .collect()
+        };
+
+        edges.into_iter()
+    }
+
+    type LazyEdgeIter<'a>
+        = Option<HierarchyEdge>
+    where
+        G: 'a;
+
+    fn lazy_edges_between<'a>(
+        &'a self,
+        from_key: &Self::Key,
+        to_key: &Self::Key,
+    ) -> Self::LazyEdgeIter<'a>
+    where
+        Self: 'a,
+        Self::Vertex: 'a,
+        Self::Key: 'a,
+        Self::EdgeAttributes: 'a,
+    {
+        let hierarchy = &self.hierarchy;
+        if from_key == to_key
+            || hierarchy.cluster_of(from_key) != hierarchy.cluster_of(to_key)
+            || hierarchy.is_entrance(to_key)
+        {
+            // Edges into entrances are already given by edges_from_vertex
+            return None;
+        }
+
+        if self.vertex(from_key).is_none() || self.vertex(to_key).is_none() {
+            return None;
+        }
+
+        Some(HierarchyEdge::straight(*from_key, *to_key))
+    }
+}
+
+impl<G: Grid> Reversible for HierarchyGraph<G> {
+    type ReversalError = NoError;
+    fn reversed(&self) -> Result<Self, Self::ReversalError>
+    where
+        Self: Sized,
+    {
+        // The hierarchy is built from the accessibility, which is always
+        // symmetric, so the graph is its own reverse.
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::AStarConnect,
+        domain::Informed,
+        graph::{occupancy::SparseGrid, SharedGraph},
+        motion::{
+            se2::{
+                DifferentialDriveLineFollow, GoalSE2, QuickestPathHeuristic, StateSE2, WaypointSE2,
+            },
+            CcbsEnvironment, CircularProfile, DynamicEnvironment, TravelEffortCost, TravelTimeCost,
+        },
+        premade::SippSE2,
+        Planner,
+    };
+    use approx::assert_relative_eq;
+
+    fn make_walled_hierarchy(entrance_width: usize) -> Hierarchy<SparseGrid> {
+        // A vertical wall at x = 5 with a single gap at y = 8, dividing the
+        // left clusters from the right clusters.
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        for y in -10..=20 {
+            if y != 8 {
+                changes.insert(Cell::new(5, y), true);
+            }
+        }
+        grid.change_cells(&changes);
+
+        Hierarchy::new(
+            Arc::new(Accessibility::new(grid, 0.1)),
+            HierarchySettings {
+                cluster_size: 5,
+                entrance_width,
+            },
+            [Cell::new(0, 0), Cell::new(9, 9)],
+        )
+    }
+
+    #[test]
+    fn test_hierarchy_find_path() {
+        let hierarchy = make_walled_hierarchy(1);
+        assert!(hierarchy.is_entrance(&Cell::new(4, 8)));
+        assert!(!hierarchy.is_entrance(&Cell::new(6, 8)));
+
+        let path = hierarchy
+            .find_path(Cell::new(4, 0), Cell::new(6, 0))
+            .unwrap();
+        assert_eq!(*path.cells.first().unwrap(), Cell::new(4, 0));
+        assert_eq!(*path.cells.last().unwrap(), Cell::new(6, 0));
+        assert!(path.cells.contains(&Cell::new(5, 8)));
+
+        // Go straight up to the gap, through it, and straight back down. The
+        // agent cannot cut diagonally past the corners of the wall.
+        let expected = 8.0 + 2.0 + 8.0;
+        assert_relative_eq!(path.cost, expected, max_relative = 1e-6);
+
+        // Every step of the refined path should be between adjacent cells.
+        for pair in path.cells.windows(2) {
+            let (dx, dy) = pair[1] - pair[0];
+            assert!(dx.abs() <= 1 && dy.abs() <= 1);
+        }
+
+        assert!(hierarchy
+            .find_path(Cell::new(4, 0), Cell::new(20, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_hierarchy_find_path_from_entrance() {
+        let hierarchy = make_walled_hierarchy(1);
+        assert!(hierarchy.is_entrance(&Cell::new(4, 8)));
+
+        let path = hierarchy
+            .find_path(Cell::new(4, 8), Cell::new(6, 8))
+            .unwrap();
+        assert_eq!(
+            path.cells,
+            vec![Cell::new(4, 8), Cell::new(5, 8), Cell::new(6, 8)]
+        );
+        assert_relative_eq!(path.cost, 2.0, max_relative = 1e-6);
+    }
+
+    #[test]
+    fn test_hierarchy_change_cells() {
+        let mut hierarchy = make_walled_hierarchy(6);
+        assert!(hierarchy
+            .find_path(Cell::new(0, 0), Cell::new(9, 0))
+            .is_some());
+
+        // Closing the gap should disconnect the two sides.
+        assert!(hierarchy.change_cells([(Cell::new(5, 8), true)].into_iter().collect()));
+        assert!(hierarchy
+            .find_path(Cell::new(0, 0), Cell::new(9, 0))
+            .is_none());
+
+        // Opening a new gap lower down should give a shorter path than before.
+        assert!(hierarchy.change_cells([(Cell::new(5, 0), false)].into_iter().collect()));
+        let path = hierarchy
+            .find_path(Cell::new(0, 0), Cell::new(9, 0))
+            .unwrap();
+        assert_relative_eq!(path.cost, 9.0, max_relative = 1e-6);
+    }
+
+    fn make_open_hierarchy() -> Hierarchy<SparseGrid> {
+        Hierarchy::new(
+            Arc::new(Accessibility::new(SparseGrid::new(1.0), 0.1)),
+            HierarchySettings {
+                cluster_size: 5,
+                ..Default::default()
+            },
+            [Cell::new(0, 0), Cell::new(9, 9)],
+        )
+    }
+
+    #[test]
+    fn test_diagonal_crossings_are_exact() {
+        let hierarchy = make_open_hierarchy();
+
+        // A path along an open border costs no more than the straight line
+        // between its ends.
+        let path = hierarchy
+            .find_path(Cell::new(0, 4), Cell::new(9, 4))
+            .unwrap();
+        assert_relative_eq!(path.cost, 9.0, max_relative = 1e-6);
+
+        // A diagonal path crosses the corner between clusters with a single
+        // diagonal step.
+        let path = hierarchy
+            .find_path(Cell::new(0, 0), Cell::new(9, 9))
+            .unwrap();
+        assert_relative_eq!(path.cost, 9.0 * 2_f64.sqrt(), max_relative = 1e-6);
+        assert!(path.cells.contains(&Cell::new(4, 4)));
+        assert!(path.cells.contains(&Cell::new(5, 5)));
+
+        // A diagonal path can also cross the side of a cluster diagonally.
+        let path = hierarchy
+            .find_path(Cell::new(2, 0), Cell::new(7, 5))
+            .unwrap();
+        assert_relative_eq!(path.cost, 5.0 * 2_f64.sqrt(), max_relative = 1e-6);
+    }
+
+    fn plan_diagonal_arrival<H>(heuristic: H) -> f64
+    where
+        H: Graph<Key = Cell, Vertex = Point> + Reversible<ReversalError = NoError>,
+        H::EdgeAttributes: SpeedLimiter + Clone,
+    {
+        let profile = CircularProfile::new(0.1, 0.0, 0.0).unwrap();
+        let accessibility = Arc::new(Accessibility::new(SparseGrid::new(1.0), 0.1));
+        let domain = SippSE2::new_sipp_se2(
+            SharedGraph::new(AccessibilityGraph::new(accessibility)),
+            SharedGraph::new(heuristic),
+            DifferentialDriveLineFollow::new(1.0, 1.0).unwrap(),
+            Arc::new(CcbsEnvironment::new(Arc::new(DynamicEnvironment::new(
+                profile,
+            )))),
+            TravelEffortCost::default(),
+        )
+        .unwrap();
+
+        let goal = Cell::new(9, 9);
+        let solution = Planner::new(AStarConnect(domain))
+            .plan((Cell::new(0, 0), 0.0), GoalSE2::new(goal))
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_eq!(final_state.key.vertex, goal);
+        final_state.waypoint.time.as_secs_f64()
+    }
+
+    #[test]
+    fn test_hierarchy_graph_as_sipp_se2_heuristic() {
+        let hierarchy = Arc::new(make_open_hierarchy());
+        let arrival = plan_diagonal_arrival(HierarchyGraph::new(hierarchy.clone()));
+        let optimal =
+            plan_diagonal_arrival(AccessibilityGraph::new(hierarchy.accessibility().clone()));
+
+        // The hierarchy never overestimates, so the plan that it guides is
+        // just as fast as the optimal one.
+        assert_relative_eq!(arrival, optimal, max_relative = 1e-6);
+    }
+
+    #[test]
+    fn test_hierarchy_graph_edges() {
+        let graph = HierarchyGraph::new(Arc::new(make_walled_hierarchy(1)));
+        let from = Cell::new(4, 0);
+        let edges: Vec<_> = graph.edges_from_vertex(&from).collect();
+        assert!(edges
+            .iter()
+            .all(|edge| graph.hierarchy().is_entrance(edge.to_vertex())));
+        assert!(graph.lazy_edges_between(&from, &Cell::new(1, 1)).is_some());
+        assert!(graph.lazy_edges_between(&from, &Cell::new(6, 0)).is_none());
+    }
+
+    #[test]
+    fn test_hierarchy_graph_edges_carry_cluster_costs() {
+        // The wall keeps the agent from cutting diagonally between the gap
+        // and the bottom of its cluster, so the abstract edge is longer than
+        // the straight line between its ends.
+        let graph = HierarchyGraph::new(Arc::new(make_walled_hierarchy(1)));
+        let (from, to) = (Cell::new(5, 8), Cell::new(6, 5));
+        let edge = graph
+            .edges_from_vertex(&from)
+            .find(|edge| *edge.to_vertex() == to)
+            .unwrap();
+        assert_relative_eq!(
+            edge.attributes().extra_distance,
+            4.0 - 10_f64.sqrt(),
+            max_relative = 1e-6
+        );
+    }
+
+    fn estimate_around_wall<H>(graph: H) -> f64
+    where
+        H: Graph<Key = Cell, Vertex = Point> + Reversible<ReversalError = NoError>,
+        H::EdgeAttributes: SpeedLimiter + Clone,
+    {
+        let heuristic: QuickestPathHeuristic<_, _, _, 100> = QuickestPathHeuristic::new(
+            SharedGraph::new(graph),
+            TravelTimeCost(1.0),
+            TravelTimeCost(1.0),
+            DifferentialDriveLineFollow::new(1.0, 1.0).unwrap(),
+        )
+        .unwrap();
+
+        let p = Cell::new(4, 0).center_point(1.0);
+        let start = StateSE2::new(Cell::new(4, 0), WaypointSE2::new_f64(0.0, p.x, p.y, 0.0));
+        heuristic
+            .estimate_remaining_cost(&start, &GoalSE2::new(Cell::new(6, 0)))
+            .unwrap()
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_hierarchy_graph_estimate_follows_cluster_costs() {
+        let hierarchy = Arc::new(make_walled_hierarchy(1));
+        let abstracted = estimate_around_wall(HierarchyGraph::new(hierarchy.clone()));
+        let exact =
+            estimate_around_wall(AccessibilityGraph::new(hierarchy.accessibility().clone()));
+
+        // The abstract edges are charged for their paths around the corners
+        // of the wall instead of the straight lines between their ends, so the
+        // estimate is as tight as the one from the full grid.
+        assert_relative_eq!(abstracted, exact, max_relative = 1e-6);
+    }
+
+    /// Get every abstract edge of a hierarchy in a stable order.
+    fn sorted_abstract_edges(hierarchy: &Hierarchy<SparseGrid>) -> Vec<(Cell, Cell, i64)> {
+        let mut edges: Vec<_> = hierarchy
+            .iter_clusters()
+            .flat_map(|k| hierarchy.entrances(&k).collect::<Vec<_>>())
+            .flat_map(|from| {
+                hierarchy
+                    .abstract_edges(&from)
+                    .map(move |(to, cost)| (from, to, (cost * 1e6).round() as i64))
+            })
+            .collect();
+        edges.sort_by_key(|(from, to, cost)| (from.x, from.y, to.x, to.y, *cost));
+        edges
+    }
+
+    #[test]
+    fn test_change_cells_matches_full_rebuild() {
+        let mut hierarchy = make_walled_hierarchy(1);
+        let changes = [(Cell::new(5, 8), true), (Cell::new(5, 2), false)];
+        assert!(hierarchy.change_cells(changes.into_iter().collect()));
+
+        let rebuilt = Hierarchy::new(
+            hierarchy.accessibility().clone(),
+            *hierarchy.settings(),
+            [Cell::new(0, 0), Cell::new(9, 9)],
+        );
+        assert_eq!(
+            sorted_abstract_edges(&hierarchy),
+            sorted_abstract_edges(&rebuilt)
+        );
+    }
+}
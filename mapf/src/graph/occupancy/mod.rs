@@ -20,6 +20,7 @@ use crate::{
     util::triangular_for,
 };
 use bitfield::{bitfield, Bit, BitMut};
+use serde::{Deserialize, Serialize};
 use std::collections::{hash_map, HashMap, HashSet};
 use std::ops::Sub;
 use util::LineSegment;
@@ -27,7 +28,7 @@ use util::LineSegment;
 pub type Point = nalgebra::geometry::Point2<f64>;
 pub type Vector = nalgebra::Vector2<f64>;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Cell {
     pub x: i64,
     pub y: i64,
@@ -840,4 +841,6 @@ pub mod visibility_graph;
 pub use visibility_graph::{NeighborhoodGraph, VisibilityGraph};
 pub mod accessibility_graph;
 pub use accessibility_graph::{Accessibility, AccessibilityGraph};
+pub mod hierarchy;
+pub use hierarchy::{Hierarchy, HierarchyEdge, HierarchyEdgeAttributes, HierarchyGraph};
 mod util;
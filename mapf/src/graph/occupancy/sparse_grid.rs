@@ -17,6 +17,10 @@
 
 use super::util::{LineSegment, SearchF64};
 use super::{Cell, ChangedCorners, ConfirmedChanges, Corner, CornerStatus, Grid, Point, Vector};
+use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::Fingerprinted,
+};
 use std::collections::{btree_map, hash_map, hash_set, BTreeMap, BTreeSet, HashMap, HashSet};
 
 #[derive(Debug, Clone)]
@@ -136,6 +140,12 @@ impl SparseGrid {
     }
 }
 
+impl Fingerprinted for SparseGrid {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(self.cell_size, &self.occupancy_map))
+    }
+}
+
 impl Grid for SparseGrid {
     type OccupiedIterator<'a> = hash_set::Iter<'a, Cell>;
     type CornerIterator<'a> = hash_map::Iter<'a, Cell, CornerStatus>;
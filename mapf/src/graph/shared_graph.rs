@@ -15,7 +15,12 @@
  *
 */
 
-use crate::{domain::Reversible, error::ThisError, graph::Graph};
+use crate::{
+    algorithm::checkpoint::CheckpointError,
+    domain::{Fingerprinted, Reversible},
+    error::ThisError,
+    graph::Graph,
+};
 use std::{
     ops::Deref,
     sync::{Arc, RwLock},
@@ -69,6 +74,13 @@ impl<G> SharedGraph<G> {
 
         f(graph).map(|graph| Self::new(graph))
     }
+
+    /// Check whether two instances share the same underlying graph. Graphs
+    /// that have been changed with [`SharedGraph::modify`] will never share
+    /// the same graph as the instance they were modified from.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.graph, &other.graph)
+    }
 }
 
 impl<G: Graph> Graph for SharedGraph<G> {
@@ -121,6 +133,12 @@ impl<G: Graph> Graph for SharedGraph<G> {
     }
 }
 
+impl<G: Fingerprinted> Fingerprinted for SharedGraph<G> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        self.graph.fingerprint()
+    }
+}
+
 impl<G> Deref for SharedGraph<G> {
     type Target = G;
     fn deref(&self) -> &Self::Target {
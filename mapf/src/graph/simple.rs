@@ -16,9 +16,11 @@
 */
 
 use crate::{
-    domain::Reversible,
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{Fingerprinted, Reversible},
     error::NoError,
     graph::{Edge, Graph},
+    motion::{r2::Positioned, se2::MaybeOriented, SpeedLimiter},
 };
 
 #[derive(Debug, Clone, Default)]
@@ -59,7 +61,7 @@ impl<V: Clone, E: Clone> Reversible for SimpleGraph<V, E> {
     type ReversalError = NoError;
     fn reversed(&self) -> Result<Self, Self::ReversalError> {
         let mut r_edges = Vec::new();
-        r_edges.resize(self.edges.len(), Vec::new());
+        r_edges.resize(self.vertices.len(), Vec::new());
         for (r_v_to, edges) in self.edges.iter().enumerate() {
             for (r_v_from, e) in edges {
                 r_edges
@@ -76,6 +78,34 @@ impl<V: Clone, E: Clone> Reversible for SimpleGraph<V, E> {
     }
 }
 
+impl<V, E> Fingerprinted for SimpleGraph<V, E>
+where
+    V: Positioned + MaybeOriented,
+    E: SpeedLimiter,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        let vertices: Vec<_> = self
+            .vertices
+            .iter()
+            .map(|v| {
+                let p = v.point();
+                (p.x, p.y, v.maybe_oriented().map(|r| r.angle()))
+            })
+            .collect();
+        let edges: Vec<Vec<_>> = self
+            .edges
+            .iter()
+            .map(|edges| {
+                edges
+                    .iter()
+                    .map(|(to, e)| (*to, e.speed_limit(), e.traversal_delay()))
+                    .collect()
+            })
+            .collect();
+        fingerprint(&(vertices, edges))
+    }
+}
+
 impl<E> Edge<usize, E> for (usize, usize, &E) {
     fn from_vertex(&self) -> &usize {
         &self.0
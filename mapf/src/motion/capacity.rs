@@ -0,0 +1,414 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{Fingerprinted, Key},
+    motion::{SafeAction, TimePoint, Timed, WaitForObstacle},
+};
+use serde::Serialize;
+use std::{collections::HashSet, sync::Arc};
+
+/// A region of graph keys that can hold at most a certain number of agents at
+/// once, e.g. a narrow doorway, a single-lane bridge, or a lift lobby. The
+/// capacity applies regardless of how much room the agents would need.
+#[derive(Debug, Clone)]
+pub struct CapacityZone<K> {
+    keys: HashSet<K>,
+    capacity: usize,
+}
+
+impl<K: Key> CapacityZone<K> {
+    pub fn new(capacity: usize, keys: impl IntoIterator<Item = K>) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+            capacity,
+        }
+    }
+
+    pub fn keys(&self) -> &HashSet<K> {
+        &self.keys
+    }
+
+    /// How many agents may be inside of the zone at the same time
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.keys.contains(key)
+    }
+}
+
+/// A span of time that begins at `begin` (inclusive) and ends at `end`
+/// (exclusive). A span without an end lasts forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occupancy {
+    pub begin: TimePoint,
+    pub end: Option<TimePoint>,
+}
+
+impl Occupancy {
+    pub fn new(begin: TimePoint, end: Option<TimePoint>) -> Self {
+        Self { begin, end }
+    }
+
+    pub fn contains(&self, time: TimePoint) -> bool {
+        self.begin <= time && self.end.map(|end| time < end).unwrap_or(true)
+    }
+
+    /// Check whether two spans share any moment in time. A span that begins
+    /// and ends at the same time still overlaps with any span that contains
+    /// that time.
+    pub fn overlaps(&self, other: &Occupancy) -> bool {
+        self.contains(other.begin) || other.contains(self.begin)
+    }
+}
+
+/// A claim that an agent has made on a capacity zone for a span of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reservation {
+    /// Index of the zone that is reserved
+    pub zone: usize,
+    /// The agent that made the reservation. This is compared against the mask
+    /// of an environment so that agents are not blocked by their own
+    /// reservations.
+    pub agent: usize,
+    pub occupancy: Occupancy,
+}
+
+/// A set of capacity zones together with the reservations that agents have
+/// made on them. Single-agent planners must not enter a zone while the
+/// reservations of other agents have already filled it.
+#[derive(Debug, Clone)]
+pub struct CapacityReservations<K> {
+    zones: Arc<Vec<CapacityZone<K>>>,
+    reservations: Vec<Reservation>,
+}
+
+impl<K> Default for CapacityReservations<K> {
+    fn default() -> Self {
+        Self {
+            zones: Arc::new(Vec::new()),
+            reservations: Vec::new(),
+        }
+    }
+}
+
+impl<K: Key> CapacityReservations<K> {
+    pub fn new(zones: Vec<CapacityZone<K>>) -> Self {
+        Self {
+            zones: Arc::new(zones),
+            reservations: Vec::new(),
+        }
+    }
+
+    pub fn zones(&self) -> &[CapacityZone<K>] {
+        &self.zones
+    }
+
+    pub fn reservations(&self) -> &[Reservation] {
+        &self.reservations
+    }
+
+    pub fn reserve(&mut self, reservation: Reservation) {
+        self.reservations.push(reservation);
+    }
+
+    /// Get the spans of time during which any zone that contains the key has
+    /// no room left, ignoring the reservations of the masked agent.
+    pub fn full_times(&self, key: &K, mask: Option<usize>) -> Vec<Occupancy> {
+        let mut full_times = Vec::new();
+        for (z, zone) in self.zones.iter().enumerate() {
+            if !zone.contains(key) {
+                continue;
+            }
+
+            let reserved = self
+                .reservations
+                .iter()
+                .filter(|r| r.zone == z && Some(r.agent) != mask)
+                .map(|r| (r.agent, r.occupancy));
+            full_times.extend(find_full_times(zone.capacity(), reserved));
+        }
+
+        full_times.sort_by_key(|span| span.begin);
+        full_times
+    }
+
+    /// Check whether the masked agent could occupy the key for a span of time
+    /// without overfilling any zone.
+    pub fn has_room(&self, key: &K, occupancy: &Occupancy, mask: Option<usize>) -> bool {
+        self.full_times(key, mask)
+            .iter()
+            .all(|full| !full.overlaps(occupancy))
+    }
+
+    /// Check whether a sequence of safe actions that moves an agent from one
+    /// key to another, starting at `start`, leaves room in every zone. The
+    /// agent is considered to occupy the zones of its initial key until the
+    /// actions are finished, and the zones of its target key from the start of
+    /// its last action.
+    pub fn has_room_for_actions<M: Timed>(
+        &self,
+        (from_key, target_key): (Option<&K>, Option<&K>),
+        start: TimePoint,
+        actions: &[SafeAction<M, WaitForObstacle>],
+        mask: Option<usize>,
+    ) -> bool {
+        if self.zones.is_empty() {
+            return true;
+        }
+
+        let mut finish = start;
+        let mut entry = start;
+        for action in actions {
+            entry = finish;
+            finish = match action {
+                SafeAction::Move(movement) => movement.time(),
+                SafeAction::Wait(wait) => wait.time_estimate,
+            };
+        }
+
+        if let Some(from_key) = from_key {
+            if !self.has_room(from_key, &Occupancy::new(start, Some(finish)), mask) {
+                return false;
+            }
+        }
+
+        if let Some(target_key) = target_key {
+            if !self.has_room(target_key, &Occupancy::new(entry, Some(finish)), mask) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl<K: Serialize> Fingerprinted for CapacityReservations<K> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        let zones = self
+            .zones
+            .iter()
+            .map(|zone| {
+                // Fingerprint each key on its own so the order of the set does
+                // not matter.
+                let mut keys = zone
+                    .keys
+                    .iter()
+                    .map(fingerprint)
+                    .collect::<Result<Vec<_>, _>>()?;
+                keys.sort_unstable();
+                Ok((zone.capacity, keys))
+            })
+            .collect::<Result<Vec<_>, CheckpointError>>()?;
+        let reservations: Vec<_> = self
+            .reservations
+            .iter()
+            .map(|r| {
+                (
+                    r.zone,
+                    r.agent,
+                    r.occupancy.begin.nanos_since_zero,
+                    r.occupancy.end.map(|t| t.nanos_since_zero),
+                )
+            })
+            .collect();
+        fingerprint(&(zones, reservations))
+    }
+}
+
+/// Find the spans of time during which the occupancies of other agents leave
+/// no room in a zone with the given capacity. Occupancies are given together
+/// with the agent that they belong to so that overlapping occupancies of one
+/// agent are only counted once.
+pub fn find_full_times(
+    capacity: usize,
+    occupancies: impl IntoIterator<Item = (usize, Occupancy)>,
+) -> Vec<Occupancy> {
+    let mut full_times = Vec::new();
+    let mut full_since = if capacity == 0 {
+        Some(TimePoint::new(i64::MIN))
+    } else {
+        None
+    };
+
+    let mut count: usize = 0;
+    for (time, change) in occupancy_events(occupancies) {
+        if change > 0 {
+            count += 1;
+        } else {
+            count -= 1;
+        }
+
+        if count >= capacity {
+            if full_since.is_none() {
+                full_since = Some(time);
+            }
+        } else if let Some(begin) = full_since.take() {
+            if begin < time {
+                full_times.push(Occupancy::new(begin, Some(time)));
+            }
+        }
+    }
+
+    if let Some(begin) = full_since {
+        full_times.push(Occupancy::new(begin, None));
+    }
+
+    full_times
+}
+
+/// Find the earliest time when more agents occupy a zone than its capacity
+/// allows. This gives back that time along with every agent that occupies
+/// the zone at that time and the occupancy that puts it there.
+pub fn find_overflow(
+    capacity: usize,
+    occupancies: &[(usize, Occupancy)],
+) -> Option<(TimePoint, Vec<(usize, Occupancy)>)> {
+    let merged = merge_occupancies(occupancies.iter().cloned());
+    let mut count: usize = 0;
+    for (time, change) in occupancy_events(merged.iter().cloned()) {
+        if change > 0 {
+            count += 1;
+        } else {
+            count -= 1;
+        }
+
+        if count > capacity {
+            let occupants = merged
+                .into_iter()
+                .filter(|(_, occupancy)| occupancy.contains(time))
+                .collect();
+            return Some((time, occupants));
+        }
+    }
+
+    None
+}
+
+/// Get the times when agents enter (+1) and leave (-1) a zone, sorted by time.
+/// When an agent leaves at the same time that another enters, the departure
+/// comes first so that back-to-back occupancies do not count as overlapping.
+fn occupancy_events(
+    occupancies: impl IntoIterator<Item = (usize, Occupancy)>,
+) -> Vec<(TimePoint, i8)> {
+    let mut events = Vec::new();
+    for (_, occupancy) in merge_occupancies(occupancies) {
+        if occupancy.end.is_some_and(|end| end <= occupancy.begin) {
+            continue;
+        }
+
+        events.push((occupancy.begin, 1));
+        if let Some(end) = occupancy.end {
+            events.push((end, -1));
+        }
+    }
+
+    events.sort();
+    events
+}
+
+/// Merge the overlapping occupancies of each agent
+fn merge_occupancies(
+    occupancies: impl IntoIterator<Item = (usize, Occupancy)>,
+) -> Vec<(usize, Occupancy)> {
+    let mut occupancies: Vec<_> = occupancies.into_iter().collect();
+    occupancies.sort_by_key(|(agent, occupancy)| (*agent, occupancy.begin));
+
+    let mut merged: Vec<(usize, Occupancy)> = Vec::new();
+    for (agent, occupancy) in occupancies {
+        if let Some((last_agent, last)) = merged.last_mut() {
+            if *last_agent == agent && last.end.map(|end| occupancy.begin <= end).unwrap_or(true) {
+                last.end = match (last.end, occupancy.end) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    _ => None,
+                };
+                continue;
+            }
+        }
+
+        merged.push((agent, occupancy));
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(begin: f64, end: Option<f64>) -> Occupancy {
+        Occupancy::new(
+            TimePoint::from_secs_f64(begin),
+            end.map(TimePoint::from_secs_f64),
+        )
+    }
+
+    #[test]
+    fn test_full_times() {
+        let occupancies = [
+            (0, span(0.0, Some(10.0))),
+            (1, span(5.0, Some(15.0))),
+            (2, span(12.0, None)),
+            // Overlapping occupancies of one agent only count once
+            (0, span(2.0, Some(8.0))),
+        ];
+
+        assert_eq!(find_full_times(1, occupancies), vec![span(0.0, None)]);
+        assert_eq!(
+            find_full_times(2, occupancies),
+            vec![span(5.0, Some(10.0)), span(12.0, Some(15.0))],
+        );
+        assert_eq!(find_full_times(3, occupancies), vec![]);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let occupancies = [
+            (0, span(0.0, Some(10.0))),
+            (1, span(10.0, Some(15.0))),
+            (2, span(12.0, Some(20.0))),
+        ];
+
+        // Back-to-back occupancies do not overflow a zone
+        let (time, occupants) = find_overflow(1, &occupancies).unwrap();
+        assert_eq!(time, TimePoint::from_secs_f64(12.0));
+        assert_eq!(
+            occupants,
+            vec![(1, span(10.0, Some(15.0))), (2, span(12.0, Some(20.0)))],
+        );
+        assert!(find_overflow(2, &occupancies).is_none());
+    }
+
+    #[test]
+    fn test_has_room() {
+        let mut reservations = CapacityReservations::new(vec![CapacityZone::new(1, [0, 1])]);
+        reservations.reserve(Reservation {
+            zone: 0,
+            agent: 7,
+            occupancy: span(5.0, Some(10.0)),
+        });
+
+        assert!(reservations.has_room(&0, &span(0.0, Some(5.0)), None));
+        assert!(!reservations.has_room(&1, &span(4.0, Some(6.0)), None));
+        assert!(reservations.has_room(&2, &span(4.0, Some(6.0)), None));
+        // An agent is never blocked by its own reservations
+        assert!(reservations.has_room(&1, &span(4.0, Some(6.0)), Some(7)));
+    }
+}
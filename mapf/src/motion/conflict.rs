@@ -21,8 +21,7 @@ use crate::{
         r2::{Point, Positioned, WaypointR2},
         se2::MaybeOriented,
         Arclength, BoundingBox, CircularProfile, Duration, DynamicCircularObstacle, Environment,
-        IntegrateWaypoints, Interpolation, Measurable, Motion, TimePoint, Timed, Trajectory,
-        Waypoint,
+        IntegrateWaypoints, Interpolation, Measurable, Motion, TimePoint, Timed, Waypoint,
     },
 };
 use arrayvec::ArrayVec;
@@ -236,11 +235,15 @@ where
         BoundingBox::for_line(in_environment.agent_profile(), from_point, to_point)
     });
 
-    let wait_hints = compute_wait_hints((&from_point, &to_point), &bb, in_environment);
-
     let dx = to_point.position - from_point.position;
     let dist = dx.norm();
-    assert!(dist >= 1e-8);
+    if dist < 1e-8 {
+        // The agent holds in place, e.g. while it rides a lift, so there is
+        // nowhere along the segment for it to wait.
+        return RankedHints::new();
+    }
+
+    let wait_hints = compute_wait_hints((&from_point, &to_point), &bb, in_environment);
     let u = dx / dist;
     let dt = (to_point.time - from_point.time).as_secs_f64();
     let speed = dist / dt;
@@ -291,26 +294,23 @@ where
     let profile = in_environment.agent_profile();
     let mut safe_arrival_times = SafeArrivalTimes::new();
 
+    // Only the obstacles that come near the point can affect when it is safe
+    // to arrive there.
+    let region = BoundingBox::for_point(for_point.position)
+        .inflated_by(f64::max(profile.footprint_radius(), 1e-3));
+    let nearby: SmallVec<[&DynamicCircularObstacle<W>; 16]> = in_environment
+        .obstacles_near(&region, for_point.time, None)
+        .into_iter()
+        .collect();
+
     // First find every safe arrival time above from_point.time
     let mut candidate_time = for_point.time;
     loop {
         let mut any_pushed = true;
         while any_pushed {
             any_pushed = false;
-            for obs in in_environment.obstacles() {
-                let obs_traj = match obs.trajectory() {
-                    Some(r) => r,
-                    None => continue,
-                };
-
-                let critical_distance_squared =
-                    profile.critical_distance_squared_for(obs.profile());
-                let adjustment = adjust_candidate_time(
-                    for_point,
-                    candidate_time,
-                    obs_traj,
-                    critical_distance_squared,
-                );
+            for obs in &nearby {
+                let adjustment = adjust_candidate_time(for_point, candidate_time, obs, profile);
 
                 if !adjustment.pushed {
                     continue;
@@ -326,6 +326,23 @@ where
                     return safe_arrival_times;
                 }
             }
+
+            for zone in in_environment.blocked_zones() {
+                if !zone.is_closed_at(candidate_time)
+                    || !zone.covers_point(profile, for_point.position)
+                {
+                    continue;
+                }
+
+                match zone.opens() {
+                    Some(opens) => {
+                        candidate_time = opens;
+                        any_pushed = true;
+                    }
+                    // The zone covers this point and never opens again
+                    None => return safe_arrival_times,
+                }
+            }
         }
 
         // We found a new safe arrival time
@@ -333,23 +350,31 @@ where
 
         // Look for the next soonest candidate time
         let mut next_candidate_time = None;
-        for obs in in_environment.obstacles() {
-            let min_distance_squared = profile.critical_distance_squared_for(obs.profile());
+        for obs in &nearby {
+            if let Some(check) = find_next_candidate_time(candidate_time, for_point, obs, profile) {
+                if let Some(next_candidate_time) = &mut next_candidate_time {
+                    if check < *next_candidate_time {
+                        *next_candidate_time = check;
+                    }
+                } else {
+                    next_candidate_time = Some(check);
+                }
+            }
+        }
 
-            let obs_traj = match obs.trajectory() {
-                Some(r) => r,
+        for zone in in_environment.blocked_zones() {
+            let opens = match zone.opens() {
+                Some(opens) => opens,
                 None => continue,
             };
 
-            if let Some(check) =
-                find_next_candidate_time(candidate_time, for_point, obs_traj, min_distance_squared)
-            {
+            if candidate_time < opens && zone.covers_point(profile, for_point.position) {
                 if let Some(next_candidate_time) = &mut next_candidate_time {
-                    if check < *next_candidate_time {
-                        *next_candidate_time = check;
+                    if opens < *next_candidate_time {
+                        *next_candidate_time = opens;
                     }
                 } else {
-                    next_candidate_time = Some(check);
+                    next_candidate_time = Some(opens);
                 }
             }
         }
@@ -378,7 +403,18 @@ where
 {
     let dx = to_point.position - from_point.position;
     let dist = dx.norm();
-    assert!(dist >= 1e-8);
+    if dist < 1e-8 {
+        // The agent holds in place for the whole motion, so it can only
+        // arrive if it can safely hold until the arrival time. Waiting before
+        // the hold begins is done by compute_delayed_departure_path.
+        let final_wp = to_point.with_time(arrival_time.max(to_point.time));
+        if is_safe_segment((&from_point, &final_wp), None, in_environment) {
+            return Some(SmallVec::from_iter([SafeAction::Move(to_point)]));
+        }
+
+        return None;
+    }
+
     let dt = (to_point.time - from_point.time).as_secs_f64();
     let speed = dist / dt;
 
@@ -547,6 +583,41 @@ where
     None
 }
 
+/// Find a path that holds at the start point and then moves straight to the
+/// target point, arriving exactly at the arrival time. This is how an agent
+/// waits outside of a region until there is room for it to enter. Get back
+/// None if the arrival time is too soon or if the path would not be safe.
+pub fn compute_delayed_departure_path<Env, W>(
+    from_point: WaypointR2,
+    to_point: WaypointR2,
+    arrival_time: TimePoint,
+    in_environment: &Env,
+) -> Option<SmallVec<[SafeAction<WaypointR2, WaitForObstacle>; 5]>>
+where
+    W: Into<WaypointR2> + Waypoint + std::fmt::Debug,
+    Env: Environment<CircularProfile, DynamicCircularObstacle<W>>,
+{
+    let departure_time = arrival_time - (to_point.time - from_point.time);
+    if departure_time <= from_point.time {
+        return None;
+    }
+
+    let departure_wp = from_point.with_time(departure_time);
+    let arrival_wp = to_point.with_time(arrival_time);
+    if !is_safe_segment((&from_point, &departure_wp), None, in_environment) {
+        return None;
+    }
+
+    if !is_safe_segment((&departure_wp, &arrival_wp), None, in_environment) {
+        return None;
+    }
+
+    Some(SmallVec::from_iter([
+        SafeAction::Move(departure_wp),
+        SafeAction::Move(arrival_wp),
+    ]))
+}
+
 #[derive(Debug)]
 pub struct RankedHint {
     contour: Duration,
@@ -739,12 +810,22 @@ struct CandidateAdjustment {
 fn adjust_candidate_time<W>(
     for_point: WaypointR2,
     candidate_time: TimePoint,
-    obs_traj: &Trajectory<W>,
-    acceptable_distance_squared: f64,
+    obs: &DynamicCircularObstacle<W>,
+    profile: &CircularProfile,
 ) -> CandidateAdjustment
 where
     W: Into<WaypointR2> + Waypoint,
 {
+    let obs_traj = match obs.trajectory() {
+        Some(r) => r,
+        None => {
+            return CandidateAdjustment {
+                pushed: false,
+                result: None,
+            }
+        }
+    };
+
     // Has this obstacle pushed the candidate time?
     let mut this_obs_violated = false;
     let mut new_candidate_time: Option<TimePoint> = None;
@@ -755,6 +836,9 @@ where
 
         let wp0: WaypointR2 = wp0.into();
         let wp1: WaypointR2 = wp1.into();
+        // The footprint of an uncertain obstacle may grow along the segment
+        let acceptable_distance_squared =
+            profile.critical_distance_squared_for(&obs.profile_between(wp0.time, wp1.time));
         let proximity = detect_proximity(
             acceptable_distance_squared,
             (
@@ -832,15 +916,19 @@ where
 fn find_next_candidate_time<W>(
     previous_candidate_time: TimePoint,
     for_point: WaypointR2,
-    obs_traj: &Trajectory<W>,
-    acceptable_distance_squared: f64,
+    obs: &DynamicCircularObstacle<W>,
+    profile: &CircularProfile,
 ) -> Option<TimePoint>
 where
     W: Into<WaypointR2> + Waypoint,
 {
+    let obs_traj = obs.trajectory()?;
     for [wp0, wp1] in obs_traj.iter_from(previous_candidate_time).pairs() {
         let wp0: WaypointR2 = wp0.into();
         let wp1: WaypointR2 = wp1.into();
+        // The footprint of an uncertain obstacle may grow along the segment
+        let acceptable_distance_squared =
+            profile.critical_distance_squared_for(&obs.profile_between(wp0.time, wp1.time));
         let proximity = detect_proximity(
             acceptable_distance_squared,
             (
@@ -863,7 +951,7 @@ where
             // does then we should use its vanishing time as a candidate time.
             let wpf: WaypointR2 = obs_traj.finish_motion().clone().into();
             let dq = wpf.position - for_point.position;
-            if dq.dot(&dq) <= acceptable_distance_squared {
+            if dq.dot(&dq) <= profile.critical_distance_squared_for(&obs.profile_at(tf)) {
                 return Some(tf);
             }
         }
@@ -888,8 +976,13 @@ where
         None => BoundingBox::for_line(profile, line_a.0, line_a.1),
     };
 
-    for obs in in_environment.obstacles() {
-        let conflict_distance_squared = profile.conflict_distance_squared_for(obs.profile());
+    for zone in in_environment.blocked_zones() {
+        if zone.blocks_line(profile, line_a) {
+            return false;
+        }
+    }
+
+    for obs in in_environment.obstacles_near(&bb, line_a.0.time, Some(line_a.1.time)) {
         let obs_traj = match obs.trajectory() {
             Some(r) => r,
             None => continue,
@@ -909,14 +1002,15 @@ where
             let wp0_b: WaypointR2 = wp0_b.into();
             let wp1_b: WaypointR2 = wp1_b.into();
             let line_b = (&wp0_b, &wp1_b);
+            let profile_b = obs.profile_between(wp0_b.time, wp1_b.time);
             if have_conflict(
                 line_a,
                 Some(bb),
                 in_environment.agent_profile(),
                 line_b,
                 None,
-                obs.profile(),
-                conflict_distance_squared,
+                &profile_b,
+                profile.conflict_distance_squared_for(&profile_b),
             ) {
                 return false;
             }
@@ -1019,17 +1113,19 @@ where
             None => continue,
         };
 
-        let min_distance = profile.critical_distance_for(obs.profile());
-        let min_distance_squared = min_distance.powi(2);
         for [obs_wp0, obs_wp1] in obs_traj.iter().pairs() {
             let obs_wp0: WaypointR2 = obs_wp0.into();
             let obs_wp1: WaypointR2 = obs_wp1.into();
+            let obs_profile = obs.profile_between(obs_wp0.time, obs_wp1.time);
             if !bb.overlaps(Some(
-                BoundingBox::for_line(obs.profile(), &obs_wp0, &obs_wp1).inflated_by(1e-3),
+                BoundingBox::for_line(&obs_profile, &obs_wp0, &obs_wp1).inflated_by(1e-3),
             )) {
                 continue;
             }
 
+            let min_distance = profile.critical_distance_for(&obs_profile);
+            let min_distance_squared = min_distance.powi(2);
+
             let obs_q = obs_wp0.position;
             let obs_r = obs_wp1.position;
             let obs_dt = (obs_wp1.time - obs_wp0.time).as_secs_f64();
@@ -1097,7 +1193,7 @@ where
                     };
 
                     if dq.dot(&dq) >= min_distance_squared {
-                        let follow_distance = profile.follow_distance_for(obs.profile());
+                        let follow_distance = profile.follow_distance_for(&obs_profile);
                         let v_rel = agent_speed - aligned_speed;
                         let obs_s0 = dq.dot(&u);
                         let t0 = wp0.time.as_secs_f64();
@@ -1235,6 +1331,31 @@ where
         }
     }
 
+    // The agent can wait where it begins until a zone along its way opens
+    // again. Zones are numbered after the obstacles.
+    let obstacle_count = in_environment.obstacles().into_iter().count();
+    let conflict_distance = f64::max(profile.footprint_radius() - 1e-3, 0.0);
+    for (i, zone) in in_environment.blocked_zones().iter().enumerate() {
+        let opens = match zone.opens() {
+            Some(opens) => opens,
+            None => continue,
+        };
+
+        if opens <= wp0.time || !bb.overlaps(Some(*zone.bounding_box())) {
+            continue;
+        }
+
+        if zone.covers_point(profile, q) || zone.distance_to_line(q, r) >= conflict_distance {
+            continue;
+        }
+
+        wait_hints.push(WaitHint {
+            at_point: q,
+            until: opens,
+            for_obstacle: obstacle_count + i,
+        });
+    }
+
     wait_hints
 }
 
@@ -1374,8 +1495,15 @@ impl From<WaitHint> for WaypointR2 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::motion::{CircularProfile, DynamicEnvironment};
+    use crate::{
+        graph::occupancy::Cell,
+        motion::{
+            BlockedZone, CcbsConstraint, CcbsEnvironment, CircularProfile, DynamicEnvironment,
+            Trajectory, Uncertainty,
+        },
+    };
     use approx::assert_relative_eq;
+    use std::sync::Arc;
 
     fn add_to_env(
         in_environment: &mut DynamicEnvironment<WaypointR2>,
@@ -1676,4 +1804,128 @@ mod tests {
             assert_eq!(expect_safe, is_safe_segment((&p0, &p1), None, &environment));
         }
     }
+
+    #[test]
+    fn test_blocked_zone_delays_arrival() {
+        let profile = CircularProfile::new(0.25, 0.0, 0.0).unwrap();
+        let zone = BlockedZone::cells(1.0, vec![Cell::new(0, 0)])
+            .with_opening_time(Some(TimePoint::from_secs_f64(10.0)));
+        let environment = DynamicEnvironment::<WaypointR2>::new(profile).with_zones(vec![zone]);
+
+        let safe_arrival_times =
+            compute_safe_arrival_times(WaypointR2::new_f64(0.0, 0.5, 0.5), &environment);
+        assert_eq!(safe_arrival_times.len(), 1);
+        assert_relative_eq!(safe_arrival_times[0].as_secs_f64(), 10.0);
+
+        // Points away from the zone are not affected
+        let safe_arrival_times =
+            compute_safe_arrival_times(WaypointR2::new_f64(0.0, 3.5, 0.5), &environment);
+        assert_eq!(safe_arrival_times.len(), 1);
+        assert_relative_eq!(safe_arrival_times[0].as_secs_f64(), 0.0);
+
+        let p0 = WaypointR2::new_f64(5.0, -1.0, 0.5);
+        let p1 = WaypointR2::new_f64(8.0, 2.0, 0.5);
+        assert!(!is_safe_segment((&p0, &p1), None, &environment));
+
+        let p0 = WaypointR2::new_f64(10.0, -1.0, 0.5);
+        let p1 = WaypointR2::new_f64(13.0, 2.0, 0.5);
+        assert!(is_safe_segment((&p0, &p1), None, &environment));
+    }
+
+    #[test]
+    fn test_far_obstacles_are_skipped() {
+        let profile = CircularProfile::new(0.5, 0.0, 0.0).unwrap();
+        let for_point = WaypointR2::new_f64(0.0, 0.0, 0.0);
+        let mut near_env = DynamicEnvironment::new(profile);
+        add_to_env(&mut near_env, profile, (0.0, -5.0, 0.0), (10.0, 5.0, 0.0));
+
+        // Many obstacles that pass far away from the point
+        let mut crowded_env = near_env.clone();
+        for i in 1..=100 {
+            let y = 10.0 * i as f64;
+            add_to_env(&mut crowded_env, profile, (0.0, -5.0, y), (10.0, 5.0, y));
+        }
+
+        let region = BoundingBox::for_point(for_point.position).inflated_by(0.5);
+        let nearby = |env: &CcbsEnvironment<WaypointR2, usize>, key| {
+            env.view_for(Some(&key))
+                .obstacles_near(&region, for_point.time, None)
+                .count()
+        };
+        assert_eq!(
+            crowded_env
+                .obstacles_near(&region, for_point.time, None)
+                .count(),
+            1
+        );
+        assert_eq!(
+            compute_safe_arrival_times(for_point, &crowded_env),
+            compute_safe_arrival_times(for_point, &near_env),
+        );
+
+        // Constraints are indexed as soon as they are inserted
+        let mut environment = CcbsEnvironment::new(Arc::new(crowded_env));
+        environment.insert_constraint(
+            (0, 1),
+            CcbsConstraint {
+                obstacle: near_env.obstacles[0].clone(),
+                mask: 7,
+            },
+        );
+        assert_eq!(nearby(&environment, (0, 1)), 2);
+        assert_eq!(nearby(&environment, (1, 0)), 1);
+        environment.set_mask(Some(7));
+        assert_eq!(nearby(&environment, (0, 1)), 1);
+
+        // Overlaid obstacles are found where their new trajectory goes
+        environment.overlay_trajectory(0, None).unwrap();
+        assert_eq!(nearby(&environment, (1, 0)), 0);
+    }
+
+    #[test]
+    fn test_uncertain_obstacle_grows() {
+        let profile = CircularProfile::new(0.5, 0.0, 0.0).unwrap();
+        let mut certain_env = DynamicEnvironment::new(profile);
+        add_to_env(
+            &mut certain_env,
+            profile,
+            (0.0, -5.0, 2.0),
+            (10.0, 5.0, 2.0),
+        );
+
+        let mut uncertain_env = DynamicEnvironment::new(profile);
+        uncertain_env.obstacles.push(
+            certain_env.obstacles[0]
+                .clone()
+                .with_uncertainty(Some(Uncertainty::Growing {
+                    rate: 0.3,
+                    limit: Some(5.0),
+                })),
+        );
+
+        let obs = &uncertain_env.obstacles[0];
+        assert_relative_eq!(obs.extra_radius_at(TimePoint::from_secs_f64(5.0)), 1.5);
+        // The radius stops growing when the trajectory finishes
+        assert_relative_eq!(obs.extra_radius_at(TimePoint::from_secs_f64(20.0)), 3.0);
+        assert_relative_eq!(obs.bounding_box().unwrap().max().y, 5.5);
+
+        let for_point = WaypointR2::new_f64(4.0, 0.0, 0.0);
+        let safe_arrival_times = compute_safe_arrival_times(for_point, &certain_env);
+        assert_relative_eq!(safe_arrival_times[0].as_secs_f64(), 4.0);
+        let safe_arrival_times = compute_safe_arrival_times(for_point, &uncertain_env);
+        assert!(safe_arrival_times[0].as_secs_f64() > 8.0);
+
+        let p0 = WaypointR2::new_f64(0.0, 0.0, 0.0);
+        let p1 = WaypointR2::new_f64(10.0, 0.0, 0.0);
+        assert!(is_safe_segment((&p0, &p1), None, &certain_env));
+        assert!(!is_safe_segment((&p0, &p1), None, &uncertain_env));
+
+        // Per-waypoint uncertainty is interpolated between the waypoints
+        let obs = obs
+            .clone()
+            .with_uncertainty(Some(Uncertainty::PerWaypoint(vec![0.2, 1.0])));
+        assert_relative_eq!(obs.extra_radius_at(TimePoint::from_secs_f64(-1.0)), 0.2);
+        assert_relative_eq!(obs.extra_radius_at(TimePoint::from_secs_f64(5.0)), 0.6);
+        assert_relative_eq!(obs.extra_radius_at(TimePoint::from_secs_f64(20.0)), 1.0);
+    }
 }
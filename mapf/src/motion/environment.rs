@@ -16,14 +16,18 @@
 */
 
 use crate::{
-    domain::Key,
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{Fingerprinted, Key},
     motion::{
         r2::{Point, WaypointR2},
+        BlockedZone, CapacityReservations, CapacityZone, Reservation, SpatialIndex, TimePoint,
         Trajectory, Waypoint,
     },
 };
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{hash_map::Entry, HashMap},
+    ops::Deref,
     sync::Arc,
 };
 
@@ -36,15 +40,39 @@ pub trait Environment<Profile, Obstacle> {
         Profile: 'a,
         Obstacle: 'a;
 
+    type NearbyObstacles<'a>: IntoIterator<Item = &'a Obstacle> + 'a
+    where
+        Self: 'a,
+        Profile: 'a,
+        Obstacle: 'a;
+
     fn agent_profile(&self) -> &Profile;
 
     fn obstacles<'a>(&'a self) -> Self::Obstacles<'a>;
+
+    /// Get the obstacles that might be inside of a region at some time between
+    /// `from` and `until`, or at any time after `from` if `until` is None.
+    /// This may give back obstacles that never actually enter the region, but
+    /// it will never leave out an obstacle that does.
+    fn obstacles_near<'a>(
+        &'a self,
+        region: &BoundingBox,
+        from: TimePoint,
+        until: Option<TimePoint>,
+    ) -> Self::NearbyObstacles<'a>;
+
+    /// Zones that the agent must stay out of while they are closed. By default
+    /// an environment does not have any zones.
+    fn blocked_zones(&self) -> &[BlockedZone] {
+        &[]
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DynamicEnvironment<W: Waypoint> {
     pub profile: CircularProfile,
-    pub obstacles: Vec<DynamicCircularObstacle<W>>,
+    pub obstacles: IndexedObstacles<W>,
+    pub zones: Vec<BlockedZone>,
 }
 
 impl<W: Waypoint> Environment<CircularProfile, DynamicCircularObstacle<W>>
@@ -58,17 +86,101 @@ impl<W: Waypoint> Environment<CircularProfile, DynamicCircularObstacle<W>>
         &self.profile
     }
 
+    type NearbyObstacles<'a> = impl Iterator<Item=&'a DynamicCircularObstacle<W>>
+    where
+        W: 'a;
+
     fn obstacles<'a>(&'a self) -> Self::Obstacles<'a> {
         self.obstacles.iter()
     }
+
+    fn obstacles_near<'a>(
+        &'a self,
+        region: &BoundingBox,
+        from: TimePoint,
+        until: Option<TimePoint>,
+    ) -> Self::NearbyObstacles<'a> {
+        self.obstacles
+            .index()
+            .query(region, from, until)
+            .into_iter()
+            .map(move |i| &self.obstacles[i])
+    }
+
+    fn blocked_zones(&self) -> &[BlockedZone] {
+        &self.zones
+    }
 }
 
 impl<W: Waypoint> DynamicEnvironment<W> {
     pub fn new(profile: CircularProfile) -> Self {
         Self {
             profile,
+            obstacles: Default::default(),
+            zones: Vec::new(),
+        }
+    }
+
+    pub fn with_zones(mut self, zones: Vec<BlockedZone>) -> Self {
+        self.zones = zones;
+        self
+    }
+}
+
+/// The obstacles of a [`DynamicEnvironment`]. The trajectories of the
+/// obstacles are kept in a [`SpatialIndex`] which is updated whenever an
+/// obstacle is added, so the obstacles can be read like a slice but can only
+/// be changed through [`IndexedObstacles::push`].
+#[derive(Debug, Clone)]
+pub struct IndexedObstacles<W: Waypoint> {
+    obstacles: Vec<DynamicCircularObstacle<W>>,
+    index: SpatialIndex,
+}
+
+impl<W: Waypoint> Default for IndexedObstacles<W> {
+    fn default() -> Self {
+        Self {
             obstacles: Vec::new(),
+            index: SpatialIndex::default(),
+        }
+    }
+}
+
+impl<W: Waypoint + Into<WaypointR2>> IndexedObstacles<W> {
+    pub fn push(&mut self, obstacle: DynamicCircularObstacle<W>) {
+        self.index.insert(self.obstacles.len(), &obstacle);
+        self.obstacles.push(obstacle);
+    }
+}
+
+impl<W: Waypoint> IndexedObstacles<W> {
+    pub fn index(&self) -> &SpatialIndex {
+        &self.index
+    }
+}
+
+impl<W: Waypoint + Into<WaypointR2>> From<Vec<DynamicCircularObstacle<W>>> for IndexedObstacles<W> {
+    fn from(obstacles: Vec<DynamicCircularObstacle<W>>) -> Self {
+        let mut indexed = Self::default();
+        for obstacle in obstacles {
+            indexed.push(obstacle);
         }
+        indexed
+    }
+}
+
+impl<W: Waypoint> Deref for IndexedObstacles<W> {
+    type Target = [DynamicCircularObstacle<W>];
+    fn deref(&self) -> &Self::Target {
+        &self.obstacles
+    }
+}
+
+impl<'a, W: Waypoint> IntoIterator for &'a IndexedObstacles<W> {
+    type Item = &'a DynamicCircularObstacle<W>;
+    type IntoIter = std::slice::Iter<'a, DynamicCircularObstacle<W>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.obstacles.iter()
     }
 }
 
@@ -89,6 +201,28 @@ impl<W: Waypoint> Default for DynamicEnvironmentOverlay<W> {
 
 pub type CcbsKey<K> = (K, K);
 
+/// Tells which level (e.g. floor of a building) each key of a graph is on, so
+/// that an environment only shows an agent the obstacles of the levels that it
+/// is moving between.
+#[derive(Clone)]
+pub struct KeyLevels<K>(Arc<dyn Fn(&K) -> usize + Send + Sync>);
+
+impl<K> KeyLevels<K> {
+    pub fn new(f: impl Fn(&K) -> usize + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub fn level_of(&self, key: &K) -> usize {
+        (self.0)(key)
+    }
+}
+
+impl<K> std::fmt::Debug for KeyLevels<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeyLevels")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CcbsConstraint<W: Waypoint> {
     pub obstacle: DynamicCircularObstacle<W>,
@@ -100,7 +234,15 @@ pub struct CcbsConstraint<W: Waypoint> {
 pub struct CcbsEnvironment<W: Waypoint, K> {
     base: Arc<DynamicEnvironment<W>>,
     overlay: DynamicEnvironmentOverlay<W>,
+    /// Spatial index of the overlay obstacles, identified by their index
+    overlay_index: SpatialIndex,
     constraints: HashMap<CcbsKey<K>, Vec<CcbsConstraint<W>>>,
+    /// Spatial index of the constraints of each key, identified by their
+    /// position in the list of constraints
+    constraint_index: HashMap<CcbsKey<K>, SpatialIndex>,
+    capacity: CapacityReservations<K>,
+    /// Levels of the keys, if the graph has more than one level
+    key_levels: Option<KeyLevels<K>>,
     mask: Option<usize>,
 }
 
@@ -109,11 +251,33 @@ impl<W: Waypoint, K> CcbsEnvironment<W, K> {
         Self {
             base,
             overlay: Default::default(),
+            overlay_index: Default::default(),
             constraints: Default::default(),
+            constraint_index: Default::default(),
+            capacity: Default::default(),
+            key_levels: None,
             mask: None,
         }
     }
 
+    /// Set the capacity zones of this environment. Any reservations that were
+    /// made on the previous zones are dropped.
+    pub fn with_capacity_zones(mut self, zones: Vec<CapacityZone<K>>) -> Self
+    where
+        K: Key,
+    {
+        self.capacity = CapacityReservations::new(zones);
+        self
+    }
+
+    /// Tell the environment which level each key is on. Obstacles that have a
+    /// level will then only be visible to motions that start or end on that
+    /// level.
+    pub fn with_key_levels(mut self, key_levels: KeyLevels<K>) -> Self {
+        self.key_levels = Some(key_levels);
+        self
+    }
+
     pub fn view_for<'a>(&'a self, key: Option<&CcbsKey<K>>) -> CcbsEnvironmentView<'a, W, K>
     where
         K: Key,
@@ -121,6 +285,10 @@ impl<W: Waypoint, K> CcbsEnvironment<W, K> {
         CcbsEnvironmentView {
             view: self,
             constraints: key.map(|key| self.constraints.get(key)).flatten(),
+            constraint_index: key.and_then(|key| self.constraint_index.get(key)),
+            levels: key
+                .zip(self.key_levels.as_ref())
+                .map(|((from, to), levels)| (levels.level_of(from), levels.level_of(to))),
         }
     }
 
@@ -192,7 +360,13 @@ impl<W: Waypoint, K> CcbsEnvironment<W, K> {
         &mut self,
         index: usize,
         obstacle: DynamicCircularObstacle<W>,
-    ) -> Result<Option<DynamicCircularObstacle<W>>, Option<DynamicCircularObstacle<W>>> {
+    ) -> Result<Option<DynamicCircularObstacle<W>>, Option<DynamicCircularObstacle<W>>>
+    where
+        W: Into<WaypointR2>,
+    {
+        self.overlay_index.remove(index);
+        self.overlay_index.insert(index, &obstacle);
+
         let r = self.overlay.obstacles.insert(index, obstacle);
         if index < self.base.obstacles.len() {
             Ok(r)
@@ -210,6 +384,7 @@ impl<W: Waypoint, K> CcbsEnvironment<W, K> {
         &mut self,
         index: usize,
     ) -> Result<Option<DynamicCircularObstacle<W>>, Option<DynamicCircularObstacle<W>>> {
+        self.overlay_index.remove(index);
         let r = self.overlay.obstacles.remove(&index);
         if index < self.base.obstacles.len() {
             Ok(r)
@@ -250,13 +425,20 @@ impl<W: Waypoint, K> CcbsEnvironment<W, K> {
                     Some(base_obs) => base_obs,
                     None => return Err(Some(trajectory)),
                 };
-                let overlay_obs =
-                    DynamicCircularObstacle::new(base_obs.profile).with_trajectory(trajectory);
+                let overlay_obs = DynamicCircularObstacle::new(base_obs.profile)
+                    .with_uncertainty(base_obs.uncertainty().cloned())
+                    .with_level(base_obs.level)
+                    .with_trajectory(trajectory);
                 vacant.insert(overlay_obs);
                 None
             }
         };
 
+        self.overlay_index.remove(index);
+        if let Some(obstacle) = self.overlay.obstacles.get(&index) {
+            self.overlay_index.insert(index, obstacle);
+        }
+
         if index < self.base.obstacles.len() {
             Ok(prior_trajectory)
         } else {
@@ -287,21 +469,124 @@ impl<W: Waypoint, K> CcbsEnvironment<W, K> {
     }
 
     pub fn insert_constraint(&mut self, key: CcbsKey<K>, constraint: CcbsConstraint<W>)
+    where
+        K: Key + Clone,
+        W: Into<WaypointR2>,
+    {
+        let constraints = self.constraints.entry(key.clone()).or_default();
+        self.constraint_index
+            .entry(key)
+            .or_default()
+            .insert(constraints.len(), &constraint.obstacle);
+        constraints.push(constraint);
+    }
+
+    /// Reserve room in a capacity zone for an agent. Agents other than the
+    /// one that made the reservation will not be able to enter the zone while
+    /// the reservations leave no room for them.
+    pub fn reserve(&mut self, reservation: Reservation)
     where
         K: Key,
     {
-        self.constraints.entry(key).or_default().push(constraint);
+        self.capacity.reserve(reservation);
     }
 
     pub fn set_mask(&mut self, mask: Option<usize>) {
         self.mask = mask;
     }
+
+    pub fn base(&self) -> &Arc<DynamicEnvironment<W>> {
+        &self.base
+    }
+
+    pub fn overlay(&self) -> &DynamicEnvironmentOverlay<W> {
+        &self.overlay
+    }
+
+    pub fn constraints(&self) -> &HashMap<CcbsKey<K>, Vec<CcbsConstraint<W>>> {
+        &self.constraints
+    }
+
+    pub fn capacity(&self) -> &CapacityReservations<K> {
+        &self.capacity
+    }
+
+    pub fn mask(&self) -> Option<usize> {
+        self.mask
+    }
+
+    pub fn key_levels(&self) -> Option<&KeyLevels<K>> {
+        self.key_levels.as_ref()
+    }
+}
+
+impl<W: Waypoint + Fingerprinted> Fingerprinted for DynamicEnvironment<W> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        let obstacles = self
+            .obstacles
+            .iter()
+            .map(|obs| obs.fingerprint())
+            .collect::<Result<Vec<_>, _>>()?;
+        let zones = self
+            .zones
+            .iter()
+            .map(|zone| zone.fingerprint())
+            .collect::<Result<Vec<_>, _>>()?;
+        fingerprint(&(self.profile.fingerprint()?, obstacles, zones))
+    }
+}
+
+impl<W, K> Fingerprinted for CcbsEnvironment<W, K>
+where
+    W: Waypoint + Fingerprinted,
+    K: Serialize,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        // The entries of each map are fingerprinted one at a time and sorted so
+        // that the order of the map does not matter.
+        let overlay_profile = self.overlay.profile.map(|p| p.fingerprint()).transpose()?;
+        let mut overlay_obstacles = self
+            .overlay
+            .obstacles
+            .iter()
+            .map(|(i, obs)| Ok((*i, obs.fingerprint()?)))
+            .collect::<Result<Vec<_>, CheckpointError>>()?;
+        overlay_obstacles.sort_unstable();
+
+        let mut constraints = self
+            .constraints
+            .iter()
+            .map(|(key, constraints)| {
+                let constraints = constraints
+                    .iter()
+                    .map(|c| Ok((c.obstacle.fingerprint()?, c.mask)))
+                    .collect::<Result<Vec<_>, CheckpointError>>()?;
+                fingerprint(&(key, constraints))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        constraints.sort_unstable();
+
+        fingerprint(&(
+            self.base.fingerprint()?,
+            overlay_profile,
+            overlay_obstacles,
+            constraints,
+            self.capacity.fingerprint()?,
+            // The levels are given by a function, so they can only be told
+            // apart by whether there are any.
+            self.key_levels.is_some(),
+            self.mask,
+        ))
+    }
 }
 
 // #[derive(Clone, Copy)]
 pub struct CcbsEnvironmentView<'a, W: Waypoint, K> {
     view: &'a CcbsEnvironment<W, K>,
     constraints: Option<&'a Vec<CcbsConstraint<W>>>,
+    constraint_index: Option<&'a SpatialIndex>,
+    /// The levels of the motion that this view is for, if they are known
+    levels: Option<(usize, usize)>,
 }
 
 impl<'a, W: Waypoint, K> Clone for CcbsEnvironmentView<'a, W, K> {
@@ -309,6 +594,8 @@ impl<'a, W: Waypoint, K> Clone for CcbsEnvironmentView<'a, W, K> {
         Self {
             view: self.view,
             constraints: self.constraints,
+            constraint_index: self.constraint_index,
+            levels: self.levels,
         }
     }
 }
@@ -324,6 +611,12 @@ impl<'e, W: Waypoint, K: Key> Environment<CircularProfile, DynamicCircularObstac
         K: 'a,
         'e: 'a;
 
+    type NearbyObstacles<'a> = impl Iterator<Item=&'a DynamicCircularObstacle<W>>
+    where
+        W: 'a,
+        K: 'a,
+        'e: 'a;
+
     fn agent_profile(&self) -> &CircularProfile {
         self.view
             .overlay
@@ -352,6 +645,56 @@ impl<'e, W: Waypoint, K: Key> Environment<CircularProfile, DynamicCircularObstac
                         Some(&constraint.obstacle)
                     }),
             )
+            .filter(move |obs| obs.is_on_levels(self.levels))
+    }
+
+    fn obstacles_near<'a>(
+        &'a self,
+        region: &BoundingBox,
+        from: TimePoint,
+        until: Option<TimePoint>,
+    ) -> Self::NearbyObstacles<'a> {
+        let base = &self.view.base.obstacles;
+        let overlay = &self.view.overlay.obstacles;
+        let constraints = self.constraints.map(|c| c.as_slice()).unwrap_or(&[]);
+        let constraint_hits = self
+            .constraint_index
+            .map(|index| index.query(region, from, until))
+            .unwrap_or_default();
+
+        base.index()
+            .query(region, from, until)
+            .into_iter()
+            // Overlaid obstacles are found through the overlay index instead
+            .filter(move |i| !overlay.contains_key(i))
+            .map(move |i| &base[i])
+            .chain(
+                self.view
+                    .overlay_index
+                    .query(region, from, until)
+                    .into_iter()
+                    // Overlays only apply to obstacles of the base environment
+                    .filter(move |i| *i < base.len())
+                    .filter_map(move |i| overlay.get(&i)),
+            )
+            .chain(
+                constraint_hits
+                    .into_iter()
+                    .filter_map(move |i| constraints.get(i))
+                    .filter_map(move |constraint| {
+                        if let Some(mask) = self.view.mask {
+                            if constraint.mask == mask {
+                                return None;
+                            }
+                        }
+                        Some(&constraint.obstacle)
+                    }),
+            )
+            .filter(move |obs| obs.is_on_levels(self.levels))
+    }
+
+    fn blocked_zones(&self) -> &[BlockedZone] {
+        &self.view.base.zones
     }
 }
 
@@ -365,6 +708,12 @@ where
         Profile: 'a,
         Obstacle: 'a;
 
+    type NearbyObstacles<'a> = Env::NearbyObstacles<'a>
+    where
+        Env: 'a,
+        Profile: 'a,
+        Obstacle: 'a;
+
     fn agent_profile(&self) -> &Profile {
         self.as_ref().agent_profile()
     }
@@ -372,6 +721,19 @@ where
     fn obstacles<'a>(&'a self) -> Self::Obstacles<'a> {
         self.as_ref().obstacles()
     }
+
+    fn obstacles_near<'a>(
+        &'a self,
+        region: &BoundingBox,
+        from: TimePoint,
+        until: Option<TimePoint>,
+    ) -> Self::NearbyObstacles<'a> {
+        self.as_ref().obstacles_near(region, from, until)
+    }
+
+    fn blocked_zones(&self) -> &[BlockedZone] {
+        self.as_ref().blocked_zones()
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -478,11 +840,58 @@ impl CircularProfile {
     }
 }
 
+impl Fingerprinted for CircularProfile {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(
+            self.footprint_radius,
+            self.safety_buffer,
+            self.follow_buffer,
+        ))
+    }
+}
+
+/// Describes how much the footprint of an obstacle grows beyond its profile
+/// because its trajectory becomes less certain further into the future. This
+/// is meant for predicted trajectories, e.g. of people or of vehicles that are
+/// not being planned for, so that plans stay tight in the near term while
+/// keeping conservative margins in the long term.
+///
+/// The extra radius never grows after the trajectory has finished.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Uncertainty {
+    /// The radius grows at a constant rate (meters per second) from the start
+    /// of the trajectory, up to an optional limit (meters).
+    Growing { rate: f64, limit: Option<f64> },
+    /// An extra radius (meters) for each waypoint of the trajectory, which is
+    /// interpolated linearly between waypoints. Waypoints past the end of the
+    /// list use its last value.
+    PerWaypoint(Vec<f64>),
+}
+
 #[derive(Debug, Clone)]
 pub struct DynamicCircularObstacle<W: Waypoint> {
     profile: CircularProfile,
     trajectory: Option<Trajectory<W>>,
+    /// Boxed because most obstacles have no uncertainty, and obstacles are
+    /// passed around by value.
+    uncertainty: Option<Box<Uncertainty>>,
     bounding_box: Option<BoundingBox>,
+    level: Option<usize>,
+}
+
+impl<W: Waypoint> DynamicCircularObstacle<W> {
+    pub fn level(&self) -> Option<usize> {
+        self.level
+    }
+
+    /// Check whether the obstacle is present on any of the given levels. Any
+    /// obstacle is present when the levels are not known.
+    pub fn is_on_levels(&self, levels: Option<(usize, usize)>) -> bool {
+        match (self.level, levels) {
+            (Some(level), Some((from, to))) => level == from || level == to,
+            _ => true,
+        }
+    }
 }
 
 impl<W: Waypoint + Into<WaypointR2>> DynamicCircularObstacle<W> {
@@ -490,18 +899,27 @@ impl<W: Waypoint + Into<WaypointR2>> DynamicCircularObstacle<W> {
         Self {
             profile,
             trajectory: None,
+            uncertainty: None,
             bounding_box: None,
+            level: None,
         }
     }
 
-    pub fn with_trajectory(self, trajectory: Option<Trajectory<W>>) -> Self {
-        Self {
-            bounding_box: trajectory
-                .as_ref()
-                .map(|t| BoundingBox::for_trajectory(&self.profile, t)),
-            profile: self.profile,
-            trajectory,
-        }
+    pub fn with_trajectory(mut self, trajectory: Option<Trajectory<W>>) -> Self {
+        self.set_trajectory(trajectory);
+        self
+    }
+
+    pub fn with_uncertainty(mut self, uncertainty: Option<Uncertainty>) -> Self {
+        self.set_uncertainty(uncertainty);
+        self
+    }
+
+    /// Put the obstacle on one level, e.g. one floor of a building. An
+    /// obstacle without a level is present on every level.
+    pub fn with_level(mut self, level: Option<usize>) -> Self {
+        self.level = level;
+        self
     }
 
     pub fn profile(&self) -> &CircularProfile {
@@ -510,10 +928,7 @@ impl<W: Waypoint + Into<WaypointR2>> DynamicCircularObstacle<W> {
 
     pub fn set_profile(&mut self, profile: CircularProfile) {
         self.profile = profile;
-        self.bounding_box = self
-            .trajectory
-            .as_ref()
-            .map(|t| BoundingBox::for_trajectory(&self.profile, t));
+        self.bounding_box = self.compute_bounding_box();
     }
 
     pub fn trajectory(&self) -> Option<&Trajectory<W>> {
@@ -522,15 +937,130 @@ impl<W: Waypoint + Into<WaypointR2>> DynamicCircularObstacle<W> {
 
     pub fn set_trajectory(&mut self, trajectory: Option<Trajectory<W>>) {
         self.trajectory = trajectory;
-        self.bounding_box = self
-            .trajectory
-            .as_ref()
-            .map(|t| BoundingBox::for_trajectory(&self.profile, t));
+        self.bounding_box = self.compute_bounding_box();
+    }
+
+    pub fn uncertainty(&self) -> Option<&Uncertainty> {
+        self.uncertainty.as_deref()
+    }
+
+    pub fn set_uncertainty(&mut self, uncertainty: Option<Uncertainty>) {
+        self.uncertainty = uncertainty.map(Box::new);
+        self.bounding_box = self.compute_bounding_box();
     }
 
+    /// The bounding box of the whole trajectory, including the largest radius
+    /// that the uncertainty of the obstacle gives it.
     pub fn bounding_box(&self) -> Option<&BoundingBox> {
         self.bounding_box.as_ref()
     }
+
+    /// Get how much the uncertainty of the obstacle adds to its footprint
+    /// radius at a certain time.
+    pub fn extra_radius_at(&self, time: TimePoint) -> f64 {
+        let (Some(uncertainty), Some(trajectory)) = (self.uncertainty(), &self.trajectory) else {
+            return 0.0;
+        };
+
+        let extra = match uncertainty {
+            Uncertainty::Growing { rate, limit } => {
+                let t0 = trajectory.initial_motion_time();
+                let t = time.max(t0).min(trajectory.finish_motion_time());
+                let extra = rate * (t - t0).as_secs_f64();
+                limit.map(|limit| extra.min(limit)).unwrap_or(extra)
+            }
+            Uncertainty::PerWaypoint(radii) => {
+                let radius_of = |i: usize| radii.get(i).or(radii.last()).copied().unwrap_or(0.0);
+                let mut extra = radius_of(0);
+                for (i, [wp0, wp1]) in trajectory.iter().pairs().enumerate() {
+                    let wp0: WaypointR2 = wp0.into();
+                    let wp1: WaypointR2 = wp1.into();
+                    if time < wp0.time {
+                        break;
+                    }
+
+                    if wp1.time <= time {
+                        extra = radius_of(i + 1);
+                        continue;
+                    }
+
+                    let s = (time - wp0.time).as_secs_f64() / (wp1.time - wp0.time).as_secs_f64();
+                    extra = radius_of(i) + s * (radius_of(i + 1) - radius_of(i));
+                    break;
+                }
+                extra
+            }
+        };
+
+        extra.max(0.0)
+    }
+
+    /// Get the profile of the obstacle at a certain time, with its footprint
+    /// grown by its uncertainty.
+    pub fn profile_at(&self, time: TimePoint) -> CircularProfile {
+        self.grown_profile(self.extra_radius_at(time))
+    }
+
+    /// Get the largest profile of the obstacle between two times. Use this
+    /// for a segment of the trajectory of the obstacle: the radius only
+    /// changes linearly within a segment, so it is largest at one of the ends.
+    pub fn profile_between(&self, t0: TimePoint, t1: TimePoint) -> CircularProfile {
+        if self.uncertainty.is_none() {
+            return self.profile;
+        }
+
+        self.grown_profile(f64::max(self.extra_radius_at(t0), self.extra_radius_at(t1)))
+    }
+
+    fn grown_profile(&self, extra: f64) -> CircularProfile {
+        if extra <= 0.0 {
+            return self.profile;
+        }
+
+        self.profile
+            .with_footprint_radius(self.profile.footprint_radius() + extra)
+            .unwrap_or(self.profile)
+    }
+
+    fn max_extra_radius(&self) -> f64 {
+        let Some(trajectory) = &self.trajectory else {
+            return 0.0;
+        };
+
+        match self.uncertainty() {
+            None => 0.0,
+            // A growing radius is largest once the trajectory has finished
+            Some(Uncertainty::Growing { .. }) => {
+                self.extra_radius_at(trajectory.finish_motion_time())
+            }
+            Some(Uncertainty::PerWaypoint(radii)) => radii
+                .iter()
+                .take(trajectory.len())
+                .fold(0.0, |a, b| f64::max(a, *b)),
+        }
+    }
+
+    fn compute_bounding_box(&self) -> Option<BoundingBox> {
+        let extra = self.max_extra_radius();
+        self.trajectory
+            .as_ref()
+            .map(|t| BoundingBox::for_trajectory(&self.profile, t).inflated_by(extra))
+    }
+}
+
+impl<W: Waypoint + Fingerprinted> Fingerprinted for DynamicCircularObstacle<W> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        // The bounding box follows from the other fields
+        fingerprint(&(
+            self.profile.fingerprint()?,
+            self.trajectory
+                .as_ref()
+                .map(|t| t.fingerprint())
+                .transpose()?,
+            &self.uncertainty,
+            self.level,
+        ))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -560,6 +1090,14 @@ impl BoundingBox {
         return true;
     }
 
+    pub fn min(&self) -> Point {
+        Point::from(self.min)
+    }
+
+    pub fn max(&self) -> Point {
+        Point::from(self.max)
+    }
+
     pub fn for_point(p: Point) -> Self {
         Self {
             min: p.coords,
@@ -0,0 +1,739 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::checkpoint::CheckpointError,
+    domain::{Cost, Fingerprinted, Informed, Key, Reversible},
+    error::{Anyhow, ThisError},
+    graph::{Edge, Graph, SharedGraph},
+    motion::{r2::Positioned, SpeedLimiter},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
+
+/// Version of the file format used by [`LandmarkTable::save`]. Files with a
+/// different version will be rejected by [`LandmarkTable::load`].
+pub const LANDMARK_TABLE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LandmarkSettings {
+    /// The speed (m/s) used to turn the length of each edge into a travel
+    /// time. This should be no less than the top speed of any agent that will
+    /// use the table, otherwise the estimates may not be admissible.
+    pub translational_speed: f64,
+    /// Stop exploring the graph once the travel time from a landmark exceeds
+    /// this limit. This must be set for graphs that are unbounded, such as an
+    /// [`crate::graph::occupancy::AccessibilityGraph`] over a sparse grid.
+    pub cost_limit: Option<f64>,
+}
+
+impl LandmarkSettings {
+    pub fn new(translational_speed: f64) -> Self {
+        Self {
+            translational_speed,
+            cost_limit: None,
+        }
+    }
+
+    pub fn with_cost_limit(mut self, cost_limit: Option<f64>) -> Self {
+        self.cost_limit = cost_limit;
+        self
+    }
+}
+
+/// How the landmarks of a table should be chosen.
+#[derive(Debug, Clone)]
+pub enum LandmarkSelection<K> {
+    /// Use exactly these vertices as landmarks.
+    Manual(Vec<K>),
+    /// Start from `seed` and repeatedly pick the vertex that is farthest from
+    /// all the landmarks chosen so far, until `count` landmarks are chosen or
+    /// there are no more vertices to choose from.
+    FarthestPoint { seed: K, count: usize },
+}
+
+/// Precomputed travel times between a set of landmark vertices and every other
+/// vertex of a graph. These are used by [`LandmarkHeuristic`] to give lower
+/// bounds on the travel time between any two vertices using the triangle
+/// inequality (the ALT technique).
+///
+/// Travel times only account for the edges given by
+/// [`Graph::edges_from_vertex`]. If a planner also uses lazy edges, the table
+/// should be built from a graph that includes those connections explicitly.
+///
+/// The table remembers the [`Fingerprinted::fingerprint`] of the graph it was
+/// built from, so a table that was saved for a map that has since changed will
+/// be rejected instead of giving inadmissible estimates.
+#[derive(Debug, Clone)]
+pub struct LandmarkTable<K> {
+    settings: LandmarkSettings,
+    /// Fingerprint of the graph that the table was built from
+    graph_fingerprint: u64,
+    landmarks: Vec<K>,
+    /// Travel time from each landmark to each vertex
+    from_landmark: Vec<HashMap<K, f64>>,
+    /// Travel time from each vertex to each landmark
+    to_landmark: Vec<HashMap<K, f64>>,
+}
+
+impl<K: Key + Clone> LandmarkTable<K> {
+    /// Build a table for the given landmarks.
+    pub fn build<G>(
+        graph: &G,
+        selection: LandmarkSelection<K>,
+        settings: LandmarkSettings,
+    ) -> Result<Self, LandmarkTableError>
+    where
+        G: Graph<Key = K> + Reversible + Fingerprinted,
+        G::ReversalError: Into<Anyhow>,
+        G::Vertex: Positioned,
+        G::EdgeAttributes: SpeedLimiter,
+    {
+        let graph_fingerprint = graph
+            .fingerprint()
+            .map_err(LandmarkTableError::Fingerprint)?;
+        let landmarks = match selection {
+            LandmarkSelection::Manual(landmarks) => landmarks,
+            LandmarkSelection::FarthestPoint { seed, count } => {
+                select_farthest_landmarks(graph, seed, count, &settings)
+            }
+        };
+
+        let reverse = graph
+            .reversed()
+            .map_err(|err| LandmarkTableError::Reversal(err.into()))?;
+        let mut from_landmark = Vec::new();
+        let mut to_landmark = Vec::new();
+        for landmark in &landmarks {
+            from_landmark.push(travel_times(graph, [landmark.clone()], &settings));
+            to_landmark.push(travel_times(&reverse, [landmark.clone()], &settings));
+        }
+
+        Ok(Self {
+            settings,
+            graph_fingerprint,
+            landmarks,
+            from_landmark,
+            to_landmark,
+        })
+    }
+
+    pub fn landmarks(&self) -> &[K] {
+        &self.landmarks
+    }
+
+    pub fn settings(&self) -> &LandmarkSettings {
+        &self.settings
+    }
+
+    /// Fingerprint of the graph that this table was built from.
+    pub fn graph_fingerprint(&self) -> u64 {
+        self.graph_fingerprint
+    }
+
+    /// Get a lower bound on the travel time (in seconds) from one vertex to
+    /// another. Landmarks that cannot reach or be reached by both vertices are
+    /// skipped, so this will return zero when no landmark is informative.
+    pub fn estimate(&self, from: &K, to: &K) -> f64 {
+        let mut best: f64 = 0.0;
+        for (from_l, to_l) in self.from_landmark.iter().zip(&self.to_landmark) {
+            // d(L, to) <= d(L, from) + d(from, to)
+            if let (Some(l_from), Some(l_to)) = (from_l.get(from), from_l.get(to)) {
+                best = best.max(l_to - l_from);
+            }
+
+            // d(from, L) <= d(from, to) + d(to, L)
+            if let (Some(from_l), Some(to_l)) = (to_l.get(from), to_l.get(to)) {
+                best = best.max(from_l - to_l);
+            }
+        }
+
+        best
+    }
+
+    /// Check that this table was built from a graph with the same fingerprint
+    /// as this one. Graphs whose fingerprint cannot be calculated are never
+    /// considered consistent.
+    pub fn is_consistent_with<G>(&self, graph: &G) -> bool
+    where
+        G: Graph<Key = K> + Fingerprinted,
+    {
+        graph
+            .fingerprint()
+            .is_ok_and(|fingerprint| fingerprint == self.graph_fingerprint)
+    }
+
+    /// Save the table to a file so it can be reused for later queries on the
+    /// same map.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LandmarkTableError>
+    where
+        K: Serialize,
+    {
+        let file = LandmarkTableFile {
+            version: LANDMARK_TABLE_VERSION,
+            settings: self.settings,
+            graph_fingerprint: self.graph_fingerprint,
+            landmarks: self.landmarks.clone(),
+            from_landmark: self.from_landmark.iter().map(flatten_times).collect(),
+            to_landmark: self.to_landmark.iter().map(flatten_times).collect(),
+        };
+
+        let writer = std::fs::File::create(path).map_err(LandmarkTableError::Io)?;
+        serde_yaml::to_writer(writer, &file).map_err(LandmarkTableError::Serialization)
+    }
+
+    /// Load a table that was saved with [`LandmarkTable::save`]. This will fail
+    /// if the table was built from a graph with a different fingerprint than
+    /// the graph it will be used with.
+    pub fn load<G>(path: impl AsRef<Path>, graph: &G) -> Result<Self, LandmarkTableError>
+    where
+        K: DeserializeOwned,
+        G: Graph<Key = K> + Fingerprinted,
+    {
+        let reader = std::fs::File::open(path).map_err(LandmarkTableError::Io)?;
+        let file: LandmarkTableFile<K> =
+            serde_yaml::from_reader(reader).map_err(LandmarkTableError::Serialization)?;
+        if file.version != LANDMARK_TABLE_VERSION {
+            return Err(LandmarkTableError::UnsupportedVersion(file.version));
+        }
+
+        let num_landmarks = file.landmarks.len();
+        if file.from_landmark.len() != num_landmarks || file.to_landmark.len() != num_landmarks {
+            return Err(LandmarkTableError::Malformed);
+        }
+
+        let fingerprint = graph
+            .fingerprint()
+            .map_err(LandmarkTableError::Fingerprint)?;
+        if file.graph_fingerprint != fingerprint {
+            return Err(LandmarkTableError::Inconsistent {
+                found: file.graph_fingerprint,
+                expected: fingerprint,
+            });
+        }
+
+        Ok(Self {
+            settings: file.settings,
+            graph_fingerprint: file.graph_fingerprint,
+            landmarks: file.landmarks,
+            from_landmark: file
+                .from_landmark
+                .into_iter()
+                .map(|t| t.into_iter().collect())
+                .collect(),
+            to_landmark: file
+                .to_landmark
+                .into_iter()
+                .map(|t| t.into_iter().collect())
+                .collect(),
+        })
+    }
+}
+
+fn flatten_times<K: Clone>(times: &HashMap<K, f64>) -> Vec<(K, f64)> {
+    times.iter().map(|(k, t)| (k.clone(), *t)).collect()
+}
+
+#[derive(Serialize, Deserialize)]
+struct LandmarkTableFile<K> {
+    version: u32,
+    settings: LandmarkSettings,
+    graph_fingerprint: u64,
+    landmarks: Vec<K>,
+    from_landmark: Vec<Vec<(K, f64)>>,
+    to_landmark: Vec<Vec<(K, f64)>>,
+}
+
+#[derive(ThisError, Debug)]
+pub enum LandmarkTableError {
+    #[error("Unable to access the landmark table file:\n{0}")]
+    Io(std::io::Error),
+    #[error("Unable to serialize or deserialize the landmark table:\n{0}")]
+    Serialization(serde_yaml::Error),
+    #[error("The landmark table file has unsupported version {0}")]
+    UnsupportedVersion(u32),
+    #[error("The landmark table file is malformed")]
+    Malformed,
+    #[error(
+        "The landmark table was built for a different graph \
+    (fingerprint [{found:016x}] instead of [{expected:016x}])"
+    )]
+    Inconsistent { found: u64, expected: u64 },
+    #[error("Unable to fingerprint the graph:\n{0}")]
+    Fingerprint(CheckpointError),
+    #[error("Unable to reverse the graph:\n{0}")]
+    Reversal(Anyhow),
+}
+
+fn edge_travel_time<'a, G>(
+    graph: &'a G,
+    edge: &G::Edge<'a>,
+    settings: &LandmarkSettings,
+) -> Option<f64>
+where
+    G: Graph,
+    G::Vertex: Positioned,
+    G::EdgeAttributes: SpeedLimiter,
+{
+    let v0 = graph.vertex(edge.from_vertex())?;
+    let v1 = graph.vertex(edge.to_vertex())?;
+    let (v0, v1): (&G::Vertex, &G::Vertex) = (v0.borrow(), v1.borrow());
+    let (p0, p1) = (v0.point(), v1.point());
+    let attributes = edge.attributes();
+    let speed = attributes
+        .speed_limit()
+        .map(|s| s.min(settings.translational_speed))
+        .unwrap_or(settings.translational_speed);
+    let delay = attributes.traversal_delay().unwrap_or(0.0);
+    let distance = (p1 - p0).norm() + attributes.extra_distance().unwrap_or(0.0);
+    Some(distance / speed + delay)
+}
+
+/// Dijkstra search that finds the travel time from the nearest of the sources
+/// to every vertex that can be reached within the cost limit.
+fn travel_times<G>(
+    graph: &G,
+    sources: impl IntoIterator<Item = G::Key>,
+    settings: &LandmarkSettings,
+) -> HashMap<G::Key, f64>
+where
+    G: Graph,
+    G::Key: Key + Clone,
+    G::Vertex: Positioned,
+    G::EdgeAttributes: SpeedLimiter,
+{
+    let mut times: HashMap<G::Key, f64> = HashMap::new();
+    let mut closed: HashSet<G::Key> = HashSet::new();
+    // Keys are not required to be ordered, so the queue refers to them by
+    // their index in this arena.
+    let mut arena: Vec<G::Key> = Vec::new();
+    let mut queue = BinaryHeap::new();
+    for source in sources {
+        if graph.vertex(&source).is_none() {
+            continue;
+        }
+
+        times.insert(source.clone(), 0.0);
+        queue.push(Reverse((Cost(0.0), arena.len())));
+        arena.push(source);
+    }
+
+    while let Some(Reverse((Cost(time), index))) = queue.pop() {
+        let key = arena[index].clone();
+        if !closed.insert(key.clone()) {
+            continue;
+        }
+
+        for edge in graph.edges_from_vertex(&key) {
+            let next = edge.to_vertex();
+            if closed.contains(next) {
+                continue;
+            }
+
+            let next_time = match edge_travel_time(graph, &edge, settings) {
+                Some(t) => time + t,
+                None => continue,
+            };
+
+            if settings
+                .cost_limit
+                .filter(|limit| next_time > *limit)
+                .is_some()
+            {
+                continue;
+            }
+
+            if times.get(next).map(|t| next_time < *t).unwrap_or(true) {
+                times.insert(next.clone(), next_time);
+                queue.push(Reverse((Cost(next_time), arena.len())));
+                arena.push(next.clone());
+            }
+        }
+    }
+
+    times
+}
+
+/// Choose landmarks by starting from the seed and repeatedly picking the
+/// vertex that is farthest from every landmark chosen so far.
+pub fn select_farthest_landmarks<G>(
+    graph: &G,
+    seed: G::Key,
+    count: usize,
+    settings: &LandmarkSettings,
+) -> Vec<G::Key>
+where
+    G: Graph,
+    G::Key: Key + Clone,
+    G::Vertex: Positioned,
+    G::EdgeAttributes: SpeedLimiter,
+{
+    let mut landmarks: Vec<G::Key> = Vec::new();
+    while landmarks.len() < count {
+        let sources = if landmarks.is_empty() {
+            vec![seed.clone()]
+        } else {
+            landmarks.clone()
+        };
+
+        let farthest = travel_times(graph, sources, settings)
+            .into_iter()
+            .filter(|(k, _)| !landmarks.contains(k))
+            .max_by_key(|(_, t)| Cost(*t));
+
+        match farthest {
+            Some((k, t)) if t > 0.0 || landmarks.is_empty() => landmarks.push(k),
+            _ => break,
+        }
+    }
+
+    landmarks
+}
+
+/// An admissible heuristic that uses a [`LandmarkTable`] to estimate the
+/// remaining travel time to a goal. The estimate is multiplied by
+/// `time_weight` to turn it into a cost, so it pairs naturally with
+/// [`crate::motion::TravelTimeCost`] or the `time` weight of
+/// [`crate::motion::TravelEffortCost`].
+///
+/// The table belongs to one specific graph, identified by its fingerprint. Use
+/// [`LandmarkHeuristic::set_graph`] whenever the [`SharedGraph`] is modified so
+/// that the table gets rebuilt if the graph really changed.
+pub struct LandmarkHeuristic<G: Graph> {
+    graph: SharedGraph<G>,
+    /// Fingerprint of the graph, which every lookup compares against the table
+    graph_fingerprint: u64,
+    table: Arc<LandmarkTable<G::Key>>,
+    selection: LandmarkSelection<G::Key>,
+    time_weight: f64,
+}
+
+impl<G: Graph> Clone for LandmarkHeuristic<G>
+where
+    G::Key: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            graph: self.graph.clone(),
+            graph_fingerprint: self.graph_fingerprint,
+            table: self.table.clone(),
+            selection: self.selection.clone(),
+            time_weight: self.time_weight,
+        }
+    }
+}
+
+impl<G> LandmarkHeuristic<G>
+where
+    G: Graph + Reversible + Fingerprinted,
+    <SharedGraph<G> as Reversible>::ReversalError: Into<Anyhow>,
+    G::Key: Key + Clone,
+    G::Vertex: Positioned,
+    G::EdgeAttributes: SpeedLimiter,
+{
+    /// Use a table that was built earlier, e.g. one loaded from disk. This
+    /// will fail if the table was built for a graph with a different
+    /// fingerprint.
+    pub fn new(
+        graph: SharedGraph<G>,
+        table: LandmarkTable<G::Key>,
+    ) -> Result<Self, LandmarkTableError> {
+        let graph_fingerprint = graph
+            .fingerprint()
+            .map_err(LandmarkTableError::Fingerprint)?;
+        if table.graph_fingerprint != graph_fingerprint {
+            return Err(LandmarkTableError::Inconsistent {
+                found: table.graph_fingerprint,
+                expected: graph_fingerprint,
+            });
+        }
+
+        Ok(Self {
+            graph,
+            graph_fingerprint,
+            selection: LandmarkSelection::Manual(table.landmarks.clone()),
+            table: Arc::new(table),
+            time_weight: 1.0,
+        })
+    }
+
+    /// Build a new table for the graph.
+    pub fn build(
+        graph: SharedGraph<G>,
+        selection: LandmarkSelection<G::Key>,
+        settings: LandmarkSettings,
+    ) -> Result<Self, LandmarkTableError> {
+        let table = LandmarkTable::build(&graph, selection.clone(), settings)?;
+        Ok(Self {
+            graph,
+            graph_fingerprint: table.graph_fingerprint,
+            table: Arc::new(table),
+            selection,
+            time_weight: 1.0,
+        })
+    }
+
+    pub fn with_time_weight(mut self, time_weight: f64) -> Self {
+        self.time_weight = time_weight;
+        self
+    }
+
+    pub fn time_weight(&self) -> f64 {
+        self.time_weight
+    }
+
+    pub fn table(&self) -> &Arc<LandmarkTable<G::Key>> {
+        &self.table
+    }
+
+    pub fn graph(&self) -> &SharedGraph<G> {
+        &self.graph
+    }
+
+    /// Check whether the table was built for this graph. Graphs that share
+    /// the same underlying graph are accepted right away, otherwise their
+    /// fingerprints are compared.
+    pub fn is_valid_for(&self, graph: &SharedGraph<G>) -> bool {
+        SharedGraph::ptr_eq(&self.graph, graph) || self.table.is_consistent_with(graph)
+    }
+
+    /// Change the graph that this heuristic is used with. If the graph does
+    /// not match the table, the table is rebuilt using the same landmark
+    /// selection and settings.
+    pub fn set_graph(&mut self, graph: SharedGraph<G>) -> Result<(), LandmarkTableError> {
+        if self.is_valid_for(&graph) {
+            self.graph = graph;
+            return Ok(());
+        }
+
+        let table = LandmarkTable::build(&graph, self.selection.clone(), self.table.settings)?;
+        self.graph = graph;
+        self.graph_fingerprint = table.graph_fingerprint;
+        self.table = Arc::new(table);
+        Ok(())
+    }
+}
+
+impl<G, State, Goal> Informed<State, Goal> for LandmarkHeuristic<G>
+where
+    G: Graph,
+    G::Key: Key + Clone,
+    State: Borrow<G::Key>,
+    Goal: Borrow<G::Key>,
+{
+    type CostEstimate = Cost<f64>;
+    type InformedError = LandmarkTableError;
+    fn estimate_remaining_cost(
+        &self,
+        from_state: &State,
+        to_goal: &Goal,
+    ) -> Result<Option<Self::CostEstimate>, Self::InformedError> {
+        if self.table.graph_fingerprint != self.graph_fingerprint {
+            return Err(LandmarkTableError::Inconsistent {
+                found: self.table.graph_fingerprint,
+                expected: self.graph_fingerprint,
+            });
+        }
+
+        let estimate = self.table.estimate(from_state.borrow(), to_goal.borrow());
+        Ok(Some(Cost(self.time_weight * estimate)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::AStar,
+        domain::KeyedCloser,
+        graph::SimpleGraph,
+        motion::{
+            r2::{DiscreteSpaceTimeR2, InitializeR2, LineFollow, Point, Position},
+            SpeedLimit, TravelTimeCost,
+        },
+        templates::{GraphMotion, InformedSearch},
+        Planner,
+    };
+    use approx::assert_relative_eq;
+
+    fn make_line_graph(length: usize) -> SimpleGraph<Point, ()> {
+        SimpleGraph::from_iters(
+            (0..length).map(|i| Point::new(i as f64, 0.0)),
+            (1..length).flat_map(|i| [(i - 1, i, ()), (i, i - 1, ())]),
+        )
+    }
+
+    #[test]
+    fn test_farthest_point_landmarks() {
+        let graph = SharedGraph::new(make_line_graph(10));
+        let heuristic = LandmarkHeuristic::build(
+            graph,
+            LandmarkSelection::FarthestPoint { seed: 3, count: 2 },
+            LandmarkSettings::new(2.0),
+        )
+        .unwrap();
+
+        assert_eq!(heuristic.table().landmarks(), &[9, 0]);
+
+        // On a line graph the landmarks at the ends give exact estimates.
+        let estimate = heuristic.estimate_remaining_cost(&2usize, &7usize).unwrap();
+        assert_relative_eq!(estimate.unwrap().0, 5.0 / 2.0);
+        let estimate = heuristic.estimate_remaining_cost(&7usize, &2usize).unwrap();
+        assert_relative_eq!(estimate.unwrap().0, 5.0 / 2.0);
+    }
+
+    #[test]
+    fn test_landmark_table_persistence() {
+        let graph = SharedGraph::new(make_line_graph(5));
+        let table = LandmarkTable::build(
+            &graph,
+            LandmarkSelection::Manual(vec![0]),
+            LandmarkSettings::new(1.0),
+        )
+        .unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "mapf_landmark_table_test_{}.yaml",
+            std::process::id()
+        ));
+        table.save(&path).unwrap();
+        let loaded = LandmarkTable::load(&path, &graph).unwrap();
+
+        assert_eq!(loaded.landmarks(), &[0]);
+        assert_relative_eq!(loaded.estimate(&1, &4), 3.0);
+
+        let mut heuristic = LandmarkHeuristic::new(graph.clone(), loaded).unwrap();
+        assert!(heuristic.is_valid_for(&graph));
+
+        // An identical graph that was built separately can keep the table.
+        let rebuilt = SharedGraph::new(make_line_graph(5));
+        assert!(heuristic.is_valid_for(&rebuilt));
+        let table = heuristic.table().clone();
+        heuristic.set_graph(rebuilt).unwrap();
+        assert!(Arc::ptr_eq(&table, heuristic.table()));
+
+        // Moving vertex 1 closer to the landmark makes the table stale.
+        let changed = graph
+            .clone()
+            .modify::<_, ()>(|mut g| {
+                g.vertices[1] = Point::new(0.5, 0.0);
+                Ok(g)
+            })
+            .unwrap();
+        assert!(!heuristic.is_valid_for(&changed));
+        assert!(!heuristic.table().is_consistent_with(&changed));
+        assert!(matches!(
+            LandmarkTable::load(&path, &changed),
+            Err(LandmarkTableError::Inconsistent { .. })
+        ));
+        assert!(LandmarkHeuristic::new(changed.clone(), (**heuristic.table()).clone()).is_err());
+        std::fs::remove_file(&path).ok();
+
+        heuristic.set_graph(changed.clone()).unwrap();
+        assert!(heuristic.is_valid_for(&changed));
+        assert_relative_eq!(heuristic.table().estimate(&1, &4), 3.5);
+    }
+
+    #[test]
+    fn test_landmark_search_matches_plain_a_star() {
+        /*
+         * A wall with a single gap at the top forces a detour that a
+         * straight-line heuristic badly underestimates.
+         *
+         * (0,4)---(1,4)---(2,4)---(3,4)---(4,4)
+         *   |       |               |       |
+         *  ...     ...     wall    ...     ...
+         *   |       |               |       |
+         * (0,0)---(1,0)           (3,0)---(4,0)
+         */
+        let width = 5;
+        let height = 5;
+        let index = |x: usize, y: usize| y * width + x;
+        let is_wall = |x: usize, y: usize| x == 2 && y < height - 1;
+        let mut edges = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if is_wall(x, y) {
+                    continue;
+                }
+
+                for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                    if nx < width && ny < height && !is_wall(nx, ny) {
+                        edges.push((index(x, y), index(nx, ny), SpeedLimit(None)));
+                        edges.push((index(nx, ny), index(x, y), SpeedLimit(None)));
+                    }
+                }
+            }
+        }
+        let graph = SharedGraph::new(SimpleGraph::from_iters(
+            (0..height).flat_map(|y| (0..width).map(move |x| Position::new(x as f64, y as f64))),
+            edges,
+        ));
+
+        let line_follow = LineFollow::new(2.0).unwrap();
+        let plain = Planner::new(AStar(InformedSearch::new_r2(graph.clone(), line_follow)));
+        let landmarks = Planner::new(AStar(
+            InformedSearch::new(
+                GraphMotion {
+                    space: DiscreteSpaceTimeR2::<usize>::new(),
+                    graph: graph.clone(),
+                    extrapolator: line_follow,
+                },
+                TravelTimeCost(1.0),
+                LandmarkHeuristic::build(
+                    graph.clone(),
+                    LandmarkSelection::FarthestPoint {
+                        seed: index(0, 0),
+                        count: 3,
+                    },
+                    LandmarkSettings::new(2.0),
+                )
+                .unwrap(),
+                KeyedCloser(DiscreteSpaceTimeR2::<usize>::new()),
+            )
+            .with_initializer(InitializeR2(graph)),
+        ));
+
+        for (start, goal) in [
+            (index(0, 0), index(4, 0)),
+            (index(4, 0), index(1, 1)),
+            (index(0, 4), index(3, 0)),
+        ] {
+            let expected = plain
+                .plan(start, goal)
+                .unwrap()
+                .solve()
+                .unwrap()
+                .solution()
+                .unwrap();
+            let found = landmarks
+                .plan(start, goal)
+                .unwrap()
+                .solve()
+                .unwrap()
+                .solution()
+                .unwrap();
+            assert_relative_eq!(found.total_cost.0, expected.total_cost.0, epsilon = 1e-6);
+        }
+    }
+}
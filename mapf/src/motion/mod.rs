@@ -45,6 +45,18 @@ pub use speed_limit::*;
 pub mod conflict;
 pub use conflict::*;
 
+pub mod zone;
+pub use zone::{BlockedZone, ZoneShape};
+
+pub mod capacity;
+pub use capacity::{CapacityReservations, CapacityZone, Occupancy, Reservation};
+
+pub mod spatial_index;
+pub use spatial_index::SpatialIndex;
+
+pub mod landmark;
+pub use landmark::{LandmarkHeuristic, LandmarkSelection, LandmarkSettings, LandmarkTable};
+
 pub use time_point::{Duration, TimePoint};
 
 use crate::error::ThisError;
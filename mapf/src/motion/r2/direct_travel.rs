@@ -16,7 +16,10 @@
 */
 
 use crate::{
-    domain::{Extrapolator, Informed, Key, KeyedSpace, Reversible, SelfKey, Weighted},
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{
+        Extrapolator, Fingerprinted, Informed, Key, KeyedSpace, Reversible, SelfKey, Weighted,
+    },
     graph::Graph,
     motion::r2::{DiscreteSpaceTimeR2, LineFollow, LineFollowError, Position, StateR2, WaypointR2},
 };
@@ -32,6 +35,21 @@ pub struct DirectTravelHeuristic<G: Graph, W> {
     pub extrapolator: LineFollow,
 }
 
+impl<G, W> Fingerprinted for DirectTravelHeuristic<G, W>
+where
+    G: Graph + Fingerprinted,
+    W: Fingerprinted,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[
+            self.space.fingerprint()?,
+            self.graph.fingerprint()?,
+            self.weight.fingerprint()?,
+            self.extrapolator.fingerprint()?,
+        ])
+    }
+}
+
 impl<G, W, Goal> Informed<StateR2<G::Key>, Goal> for DirectTravelHeuristic<G, W>
 where
     G: Graph,
@@ -17,17 +17,18 @@
 
 use super::{Position, Positioned, WaypointR2};
 use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
     domain::{
         backtrack_times, flip_endpoint_times, Backtrack, ConflictAvoider, ExtrapolationProgress,
-        Extrapolator, IncrementalExtrapolator, Key, Reversible,
+        Extrapolator, Fingerprinted, IncrementalExtrapolator, Key, Reversible,
     },
     error::{NoError, ThisError},
     graph::Graph,
     motion::{
         self,
         conflict::{
-            compute_safe_arrival_path, compute_safe_linear_path_wait_hints, SafeAction,
-            WaitForObstacle,
+            compute_delayed_departure_path, compute_safe_arrival_path,
+            compute_safe_linear_path_wait_hints, SafeAction, WaitForObstacle,
         },
         se2, Duration, SafeArrivalTimes, SafeIntervalCache, SafeIntervalMotionError, SpeedLimiter,
     },
@@ -73,9 +74,9 @@ impl LineFollow {
         &self,
         from_waypoint: &WaypointR2,
         to_target: &Position,
-        speed_limit: Option<f64>,
+        guidance: &impl SpeedLimiter,
     ) -> Result<(ArrayVec<WaypointR2, 1>, WaypointR2), LineFollowError> {
-        let speed = if let Some(limit) = speed_limit {
+        let speed = if let Some(limit) = guidance.speed_limit() {
             if limit <= 0.0 {
                 return Err(LineFollowError::InvalidSpeedLimit(limit));
             }
@@ -92,6 +93,7 @@ impl LineFollow {
             return Ok((ArrayVec::new(), wp));
         }
 
+        let dx = dx + guidance.extra_distance().unwrap_or(0.0);
         let t = Duration::from_secs_f64(self.direction * dx / speed) + from_waypoint.time;
         let wp = WaypointR2::new(t, to_target.x, to_target.y);
         let extrap = ArrayVec::from_iter([wp]);
@@ -124,7 +126,7 @@ where
         Guidance: 'a,
         Key: 'a,
     {
-        Some(self.extrapolate_impl(from_state, &to_target.point(), with_guidance.speed_limit()))
+        Some(self.extrapolate_impl(from_state, &to_target.point(), with_guidance))
     }
 }
 
@@ -224,7 +226,7 @@ where
         let to_point = match self.extrapolate_impl(
             &from_point,
             &to_target.point(),
-            with_guidance.speed_limit(),
+            with_guidance,
         ) {
             Ok(extrapolation) => extrapolation.1,
             Err(err) => {
@@ -252,6 +254,8 @@ where
             None
         };
         let environment_view = safe_intervals.environment().view_for(motion_key.as_ref());
+        let capacity = safe_intervals.environment().capacity();
+        let mask = safe_intervals.environment().mask();
 
         safe_arrival_times.retain(|t| *t >= to_point.time);
         // Add the time when the agent would normally arrive at the vertex.
@@ -263,13 +267,33 @@ where
         let paths: SmallVec<[_; 5]> = safe_arrival_times
             .into_iter()
             .filter_map(move |arrival_time| {
-                compute_safe_arrival_path(
+                let has_room = |path: &SmallVec<[SafeAction<WaypointR2, WaitForObstacle>; 5]>| {
+                    capacity.has_room_for_actions(
+                        (from_key, target_key),
+                        from_point.time,
+                        path,
+                        mask,
+                    )
+                };
+
+                match compute_safe_arrival_path(
                     from_point,
                     to_point,
                     arrival_time,
                     &ranked_hints,
                     &environment_view,
-                )
+                ) {
+                    Some(path) if has_room(&path) => Some(path),
+                    // Wait at the start until there is room to enter the
+                    // capacity zones of the target.
+                    _ => compute_delayed_departure_path(
+                        from_point,
+                        to_point,
+                        arrival_time,
+                        &environment_view,
+                    )
+                    .filter(has_room),
+                }
             })
             .map(move |action| {
                 // TODO(@mxgrey): Remove these unwraps before targeting
@@ -290,6 +314,12 @@ pub enum LineFollowError {
     InvalidSpeedLimit(f64),
 }
 
+impl Fingerprinted for LineFollow {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(self.speed, self.direction, self.distance_threshold))
+    }
+}
+
 impl Reversible for LineFollow {
     type ReversalError = NoError;
 
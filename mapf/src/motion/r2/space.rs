@@ -16,7 +16,10 @@
 */
 
 use crate::{
-    domain::{Initializable, Key, Keyed, KeyedSpace, Keyring, Reversible, SelfKey, Space},
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{
+        Fingerprinted, Initializable, Key, Keyed, KeyedSpace, Keyring, Reversible, SelfKey, Space,
+    },
     error::{NoError, ThisError},
     graph::Graph,
     motion::{
@@ -35,6 +38,12 @@ impl<Key> DiscreteSpaceTimeR2<Key> {
     }
 }
 
+impl<Key> Fingerprinted for DiscreteSpaceTimeR2<Key> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&"discrete_space_time_r2")
+    }
+}
+
 impl<Key> Clone for DiscreteSpaceTimeR2<Key> {
     fn clone(&self) -> Self {
         Self::new()
@@ -222,6 +231,12 @@ impl<K> From<K> for StartR2<K> {
 #[derive(Debug, Clone)]
 pub struct InitializeR2<G>(pub G);
 
+impl<G: Fingerprinted> Fingerprinted for InitializeR2<G> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        self.0.fingerprint()
+    }
+}
+
 impl<G, Start, Goal> Initializable<Start, Goal, StateR2<G::Key>> for InitializeR2<G>
 where
     G: Graph,
@@ -16,7 +16,8 @@
 */
 
 use crate::{
-    domain::{Closable, CloseResult, ClosedSet, ClosedStatus, Key, Keyed, Keyring},
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{Closable, CloseResult, ClosedSet, ClosedStatus, Fingerprinted, Key, Keyed, Keyring},
     error::ThisError,
     graph::Graph,
     motion::{
@@ -25,8 +26,9 @@ use crate::{
         se2::WaypointSE2,
         CcbsEnvironment, SafeArrivalTimes, TimePoint, Timed,
     },
-    util::Minimum,
+    util::{get_or_compute, Minimum},
 };
+use serde::Serialize;
 use smallvec::SmallVec;
 use std::{
     borrow::Borrow,
@@ -53,6 +55,18 @@ impl<G: Graph> SafeIntervalCache<G> {
             }
         }
 
+        for zone in &environment.base().zones {
+            // A zone that never opens does not introduce any safe intervals,
+            // it only blocks motions.
+            if let Some(opens) = zone.opens() {
+                earliest_time.consider(&zone.closes().unwrap_or(opens));
+            }
+        }
+
+        for reservation in environment.capacity().reservations() {
+            earliest_time.consider(&reservation.occupancy.begin);
+        }
+
         let earliest_time = earliest_time.result();
         Self {
             environment,
@@ -83,38 +97,59 @@ impl<G: Graph> SafeIntervalCache<G> {
             None => return Ok(SafeArrivalTimes::new()),
         };
 
-        match self.safe_intervals.read() {
-            Ok(guard) => {
-                if let Some(times) = guard.get(key) {
-                    // We have already calculated the safe interval for this
-                    // key, so just give back the view.
-                    return Ok(times.clone());
+        get_or_compute(
+            &self.safe_intervals,
+            key.clone(),
+            || SafeIntervalCacheError::PoisonedMutex,
+            || {
+                // Calculate the safe intervals for this key.
+                let p = self
+                    .graph
+                    .vertex(key)
+                    .ok_or_else(|| SafeIntervalCacheError::MissingVertex(key.clone()))?
+                    .borrow()
+                    .point();
+
+                let wp = WaypointR2::new(earliest_time, p.x, p.y);
+                let ccbs_key = (key.clone(), key.clone());
+                let mut safe_arrivals =
+                    compute_safe_arrival_times(wp, &self.environment.view_for(Some(&ccbs_key)));
+
+                // The agent cannot arrive while the capacity zones of this key are
+                // full, but it may arrive as soon as one of them has room again.
+                let full_times = self
+                    .environment
+                    .capacity()
+                    .full_times(key, self.environment.mask());
+                if !full_times.is_empty() {
+                    safe_arrivals.retain(|t| !full_times.iter().any(|full| full.contains(*t)));
+                    for full in &full_times {
+                        if let Some(end) = full.end {
+                            if !full_times.iter().any(|other| other.contains(end)) {
+                                safe_arrivals.push(end);
+                            }
+                        }
+                    }
+                    safe_arrivals.sort();
+                    safe_arrivals.dedup();
                 }
-            }
-            Err(_) => return Err(SafeIntervalCacheError::PoisonedMutex),
-        }
 
-        // Calculate the safe intervals for this key.
-        let p = self
-            .graph
-            .vertex(key)
-            .ok_or_else(|| SafeIntervalCacheError::MissingVertex(key.clone()))?
-            .borrow()
-            .point();
-
-        let wp = WaypointR2::new(earliest_time, p.x, p.y);
-        let ccbs_key = (key.clone(), key.clone());
-        let safe_arrivals =
-            compute_safe_arrival_times(wp, &self.environment.view_for(Some(&ccbs_key)));
-        // let safe_arrivals =
-        //     compute_safe_arrival_times(WaypointR2::new(earliest_time, p.x, p.y), &self.environment);
-        match self.safe_intervals.write() {
-            Ok(mut guard) => {
-                guard.insert(key.clone(), safe_arrivals.clone());
-            }
-            Err(_) => return Err(SafeIntervalCacheError::PoisonedMutex),
-        }
-        Ok(safe_arrivals)
+                // let safe_arrivals =
+                //     compute_safe_arrival_times(WaypointR2::new(earliest_time, p.x, p.y), &self.environment);
+                Ok(safe_arrivals)
+            },
+        )
+    }
+}
+
+impl<G> Fingerprinted for SafeIntervalCache<G>
+where
+    G: Graph + Fingerprinted,
+    G::Key: Serialize,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        // The cached intervals and the earliest time follow from these
+        fingerprint(&[self.graph.fingerprint()?, self.environment.fingerprint()?])
     }
 }
 
@@ -141,6 +176,17 @@ impl<Ring, G: Graph> SafeIntervalCloser<Ring, G> {
     }
 }
 
+impl<Ring, G> Fingerprinted for SafeIntervalCloser<Ring, G>
+where
+    Ring: Fingerprinted,
+    G: Graph,
+    SafeIntervalCache<G>: Fingerprinted,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[self.keyring.fingerprint()?, self.cache.fingerprint()?])
+    }
+}
+
 impl<Ring, G, State> Closable<State> for SafeIntervalCloser<Ring, G>
 where
     Ring: Keyring<State> + Clone,
@@ -235,6 +281,13 @@ where
         }
     }
 
+    fn remove(&mut self, state: &State) -> Option<T> {
+        let key = self.keyring.key_for(state);
+        self.container
+            .get_mut(key.borrow().borrow())?
+            .remove(state.time())
+    }
+
     type ClosedSetIter<'a> = impl Iterator<Item=&'a T> + 'a
     where
         Self: 'a,
@@ -343,6 +396,13 @@ impl<T> ClosedIntervals<T> {
         prior.into()
     }
 
+    fn remove(&mut self, time: TimePoint) -> Option<T> {
+        match self.get_index(time) {
+            Some(index) => self.intervals.get_mut(index).unwrap().1.take(),
+            None => self.indefinite_start.take(),
+        }
+    }
+
     fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T> + 'a {
         self.indefinite_start.iter().chain(
             self.intervals
@@ -16,26 +16,29 @@
 */
 
 use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
     domain::{
         backtrack_times, flip_endpoint_times, Backtrack, ConflictAvoider, Connectable,
-        ExtrapolationProgress, Extrapolator, IncrementalExtrapolator, Key, Reversible,
+        ExtrapolationProgress, Extrapolator, Fingerprinted, IncrementalExtrapolator, Key,
+        Reversible,
     },
     error::{NoError, ThisError},
     graph::Graph,
     motion::{
         self,
         conflict::{
-            compute_safe_arrival_path, compute_safe_linear_path_wait_hints, is_safe_segment,
-            SafeAction, WaitForObstacle,
+            compute_delayed_departure_path, compute_safe_arrival_path,
+            compute_safe_linear_path_wait_hints, is_safe_segment, SafeAction, WaitForObstacle,
         },
         r2::{MaybePositioned, Positioned, WaypointR2},
         se2::{MaybeOriented, Orientation, Point, Position, StateSE2, WaypointSE2},
-        CcbsEnvironment, Duration, MaybeTimed, SafeArrivalTimes, SafeIntervalCache,
+        CcbsEnvironment, Duration, MaybeTimed, Occupancy, SafeArrivalTimes, SafeIntervalCache,
         SafeIntervalMotionError, SpeedLimiter, Timed,
     },
     util::ForkIter,
 };
 use arrayvec::ArrayVec;
+use serde::Serialize;
 use smallvec::SmallVec;
 use std::{borrow::Borrow, sync::Arc};
 use time_point::TimePoint;
@@ -58,6 +61,11 @@ pub struct DifferentialDriveLineFollow {
     /// If the initial waypoint is within this rotational threshold (in radians)
     /// then rotation may be skipped while extrapolating.
     rotational_threshold: f64,
+
+    /// Can the agent translate in any direction without first turning to face
+    /// it? A holonomic agent keeps its yaw while it translates, and only
+    /// rotates when a target asks for a specific orientation.
+    holonomic: bool,
 }
 
 impl DifferentialDriveLineFollow {
@@ -79,6 +87,7 @@ impl DifferentialDriveLineFollow {
             direction: 1.0,
             translational_threshold: motion::DEFAULT_TRANSLATIONAL_THRESHOLD,
             rotational_threshold: motion::DEFAULT_ROTATIONAL_THRESHOLD,
+            holonomic: false,
         });
     }
 
@@ -118,6 +127,13 @@ impl DifferentialDriveLineFollow {
         return Ok(());
     }
 
+    /// Set whether the agent is holonomic. A holonomic agent follows lines
+    /// through the plane without turning to face its direction of travel, so
+    /// the rotational speed is only used to reach the orientation of a target.
+    pub fn set_holonomic(&mut self, holonomic: bool) {
+        self.holonomic = holonomic;
+    }
+
     pub fn translational_speed(&self) -> f64 {
         return self.translational_speed;
     }
@@ -138,6 +154,10 @@ impl DifferentialDriveLineFollow {
         self.direction
     }
 
+    pub fn is_holonomic(&self) -> bool {
+        self.holonomic
+    }
+
     /// Helper function for the implementations of extrapolate(). Not meant for
     /// use by other functions
     pub(crate) fn move_towards_target(
@@ -163,7 +183,11 @@ impl DifferentialDriveLineFollow {
         let delta_p = self.direction * (*p1 - p0);
         let distance = delta_p.norm();
         if distance > self.translational_threshold {
-            let approach_yaw = Orientation::from_angle(f64::atan2(delta_p[1], delta_p[0]));
+            let approach_yaw = if self.holonomic {
+                from_waypoint.position.rotation
+            } else {
+                Orientation::from_angle(f64::atan2(delta_p[1], delta_p[0]))
+            };
             let delta_yaw_abs = (approach_yaw / from_waypoint.position.rotation)
                 .angle()
                 .abs();
@@ -182,13 +206,28 @@ impl DifferentialDriveLineFollow {
             }
 
             current_yaw = approach_yaw;
+            let distance = distance + speed_limiter.extra_distance().unwrap_or(0.0);
             current_time += time_point::Duration::from_secs_f64(
                 self.direction * distance / translational_speed,
             );
+            if let Some(delay) = speed_limiter.traversal_delay() {
+                current_time += time_point::Duration::from_secs_f64(self.direction * delay);
+            }
             output.push(WaypointSE2 {
                 time: current_time,
                 position: Position::new(p1.coords, approach_yaw.angle()),
             });
+        } else if let Some(delay) = speed_limiter.traversal_delay() {
+            // The edge does not move the agent through the plane, e.g. riding
+            // a lift between levels, so the delay is spent holding in place.
+            current_time += time_point::Duration::from_secs_f64(self.direction * delay);
+            output.push(WaypointSE2 {
+                time: current_time,
+                position: Position::from_parts(
+                    from_waypoint.position.translation,
+                    from_waypoint.position.rotation,
+                ),
+            });
         }
 
         return Ok(ReachedTarget {
@@ -421,6 +460,9 @@ where
             None
         };
         let environment_view = safe_intervals.environment().view_for(motion_key.as_ref());
+        let capacity = safe_intervals.environment().capacity();
+        let mask = safe_intervals.environment().mask();
+        let start_time = from_state.time;
 
         let target_point = to_target.point();
         let mut arrival = match self.move_towards_target(&from_state, &target_point, with_guidance)
@@ -464,13 +506,34 @@ where
         let paths: SmallVec<[_; 5]> = safe_arrival_times
             .into_iter()
             .filter_map(move |arrival_time| {
-                compute_safe_arrival_path(
+                let has_room = |path: &SmallVec<[SafeAction<WaypointR2, WaitForObstacle>; 5]>| {
+                    capacity.has_room_for_actions((from_key, target_key), start_time, path, mask)
+                };
+
+                match compute_safe_arrival_path(
                     from_point,
                     to_point,
                     arrival_time,
                     &ranked_hints,
                     &environment_view,
-                )
+                ) {
+                    Some(path) if has_room(&path) => Some(path),
+                    // Wait at the start until there is room to enter the
+                    // capacity zones of the target. The agent takes up room
+                    // in the target from the moment it departs, so also try
+                    // to depart at the arrival time.
+                    _ => [arrival_time, arrival_time + (to_point.time - from_point.time)]
+                        .into_iter()
+                        .find_map(|arrival_time| {
+                            compute_delayed_departure_path(
+                                from_point,
+                                to_point,
+                                arrival_time,
+                                &environment_view,
+                            )
+                            .filter(has_room)
+                        }),
+                }
             })
             .filter_map(move |action| {
                 let mut action: SmallVec<[SafeAction<WaypointSE2, WaitForObstacle>; 5]> = action
@@ -508,6 +571,13 @@ where
                             // so this is not a valid action.
                             return None;
                         }
+
+                        if let Some(target_key) = target_key {
+                            let rotating = Occupancy::new(arrival_wp.time, Some(final_wp.time));
+                            if !capacity.has_room(target_key, &rotating, mask) {
+                                return None;
+                            }
+                        }
                         action.push(SafeAction::Move(final_wp));
                     }
                 }
@@ -527,6 +597,19 @@ pub enum DifferentialDriveLineFollowError {
     InvalidSpeedLimit(f64),
 }
 
+impl Fingerprinted for DifferentialDriveLineFollow {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(
+            self.translational_speed,
+            self.rotational_speed,
+            self.direction,
+            self.translational_threshold,
+            self.rotational_threshold,
+            self.holonomic,
+        ))
+    }
+}
+
 impl Reversible for DifferentialDriveLineFollow {
     type ReversalError = NoError;
     fn reversed(&self) -> Result<Self, Self::ReversalError> {
@@ -540,6 +623,12 @@ impl Reversible for DifferentialDriveLineFollow {
 #[derive(Debug, Clone)]
 pub struct MergeIntoGoal<const R: u32>(pub DifferentialDriveLineFollow);
 
+impl<const R: u32> Fingerprinted for MergeIntoGoal<R> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(self.0.fingerprint()?, R))
+    }
+}
+
 impl<K, Target, Action, const R: u32> Connectable<StateSE2<K, R>, Action, Target>
     for MergeIntoGoal<R>
 where
@@ -639,6 +728,16 @@ impl<K, const R: u32> SafeMergeIntoGoal<K, R> {
     }
 }
 
+impl<K: Serialize, const R: u32> Fingerprinted for SafeMergeIntoGoal<K, R> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(
+            self.motion.fingerprint()?,
+            self.environment.fingerprint()?,
+            R,
+        ))
+    }
+}
+
 impl<K, Target, Action, const R: u32> Connectable<StateSE2<K, R>, Action, Target>
     for SafeMergeIntoGoal<K, R>
 where
@@ -734,4 +833,43 @@ mod tests {
         trajectory.insert(wp0).expect("Waypoint insertion failed");
         assert_eq!(trajectory.len(), 4);
     }
+
+    #[test]
+    fn test_holonomic_extrapolation() {
+        let t0 = time_point::TimePoint::from_secs_f64(3.0);
+        let wp0 = WaypointSE2::new(t0, 1.0, -3.0, -40f64.to_radians());
+        let mut movement = DifferentialDriveLineFollow::new(2.0, 3.0)
+            .expect("Failed to make DifferentialLineFollow");
+        movement.set_holonomic(true);
+
+        // The agent moves sideways without turning to face the target
+        let p_target = Point::new(1.0, 3.0);
+        let (waypoints, end) = movement
+            .extrapolate(&wp0, &p_target, &(), (Some(&0), Some(&1)))
+            .expect("Failed to extrapolate")
+            .expect("The extrapolation should have produced a path");
+        assert_eq!(waypoints.len(), 1);
+        assert_relative_eq!(
+            end.time.as_secs_f64(),
+            (t0 + time_point::Duration::from_secs_f64(6f64 / movement.translational_speed))
+                .as_secs_f64()
+        );
+        assert_relative_eq!(end.position.rotation.angle(), -40f64.to_radians());
+
+        // It only rotates to reach the orientation that the target asks for
+        let p_target = Position::new(Vector::new(1.0, 3.0), 60f64.to_radians());
+        let (waypoints, end) = movement
+            .extrapolate(&wp0, &p_target, &(), (Some(&0), Some(&1)))
+            .expect("Failed to extrapolate")
+            .expect("The extrapolation should have produced a path");
+        assert_eq!(waypoints.len(), 2);
+        assert_relative_eq!(
+            end.time.as_secs_f64(),
+            (t0 + time_point::Duration::from_secs_f64(
+                100f64.to_radians() / movement.rotational_speed()
+                    + 6f64 / movement.translational_speed
+            ))
+            .as_secs_f64()
+        );
+    }
 }
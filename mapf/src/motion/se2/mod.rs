@@ -52,3 +52,11 @@ pub use quickest_path::{QuickestPathHeuristic, QuickestPathPlanner};
 
 pub mod differential_drive_line_follow;
 pub use differential_drive_line_follow::*;
+
+pub mod sequence;
+pub use sequence::{
+    plan_sequence, SafeHoldSE2, SequenceSE2, SequenceState, SequentialGoalSE2, StopSE2,
+};
+
+pub mod region;
+pub use region::GoalRegionSE2;
@@ -16,9 +16,15 @@
 */
 
 use crate::{
-    algorithm::{BackwardDijkstra, Path},
-    domain::{Connectable, Extrapolator, Informed, Key, KeyedCloser, Reversible, Weighted},
+    algorithm::{
+        checkpoint::{fingerprint, CheckpointError},
+        BackwardDijkstra, Path,
+    },
+    domain::{
+        Connectable, Extrapolator, Fingerprinted, Informed, Key, KeyedCloser, Reversible, Weighted,
+    },
     error::{Anyhow, ThisError},
+    graph::Edge,
     motion::{
         r2::{
             DiscreteSpaceTimeR2, InitializeR2, LineFollow, MaybePositioned, Positioned, StateR2,
@@ -26,12 +32,14 @@ use crate::{
         },
         se2::{
             DifferentialDriveLineFollow, DifferentialDriveLineFollowError,
-            DifferentialDriveLineFollowMotion, KeySE2, MaybeOriented, MergeIntoGoal, StateSE2,
+            DifferentialDriveLineFollowMotion, GoalSE2, KeySE2, MaybeOriented, MergeIntoGoal,
+            StateSE2,
         },
         MaybeTimed, SpeedLimiter, TimePoint, Timed,
     },
     planner::halt::StepLimit,
     templates::{GraphMotion, LazyGraphMotion, UninformedSearch},
+    util::get_or_compute,
     Graph, Planner,
 };
 use arrayvec::ArrayVec;
@@ -67,11 +75,16 @@ where
     G::EdgeAttributes: SpeedLimiter + Clone,
 {
     planner: QuickestPathPlanner<G, WeightR2>,
+    /// The graph and weight that the planner was made with, kept so that the
+    /// heuristic can be fingerprinted.
+    graph: G,
+    weight_r2: WeightR2,
     extrapolator: DifferentialDriveLineFollow,
     weight_se2: WeightSE2,
     cost_cache: Arc<
         RwLock<HashMap<HeuristicKey<G::Key, R>, Option<CostCache<WeightSE2::Cost, G::Key, R>>>>,
     >,
+    region_cache: Arc<RegionCache<WeightSE2::Cost, G::Key, R>>,
 }
 
 #[derive(Clone, Copy)]
@@ -82,6 +95,15 @@ struct CostCache<Cost, Key, const R: u32> {
 
 type HeuristicKey<K, const R: u32> = (KeySE2<K, R>, K);
 
+type RegionKey<K, const R: u32> = (KeySE2<K, R>, Vec<K>);
+
+/// The reachable keys of a goal region, sorted from the lowest invariant cost
+/// to the highest.
+pub(crate) type RegionCandidates<Cost, K> = Arc<Vec<(Cost, K)>>;
+
+type RegionCache<Cost, K, const R: u32> =
+    RwLock<HashMap<RegionKey<K, R>, RegionCandidates<Cost, K>>>;
+
 impl<G, WeightR2, WeightSE2, const R: u32> QuickestPathHeuristic<G, WeightR2, WeightSE2, R>
 where
     WeightR2: Reversible,
@@ -98,7 +120,10 @@ where
         weight_r2: WeightR2,
         weight_se2: WeightSE2,
         extrapolator: DifferentialDriveLineFollow,
-    ) -> Result<Self, <QuickestPathSearch<G, WeightR2> as Reversible>::ReversalError> {
+    ) -> Result<Self, <QuickestPathSearch<G, WeightR2> as Reversible>::ReversalError>
+    where
+        WeightR2: Clone,
+    {
         let line_follow: LineFollow = extrapolator.into();
         let motion = GraphMotion {
             space: DiscreteSpaceTimeR2::new(),
@@ -111,7 +136,7 @@ where
             planner: Planner::new(Arc::new(BackwardDijkstra::new(
                 &UninformedSearch::new_uninformed(
                     motion.clone(),
-                    weight_r2,
+                    weight_r2.clone(),
                     KeyedCloser(DiscreteSpaceTimeR2::new()),
                 )
                 .with_initializer(InitializeR2(graph.clone()))
@@ -122,9 +147,12 @@ where
                 }),
             )?))
             .with_halting(step_limit),
+            graph,
+            weight_r2,
             extrapolator,
             weight_se2,
             cost_cache: Arc::new(RwLock::new(HashMap::new())),
+            region_cache: Arc::new(RwLock::new(HashMap::new())),
         })
     }
 
@@ -136,6 +164,22 @@ where
         self.planner.set_default_halting(limit);
     }
 
+    /// Get the attributes of the edge that leads from one vertex of the graph
+    /// to another, so that the speed limits and extra distances of the edges
+    /// along the quickest path are included in its cost.
+    fn edge_attributes(&self, from: &G::Key, to: &G::Key) -> Option<G::EdgeAttributes> {
+        if from == to {
+            return None;
+        }
+
+        self.graph
+            .edges_from_vertex(from)
+            .into_iter()
+            .chain(self.graph.lazy_edges_between(from, to))
+            .find(|edge| edge.to_vertex() == to)
+            .map(|edge| edge.attributes().clone())
+    }
+
     fn invariant_cost<State, Goal>(
         &self,
         from_state: &State,
@@ -158,84 +202,134 @@ where
         let start: &StateSE2<G::Key, R> = from_state.borrow();
         let goal: &G::Key = to_goal.borrow();
 
-        // First check if we've calculated this cost before
-        match self.cost_cache.read() {
-            Ok(cache) => match cache.get(&(start.key.clone(), goal.clone())) {
-                Some(cost) => return Ok(cost.clone()),
-                None => {}
-            },
-            Err(_) => return Err(QuickestPathHeuristicError::PoisonedMutex),
-        }
-
-        // The cost wasn't in the cache, so let's use the planner to find it.
-        let invariant = 'cost: {
-            let solution: Path<_, _, _> = match self
-                .planner
-                .plan(start.key.vertex.clone(), goal.clone())
-                .map_err(|err| QuickestPathHeuristicError::PlannerError(err.into()))?
-                .solve()
-                .map_err(|err| QuickestPathHeuristicError::PlannerError(err.into()))?
-                .solution()
-            {
-                Some(solution) => solution,
-                None => break 'cost None,
-            };
-
-            let mut cost = match self
-                .weight_se2
-                .initial_cost(start)
-                .map_err(|err| QuickestPathHeuristicError::BrokenWeight(err.into()))?
-            {
-                Some(cost) => cost,
-                None => break 'cost None,
-            };
-            let mut previous_state = start.clone();
-
-            for (_, child_state) in &solution.sequence {
-                // TODO(@mxgrey): Should we consider pulling information from
-                // the graph for the guidance argument?
-                let (action, child_wp) = match self.extrapolator.extrapolate(
-                    &previous_state.waypoint,
-                    &child_state.waypoint.position,
-                    &(),
-                    (Some(&start.key.vertex), Some(goal)),
-                ) {
-                    Some(wp) => wp.map_err(QuickestPathHeuristicError::Extrapolation)?,
-                    None => break 'cost None,
-                };
-
-                let child_state = StateSE2::new(child_state.key.clone(), child_wp);
-                let child_cost = match self
-                    .weight_se2
-                    .cost(&previous_state, &action, &child_state)
-                    .map_err(|err| QuickestPathHeuristicError::BrokenWeight(err.into()))?
-                {
-                    Some(cost) => cost,
-                    None => break 'cost None,
+        // Reuse the cost if it has been calculated before
+        get_or_compute(
+            self.cost_cache.as_ref(),
+            (start.key.clone(), goal.clone()),
+            || QuickestPathHeuristicError::PoisonedMutex,
+            || {
+                // The cost wasn't in the cache, so let's use the planner to find it.
+                let invariant = 'cost: {
+                    let solution: Path<_, _, _> = match self
+                        .planner
+                        .plan(start.key.vertex.clone(), goal.clone())
+                        .map_err(|err| QuickestPathHeuristicError::PlannerError(err.into()))?
+                        .solve()
+                        .map_err(|err| QuickestPathHeuristicError::PlannerError(err.into()))?
+                        .solution()
+                    {
+                        Some(solution) => solution,
+                        None => break 'cost None,
+                    };
+
+                    let mut cost = match self
+                        .weight_se2
+                        .initial_cost(start)
+                        .map_err(|err| QuickestPathHeuristicError::BrokenWeight(err.into()))?
+                    {
+                        Some(cost) => cost,
+                        None => break 'cost None,
+                    };
+                    let mut previous_state = start.clone();
+
+                    for (_, child_state) in &solution.sequence {
+                        let guidance =
+                            self.edge_attributes(&previous_state.key.vertex, &child_state.key);
+                        let (action, child_wp) = match self.extrapolator.extrapolate(
+                            &previous_state.waypoint,
+                            &child_state.waypoint.position,
+                            &guidance,
+                            (Some(&start.key.vertex), Some(goal)),
+                        ) {
+                            Some(wp) => wp.map_err(QuickestPathHeuristicError::Extrapolation)?,
+                            None => break 'cost None,
+                        };
+
+                        let child_state = StateSE2::new(child_state.key.clone(), child_wp);
+                        let child_cost = match self
+                            .weight_se2
+                            .cost(&previous_state, &action, &child_state)
+                            .map_err(|err| QuickestPathHeuristicError::BrokenWeight(err.into()))?
+                        {
+                            Some(cost) => cost,
+                            None => break 'cost None,
+                        };
+
+                        cost = cost + child_cost;
+                        previous_state = child_state;
+                    }
+
+                    // Shift the time of the final state to what it would be if the
+                    // start time had been zero.
+                    previous_state.waypoint.time =
+                        TimePoint::zero() + (previous_state.waypoint.time - start.waypoint.time);
+                    Some(CostCache {
+                        cost,
+                        arrival_state: previous_state,
+                    })
                 };
 
-                cost = cost + child_cost;
-                previous_state = child_state;
-            }
+                Ok(invariant)
+            },
+        )
+    }
 
-            // Shift the time of the final state to what it would be if the
-            // start time had been zero.
-            previous_state.waypoint.time =
-                TimePoint::zero() + (previous_state.waypoint.time - start.waypoint.time);
-            Some(CostCache {
-                cost,
-                arrival_state: previous_state,
-            })
-        };
+    /// Get the invariant cost of reaching each of the goal keys from the
+    /// vertex of the state, sorted from cheapest to most expensive. Keys that
+    /// cannot be reached are left out. The result is cached per start key, so
+    /// a region only gets measured once for each vertex that it is estimated
+    /// from.
+    pub(crate) fn region_candidates<State>(
+        &self,
+        from_state: &State,
+        goal_keys: &[G::Key],
+    ) -> Result<RegionCandidates<WeightSE2::Cost, G::Key>, QuickestPathHeuristicError>
+    where
+        WeightR2::Cost: Clone + Ord + Add<WeightR2::Cost, Output = WeightR2::Cost>,
+        WeightSE2::Cost: Clone + Ord + Add<Output = WeightSE2::Cost>,
+        WeightSE2::WeightedError: Into<Anyhow>,
+        State: Borrow<StateSE2<G::Key, R>>,
+    {
+        let start: &StateSE2<G::Key, R> = from_state.borrow();
+        get_or_compute(
+            self.region_cache.as_ref(),
+            (start.key.clone(), goal_keys.to_vec()),
+            || QuickestPathHeuristicError::PoisonedMutex,
+            || {
+                let mut candidates = Vec::new();
+                for key in goal_keys {
+                    let goal = GoalSE2::new(key.clone());
+                    if let Some(invariant) = self.invariant_cost(from_state, &goal)? {
+                        candidates.push((invariant.cost, key.clone()));
+                    }
+                }
 
-        match self.cost_cache.write() {
-            Ok(mut cache) => {
-                cache.insert((start.key.clone(), goal.clone()), invariant.clone());
-            }
-            Err(_) => return Err(QuickestPathHeuristicError::PoisonedMutex),
-        }
+                candidates.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Ok(Arc::new(candidates))
+            },
+        )
+    }
+}
 
-        Ok(invariant)
+impl<G, WeightR2, WeightSE2, const R: u32> Fingerprinted
+    for QuickestPathHeuristic<G, WeightR2, WeightSE2, R>
+where
+    WeightR2: Reversible + Weighted<StateR2<G::Key>, ArrayVec<WaypointR2, 1>> + Fingerprinted,
+    WeightR2::WeightedError: Into<Anyhow>,
+    WeightSE2: Weighted<StateSE2<G::Key, R>, DifferentialDriveLineFollowMotion> + Fingerprinted,
+    G: Graph + Reversible + Clone + Fingerprinted,
+    G::Key: Key + Clone,
+    G::Vertex: Positioned + MaybeOriented,
+    G::EdgeAttributes: SpeedLimiter + Clone,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(
+            self.graph.fingerprint()?,
+            self.weight_r2.fingerprint()?,
+            self.weight_se2.fingerprint()?,
+            self.extrapolator.fingerprint()?,
+            R,
+        ))
     }
 }
 
@@ -278,6 +372,14 @@ where
             .arrival_state
             .time_shifted_by(start.time() - TimePoint::zero());
 
+        if let Some(deadline) = to_goal.maybe_deadline() {
+            // Even the quickest path would arrive too late, so there is no
+            // point in expanding this state any further.
+            if arrival_state.time() > deadline {
+                return Ok(None);
+            }
+        }
+
         // Now add the cost of the final connection, which may vary based on time
         let cost = match MergeIntoGoal(self.extrapolator).connect(arrival_state.clone(), to_goal) {
             Some(r) => {
@@ -321,7 +423,7 @@ mod tests {
             SharedGraph, SimpleGraph,
         },
         motion::{
-            se2::{GoalSE2, Point, WaypointSE2},
+            se2::{Point, WaypointSE2},
             CircularProfile, TimePoint, TravelTimeCost,
         },
     };
@@ -468,4 +570,44 @@ mod tests {
             .unwrap()
             .unwrap();
     }
+
+    #[test]
+    fn test_deadline_prunes_late_arrival() {
+        let cell_size = 1.0;
+        let profile = CircularProfile::new(0.75, 0.0, 0.0).unwrap();
+        let visibility = Arc::new(Visibility::new(
+            SparseGrid::new(cell_size),
+            profile.footprint_radius(),
+        ));
+
+        let drive = DifferentialDriveLineFollow::new(3.0, 1.0).unwrap();
+        let heuristic: QuickestPathHeuristic<_, _, _, 100> = QuickestPathHeuristic::new(
+            SharedGraph::new(VisibilityGraph::new(visibility, [])),
+            TravelTimeCost(1.0),
+            TravelTimeCost(1.0),
+            drive,
+        )
+        .unwrap();
+
+        let state_cell = Cell::new(0, 0);
+        let state_p = state_cell.center_point(cell_size);
+        let from_state = StateSE2::new(
+            state_cell,
+            WaypointSE2::new_f64(2.0, state_p.x, state_p.y, 0.0),
+        );
+
+        // The quickest arrival at the goal is a little after t=5.33
+        let goal = GoalSE2::new(Cell::new(10, 0));
+        let reachable = goal.with_maximum_time(Some(TimePoint::from_secs_f64(6.0)));
+        assert!(heuristic
+            .estimate_remaining_cost(&from_state, &reachable)
+            .unwrap()
+            .is_some());
+
+        let unreachable = goal.with_maximum_time(Some(TimePoint::from_secs_f64(5.0)));
+        assert!(heuristic
+            .estimate_remaining_cost(&from_state, &unreachable)
+            .unwrap()
+            .is_none());
+    }
 }
@@ -0,0 +1,579 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    domain::{ArrivalKeyring, Connectable, Informed, Key, Reversible, Satisfiable, Weighted},
+    error::{Anyhow, NoError},
+    graph::{occupancy::Cell, Graph},
+    motion::{
+        r2::{Point, Positioned, StateR2, WaypointR2},
+        se2::{
+            DifferentialDriveLineFollowError, DifferentialDriveLineFollowMotion, GoalSE2,
+            MaybeOriented, MergeIntoGoal, Orientation, Position, QuickestPathHeuristic,
+            SafeMergeIntoGoal, SatisfySE2, StateSE2, WaypointSE2,
+        },
+        MaybeTimed, SafeAction, SpeedLimiter, TimePoint, Timed, WaitForObstacle,
+    },
+};
+use arrayvec::ArrayVec;
+use std::{borrow::Borrow, ops::Add};
+
+/// A goal that is satisfied by arriving at any one of a set of graph vertices,
+/// e.g. "anywhere inside staging zone B" or "within 0.5m and 10 degrees of
+/// this pose".
+///
+/// The region is described by the set of vertex keys that belong to it. Use
+/// [`GoalRegionSE2::within_polygon`] or [`GoalRegionSE2::within_tolerance`] to
+/// pick those keys out of a graph, or [`GoalRegionSE2::polygon_cells`] and
+/// [`GoalRegionSE2::tolerance_cells`] for occupancy grids.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoalRegionSE2<K> {
+    /// Any of these vertices will satisfy the goal
+    pub keys: Vec<K>,
+    /// The orientation that the agent should arrive with
+    pub orientation: Option<Orientation>,
+    /// How far the final orientation is allowed to deviate from the goal
+    /// orientation (radians). When this is None, the rotational threshold of
+    /// the satisfier will be used.
+    pub orientation_tolerance: Option<f64>,
+    /// The region cannot be reached before this time
+    pub minimum_time: Option<TimePoint>,
+    /// The region must be reached no later than this time
+    pub maximum_time: Option<TimePoint>,
+}
+
+impl<K> GoalRegionSE2<K> {
+    pub fn new(keys: impl IntoIterator<Item = K>) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+            orientation: None,
+            orientation_tolerance: None,
+            minimum_time: None,
+            maximum_time: None,
+        }
+    }
+
+    /// Make a region out of the candidate keys whose vertices are inside of the
+    /// polygon.
+    pub fn within_polygon<G>(
+        graph: &G,
+        polygon: &[Point],
+        candidates: impl IntoIterator<Item = K>,
+    ) -> Self
+    where
+        G: Graph<Key = K>,
+        G::Vertex: Positioned,
+    {
+        Self::new(candidates.into_iter().filter(|k| {
+            graph
+                .vertex(k)
+                .map(|v| {
+                    let v: &G::Vertex = v.borrow();
+                    polygon_contains(polygon, &v.point())
+                })
+                .unwrap_or(false)
+        }))
+    }
+
+    /// Make a region out of the candidate keys whose vertices are within
+    /// `distance` of the pose. Arrival orientations within `angle` radians of
+    /// the pose will be accepted.
+    pub fn within_tolerance<G>(
+        graph: &G,
+        pose: Position,
+        distance: f64,
+        angle: f64,
+        candidates: impl IntoIterator<Item = K>,
+    ) -> Self
+    where
+        G: Graph<Key = K>,
+        G::Vertex: Positioned,
+    {
+        let center: Point = pose.translation.vector.into();
+        Self::new(candidates.into_iter().filter(|k| {
+            graph
+                .vertex(k)
+                .map(|v| {
+                    let v: &G::Vertex = v.borrow();
+                    (v.point() - center).norm() <= distance
+                })
+                .unwrap_or(false)
+        }))
+        .with_orientation(Some(pose.rotation))
+        .with_orientation_tolerance(Some(angle))
+    }
+
+    pub fn with_orientation(mut self, orientation: Option<Orientation>) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn with_orientation_tolerance(mut self, tolerance: Option<f64>) -> Self {
+        self.orientation_tolerance = tolerance;
+        self
+    }
+
+    pub fn with_minimum_time(mut self, minimum_time: Option<TimePoint>) -> Self {
+        self.minimum_time = minimum_time;
+        self
+    }
+
+    pub fn with_maximum_time(mut self, maximum_time: Option<TimePoint>) -> Self {
+        self.maximum_time = maximum_time;
+        self
+    }
+
+    pub fn contains(&self, key: &K) -> bool
+    where
+        K: PartialEq,
+    {
+        self.keys.contains(key)
+    }
+
+    /// Check whether an arrival orientation is acceptable for this goal, using
+    /// `default_tolerance` if the goal does not specify its own tolerance.
+    pub fn accepts_orientation(&self, orientation: Orientation, default_tolerance: f64) -> bool {
+        let Some(target) = self.orientation else {
+            return true;
+        };
+        let tolerance = self.orientation_tolerance.unwrap_or(default_tolerance);
+        (target / orientation).angle().abs() <= tolerance
+    }
+}
+
+impl GoalRegionSE2<Cell> {
+    /// Make a region out of every cell whose center is inside the polygon.
+    pub fn polygon_cells(polygon: &[Point], cell_size: f64) -> Self {
+        let Some(first) = polygon.first() else {
+            return Self::new([]);
+        };
+        let (mut min, mut max) = (*first, *first);
+        for p in polygon {
+            min = min.inf(p);
+            max = max.sup(p);
+        }
+
+        Self::new(
+            cells_in_bounds(min, max, cell_size)
+                .filter(|cell| polygon_contains(polygon, &cell.center_point(cell_size))),
+        )
+    }
+
+    /// Make a region out of every cell whose center is within `distance` of the
+    /// pose. Arrival orientations within `angle` radians of the pose will be
+    /// accepted.
+    pub fn tolerance_cells(pose: Position, distance: f64, angle: f64, cell_size: f64) -> Self {
+        let center: Point = pose.translation.vector.into();
+        let offset = nalgebra::Vector2::new(distance, distance);
+        Self::new(
+            cells_in_bounds(center - offset, center + offset, cell_size)
+                .filter(|cell| (cell.center_point(cell_size) - center).norm() <= distance),
+        )
+        .with_orientation(Some(pose.rotation))
+        .with_orientation_tolerance(Some(angle))
+    }
+}
+
+impl<K> MaybeOriented for GoalRegionSE2<K> {
+    fn maybe_oriented(&self) -> Option<Orientation> {
+        self.orientation
+    }
+}
+
+impl<K> MaybeTimed for GoalRegionSE2<K> {
+    fn maybe_time(&self) -> Option<TimePoint> {
+        self.minimum_time
+    }
+
+    fn maybe_deadline(&self) -> Option<TimePoint> {
+        self.maximum_time
+    }
+}
+
+impl<K, const R: u32> Satisfiable<StateSE2<K, R>, GoalRegionSE2<K>> for SatisfySE2
+where
+    K: PartialEq,
+{
+    type SatisfactionError = NoError;
+    fn is_satisfied(
+        &self,
+        by_state: &StateSE2<K, R>,
+        for_goal: &GoalRegionSE2<K>,
+    ) -> Result<bool, Self::SatisfactionError> {
+        if !for_goal.contains(&by_state.key.vertex) {
+            return Ok(false);
+        }
+
+        if !for_goal.accepts_orientation(
+            by_state.waypoint.position.rotation,
+            self.rotational_threshold,
+        ) {
+            return Ok(false);
+        }
+
+        if let Some(target_time) = for_goal.minimum_time {
+            if by_state.time() < target_time {
+                return Ok(false);
+            }
+        }
+
+        if let Some(deadline) = for_goal.maximum_time {
+            if by_state.time() > deadline {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<K, Start> ArrivalKeyring<K, Start, GoalRegionSE2<K>> for SatisfySE2
+where
+    K: Clone,
+{
+    type ArrivalKeyError = NoError;
+    type ArrivalKeys<'a>
+        = Vec<Result<K, NoError>>
+    where
+        K: 'a,
+        Start: 'a;
+
+    fn get_arrival_keys<'a>(&'a self, _: &Start, goal: &GoalRegionSE2<K>) -> Self::ArrivalKeys<'a>
+    where
+        Self: 'a,
+        Self::ArrivalKeyError: 'a,
+        K: 'a,
+        Start: 'a,
+    {
+        goal.keys.iter().cloned().map(Ok).collect()
+    }
+}
+
+/// Lets graph motions with the default keyring, such as the connector of
+/// [`crate::premade::SippSE2`], look for lazy edges into every vertex of the
+/// region.
+impl<K, Start> ArrivalKeyring<K, Start, GoalRegionSE2<K>> for ()
+where
+    K: Clone,
+{
+    type ArrivalKeyError = NoError;
+    type ArrivalKeys<'a>
+        = Vec<Result<K, NoError>>
+    where
+        K: 'a,
+        Start: 'a;
+
+    fn get_arrival_keys<'a>(&'a self, _: &Start, goal: &GoalRegionSE2<K>) -> Self::ArrivalKeys<'a>
+    where
+        Self: 'a,
+        Self::ArrivalKeyError: 'a,
+        K: 'a,
+        Start: 'a,
+    {
+        goal.keys.iter().cloned().map(Ok).collect()
+    }
+}
+
+impl<K, Action, const R: u32> Connectable<StateSE2<K, R>, Action, GoalRegionSE2<K>>
+    for MergeIntoGoal<R>
+where
+    Action: FromIterator<WaypointSE2> + std::fmt::Debug,
+    K: Clone + PartialEq + std::fmt::Debug,
+{
+    type ConnectionError = DifferentialDriveLineFollowError;
+    type Connections<'a>
+        = Option<Result<(Action, StateSE2<K, R>), Self::ConnectionError>>
+    where
+        K: 'a,
+        Action: 'a;
+
+    fn connect<'a>(
+        &'a self,
+        from_state: StateSE2<K, R>,
+        to_target: &'a GoalRegionSE2<K>,
+    ) -> Self::Connections<'a>
+    where
+        Self: 'a,
+        Self::ConnectionError: 'a,
+        StateSE2<K, R>: 'a,
+        Action: 'a,
+    {
+        let goal = merge_goal(&from_state, to_target, self.0.rotational_threshold())?;
+        <Self as Connectable<StateSE2<K, R>, Action, GoalSE2<K>>>::connect(self, from_state, &goal)
+    }
+}
+
+impl<K, Action, const R: u32> Connectable<StateSE2<K, R>, Action, GoalRegionSE2<K>>
+    for SafeMergeIntoGoal<K, R>
+where
+    Action: FromIterator<SafeAction<WaypointSE2, WaitForObstacle>>,
+    K: Clone + Key,
+{
+    type ConnectionError = DifferentialDriveLineFollowError;
+    type Connections<'a>
+        = Option<Result<(Action, StateSE2<K, R>), Self::ConnectionError>>
+    where
+        K: 'a,
+        Action: 'a;
+
+    fn connect<'a>(
+        &'a self,
+        from_state: StateSE2<K, R>,
+        to_target: &'a GoalRegionSE2<K>,
+    ) -> Self::Connections<'a>
+    where
+        Self: 'a,
+        Self::ConnectionError: 'a,
+        StateSE2<K, R>: 'a,
+        Action: 'a,
+    {
+        let goal = merge_goal(&from_state, to_target, self.motion.rotational_threshold())?;
+        <Self as Connectable<StateSE2<K, R>, Action, GoalSE2<K>>>::connect(self, from_state, &goal)
+    }
+}
+
+/// Decide what a state inside the region still needs to do to satisfy the
+/// goal. Orientations that are already within tolerance are left alone.
+fn merge_goal<K: Clone + PartialEq, const R: u32>(
+    from_state: &StateSE2<K, R>,
+    region: &GoalRegionSE2<K>,
+    default_tolerance: f64,
+) -> Option<GoalSE2<K>> {
+    if !region.contains(&from_state.key.vertex) {
+        return None;
+    }
+
+    let orientation =
+        if region.accepts_orientation(from_state.waypoint.position.rotation, default_tolerance) {
+            None
+        } else {
+            region.orientation
+        };
+
+    Some(
+        GoalSE2::new(from_state.key.vertex.clone())
+            .with_orientation(orientation)
+            .with_minimum_time(region.minimum_time)
+            .with_maximum_time(region.maximum_time),
+    )
+}
+
+impl<G, WeightR2, WeightSE2, const R: u32, State> Informed<State, GoalRegionSE2<G::Key>>
+    for QuickestPathHeuristic<G, WeightR2, WeightSE2, R>
+where
+    WeightR2: Reversible + Weighted<StateR2<G::Key>, ArrayVec<WaypointR2, 1>>,
+    WeightR2::Cost: Clone + Ord + Add<WeightR2::Cost, Output = WeightR2::Cost>,
+    WeightR2::WeightedError: Into<Anyhow>,
+    WeightSE2: Weighted<StateSE2<G::Key, R>, DifferentialDriveLineFollowMotion>,
+    WeightSE2::Cost: Clone + Ord + Add<Output = WeightSE2::Cost>,
+    WeightSE2::WeightedError: Into<Anyhow>,
+    G: Graph + Reversible + Clone,
+    G::Key: Key + Clone,
+    G::Vertex: Positioned + MaybeOriented,
+    G::EdgeAttributes: SpeedLimiter + Clone,
+    State: Borrow<StateSE2<G::Key, R>>,
+{
+    type CostEstimate = WeightSE2::Cost;
+    type InformedError = <Self as Informed<State, GoalSE2<G::Key>>>::InformedError;
+    fn estimate_remaining_cost(
+        &self,
+        from_state: &State,
+        to_goal: &GoalRegionSE2<G::Key>,
+    ) -> Result<Option<Self::CostEstimate>, Self::InformedError> {
+        // Take the cheapest estimate across the whole region. When the goal
+        // has an orientation tolerance we leave out the final rotation so the
+        // estimate never exceeds what the tolerance actually demands.
+        let orientation = to_goal
+            .orientation
+            .filter(|_| to_goal.orientation_tolerance.is_none());
+
+        // The final connection can only add to the invariant cost of a key,
+        // so once the invariant alone reaches the best estimate found so far,
+        // none of the remaining keys can do better.
+        let mut best: Option<Self::CostEstimate> = None;
+        for (invariant, key) in self.region_candidates(from_state, &to_goal.keys)?.iter() {
+            if best.as_ref().is_some_and(|best| best <= invariant) {
+                break;
+            }
+
+            let goal = GoalSE2::new(key.clone())
+                .with_orientation(orientation)
+                .with_minimum_time(to_goal.minimum_time)
+                .with_maximum_time(to_goal.maximum_time);
+            let estimate = <Self as Informed<State, GoalSE2<G::Key>>>::estimate_remaining_cost(
+                self, from_state, &goal,
+            )?;
+            let Some(cost) = estimate else {
+                continue;
+            };
+
+            if best.as_ref().filter(|best| **best <= cost).is_none() {
+                best = Some(cost);
+            }
+        }
+
+        Ok(best)
+    }
+}
+
+fn cells_in_bounds(min: Point, max: Point, cell_size: f64) -> impl Iterator<Item = Cell> {
+    let min = Cell::from_point(min, cell_size);
+    let max = Cell::from_point(max, cell_size);
+    (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| Cell::new(x, y)))
+}
+
+/// Even-odd test for whether a point is inside of a simple polygon.
+fn polygon_contains(polygon: &[Point], p: &Point) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+    for i in 0..polygon.len() {
+        let (a, b) = (&polygon[i], &polygon[j]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::AStarConnect,
+        graph::{
+            occupancy::{
+                Accessibility, AccessibilityGraph, SparseGrid, Visibility, VisibilityGraph,
+            },
+            SharedGraph,
+        },
+        motion::{
+            se2::DifferentialDriveLineFollow, CcbsEnvironment, CircularProfile, DynamicEnvironment,
+            TravelEffortCost, TravelTimeCost,
+        },
+        premade::SippSE2,
+        Planner,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_polygon_cells() {
+        let region = GoalRegionSE2::polygon_cells(
+            &[
+                Point::new(0.0, 0.0),
+                Point::new(3.0, 0.0),
+                Point::new(3.0, 2.0),
+                Point::new(0.0, 2.0),
+            ],
+            1.0,
+        );
+        assert_eq!(region.keys.len(), 6);
+        assert!(region.contains(&Cell::new(2, 1)));
+        assert!(!region.contains(&Cell::new(3, 1)));
+
+        let region = GoalRegionSE2::tolerance_cells(
+            Position::new(nalgebra::Vector2::new(0.5, 0.5), 0.0),
+            1.0,
+            10_f64.to_radians(),
+            1.0,
+        );
+        assert_eq!(region.keys.len(), 5);
+        assert!(region.accepts_orientation(Orientation::new(5_f64.to_radians()), 0.0));
+        assert!(!region.accepts_orientation(Orientation::new(15_f64.to_radians()), 0.0));
+    }
+
+    #[test]
+    fn test_plan_into_region() {
+        let profile = CircularProfile::new(0.4, 0.0, 0.0).unwrap();
+        let accessibility = Arc::new(Accessibility::new(SparseGrid::new(1.0), 0.4));
+        let graph = SharedGraph::new(AccessibilityGraph::new(accessibility));
+        let planner = Planner::new(AStarConnect(
+            SippSE2::new_sipp_se2(
+                graph.clone(),
+                graph,
+                DifferentialDriveLineFollow::new(1.0, 1.0).unwrap(),
+                Arc::new(CcbsEnvironment::new(Arc::new(DynamicEnvironment::new(
+                    profile,
+                )))),
+                TravelEffortCost::default(),
+            )
+            .unwrap(),
+        ));
+
+        let region = GoalRegionSE2::new([Cell::new(5, 0), Cell::new(10, 0), Cell::new(-8, 0)]);
+        let solution = planner
+            .plan((Cell::new(0, 0), 0.0), region)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        // The nearest cell of the region should be chosen
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_eq!(final_state.key.vertex, Cell::new(5, 0));
+    }
+
+    #[test]
+    fn test_region_estimate_measures_each_vertex_once() {
+        let visibility = Arc::new(Visibility::new(SparseGrid::new(1.0), 0.4));
+        let heuristic: QuickestPathHeuristic<_, _, _, 100> = QuickestPathHeuristic::new(
+            SharedGraph::new(VisibilityGraph::new(visibility, [])),
+            TravelTimeCost(1.0),
+            TravelTimeCost(1.0),
+            DifferentialDriveLineFollow::new(1.0, 1.0).unwrap(),
+        )
+        .unwrap();
+
+        let from_state = StateSE2::new(Cell::new(0, 0), WaypointSE2::new_f64(0.0, 0.5, 0.5, 0.0));
+        let region = GoalRegionSE2::new([Cell::new(10, 0), Cell::new(5, 0), Cell::new(-8, 0)])
+            .with_orientation(Some(Orientation::new(90_f64.to_radians())));
+
+        let estimate = heuristic
+            .estimate_remaining_cost(&from_state, &region)
+            .unwrap()
+            .unwrap();
+
+        let expected = region
+            .keys
+            .iter()
+            .filter_map(|key| {
+                let goal = GoalSE2::new(*key).with_orientation(region.orientation);
+                heuristic
+                    .estimate_remaining_cost(&from_state, &goal)
+                    .unwrap()
+            })
+            .min()
+            .unwrap();
+        assert_eq!(estimate, expected);
+
+        // The candidates are sorted and only measured the first time
+        let candidates = heuristic
+            .region_candidates(&from_state, &region.keys)
+            .unwrap();
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0].1, Cell::new(5, 0));
+        assert!(candidates.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(Arc::ptr_eq(
+            &candidates,
+            &heuristic
+                .region_candidates(&from_state, &region.keys)
+                .unwrap()
+        ));
+    }
+}
@@ -0,0 +1,914 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::{AStarConnect, Algorithm, Coherent, Path, SearchStatus, Solvable},
+    domain::{
+        Activity, Closable, CloseResult, ClosedSet, ClosedStatus, Connectable, Domain, Informed,
+        Initializable, Key, Satisfiable, Weighted,
+    },
+    error::{Anyhow, NoError, ThisError},
+    graph::Graph,
+    motion::{
+        is_safe_segment,
+        se2::{GoalSE2, Orientation, StartSE2, StateSE2, WaypointSE2},
+        Duration, Occupancy, SafeAction, SafeIntervalCache, TimePoint, WaitForObstacle,
+    },
+    planner::{Halt, Planner},
+    templates::{ConflictAvoidance, GraphMotion, InformedSearch},
+};
+use num::Zero;
+use smallvec::{smallvec, SmallVec};
+use std::{collections::HashMap, sync::Arc};
+
+/// The action type produced by SE(2) planners that avoid conflicts, such as
+/// [`crate::premade::SippSE2`].
+pub type SafeActionSE2 = SmallVec<[SafeAction<WaypointSE2, WaitForObstacle>; 5]>;
+
+/// One stop within a [`SequentialGoalSE2`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StopSE2<K> {
+    /// The vertex that needs to be reached
+    pub key: K,
+    /// The orientation that the agent must have when arriving at the stop
+    pub orientation: Option<Orientation>,
+    /// How long the agent must remain at the stop before it may leave
+    pub dwell: Duration,
+    /// The agent may not arrive at the stop before this time
+    pub earliest_arrival: Option<TimePoint>,
+    /// The agent must arrive at the stop no later than this time
+    pub latest_arrival: Option<TimePoint>,
+    /// The agent may not leave the stop before this time
+    pub earliest_departure: Option<TimePoint>,
+}
+
+impl<K> StopSE2<K> {
+    pub fn new(key: K) -> Self {
+        Self {
+            key,
+            orientation: None,
+            dwell: Duration::from_secs(0),
+            earliest_arrival: None,
+            latest_arrival: None,
+            earliest_departure: None,
+        }
+    }
+
+    pub fn with_orientation(mut self, orientation: Option<Orientation>) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn with_dwell(mut self, dwell: Duration) -> Self {
+        self.dwell = dwell;
+        self
+    }
+
+    pub fn with_earliest_arrival(mut self, time: Option<TimePoint>) -> Self {
+        self.earliest_arrival = time;
+        self
+    }
+
+    pub fn with_latest_arrival(mut self, time: Option<TimePoint>) -> Self {
+        self.latest_arrival = time;
+        self
+    }
+
+    pub fn with_earliest_departure(mut self, time: Option<TimePoint>) -> Self {
+        self.earliest_departure = time;
+        self
+    }
+}
+
+impl<K> From<K> for StopSE2<K> {
+    fn from(key: K) -> Self {
+        Self::new(key)
+    }
+}
+
+/// An ordered sequence of stops that an agent must visit, e.g. a pick
+/// location, then a drop location, then a charger.
+///
+/// Each stop is reached through a leg whose goal is a [`GoalSE2`], so any
+/// domain that supports [`GoalSE2`] (including [`super::SatisfySE2`] and
+/// [`super::QuickestPathHeuristic`]) can be wrapped in a [`SequenceSE2`] to
+/// search through all of the stops. Use [`plan_sequence`] to produce a single
+/// continuous [`Path`] through all of the stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequentialGoalSE2<K> {
+    pub stops: Vec<StopSE2<K>>,
+    /// The agent must hold at its final stop until this time. This does not
+    /// delay the arrival itself, so the latest arrival of the final stop is
+    /// still checked against the time that the agent actually arrives.
+    pub minimum_time: Option<TimePoint>,
+    /// The agent may not leave its start before this time.
+    pub earliest_departure: Option<TimePoint>,
+}
+
+impl<K> SequentialGoalSE2<K> {
+    pub fn new(stops: Vec<StopSE2<K>>) -> Self {
+        Self {
+            stops,
+            minimum_time: None,
+            earliest_departure: None,
+        }
+    }
+
+    pub fn with_stop(mut self, stop: impl Into<StopSE2<K>>) -> Self {
+        self.stops.push(stop.into());
+        self
+    }
+
+    pub fn with_minimum_time(mut self, minimum_time: Option<TimePoint>) -> Self {
+        self.minimum_time = minimum_time;
+        self
+    }
+
+    pub fn with_earliest_departure(mut self, time: Option<TimePoint>) -> Self {
+        self.earliest_departure = time;
+        self
+    }
+
+    /// Get the goal for the leg that arrives at the stop with the given index.
+    pub fn leg_goal(&self, index: usize) -> Option<GoalSE2<K>>
+    where
+        K: Clone,
+    {
+        let stop = self.stops.get(index)?;
+        Some(
+            GoalSE2::new(stop.key.clone())
+                .with_orientation(stop.orientation)
+                .with_minimum_time(stop.earliest_arrival)
+                .with_maximum_time(stop.latest_arrival),
+        )
+    }
+
+    /// Get the earliest time that an agent may set out for the stop with the
+    /// given index if it arrived at the stop before it (or started, for the
+    /// first stop) at `arrival`. The final stop is left at `stops.len()`, and
+    /// it is also held until the minimum time of the sequence.
+    pub fn departure_time(&self, index: usize, arrival: TimePoint) -> TimePoint {
+        match index
+            .checked_sub(1)
+            .map(|previous| self.stops.get(previous))
+        {
+            Some(Some(previous)) => {
+                let mut departure = arrival + previous.dwell;
+                if let Some(earliest) = previous.earliest_departure {
+                    departure = departure.max(earliest);
+                }
+                if index == self.stops.len() {
+                    if let Some(minimum) = self.minimum_time {
+                        departure = departure.max(minimum);
+                    }
+                }
+                departure
+            }
+            Some(None) => arrival,
+            None => self
+                .earliest_departure
+                .map_or(arrival, |earliest| earliest.max(arrival)),
+        }
+    }
+}
+
+impl<K> From<GoalSE2<K>> for SequentialGoalSE2<K> {
+    fn from(goal: GoalSE2<K>) -> Self {
+        Self {
+            stops: vec![StopSE2::new(goal.key)
+                .with_orientation(goal.orientation)
+                .with_earliest_arrival(goal.minimum_time)
+                .with_latest_arrival(goal.maximum_time)],
+            minimum_time: None,
+            earliest_departure: None,
+        }
+    }
+}
+
+/// Implemented by domains that can tell whether an agent may hold its pose at
+/// a vertex for a span of time. [`SequenceSE2`] uses this to make sure that
+/// the agent can safely wait at its start and at each stop.
+pub trait SafeHoldSE2<K> {
+    /// Check whether the agent can stay at the vertex of `key`, starting from
+    /// the waypoint `from`, until the time `until`.
+    fn is_safe_hold(&self, key: &K, from: &WaypointSE2, until: TimePoint) -> bool;
+}
+
+impl<G> SafeHoldSE2<G::Key> for SafeIntervalCache<G>
+where
+    G: Graph,
+    G::Key: Key + Clone,
+{
+    fn is_safe_hold(&self, key: &G::Key, from: &WaypointSE2, until: TimePoint) -> bool {
+        let mut to = *from;
+        to.time = until;
+        let environment = self.environment();
+        let hold_key = (key.clone(), key.clone());
+        if !is_safe_segment(
+            (&(*from).into(), &to.into()),
+            None,
+            &environment.view_for(Some(&hold_key)),
+        ) {
+            return false;
+        }
+
+        environment.capacity().has_room(
+            key,
+            &Occupancy::new(from.time, Some(until)),
+            environment.mask(),
+        )
+    }
+}
+
+impl<S, G, A, Env, W, H, X, I, Sat, C, K> SafeHoldSE2<K>
+    for InformedSearch<GraphMotion<S, G, ConflictAvoidance<A, Env>>, W, H, X, I, Sat, C>
+where
+    Env: SafeHoldSE2<K>,
+{
+    fn is_safe_hold(&self, key: &K, from: &WaypointSE2, until: TimePoint) -> bool {
+        self.activity
+            .extrapolator
+            .environment
+            .is_safe_hold(key, from, until)
+    }
+}
+
+/// The state of a [`SequenceSE2`] search. Besides the state of the agent, it
+/// tracks how far the agent has progressed through the sequence of stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequenceState<S> {
+    pub state: S,
+    /// Index of the next stop that the agent needs to reach. Every stop before
+    /// this index has already been reached.
+    pub stop: usize,
+    /// Whether the agent has finished holding at the stop it reached most
+    /// recently, or at its start if it has not reached any stop yet. The agent
+    /// may not move on until this is true.
+    pub dwelled: bool,
+}
+
+impl<K, const R: u32> SequenceState<StateSE2<K, R>> {
+    fn started(state: StateSE2<K, R>, goal: &SequentialGoalSE2<K>) -> Self {
+        let time = state.waypoint.time;
+        Self {
+            state,
+            stop: 0,
+            dwelled: goal.departure_time(0, time) <= time,
+        }
+    }
+
+    fn arrived(state: StateSE2<K, R>, stop: usize, goal: &SequentialGoalSE2<K>) -> Self {
+        let next = stop + 1;
+        let time = state.waypoint.time;
+        // The final stop is held like every other stop, so the goal is only
+        // satisfied once the agent may depart from it.
+        let dwelled = goal.departure_time(next, time) <= time;
+        Self {
+            state,
+            stop: next,
+            dwelled,
+        }
+    }
+}
+
+/// A domain that searches through every stop of a [`SequentialGoalSE2`] at
+/// once by wrapping an SE(2) domain, such as [`crate::premade::SippSE2`],
+/// that supports [`GoalSE2`].
+///
+/// Each [`SequenceState`] carries the index of the next stop and whether the
+/// agent has finished holding at its previous stop, so one search can find
+/// that arriving later at one stop makes the rest of the sequence possible.
+/// Arrivals at each stop are found with the goal connections of the wrapped
+/// domain. Holds for dwelling and for earliest departure times are checked
+/// with [`SafeHoldSE2`], so the agent never waits where it would collide with
+/// an obstacle.
+///
+/// Arrivals and holds are connections, so this domain needs to be given to an
+/// [`AStarConnect`].
+#[derive(Debug)]
+pub struct SequenceSE2<D> {
+    pub domain: Arc<D>,
+}
+
+impl<D> SequenceSE2<D> {
+    pub fn new(domain: D) -> Self {
+        Self {
+            domain: Arc::new(domain),
+        }
+    }
+}
+
+impl<D> Clone for SequenceSE2<D> {
+    fn clone(&self) -> Self {
+        Self {
+            domain: self.domain.clone(),
+        }
+    }
+}
+
+impl<D: Domain> Domain for SequenceSE2<D> {
+    type State = SequenceState<D::State>;
+    type Error = Anyhow;
+}
+
+impl<D, K, const R: u32>
+    Initializable<StartSE2<K>, SequentialGoalSE2<K>, SequenceState<StateSE2<K, R>>>
+    for SequenceSE2<D>
+where
+    D: Initializable<StartSE2<K>, GoalSE2<K>, StateSE2<K, R>>,
+    K: Clone,
+{
+    type InitialError = D::InitialError;
+    type InitialStates<'a> = Vec<Result<SequenceState<StateSE2<K, R>>, D::InitialError>>
+    where
+        Self: 'a,
+        Self::InitialError: 'a,
+        StartSE2<K>: 'a,
+        SequentialGoalSE2<K>: 'a,
+        SequenceState<StateSE2<K, R>>: 'a;
+
+    fn initialize<'a>(
+        &'a self,
+        from_start: StartSE2<K>,
+        to_goal: &SequentialGoalSE2<K>,
+    ) -> Self::InitialStates<'a>
+    where
+        Self: 'a,
+        Self::InitialError: 'a,
+        StartSE2<K>: 'a,
+        SequentialGoalSE2<K>: 'a,
+        SequenceState<StateSE2<K, R>>: 'a,
+    {
+        let Some(first_leg) = to_goal.leg_goal(0) else {
+            return Vec::new();
+        };
+
+        self.domain
+            .initialize(from_start, &first_leg)
+            .into_iter()
+            .map(|r| r.map(|state| SequenceState::started(state, to_goal)))
+            .collect()
+    }
+}
+
+impl<D, S> Closable<SequenceState<S>> for SequenceSE2<D>
+where
+    D: Closable<S>,
+{
+    type ClosedSet<T> = SequenceClosedSet<D, S, T>;
+    fn new_closed_set<T>(&self) -> Self::ClosedSet<T> {
+        SequenceClosedSet {
+            domain: self.domain.clone(),
+            progress: HashMap::new(),
+        }
+    }
+}
+
+/// The closed set of a [`SequenceSE2`]. Each step of progress through the
+/// sequence gets its own closed set from the wrapped domain, so reaching a
+/// vertex before a stop does not close it for after the stop.
+pub struct SequenceClosedSet<D: Closable<S>, S, T> {
+    domain: Arc<D>,
+    progress: HashMap<(usize, bool), <D as Closable<S>>::ClosedSet<T>>,
+}
+
+impl<D: Closable<S>, S, T> SequenceClosedSet<D, S, T> {
+    fn closed_set_for(
+        &mut self,
+        state: &SequenceState<S>,
+    ) -> &mut <D as Closable<S>>::ClosedSet<T> {
+        let domain = &self.domain;
+        self.progress
+            .entry((state.stop, state.dwelled))
+            .or_insert_with(|| domain.new_closed_set())
+    }
+}
+
+impl<D: Closable<S>, S, T> ClosedSet<SequenceState<S>, T> for SequenceClosedSet<D, S, T> {
+    fn close<'a>(&'a mut self, state: &SequenceState<S>, value: T) -> CloseResult<'a, T> {
+        self.closed_set_for(state).close(&state.state, value)
+    }
+
+    fn replace(&mut self, state: &SequenceState<S>, value: T) -> Option<T> {
+        self.closed_set_for(state).replace(&state.state, value)
+    }
+
+    fn status<'a>(&'a self, state: &SequenceState<S>) -> ClosedStatus<'a, T> {
+        match self.progress.get(&(state.stop, state.dwelled)) {
+            Some(closed_set) => closed_set.status(&state.state),
+            None => ClosedStatus::Open,
+        }
+    }
+
+    fn remove(&mut self, state: &SequenceState<S>) -> Option<T> {
+        self.progress
+            .get_mut(&(state.stop, state.dwelled))?
+            .remove(&state.state)
+    }
+
+    type ClosedSetIter<'a> = impl Iterator<Item=&'a T> + 'a
+    where
+        Self: 'a,
+        SequenceState<S>: 'a,
+        T: 'a;
+
+    fn iter_closed<'a>(&'a self) -> Self::ClosedSetIter<'a>
+    where
+        Self: 'a,
+        SequenceState<S>: 'a,
+        T: 'a,
+    {
+        self.progress
+            .values()
+            .flat_map(|closed_set| closed_set.iter_closed())
+    }
+}
+
+impl<D, S> Activity<SequenceState<S>> for SequenceSE2<D>
+where
+    D: Activity<S>,
+{
+    type ActivityAction = D::ActivityAction;
+    type ActivityError = D::ActivityError;
+    type Choices<'a> = impl IntoIterator<Item = Result<(D::ActivityAction, SequenceState<S>), D::ActivityError>> + 'a
+    where
+        Self: 'a,
+        Self::ActivityAction: 'a,
+        Self::ActivityError: 'a,
+        SequenceState<S>: 'a;
+
+    fn choices<'a>(&'a self, from_state: SequenceState<S>) -> Self::Choices<'a>
+    where
+        Self: 'a,
+        Self::ActivityAction: 'a,
+        Self::ActivityError: 'a,
+        SequenceState<S>: 'a,
+    {
+        let stop = from_state.stop;
+        // An agent that still needs to hold cannot go anywhere. Its hold is
+        // produced by the connections.
+        let choices = if from_state.dwelled {
+            Some(self.domain.choices(from_state.state))
+        } else {
+            None
+        };
+
+        choices.into_iter().flatten().map(move |r| {
+            r.map(|(action, state)| {
+                (
+                    action,
+                    SequenceState {
+                        state,
+                        stop,
+                        dwelled: true,
+                    },
+                )
+            })
+        })
+    }
+}
+
+impl<D, S, Action> Weighted<SequenceState<S>, Action> for SequenceSE2<D>
+where
+    D: Weighted<S, Action>,
+{
+    type Cost = D::Cost;
+    type WeightedError = D::WeightedError;
+
+    fn cost(
+        &self,
+        from_state: &SequenceState<S>,
+        action: &Action,
+        to_state: &SequenceState<S>,
+    ) -> Result<Option<Self::Cost>, Self::WeightedError> {
+        self.domain.cost(&from_state.state, action, &to_state.state)
+    }
+
+    fn initial_cost(
+        &self,
+        for_state: &SequenceState<S>,
+    ) -> Result<Option<Self::Cost>, Self::WeightedError> {
+        self.domain.initial_cost(&for_state.state)
+    }
+}
+
+impl<D, K, const R: u32> Informed<SequenceState<StateSE2<K, R>>, SequentialGoalSE2<K>>
+    for SequenceSE2<D>
+where
+    D: Informed<StateSE2<K, R>, GoalSE2<K>>,
+    D::CostEstimate: Zero,
+    K: Clone,
+{
+    type CostEstimate = D::CostEstimate;
+    type InformedError = D::InformedError;
+
+    fn estimate_remaining_cost(
+        &self,
+        from_state: &SequenceState<StateSE2<K, R>>,
+        to_goal: &SequentialGoalSE2<K>,
+    ) -> Result<Option<Self::CostEstimate>, Self::InformedError> {
+        // Only the way to the next stop is estimated. The legs after it can
+        // only add to the cost, so this remains admissible.
+        match to_goal.leg_goal(from_state.stop) {
+            Some(leg) => self.domain.estimate_remaining_cost(&from_state.state, &leg),
+            None => Ok(Some(D::CostEstimate::zero())),
+        }
+    }
+}
+
+impl<D, S, K> Satisfiable<SequenceState<S>, SequentialGoalSE2<K>> for SequenceSE2<D> {
+    type SatisfactionError = NoError;
+
+    fn is_satisfied(
+        &self,
+        by_state: &SequenceState<S>,
+        for_goal: &SequentialGoalSE2<K>,
+    ) -> Result<bool, Self::SatisfactionError> {
+        Ok(by_state.stop >= for_goal.stops.len() && by_state.dwelled)
+    }
+}
+
+impl<D, K, const R: u32>
+    Connectable<SequenceState<StateSE2<K, R>>, SafeActionSE2, SequentialGoalSE2<K>>
+    for SequenceSE2<D>
+where
+    D: Connectable<StateSE2<K, R>, SafeActionSE2, GoalSE2<K>>
+        + Satisfiable<StateSE2<K, R>, GoalSE2<K>>
+        + SafeHoldSE2<K>,
+    D::ConnectionError: Into<Anyhow>,
+    D::SatisfactionError: Into<Anyhow>,
+    K: Clone,
+{
+    type ConnectionError = Anyhow;
+    type Connections<'a> = Vec<Result<(SafeActionSE2, SequenceState<StateSE2<K, R>>), Anyhow>>
+    where
+        Self: 'a,
+        Self::ConnectionError: 'a,
+        SequenceState<StateSE2<K, R>>: 'a,
+        SafeActionSE2: 'a,
+        SequentialGoalSE2<K>: 'a;
+
+    fn connect<'a>(
+        &'a self,
+        from_state: SequenceState<StateSE2<K, R>>,
+        to_target: &'a SequentialGoalSE2<K>,
+    ) -> Self::Connections<'a>
+    where
+        Self: 'a,
+        Self::ConnectionError: 'a,
+        SequenceState<StateSE2<K, R>>: 'a,
+        SafeActionSE2: 'a,
+        SequentialGoalSE2<K>: 'a,
+    {
+        let stop = from_state.stop;
+        let mut connections = Vec::new();
+        if !from_state.dwelled {
+            // Hold until the agent may depart, as long as it is safe to stay.
+            let mut state = from_state.state;
+            let departure = to_target.departure_time(stop, state.waypoint.time);
+            if self
+                .domain
+                .is_safe_hold(&state.key.vertex, &state.waypoint, departure)
+            {
+                state.waypoint.time = departure;
+                let hold: SafeActionSE2 = smallvec![SafeAction::Move(state.waypoint)];
+                connections.push(Ok((
+                    hold,
+                    SequenceState {
+                        state,
+                        stop,
+                        dwelled: true,
+                    },
+                )));
+            }
+
+            return connections;
+        }
+
+        let Some(leg) = to_target.leg_goal(stop) else {
+            return connections;
+        };
+
+        match self.domain.is_satisfied(&from_state.state, &leg) {
+            Ok(true) => {
+                // The agent is already at the stop, so it arrives without
+                // moving.
+                connections.push(Ok((
+                    SafeActionSE2::new(),
+                    SequenceState::arrived(from_state.state, stop, to_target),
+                )));
+                return connections;
+            }
+            Ok(false) => {}
+            Err(err) => {
+                connections.push(Err(err.into()));
+                return connections;
+            }
+        }
+
+        for connection in self.domain.connect(from_state.state, &leg) {
+            let (action, state) = match connection {
+                Ok(connection) => connection,
+                Err(err) => {
+                    connections.push(Err(err.into()));
+                    continue;
+                }
+            };
+
+            let state = match self.domain.is_satisfied(&state, &leg) {
+                Ok(true) => SequenceState::arrived(state, stop, to_target),
+                Ok(false) => SequenceState {
+                    state,
+                    stop,
+                    dwelled: true,
+                },
+                Err(err) => {
+                    connections.push(Err(err.into()));
+                    continue;
+                }
+            };
+            connections.push(Ok((action, state)));
+        }
+
+        connections
+    }
+}
+
+/// Plan a single continuous path that visits each stop of a
+/// [`SequentialGoalSE2`] in order.
+///
+/// The domain of the planner is wrapped in a [`SequenceSE2`] so that one
+/// search covers the whole sequence. When a stop has a dwell duration or an
+/// earliest departure time, the path holds its arrival pose until the agent
+/// may depart, and the same applies to the start when the sequence has an
+/// earliest departure time. Each hold is only allowed where it is safe, and
+/// it is weighted by the planner like any other wait.
+///
+/// Arrival windows are enforced by the [`GoalSE2`] of each leg, so a sequence
+/// whose stops cannot all be reached within their windows is reported as
+/// impossible.
+pub fn plan_sequence<D, Halting, K, const R: u32, Cost>(
+    planner: &Planner<AStarConnect<D>, Halting>,
+    start: StartSE2<K>,
+    goal: &SequentialGoalSE2<K>,
+) -> PlanSequenceResult<D, K, R, Cost>
+where
+    AStarConnect<SequenceSE2<D>>: Coherent<StartSE2<K>, SequentialGoalSE2<K>>
+        + Solvable<
+            SequentialGoalSE2<K>,
+            Solution = Path<SequenceState<StateSE2<K, R>>, SafeActionSE2, Cost>,
+        >,
+    Halting: Halt<<AStarConnect<SequenceSE2<D>> as Algorithm>::Memory> + Clone,
+    D: Clone,
+    K: Clone,
+{
+    if goal.stops.is_empty() {
+        return Err(PlanSequenceError::EmptySequence);
+    }
+
+    let status = planner
+        .clone()
+        .map(|AStarConnect(domain)| AStarConnect(SequenceSE2::new(domain)))
+        .plan(start, goal.clone())
+        .map_err(PlanSequenceError::Init)?
+        .solve()
+        .map_err(PlanSequenceError::Step)?;
+
+    Ok(status.map(|path| Path {
+        initial_state: path.initial_state.state,
+        sequence: path
+            .sequence
+            .into_iter()
+            // Arriving at a stop that the agent is already on has no motion.
+            .filter(|(action, _)| !action.is_empty())
+            .map(|(action, state)| (action, state.state))
+            .collect(),
+        total_cost: path.total_cost,
+    }))
+}
+
+/// The result of [`plan_sequence`] for a domain `D`.
+pub type PlanSequenceResult<D, K, const R: u32, Cost> = Result<
+    SearchStatus<Path<StateSE2<K, R>, SafeActionSE2, Cost>>,
+    PlanSequenceError<
+        <AStarConnect<SequenceSE2<D>> as Coherent<StartSE2<K>, SequentialGoalSE2<K>>>::InitError,
+        <AStarConnect<SequenceSE2<D>> as Solvable<SequentialGoalSE2<K>>>::StepError,
+    >,
+>;
+
+#[derive(Debug, ThisError)]
+pub enum PlanSequenceError<I, S> {
+    #[error("The sequential goal does not contain any stops")]
+    EmptySequence,
+    #[error("The search for the sequence could not be initialized:\n{0:?}")]
+    Init(I),
+    #[error("An error occurred while searching for the sequence:\n{0:?}")]
+    Step(S),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        graph::{SharedGraph, SimpleGraph},
+        motion::{
+            r2::WaypointR2,
+            se2::{DifferentialDriveLineFollow, Point},
+            CcbsEnvironment, CircularProfile, DynamicCircularObstacle, DynamicEnvironment,
+            Trajectory, TravelEffortCost,
+        },
+        premade::SippSE2,
+    };
+    use approx::assert_relative_eq;
+
+    fn make_planner_in(
+        environment: DynamicEnvironment<WaypointSE2>,
+    ) -> Planner<AStarConnect<SippSE2<SimpleGraph<Point, ()>>>> {
+        let graph = SharedGraph::new(SimpleGraph::from_iters(
+            [
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 0.0),
+                Point::new(2.0, 0.0),
+            ],
+            [(0, 1, ()), (1, 0, ()), (1, 2, ()), (2, 1, ())],
+        ));
+        let environment = Arc::new(CcbsEnvironment::new(Arc::new(environment)));
+
+        Planner::new(AStarConnect(
+            SippSE2::new_sipp_se2(
+                graph.clone(),
+                graph,
+                DifferentialDriveLineFollow::new(1.0, 1.0).unwrap(),
+                environment,
+                TravelEffortCost::default(),
+            )
+            .unwrap(),
+        ))
+    }
+
+    fn make_profile() -> CircularProfile {
+        CircularProfile::new(0.1, 0.25, 1.0).unwrap()
+    }
+
+    fn make_planner() -> Planner<AStarConnect<SippSE2<SimpleGraph<Point, ()>>>> {
+        make_planner_in(DynamicEnvironment::new(make_profile()))
+    }
+
+    /// An obstacle that crosses vertex 1 at 3s
+    fn make_crossing_environment() -> DynamicEnvironment<WaypointSE2> {
+        let mut environment = DynamicEnvironment::new(make_profile());
+        environment.obstacles.push(
+            DynamicCircularObstacle::new(make_profile()).with_trajectory(Some(
+                Trajectory::from_iter([
+                    WaypointSE2::new_f64(0.0, 1.0, -3.0, 90f64.to_radians()),
+                    WaypointSE2::new_f64(6.0, 1.0, 3.0, 90f64.to_radians()),
+                ])
+                .unwrap(),
+            )),
+        );
+        environment
+    }
+
+    #[test]
+    fn test_sequence_visits_stops_in_order() {
+        let planner = make_planner();
+        let goal = SequentialGoalSE2::new(Vec::new())
+            .with_stop(StopSE2::new(2usize).with_dwell(Duration::from_secs(5)))
+            .with_stop(0usize);
+
+        let solution = plan_sequence(&planner, (0usize, 0.0).into(), &goal)
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        assert!(solution
+            .sequence
+            .iter()
+            .any(|(_, state)| state.key.vertex == 2));
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_eq!(final_state.key.vertex, 0);
+
+        // Drive 2m out, dwell for 5s, turn around, and drive 2m back.
+        let expected_arrival = 2.0 + 5.0 + std::f64::consts::PI + 2.0;
+        assert_relative_eq!(
+            final_state.waypoint.time.as_secs_f64(),
+            expected_arrival,
+            max_relative = 0.01
+        );
+
+        let trajectory = solution
+            .make_trajectory::<WaypointSE2>()
+            .unwrap()
+            .unwrap()
+            .trajectory;
+        assert_relative_eq!(
+            trajectory.finish_motion_time().as_secs_f64(),
+            expected_arrival,
+            max_relative = 0.01
+        );
+    }
+
+    #[test]
+    fn test_sequence_time_windows() {
+        let planner = make_planner();
+
+        // The agent may not leave until 3s have passed
+        let goal = SequentialGoalSE2::new(Vec::new())
+            .with_stop(2usize)
+            .with_earliest_departure(Some(TimePoint::from_secs_f64(3.0)));
+        let solution = plan_sequence(&planner, (0usize, 0.0).into(), &goal)
+            .unwrap()
+            .solution()
+            .unwrap();
+        assert_relative_eq!(solution.initial_state.waypoint.time.as_secs_f64(), 0.0);
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_relative_eq!(
+            final_state.waypoint.time.as_secs_f64(),
+            5.0,
+            max_relative = 0.01
+        );
+
+        // The agent holds at its final stop until it may depart
+        let goal = SequentialGoalSE2::new(Vec::new()).with_stop(
+            StopSE2::new(2usize)
+                .with_dwell(Duration::from_secs(5))
+                .with_earliest_departure(Some(TimePoint::from_secs_f64(10.0))),
+        );
+        let solution = plan_sequence(&planner, (0usize, 0.0).into(), &goal)
+            .unwrap()
+            .solution()
+            .unwrap();
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_eq!(final_state.key.vertex, 2);
+        assert_relative_eq!(
+            final_state.waypoint.time.as_secs_f64(),
+            10.0,
+            max_relative = 0.01
+        );
+
+        let goal = SequentialGoalSE2::new(Vec::new())
+            .with_stop(StopSE2::new(2usize).with_dwell(Duration::from_secs(5)));
+        let solution = plan_sequence(&planner, (0usize, 0.0).into(), &goal)
+            .unwrap()
+            .solution()
+            .unwrap();
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_relative_eq!(
+            final_state.waypoint.time.as_secs_f64(),
+            7.0,
+            max_relative = 0.01
+        );
+
+        // The stop cannot be reached before its deadline
+        let goal = SequentialGoalSE2::new(Vec::new()).with_stop(
+            StopSE2::new(2usize).with_latest_arrival(Some(TimePoint::from_secs_f64(1.0))),
+        );
+        let status = plan_sequence(&planner, (0usize, 0.0).into(), &goal).unwrap();
+        assert!(status.impossible());
+    }
+
+    #[test]
+    fn test_sequence_holds_are_safe() {
+        let planner = make_planner_in(make_crossing_environment());
+        let goal = SequentialGoalSE2::new(Vec::new())
+            .with_stop(StopSE2::new(1usize).with_dwell(Duration::from_secs(5)))
+            .with_stop(2usize);
+
+        let solution = plan_sequence(&planner, (0usize, 0.0).into(), &goal)
+            .unwrap()
+            .solution()
+            .unwrap();
+        let final_state = &solution.sequence.last().unwrap().1;
+        assert_eq!(final_state.key.vertex, 2);
+
+        // Dwelling at the first stop as soon as possible would overlap the
+        // obstacle, so the agent needs to arrive there after it has passed.
+        assert!(final_state.waypoint.time.as_secs_f64() > 8.0);
+
+        let environment = make_crossing_environment();
+        let trajectory = solution
+            .make_trajectory::<WaypointSE2>()
+            .unwrap()
+            .unwrap()
+            .trajectory;
+        for [wp0, wp1] in trajectory.iter().pairs() {
+            let wp0: WaypointR2 = wp0.into();
+            let wp1: WaypointR2 = wp1.into();
+            assert!(is_safe_segment((&wp0, &wp1), None, &environment));
+        }
+    }
+}
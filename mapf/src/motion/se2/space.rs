@@ -16,9 +16,10 @@
 */
 
 use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
     domain::{
-        ArrivalKeyring, Initializable, Key, Keyed, KeyedSpace, Keyring, Reversible, Satisfiable,
-        SelfKey, Space,
+        ArrivalKeyring, Fingerprinted, Initializable, Key, Keyed, KeyedSpace, Keyring, Reversible,
+        Satisfiable, SelfKey, Space,
     },
     error::{NoError, ThisError},
     graph::{Edge, Graph},
@@ -101,6 +102,12 @@ impl<K, const R: u32> Reversible for DiscreteSpaceTimeSE2<K, R> {
     }
 }
 
+impl<K, const R: u32> Fingerprinted for DiscreteSpaceTimeSE2<K, R> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&("discrete_space_time_se2", R))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct StateSE2<K, const R: u32> {
     pub key: KeySE2<K, R>,
@@ -310,6 +317,12 @@ impl<K> From<(TimePoint, K, f64)> for StartSE2<K> {
 #[derive(Debug, Clone)]
 pub struct InitializeSE2<G, const R: u32>(pub G);
 
+impl<G: Fingerprinted, const R: u32> Fingerprinted for InitializeSE2<G, R> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&(self.0.fingerprint()?, R))
+    }
+}
+
 impl<G, State, Start, Goal, const R: u32> Initializable<Start, Goal, State> for InitializeSE2<G, R>
 where
     G: Graph,
@@ -351,6 +364,16 @@ where
     }
 }
 
+impl<G: Reversible, const R: u32> Reversible for InitializeSE2<G, R> {
+    type ReversalError = G::ReversalError;
+    fn reversed(&self) -> Result<Self, Self::ReversalError>
+    where
+        Self: Sized,
+    {
+        Ok(Self(self.0.reversed()?))
+    }
+}
+
 /// Use this to initialize a SE(2) domain for [`crate::algorithm::Dijkstra`]
 /// search algorithms when using a [`DifferentialDriveLineFollow`].
 ///
@@ -737,6 +760,12 @@ impl Default for SatisfySE2 {
     }
 }
 
+impl Fingerprinted for SatisfySE2 {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&("satisfy_se2", self.rotational_threshold))
+    }
+}
+
 impl From<DifferentialDriveLineFollow> for SatisfySE2 {
     fn from(value: DifferentialDriveLineFollow) -> Self {
         Self::new(value.rotational_threshold())
@@ -773,6 +802,12 @@ where
             }
         }
 
+        if let Some(deadline) = for_goal.maybe_deadline() {
+            if by_state.time() > deadline {
+                return Ok(false);
+            }
+        }
+
         Ok(true)
     }
 }
@@ -781,7 +816,10 @@ where
 pub struct GoalSE2<K> {
     pub key: K,
     pub orientation: Option<Orientation>,
+    /// The goal cannot be reached before this time
     pub minimum_time: Option<TimePoint>,
+    /// The goal must be reached no later than this time
+    pub maximum_time: Option<TimePoint>,
 }
 
 impl<K> GoalSE2<K> {
@@ -790,6 +828,7 @@ impl<K> GoalSE2<K> {
             key,
             orientation: None,
             minimum_time: None,
+            maximum_time: None,
         }
     }
 
@@ -807,6 +846,11 @@ impl<K> GoalSE2<K> {
         self.minimum_time = minimum_time;
         self
     }
+
+    pub fn with_maximum_time(mut self, maximum_time: Option<TimePoint>) -> Self {
+        self.maximum_time = maximum_time;
+        self
+    }
 }
 
 impl<K: Key> Keyed for GoalSE2<K> {
@@ -839,6 +883,10 @@ impl<K> MaybeTimed for GoalSE2<K> {
     fn maybe_time(&self) -> Option<TimePoint> {
         self.minimum_time
     }
+
+    fn maybe_deadline(&self) -> Option<TimePoint> {
+        self.maximum_time
+    }
 }
 
 impl<K> Borrow<K> for GoalSE2<K> {
@@ -17,6 +17,8 @@
 
 use super::{Position, Vector, Velocity};
 use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::Fingerprinted,
     error::NoError,
     motion::{
         self,
@@ -89,6 +91,18 @@ impl MaybeOriented for WaypointSE2 {
     }
 }
 
+impl Fingerprinted for WaypointSE2 {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        let p = self.position.translation.vector;
+        fingerprint(&(
+            self.time.nanos_since_zero,
+            p.x,
+            p.y,
+            self.position.rotation.angle(),
+        ))
+    }
+}
+
 impl std::fmt::Debug for WaypointSE2 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("WaypointSE2")
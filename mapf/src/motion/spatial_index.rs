@@ -0,0 +1,319 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::motion::{r2::WaypointR2, BoundingBox, DynamicCircularObstacle, TimePoint, Waypoint};
+use smallvec::SmallVec;
+use std::collections::HashMap;
+
+/// The default width of the cells that trajectory segments are hashed into
+pub const DEFAULT_INDEX_CELL_SIZE: f64 = 2.0;
+
+/// Segments that would need to be hashed into more cells than this are kept in
+/// a separate list that every query inspects.
+const MAX_CELLS_PER_SEGMENT: i64 = 64;
+
+/// A uniform grid that hashes each line segment of the trajectories of
+/// obstacles into the cells covered by its bounding box. Queries only inspect
+/// the segments that are near a region and that overlap a span of time, so
+/// the cost of a query does not grow with the number of far away obstacles.
+///
+/// Items are identified by a `usize`, e.g. the index of an obstacle within a
+/// list. Queries give back the identifiers of the items that may be nearby.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    cell_size: f64,
+    cells: HashMap<[i64; 2], Vec<IndexedSegment>>,
+    large: Vec<IndexedSegment>,
+    /// The cells that each item was hashed into, so the item can be removed
+    cells_of_item: HashMap<usize, Vec<[i64; 2]>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IndexedSegment {
+    item: usize,
+    bounding_box: BoundingBox,
+    /// None if the segment extends into the indefinite past
+    begin: Option<TimePoint>,
+    /// None if the segment extends into the indefinite future
+    end: Option<TimePoint>,
+}
+
+impl IndexedSegment {
+    fn overlaps(&self, region: &BoundingBox, from: TimePoint, until: Option<TimePoint>) -> bool {
+        if self.end.is_some_and(|end| end < from) {
+            return false;
+        }
+
+        if let (Some(begin), Some(until)) = (self.begin, until) {
+            if until < begin {
+                return false;
+            }
+        }
+
+        region.overlaps(Some(self.bounding_box))
+    }
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        Self::new(DEFAULT_INDEX_CELL_SIZE)
+    }
+}
+
+impl SpatialIndex {
+    pub fn new(cell_size: f64) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            large: Vec::new(),
+            cells_of_item: HashMap::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells_of_item.is_empty()
+    }
+
+    /// Add every segment of the trajectory of an obstacle to the index. Each
+    /// segment is inflated by the footprint that the obstacle has along it,
+    /// including any growth from its uncertainty. Obstacles without a
+    /// trajectory are not added.
+    pub fn insert<W>(&mut self, item: usize, obstacle: &DynamicCircularObstacle<W>)
+    where
+        W: Into<WaypointR2> + Waypoint,
+    {
+        let Some(trajectory) = obstacle.trajectory() else {
+            return;
+        };
+
+        let segment_count = trajectory.len() - 1;
+        for (i, [wp0, wp1]) in trajectory.iter().pairs().enumerate() {
+            let wp0: WaypointR2 = wp0.into();
+            let wp1: WaypointR2 = wp1.into();
+            let begin = if i == 0 && trajectory.has_indefinite_initial_time() {
+                None
+            } else {
+                Some(wp0.time)
+            };
+            let end = if i + 1 == segment_count && trajectory.has_indefinite_finish_time() {
+                None
+            } else {
+                Some(wp1.time)
+            };
+
+            self.insert_segment(IndexedSegment {
+                item,
+                bounding_box: BoundingBox::for_line(
+                    &obstacle.profile_between(wp0.time, wp1.time),
+                    &wp0,
+                    &wp1,
+                ),
+                begin,
+                end,
+            });
+        }
+
+        // Items without any cells still need an entry so that they can be
+        // found when they are removed.
+        self.cells_of_item.entry(item).or_default();
+    }
+
+    /// Remove every segment of an item from the index.
+    pub fn remove(&mut self, item: usize) {
+        let Some(cells) = self.cells_of_item.remove(&item) else {
+            return;
+        };
+
+        for cell in cells {
+            if let Some(segments) = self.cells.get_mut(&cell) {
+                segments.retain(|s| s.item != item);
+                if segments.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+
+        self.large.retain(|s| s.item != item);
+    }
+
+    /// Get the items that have a segment which overlaps the region at some
+    /// time between `from` and `until`. A query without an `until` time looks
+    /// into the indefinite future. The items are sorted and each one is only
+    /// given once.
+    pub fn query(
+        &self,
+        region: &BoundingBox,
+        from: TimePoint,
+        until: Option<TimePoint>,
+    ) -> SmallVec<[usize; 16]> {
+        let mut items = SmallVec::new();
+        let mut inspect = |segment: &IndexedSegment| {
+            if segment.overlaps(region, from, until) {
+                items.push(segment.item);
+            }
+        };
+
+        let ([x0, y0], [x1, y1]) = self.cell_range(region);
+        let cell_count = x1
+            .saturating_sub(x0)
+            .saturating_add(1)
+            .saturating_mul(y1.saturating_sub(y0).saturating_add(1));
+        if cell_count > self.cells.len() as i64 {
+            // The region covers more cells than have anything in them, so it
+            // is cheaper to go through the occupied cells.
+            self.cells.values().flatten().for_each(&mut inspect);
+        } else {
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    if let Some(segments) = self.cells.get(&[x, y]) {
+                        segments.iter().for_each(&mut inspect);
+                    }
+                }
+            }
+        }
+
+        self.large.iter().for_each(&mut inspect);
+
+        items.sort_unstable();
+        items.dedup();
+        items
+    }
+
+    fn insert_segment(&mut self, segment: IndexedSegment) {
+        let ([x0, y0], [x1, y1]) = self.cell_range(&segment.bounding_box);
+        let cell_count = x1
+            .saturating_sub(x0)
+            .saturating_add(1)
+            .saturating_mul(y1.saturating_sub(y0).saturating_add(1));
+        if !(0..=MAX_CELLS_PER_SEGMENT).contains(&cell_count) {
+            self.large.push(segment);
+            return;
+        }
+
+        let cells_of_item = self.cells_of_item.entry(segment.item).or_default();
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                self.cells.entry([x, y]).or_default().push(segment);
+                cells_of_item.push([x, y]);
+            }
+        }
+    }
+
+    fn cell_range(&self, bounding_box: &BoundingBox) -> ([i64; 2], [i64; 2]) {
+        let min = bounding_box.min();
+        let max = bounding_box.max();
+        let cell = |v: f64| (v / self.cell_size).floor() as i64;
+        ([cell(min.x), cell(min.y)], [cell(max.x), cell(max.y)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{r2::Point, CircularProfile, Trajectory, Uncertainty};
+
+    fn region(x: f64, y: f64) -> BoundingBox {
+        BoundingBox::for_point(Point::new(x, y)).inflated_by(0.5)
+    }
+
+    #[test]
+    fn test_spatial_index_query() {
+        let profile = CircularProfile::new(0.5, 0.0, 0.0).unwrap();
+        let t = TimePoint::from_secs_f64;
+        let trajectory = Trajectory::from_iter([
+            WaypointR2::new(t(0.0), 0.0, 0.0),
+            WaypointR2::new(t(10.0), 10.0, 0.0),
+            WaypointR2::new(t(20.0), 10.0, 10.0),
+        ])
+        .unwrap();
+
+        let obstacle = DynamicCircularObstacle::new(profile).with_trajectory(Some(trajectory));
+        let mut index = SpatialIndex::new(1.0);
+        index.insert(3, &obstacle);
+        assert!(!index.is_empty());
+
+        assert_eq!(index.query(&region(5.0, 0.0), t(0.0), None).as_slice(), [3]);
+        assert!(index.query(&region(5.0, 5.0), t(0.0), None).is_empty());
+        // The obstacle has already left the first segment by this time
+        assert!(index.query(&region(5.0, 0.0), t(12.0), None).is_empty());
+        assert_eq!(
+            index.query(&region(10.0, 5.0), t(12.0), None).as_slice(),
+            [3]
+        );
+        // The obstacle has not reached the second segment yet
+        assert!(index
+            .query(&region(10.0, 5.0), t(0.0), Some(t(5.0)))
+            .is_empty());
+
+        index.remove(3);
+        assert!(index.is_empty());
+        assert!(index.query(&region(5.0, 0.0), t(0.0), None).is_empty());
+    }
+
+    #[test]
+    fn test_spatial_index_indefinite_finish() {
+        let profile = CircularProfile::new(0.5, 0.0, 0.0).unwrap();
+        let t = TimePoint::from_secs_f64;
+        let trajectory = Trajectory::from_iter([
+            WaypointR2::new(t(0.0), 0.0, 0.0),
+            WaypointR2::new(t(10.0), 0.0, 0.0),
+        ])
+        .unwrap()
+        .with_indefinite_finish_time(true);
+
+        // A segment that is too large for the cells is still found
+        let obstacle = DynamicCircularObstacle::new(profile).with_trajectory(Some(trajectory));
+        let mut index = SpatialIndex::new(0.01);
+        index.insert(0, &obstacle);
+        assert_eq!(
+            index.query(&region(0.0, 0.0), t(100.0), None).as_slice(),
+            [0]
+        );
+    }
+
+    #[test]
+    fn test_spatial_index_growing_uncertainty() {
+        let profile = CircularProfile::new(0.5, 0.0, 0.0).unwrap();
+        let t = TimePoint::from_secs_f64;
+        let trajectory = Trajectory::from_iter([
+            WaypointR2::new(t(0.0), 0.0, 0.0),
+            WaypointR2::new(t(10.0), 10.0, 0.0),
+            WaypointR2::new(t(20.0), 20.0, 0.0),
+        ])
+        .unwrap();
+        let obstacle = DynamicCircularObstacle::new(profile)
+            .with_trajectory(Some(trajectory))
+            .with_uncertainty(Some(Uncertainty::Growing {
+                rate: 0.2,
+                limit: None,
+            }));
+
+        let mut index = SpatialIndex::new(1.0);
+        index.insert(0, &obstacle);
+        // The first segment is only inflated by up to 2.5m, but the second
+        // segment is inflated by up to 4.5m.
+        assert!(index.query(&region(3.0, 4.0), t(0.0), None).is_empty());
+        assert_eq!(
+            index.query(&region(15.0, 4.0), t(0.0), None).as_slice(),
+            [0]
+        );
+    }
+}
@@ -18,6 +18,20 @@
 /// A trait for properties that can specify speed limits.]
 pub trait SpeedLimiter {
     fn speed_limit(&self) -> Option<f64>;
+
+    /// Extra time (in seconds) that must be spent traversing an edge on top of
+    /// the time needed to move along it, e.g. waiting for a lift to board and
+    /// then riding it to another level. By default there is no delay.
+    fn traversal_delay(&self) -> Option<f64> {
+        None
+    }
+
+    /// Extra distance (in meters) that must be travelled along an edge on top
+    /// of the straight line between its vertices, e.g. when the edge stands
+    /// for a route that winds around obstacles. By default there is none.
+    fn extra_distance(&self) -> Option<f64> {
+        None
+    }
 }
 
 /// Empty tuples `()` can be used for [`SpeedLimiter`]. They always return that
@@ -36,3 +50,18 @@ impl SpeedLimiter for SpeedLimit {
         self.0
     }
 }
+
+/// An optional [`SpeedLimiter`] places no limits when it is [`None`].
+impl<T: SpeedLimiter> SpeedLimiter for Option<T> {
+    fn speed_limit(&self) -> Option<f64> {
+        self.as_ref().and_then(T::speed_limit)
+    }
+
+    fn traversal_delay(&self) -> Option<f64> {
+        self.as_ref().and_then(T::traversal_delay)
+    }
+
+    fn extra_distance(&self) -> Option<f64> {
+        self.as_ref().and_then(T::extra_distance)
+    }
+}
@@ -40,6 +40,12 @@ pub trait Timed: MaybeTimed {
 
 pub trait MaybeTimed {
     fn maybe_time(&self) -> Option<TimePoint>;
+
+    /// The latest time that this is allowed to be reached, if there is one.
+    /// Goals use this to express arrival deadlines.
+    fn maybe_deadline(&self) -> Option<TimePoint> {
+        None
+    }
 }
 
 impl MaybeTimed for usize {
@@ -16,6 +16,10 @@
 */
 
 use super::{timed::TimeCmp, Duration, InterpError, Motion, TimePoint, Waypoint};
+use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::Fingerprinted,
+};
 use cached::{Cached, UnboundCache};
 use sorted_vec::{FindOrInsert, SortedSet};
 use std::cell::RefCell;
@@ -380,6 +384,20 @@ impl<W: Waypoint> Trajectory<W> {
     }
 }
 
+impl<W: Waypoint + Fingerprinted> Fingerprinted for Trajectory<W> {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        let waypoints = self
+            .iter()
+            .map(|wp| wp.fingerprint())
+            .collect::<Result<Vec<_>, _>>()?;
+        fingerprint(&(
+            waypoints,
+            self.indefinite_initial_time,
+            self.indefinite_finish_time,
+        ))
+    }
+}
+
 impl<W: Waypoint> std::fmt::Debug for Trajectory<W> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.debug_struct("Trajectory")
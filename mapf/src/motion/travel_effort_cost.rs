@@ -16,7 +16,8 @@
 */
 
 use crate::{
-    domain::{Cost, Reversible, Weighted},
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{Cost, Fingerprinted, Reversible, Weighted},
     error::NoError,
     motion::{Measurable, Timed},
 };
@@ -110,6 +111,12 @@ impl<State: Timed, Action: Measurable<State>> Weighted<State, Action> for Travel
     }
 }
 
+impl Fingerprinted for TravelEffortCost {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&("travel_effort", self.time, self.translation, self.rotation))
+    }
+}
+
 impl Reversible for TravelEffortCost {
     type ReversalError = NoError;
     fn reversed(&self) -> Result<Self, Self::ReversalError>
@@ -16,7 +16,8 @@
 */
 
 use crate::{
-    domain::{Cost, Reversible, Weighted},
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{Cost, Fingerprinted, Reversible, Weighted},
     error::NoError,
     motion::Timed,
 };
@@ -53,6 +54,12 @@ impl<State: Timed, Action> Weighted<State, Action> for TravelTimeCost {
     }
 }
 
+impl Fingerprinted for TravelTimeCost {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&("travel_time", self.0))
+    }
+}
+
 impl Reversible for TravelTimeCost {
     type ReversalError = NoError;
     fn reversed(&self) -> Result<Self, Self::ReversalError>
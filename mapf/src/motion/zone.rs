@@ -0,0 +1,350 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::Fingerprinted,
+    graph::occupancy::Cell,
+    motion::{
+        r2::{Point, WaypointR2},
+        BoundingBox, CircularProfile, Interpolation, Motion, TimePoint,
+    },
+};
+
+/// The area covered by a [`BlockedZone`].
+#[derive(Debug, Clone)]
+pub enum ZoneShape {
+    /// A polygon given by its corners in order. The polygon does not need to
+    /// be convex, but its edges should not cross each other.
+    Polygon(Vec<Point>),
+    /// A set of square cells from an occupancy grid.
+    Cells { cell_size: f64, cells: Vec<Cell> },
+}
+
+/// A region that agents must stay out of while it is closed, e.g. a doorway
+/// that will be shut for a few minutes or an area reserved for people.
+///
+/// A zone is closed from its closing time (inclusive) until its opening time
+/// (exclusive). A zone without a closing time has been closed since the
+/// beginning of time, and a zone without an opening time never opens again.
+#[derive(Debug, Clone)]
+pub struct BlockedZone {
+    shape: ZoneShape,
+    closes: Option<TimePoint>,
+    opens: Option<TimePoint>,
+    bounding_box: BoundingBox,
+}
+
+impl BlockedZone {
+    /// Make a zone that is closed for all time. Use [`Self::with_closing_time`]
+    /// and [`Self::with_opening_time`] to limit when it is closed.
+    pub fn new(shape: ZoneShape) -> Self {
+        let bounding_box = match &shape {
+            ZoneShape::Polygon(corners) => {
+                let mut corners = corners.iter();
+                let initial_bb = BoundingBox::for_point(
+                    corners
+                        .next()
+                        .cloned()
+                        .unwrap_or_else(|| Point::new(0.0, 0.0)),
+                );
+                corners.fold(initial_bb, |b, p| b.incorporating(*p))
+            }
+            ZoneShape::Cells { cell_size, cells } => {
+                let mut squares = cells.iter().map(|cell| cell_corners(cell, *cell_size));
+                let initial_bb = match squares.next() {
+                    Some([p0, _, p2, _]) => BoundingBox::for_point(p0).incorporating(p2),
+                    None => BoundingBox::for_point(Point::new(0.0, 0.0)),
+                };
+                squares.fold(initial_bb, |b, [p0, _, p2, _]| {
+                    b.incorporating(p0).incorporating(p2)
+                })
+            }
+        };
+
+        Self {
+            shape,
+            closes: None,
+            opens: None,
+            bounding_box,
+        }
+    }
+
+    pub fn polygon(corners: Vec<Point>) -> Self {
+        Self::new(ZoneShape::Polygon(corners))
+    }
+
+    pub fn cells(cell_size: f64, cells: Vec<Cell>) -> Self {
+        Self::new(ZoneShape::Cells { cell_size, cells })
+    }
+
+    pub fn with_closing_time(mut self, closes: Option<TimePoint>) -> Self {
+        self.closes = closes;
+        self
+    }
+
+    pub fn with_opening_time(mut self, opens: Option<TimePoint>) -> Self {
+        self.opens = opens;
+        self
+    }
+
+    pub fn shape(&self) -> &ZoneShape {
+        &self.shape
+    }
+
+    /// When the zone closes, or None if it has always been closed.
+    pub fn closes(&self) -> Option<TimePoint> {
+        self.closes
+    }
+
+    /// When the zone opens again, or None if it never opens.
+    pub fn opens(&self) -> Option<TimePoint> {
+        self.opens
+    }
+
+    pub fn bounding_box(&self) -> &BoundingBox {
+        &self.bounding_box
+    }
+
+    pub fn is_closed_at(&self, time: TimePoint) -> bool {
+        self.closes.map(|t| t <= time).unwrap_or(true)
+            && self.opens.map(|t| time < t).unwrap_or(true)
+    }
+
+    /// Get the distance from a point to the zone. Points inside the zone have
+    /// a distance of zero.
+    pub fn distance_to_point(&self, p: Point) -> f64 {
+        self.distance_to_line(p, p)
+    }
+
+    /// Get the distance from a line segment to the zone. Segments that touch
+    /// the inside of the zone have a distance of zero.
+    pub fn distance_to_line(&self, p0: Point, p1: Point) -> f64 {
+        match &self.shape {
+            ZoneShape::Polygon(corners) => polygon_line_distance(corners, p0, p1),
+            ZoneShape::Cells { cell_size, cells } => cells
+                .iter()
+                .map(|cell| polygon_line_distance(&cell_corners(cell, *cell_size), p0, p1))
+                .fold(f64::INFINITY, f64::min),
+        }
+    }
+
+    /// Check whether an agent with the given profile would be inside of this
+    /// zone while the zone is closed as the agent moves along a line segment.
+    pub fn blocks_line(
+        &self,
+        profile: &CircularProfile,
+        (wp0, wp1): (&WaypointR2, &WaypointR2),
+    ) -> bool {
+        let bb = BoundingBox::for_line(profile, wp0, wp1);
+        if !bb.overlaps(Some(self.bounding_box)) {
+            return false;
+        }
+
+        let conflict_distance = f64::max(profile.footprint_radius() - 1e-3, 0.0);
+        if (wp1.time - wp0.time).as_secs_f64().abs() < 1e-8 {
+            return (self.is_closed_at(wp0.time) || self.is_closed_at(wp1.time))
+                && self.distance_to_line(wp0.position, wp1.position) < conflict_distance;
+        }
+
+        // Only the part of the line that happens while the zone is closed
+        // matters.
+        let t_begin = match self.closes {
+            Some(closes) => closes.max(wp0.time),
+            None => wp0.time,
+        };
+        let t_end = match self.opens {
+            Some(opens) => opens.min(wp1.time),
+            None => wp1.time,
+        };
+
+        if t_end <= t_begin {
+            return false;
+        }
+
+        let motion = wp0.interpolate(wp1);
+        let (p0, p1) = match (
+            motion.compute_position(&t_begin),
+            motion.compute_position(&t_end),
+        ) {
+            (Ok(p0), Ok(p1)) => (p0, p1),
+            _ => return false,
+        };
+
+        self.distance_to_line(p0, p1) < conflict_distance
+    }
+
+    /// Check whether an agent with the given profile may not stand at a point
+    /// while the zone is closed.
+    pub fn covers_point(&self, profile: &CircularProfile, p: Point) -> bool {
+        let critical_distance = f64::max(profile.footprint_radius(), 1e-3);
+        self.distance_to_point(p) < critical_distance
+    }
+}
+
+impl Fingerprinted for BlockedZone {
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        let closes = self.closes.map(|t| t.nanos_since_zero);
+        let opens = self.opens.map(|t| t.nanos_since_zero);
+        match &self.shape {
+            ZoneShape::Polygon(corners) => {
+                let corners: Vec<_> = corners.iter().map(|p| (p.x, p.y)).collect();
+                fingerprint(&("polygon", corners, closes, opens))
+            }
+            ZoneShape::Cells { cell_size, cells } => {
+                fingerprint(&("cells", cell_size, cells, closes, opens))
+            }
+        }
+    }
+}
+
+fn cell_corners(cell: &Cell, cell_size: f64) -> [Point; 4] {
+    [
+        cell.bottom_left_point(cell_size),
+        cell.bottom_right_point(cell_size),
+        cell.top_right_point(cell_size),
+        cell.top_left_point(cell_size),
+    ]
+}
+
+fn polygon_line_distance(corners: &[Point], p0: Point, p1: Point) -> f64 {
+    if corners.len() > 2 && (polygon_contains(corners, p0) || polygon_contains(corners, p1)) {
+        return 0.0;
+    }
+
+    let mut distance = f64::INFINITY;
+    for (i, a) in corners.iter().enumerate() {
+        let b = &corners[(i + 1) % corners.len()];
+        distance = f64::min(distance, line_line_distance((a, b), (&p0, &p1)));
+    }
+
+    distance
+}
+
+fn polygon_contains(corners: &[Point], p: Point) -> bool {
+    // Count how many edges are crossed by a ray cast from p in the +x direction
+    let mut inside = false;
+    for (i, a) in corners.iter().enumerate() {
+        let b = &corners[(i + 1) % corners.len()];
+        if (a.y > p.y) != (b.y > p.y) {
+            let x_cross = a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y);
+            if p.x < x_cross {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+fn point_line_distance(p: &Point, (a, b): (&Point, &Point)) -> f64 {
+    let ab = b - a;
+    let length_squared = ab.dot(&ab);
+    if length_squared < 1e-16 {
+        return (p - a).norm();
+    }
+
+    let s = ((p - a).dot(&ab) / length_squared).clamp(0.0, 1.0);
+    (p - (a + ab * s)).norm()
+}
+
+fn line_line_distance(line_a: (&Point, &Point), line_b: (&Point, &Point)) -> f64 {
+    let cross = |o: &Point, p: &Point, q: &Point| -> f64 {
+        let u = p - o;
+        let v = q - o;
+        u.x * v.y - u.y * v.x
+    };
+
+    let d0 = cross(line_a.0, line_a.1, line_b.0);
+    let d1 = cross(line_a.0, line_a.1, line_b.1);
+    let d2 = cross(line_b.0, line_b.1, line_a.0);
+    let d3 = cross(line_b.0, line_b.1, line_a.1);
+    if d0 * d1 < 0.0 && d2 * d3 < 0.0 {
+        // The lines cross each other
+        return 0.0;
+    }
+
+    [
+        point_line_distance(line_a.0, line_b),
+        point_line_distance(line_a.1, line_b),
+        point_line_distance(line_b.0, line_a),
+        point_line_distance(line_b.1, line_a),
+    ]
+    .into_iter()
+    .fold(f64::INFINITY, f64::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_zone_distances() {
+        let zone = BlockedZone::polygon(vec![
+            Point::new(0.0, 0.0),
+            Point::new(2.0, 0.0),
+            Point::new(2.0, 2.0),
+            Point::new(0.0, 2.0),
+        ]);
+
+        assert_relative_eq!(zone.distance_to_point(Point::new(1.0, 1.0)), 0.0);
+        assert_relative_eq!(zone.distance_to_point(Point::new(3.0, 1.0)), 1.0);
+        assert_relative_eq!(
+            zone.distance_to_line(Point::new(-1.0, 1.0), Point::new(3.0, 1.0)),
+            0.0
+        );
+        assert_relative_eq!(
+            zone.distance_to_line(Point::new(-1.0, 3.0), Point::new(3.0, 3.0)),
+            1.0
+        );
+
+        let zone = BlockedZone::cells(1.0, vec![Cell::new(0, 0), Cell::new(2, 0)]);
+        assert_relative_eq!(zone.distance_to_point(Point::new(1.5, 0.5)), 0.5);
+        assert_relative_eq!(zone.distance_to_point(Point::new(2.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn test_zone_closing_times() {
+        let profile = CircularProfile::new(0.25, 0.0, 0.0).unwrap();
+        let zone = BlockedZone::cells(1.0, vec![Cell::new(0, 0)])
+            .with_closing_time(Some(TimePoint::from_secs_f64(10.0)))
+            .with_opening_time(Some(TimePoint::from_secs_f64(20.0)));
+
+        assert!(!zone.is_closed_at(TimePoint::from_secs_f64(5.0)));
+        assert!(zone.is_closed_at(TimePoint::from_secs_f64(10.0)));
+        assert!(!zone.is_closed_at(TimePoint::from_secs_f64(20.0)));
+
+        // Crossing the cell before it closes or after it opens is fine
+        let crossing = |t0: f64, t1: f64| {
+            (
+                WaypointR2::new(TimePoint::from_secs_f64(t0), -1.0, 0.5),
+                WaypointR2::new(TimePoint::from_secs_f64(t1), 2.0, 0.5),
+            )
+        };
+        let (wp0, wp1) = crossing(0.0, 3.0);
+        assert!(!zone.blocks_line(&profile, (&wp0, &wp1)));
+        let (wp0, wp1) = crossing(20.0, 23.0);
+        assert!(!zone.blocks_line(&profile, (&wp0, &wp1)));
+        let (wp0, wp1) = crossing(9.0, 12.0);
+        assert!(zone.blocks_line(&profile, (&wp0, &wp1)));
+
+        // The agent has already passed the cell by the time it closes
+        let (wp0, wp1) = crossing(7.5, 10.5);
+        assert!(!zone.blocks_line(&profile, (&wp0, &wp1)));
+    }
+}
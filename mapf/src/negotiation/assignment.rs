@@ -0,0 +1,276 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{make_planner, negotiate, NegotiationError, NegotiationNode, Scenario};
+use crate::{
+    algorithm::SearchStatus, error::ThisError, motion::se2::plan_sequence, motion::CircularProfile,
+};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, ThisError)]
+pub enum AssignmentError {
+    #[error("Unable to create a planner for {0}: {1}")]
+    PlannerSetup(String, String),
+    #[error("No agent can reach goal {0:?}")]
+    UnreachableGoal([i64; 2]),
+    #[error("The assigned paths could not be negotiated:\n{0}")]
+    Negotiation(NegotiationError),
+}
+
+/// The outcome of [`negotiate_assignment`]
+pub struct AssignmentSolution {
+    /// The goal that was assigned to each agent. Agents that were not needed
+    /// for any goal are assigned their own start cell.
+    pub goals: BTreeMap<String, [i64; 2]>,
+    /// The cost that each agent would have to reach its assigned goal if it
+    /// were alone.
+    pub costs: BTreeMap<String, f64>,
+    pub solution: NegotiationNode,
+    pub nodes: Vec<NegotiationNode>,
+    pub name_map: HashMap<usize, String>,
+}
+
+/// Assign the agents of the scenario to the given goals and then negotiate
+/// their paths. The goals of the scenario agents are ignored, as well as their
+/// goal regions, but their stops and time windows are kept.
+///
+/// The cost of each agent reaching each goal is found with the single-agent
+/// planner of the agent, and the assignment that minimizes the sum of those
+/// costs is found with the Hungarian algorithm. If there are more goals than
+/// agents then some goals will be left unassigned. If there are more agents
+/// than goals then some agents will stay where they are.
+pub fn negotiate_assignment(
+    scenario: &Scenario,
+    goals: &[[i64; 2]],
+    queue_length_limit: Option<usize>,
+) -> Result<AssignmentSolution, AssignmentError> {
+    let grid = scenario.make_grid();
+    let zones = scenario.make_blocked_zones();
+    let names: Vec<_> = scenario.agents.keys().cloned().collect();
+    let agents: Vec<_> = scenario.agents.values().cloned().collect();
+
+    let mut costs = vec![vec![f64::INFINITY; goals.len()]; agents.len()];
+    for (i, (name, agent)) in names.iter().zip(agents.iter()).enumerate() {
+        let profile = CircularProfile::new(agent.radius, 0.0, 0.0)
+            .map_err(|_| AssignmentError::PlannerSetup(name.clone(), "radius".to_owned()))?;
+        let planner = make_planner(agent, profile, &grid, &zones, queue_length_limit)
+            .map_err(|err| AssignmentError::PlannerSetup(name.clone(), err))?;
+
+        for (j, goal) in goals.iter().enumerate() {
+            let mut agent = agent.clone();
+            agent.goal = *goal;
+            let status = plan_sequence(&planner, agent.make_start(), &agent.make_sequential_goal());
+            if let Ok(SearchStatus::Solved(solution)) = status {
+                costs[i][j] = solution.total_cost.0;
+            }
+        }
+    }
+
+    // Agents that do not receive a goal stay at their start cell, which costs
+    // nothing.
+    let size = agents.len().max(goals.len());
+    let square: Vec<Vec<f64>> = (0..size)
+        .map(|i| {
+            (0..size)
+                .map(|j| {
+                    costs
+                        .get(i)
+                        .and_then(|row| row.get(j))
+                        .cloned()
+                        .unwrap_or(0.0)
+                })
+                .collect()
+        })
+        .collect();
+
+    let assignment = hungarian(&square);
+
+    let mut assigned_scenario_agents = BTreeMap::new();
+    let mut assigned_goals = BTreeMap::new();
+    let mut assigned_costs = BTreeMap::new();
+    for (i, (name, agent)) in names.iter().zip(agents).enumerate() {
+        let j = assignment[i];
+        let mut agent = agent;
+        agent.goal_region.clear();
+        let cost = if j < goals.len() {
+            if costs[i][j].is_infinite() {
+                return Err(AssignmentError::UnreachableGoal(goals[j]));
+            }
+            agent.goal = goals[j];
+            costs[i][j]
+        } else {
+            agent.goal = agent.start;
+            agent.stops.clear();
+            0.0
+        };
+
+        assigned_goals.insert(name.clone(), agent.goal);
+        assigned_costs.insert(name.clone(), cost);
+        assigned_scenario_agents.insert(name.clone(), agent);
+    }
+
+    let scenario = Scenario {
+        agents: assigned_scenario_agents,
+        // Negotiation does not consider the scenario obstacles
+        obstacles: Vec::new(),
+        zones: scenario.zones.clone(),
+        capacity_regions: scenario.capacity_regions.clone(),
+        occupancy: scenario.occupancy.clone(),
+        cell_size: scenario.cell_size,
+        camera_bounds: scenario.camera_bounds,
+    };
+
+    let (solution, nodes, name_map) =
+        negotiate(&scenario, queue_length_limit).map_err(AssignmentError::Negotiation)?;
+
+    Ok(AssignmentSolution {
+        goals: assigned_goals,
+        costs: assigned_costs,
+        solution,
+        nodes,
+        name_map,
+    })
+}
+
+/// Solve the linear assignment problem for a square cost matrix using the
+/// Hungarian algorithm. Element `i` of the return value is the column that row
+/// `i` is assigned to. Infinite costs are treated as forbidden assignments,
+/// but a forbidden assignment may still be returned when there is no way to
+/// avoid it.
+pub fn hungarian(costs: &[Vec<f64>]) -> Vec<usize> {
+    let n = costs.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    // Replace infinite costs with a penalty that outweighs any finite
+    // assignment so the potentials stay finite.
+    let finite_sum: f64 = costs
+        .iter()
+        .flat_map(|row| row.iter())
+        .filter(|c| c.is_finite())
+        .map(|c| c.abs())
+        .sum();
+    let forbidden = 1.0 + 2.0 * finite_sum;
+    let cost = |i: usize, j: usize| {
+        let c = costs[i][j];
+        if c.is_finite() {
+            c
+        } else {
+            forbidden
+        }
+    };
+
+    // Rows and columns are indexed from 1 so that index 0 can be used as the
+    // virtual starting column of each augmenting path.
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut row_of_column = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+    for i in 1..=n {
+        row_of_column[0] = i;
+        let mut j0 = 0;
+        let mut min_v = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of_column[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+
+                let reduced = cost(i0 - 1, j - 1) - u[i0] - v[j];
+                if reduced < min_v[j] {
+                    min_v[j] = reduced;
+                    way[j] = j0;
+                }
+
+                if min_v[j] < delta {
+                    delta = min_v[j];
+                    j1 = j;
+                }
+            }
+
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of_column[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_v[j] -= delta;
+                }
+            }
+
+            j0 = j1;
+            if row_of_column[j0] == 0 {
+                break;
+            }
+        }
+
+        loop {
+            let j1 = way[j0];
+            row_of_column[j0] = row_of_column[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..=n {
+        assignment[row_of_column[j] - 1] = j - 1;
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::{test_scenario, Agent};
+
+    #[test]
+    fn test_hungarian() {
+        let costs = vec![
+            vec![4.0, 1.0, 3.0],
+            vec![2.0, 0.0, 5.0],
+            vec![3.0, 2.0, 2.0],
+        ];
+        assert_eq!(hungarian(&costs), vec![1, 0, 2]);
+
+        let costs = vec![vec![1.0, f64::INFINITY], vec![2.0, 10.0]];
+        assert_eq!(hungarian(&costs), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_anonymous_goals() {
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [0, 0])),
+            ("B", Agent::new([5, 0], [5, 0])),
+            ("C", Agent::new([0, 5], [0, 5])),
+        ]);
+
+        let outcome = negotiate_assignment(&scenario, &[[6, 0], [1, 0]], None).unwrap();
+        assert_eq!(outcome.goals["A"], [1, 0]);
+        assert_eq!(outcome.goals["B"], [6, 0]);
+        // There is no goal left for C, so it stays where it is.
+        assert_eq!(outcome.goals["C"], [0, 5]);
+        assert_eq!(outcome.solution.proposals.len(), 3);
+    }
+}
@@ -0,0 +1,490 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{Agent, Conflict, Proposal, Segment};
+use crate::{
+    algorithm::path::DecisionRange,
+    domain::Cost,
+    graph::{
+        occupancy::{AccessibilityGraph, Cell, SparseGrid},
+        Graph,
+    },
+    motion::TravelEffortCost,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+};
+
+/// How much a conflict is known to cost the agents involved in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Cardinality {
+    /// Both agents must increase their cost to resolve the conflict.
+    Cardinal,
+    /// One of the agents must increase its cost to resolve the conflict.
+    SemiCardinal,
+    /// Either agent may be able to resolve the conflict without increasing its
+    /// cost.
+    NonCardinal,
+}
+
+/// How the negotiation chooses which conflict of a node to split on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictSelection {
+    /// Split on the earliest conflict
+    #[default]
+    Earliest,
+    /// Split on cardinal conflicts first, then semi-cardinal conflicts, then
+    /// non-cardinal conflicts. Conflicts of the same cardinality are split
+    /// earliest first.
+    Cardinal,
+}
+
+/// Heuristics that estimate how many agents of a negotiation node still need
+/// to increase their cost. Nodes are expanded in order of their cost plus a
+/// small increase for each of those agents, and nodes that are tied on that
+/// are ranked by their heuristic and then their depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HighLevelHeuristic {
+    /// Nodes are only ranked by their cost and depth
+    #[default]
+    Zero,
+    /// The size of the minimum vertex cover of the graph whose edges are the
+    /// cardinal conflicts of the node. This is a lower bound on how many agents
+    /// still need to increase their cost.
+    ConflictGraph,
+}
+
+/// Finds the cardinality of conflicts by comparing them against the cells and
+/// edges that each agent could occupy at the time of the conflict without its
+/// path costing any more than its current proposal does.
+///
+/// The agent leaves at the start time of its proposal. The cost of reaching
+/// its goal through a cell is bounded from below using straight-line distances
+/// between neighboring cells at full speed while ignoring rotation, so the
+/// agent could really occupy no more than what is found. As a result a
+/// conflict may be reported as less cardinal than it is, but never as more
+/// cardinal.
+///
+/// Agents with stops are never treated as bottlenecked, since their reachable
+/// sets depend on which stop they are heading to.
+pub(super) struct CardinalityAnalysis<'a> {
+    graphs: &'a [AccessibilityGraph<SparseGrid>],
+    agents: &'a [Agent],
+    cell_size: f64,
+    cache: HashMap<(usize, i64, u64), Arc<Reachability>>,
+}
+
+struct Reachability {
+    /// Shortest distance from the start to each cell
+    from_start: HashMap<Cell, f64>,
+    /// Shortest distance from each cell to the goal
+    to_goal: HashMap<Cell, f64>,
+    /// When the proposal leaves its start, in seconds
+    start_time: f64,
+    /// The cost of the proposal
+    cost: f64,
+    speed: f64,
+    weights: TravelEffortCost,
+}
+
+impl Reachability {
+    /// The earliest and latest time that the agent could be on a cell without
+    /// its cost exceeding the cost of its proposal.
+    fn window(&self, cell: &Cell) -> Option<(f64, f64)> {
+        let from_start = *self.from_start.get(cell)?;
+        let to_goal = *self.to_goal.get(cell)?;
+        let spare = self.cost - self.weights.translation * (from_start + to_goal);
+        let earliest = self.start_time + from_start / self.speed;
+        let latest = if self.weights.time > 0.0 {
+            self.start_time + spare / self.weights.time - to_goal / self.speed
+        } else if spare >= -COST_TOLERANCE {
+            f64::INFINITY
+        } else {
+            f64::NEG_INFINITY
+        };
+
+        (earliest <= latest + TIME_TOLERANCE).then_some((earliest, latest))
+    }
+}
+
+/// Allowance for rounding when comparing travel times, in seconds
+const TIME_TOLERANCE: f64 = 1e-3;
+
+/// Allowance for rounding when comparing costs
+const COST_TOLERANCE: f64 = 1e-3;
+
+/// Allowance for rounding when comparing distances, in meters
+const DISTANCE_TOLERANCE: f64 = 1e-3;
+
+impl<'a> CardinalityAnalysis<'a> {
+    pub(super) fn new(
+        graphs: &'a [AccessibilityGraph<SparseGrid>],
+        agents: &'a [Agent],
+        cell_size: f64,
+    ) -> Self {
+        Self {
+            graphs,
+            agents,
+            cell_size,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub(super) fn classify(
+        &mut self,
+        conflict: &Conflict,
+        proposals: &HashMap<usize, Proposal>,
+    ) -> Cardinality {
+        let bottlenecks = conflict
+            .segments
+            .iter()
+            .filter(|segment| self.is_bottleneck(conflict, segment, proposals))
+            .count();
+
+        match bottlenecks {
+            2 => Cardinality::Cardinal,
+            1 => Cardinality::SemiCardinal,
+            _ => Cardinality::NonCardinal,
+        }
+    }
+
+    /// Find the lower bound of the selected heuristic for a set of conflicts.
+    pub(super) fn heuristic(
+        &mut self,
+        heuristic: HighLevelHeuristic,
+        conflicts: &[Conflict],
+        proposals: &HashMap<usize, Proposal>,
+    ) -> usize {
+        match heuristic {
+            HighLevelHeuristic::Zero => 0,
+            HighLevelHeuristic::ConflictGraph => {
+                let mut edges: Vec<(usize, usize)> = conflicts
+                    .iter()
+                    .filter(|c| self.classify(c, proposals) == Cardinality::Cardinal)
+                    .map(|c| {
+                        let (a, b) = (c.segments[0].agent, c.segments[1].agent);
+                        (a.min(b), a.max(b))
+                    })
+                    .collect();
+                edges.sort_unstable();
+                edges.dedup();
+                minimum_vertex_cover(&edges)
+            }
+        }
+    }
+
+    /// Check whether every way that the agent could reach its goal without
+    /// increasing its cost passes through the cells of its segment at the time
+    /// of the conflict.
+    fn is_bottleneck(
+        &mut self,
+        conflict: &Conflict,
+        segment: &Segment,
+        proposals: &HashMap<usize, Proposal>,
+    ) -> bool {
+        let Some(proposal) = proposals.get(&segment.agent) else {
+            return false;
+        };
+        let Some(reachability) = self.reachability(segment.agent, proposal) else {
+            return false;
+        };
+
+        let footprint: HashSet<Cell> = match &segment.range {
+            DecisionRange::Between(range) => range.iter().map(|dp| dp.state.key.vertex).collect(),
+            DecisionRange::Before(s, _) | DecisionRange::After(s, _) => [s.key.vertex].into(),
+        };
+
+        let t = conflict.time.as_secs_f64();
+        let windows: HashMap<Cell, (f64, f64)> = reachability
+            .from_start
+            .keys()
+            .filter_map(|cell| Some((*cell, reachability.window(cell)?)))
+            .collect();
+
+        let mut occupied = false;
+        for (cell, (earliest, latest)) in &windows {
+            if *earliest <= t + TIME_TOLERANCE && t - TIME_TOLERANCE <= *latest {
+                occupied = true;
+                if !footprint.contains(cell) {
+                    return false;
+                }
+            }
+
+            // The agent could also be in the middle of moving to a neighbor
+            let graph = &self.graphs[segment.agent];
+            let p = cell.center_point(self.cell_size);
+            for (_, next) in graph.edges_from_vertex(cell) {
+                let Some((_, next_latest)) = windows.get(&next) else {
+                    continue;
+                };
+                let duration = (next.center_point(self.cell_size) - p).norm() / reachability.speed;
+                let moving = *earliest < t - TIME_TOLERANCE
+                    && t + TIME_TOLERANCE < *next_latest
+                    && *earliest + duration <= *next_latest + TIME_TOLERANCE;
+                if moving {
+                    occupied = true;
+                    if !footprint.contains(cell) || !footprint.contains(&next) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        // If the agent could not be anywhere then the analysis does not apply
+        occupied
+    }
+
+    fn reachability(&mut self, agent: usize, proposal: &Proposal) -> Option<Arc<Reachability>> {
+        let params = &self.agents[agent];
+        if !params.stops.is_empty() {
+            return None;
+        }
+
+        let start_time = proposal.meta.trajectory.initial_motion_time();
+        let key = (
+            agent,
+            start_time.nanos_since_zero,
+            proposal.cost.0.to_bits(),
+        );
+        if let Some(reachability) = self.cache.get(&key) {
+            return Some(reachability.clone());
+        }
+
+        // Every meter of the path costs at least this much
+        let weights = params.make_cost();
+        let rate = weights.time / params.speed + weights.translation;
+        if rate <= 0.0 || !rate.is_finite() {
+            return None;
+        }
+        let max_distance = proposal.cost.0 / rate;
+
+        let graph = &self.graphs[agent];
+        let reachability = Arc::new(Reachability {
+            from_start: distances(graph, params.start_cell(), self.cell_size, max_distance),
+            to_goal: distances(graph, params.goal_cell(), self.cell_size, max_distance),
+            start_time: start_time.as_secs_f64(),
+            cost: proposal.cost.0,
+            speed: params.speed,
+            weights,
+        });
+        self.cache.insert(key, reachability.clone());
+        Some(reachability)
+    }
+}
+
+/// Dijkstra search over the cells of the graph, measuring the distance to
+/// each cell. Cells that are further than `limit` are left out.
+fn distances(
+    graph: &AccessibilityGraph<SparseGrid>,
+    from: Cell,
+    cell_size: f64,
+    limit: f64,
+) -> HashMap<Cell, f64> {
+    let mut distances = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((Cost(0.0), from.x, from.y)));
+    while let Some(Reverse((Cost(distance), x, y))) = queue.pop() {
+        let cell = Cell::new(x, y);
+        if distances.contains_key(&cell) {
+            continue;
+        }
+        distances.insert(cell, distance);
+
+        let p = cell.center_point(cell_size);
+        for (_, next) in graph.edges_from_vertex(&cell) {
+            if distances.contains_key(&next) {
+                continue;
+            }
+
+            let next_distance = distance + (next.center_point(cell_size) - p).norm();
+            if next_distance <= limit + DISTANCE_TOLERANCE {
+                queue.push(Reverse((Cost(next_distance), next.x, next.y)));
+            }
+        }
+    }
+
+    distances
+}
+
+/// Graphs with more vertices than this use a matching as the lower bound of
+/// their minimum vertex cover.
+const MAX_EXACT_COVER_VERTICES: usize = 16;
+
+/// Size of the minimum vertex cover of an undirected graph. Large graphs get
+/// the size of a maximal matching instead, which is never larger than the
+/// minimum vertex cover.
+fn minimum_vertex_cover(edges: &[(usize, usize)]) -> usize {
+    let mut vertices: Vec<usize> = edges.iter().flat_map(|(a, b)| [*a, *b]).collect();
+    vertices.sort_unstable();
+    vertices.dedup();
+
+    if vertices.len() > MAX_EXACT_COVER_VERTICES {
+        let mut matched = HashSet::new();
+        let mut matching = 0;
+        for (a, b) in edges {
+            if !matched.contains(a) && !matched.contains(b) {
+                matched.insert(*a);
+                matched.insert(*b);
+                matching += 1;
+            }
+        }
+        return matching;
+    }
+
+    let index: HashMap<usize, usize> = vertices.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+    let masks: Vec<(u32, u32)> = edges
+        .iter()
+        .map(|(a, b)| (1 << index[a], 1 << index[b]))
+        .collect();
+    (0_u32..(1 << vertices.len()))
+        .filter(|cover| masks.iter().all(|(a, b)| cover & (a | b) != 0))
+        .map(|cover| cover.count_ones() as usize)
+        .min()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        graph::occupancy::{Accessibility, Grid},
+        negotiation::{make_graph, negotiate, test_scenario, Scenario},
+    };
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    #[test]
+    fn test_minimum_vertex_cover() {
+        assert_eq!(minimum_vertex_cover(&[]), 0);
+        // A star is covered by its center
+        assert_eq!(minimum_vertex_cover(&[(0, 1), (0, 2), (0, 3)]), 1);
+        // A triangle needs two vertices
+        assert_eq!(minimum_vertex_cover(&[(0, 1), (1, 2), (0, 2)]), 2);
+        // Two separate edges
+        assert_eq!(minimum_vertex_cover(&[(4, 7), (1, 9)]), 2);
+    }
+
+    #[test]
+    fn test_cardinal_selection_finds_solution() {
+        use crate::negotiation::{negotiate_with_settings, test_scenario, NegotiationSettings};
+
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [6, 0])),
+            ("B", Agent::new([6, 0], [0, 0])),
+        ]);
+
+        let settings = NegotiationSettings::default()
+            .with_conflict_selection(ConflictSelection::Cardinal)
+            .with_heuristic(HighLevelHeuristic::ConflictGraph);
+        let (solution, _, _) = negotiate_with_settings(&scenario, settings).unwrap();
+        assert!(solution.negotiation.conflicts.is_empty());
+        assert_eq!(solution.proposals.len(), 2);
+    }
+
+    #[test]
+    fn test_distances_respect_limit() {
+        let mut grid = SparseGrid::new(1.0);
+        let changes = (-2..=2).map(|y| (Cell::new(1, y), true)).collect();
+        grid.change_cells(&changes);
+        let graph = AccessibilityGraph::new(Arc::new(Accessibility::new(grid, 0.45)));
+
+        let distances = distances(&graph, Cell::new(0, 0), 1.0, 3.0);
+        assert_eq!(distances[&Cell::new(0, 0)], 0.0);
+        assert!((distances[&Cell::new(-3, 0)] - 3.0).abs() < 1e-6);
+        assert!(!distances.contains_key(&Cell::new(-4, 0)));
+        // The wall has to be walked around, which is too far
+        assert!(!distances.contains_key(&Cell::new(2, 0)));
+        assert!(distances.values().all(|d| *d <= 3.0 + DISTANCE_TOLERANCE));
+    }
+
+    /// Classify the conflicts between the ideal plans of a scenario
+    fn classify_ideal_conflicts(scenario: &Scenario) -> Vec<Cardinality> {
+        let (_, nodes, _) = negotiate(scenario, None).unwrap();
+        let root = &nodes[0];
+        let agents: Vec<Agent> = scenario.agents.values().cloned().collect();
+        let grid = scenario.make_grid();
+        let graphs: Vec<_> = agents.iter().map(|a| make_graph(a, &grid)).collect();
+        let mut analysis = CardinalityAnalysis::new(&graphs, &agents, scenario.cell_size);
+        root.negotiation
+            .conflicts
+            .iter()
+            .map(|c| analysis.classify(c, &root.proposals))
+            .collect()
+    }
+
+    #[test]
+    fn test_head_on_in_corridor_is_cardinal() {
+        let mut scenario = test_scenario([
+            ("A", Agent::new([-3, 0], [9, 0])),
+            ("B", Agent::new([9, 0], [-3, 0]).with_yaw(PI)),
+        ]);
+        scenario.occupancy = HashMap::from_iter([(1, (0..=6).collect()), (-1, (0..=6).collect())]);
+        assert_eq!(classify_ideal_conflicts(&scenario), [Cardinality::Cardinal]);
+    }
+
+    #[test]
+    fn test_crossing_with_detour_is_semi_cardinal() {
+        // A can swap the order of its diagonal and straight moves for free,
+        // but B drives straight ahead and has no other way to go.
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [4, 2])),
+            ("B", Agent::new([2, -3], [2, 5]).with_yaw(FRAC_PI_2)),
+        ]);
+        assert_eq!(
+            classify_ideal_conflicts(&scenario),
+            [Cardinality::SemiCardinal]
+        );
+    }
+
+    #[test]
+    fn test_crossing_in_open_room_is_non_cardinal() {
+        // Both agents can swap the order of their diagonal and straight moves
+        // for free.
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [4, 2])),
+            ("B", Agent::new([0, 2], [4, 0])),
+        ]);
+        assert_eq!(
+            classify_ideal_conflicts(&scenario),
+            [Cardinality::NonCardinal]
+        );
+    }
+
+    #[test]
+    fn test_window_follows_proposal() {
+        let cell = Cell::new(1, 0);
+        let mut reachability = Reachability {
+            from_start: HashMap::from_iter([(cell, 1.0)]),
+            to_goal: HashMap::from_iter([(cell, 2.0)]),
+            start_time: 5.0,
+            cost: 4.0,
+            speed: 1.0,
+            weights: TravelEffortCost {
+                time: 1.0,
+                translation: 0.0,
+                rotation: 0.0,
+            },
+        };
+        // The agent leaves at 5s and has 1s to spare on its way to the goal
+        assert_eq!(reachability.window(&cell), Some((6.0, 7.0)));
+
+        // Going through the cell costs more than the proposal
+        reachability.weights.translation = 0.5;
+        assert_eq!(reachability.window(&cell), None);
+    }
+}
@@ -0,0 +1,718 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{
+    find_capacity_conflicts, reasses_conflicts, Negotiation, NegotiationCloser, NegotiationKey,
+    NegotiationNode, NodeOutcome, Proposal, Scenario,
+};
+use crate::{
+    algorithm::{
+        checkpoint::{fingerprint, Checkpoint, CheckpointError},
+        path::{DecisionPoint, MetaTrajectory},
+    },
+    domain::Cost,
+    graph::occupancy::Cell,
+    motion::{
+        se2::{KeySE2, WaypointSE2},
+        CcbsConstraint, CcbsEnvironment, CircularProfile, DynamicCircularObstacle,
+        DynamicEnvironment, Occupancy, Reservation, TimePoint, Trajectory, Uncertainty,
+    },
+    premade::StateSippSE2,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+impl Scenario {
+    /// Calculate the fingerprint that negotiation checkpoints of this scenario
+    /// are validated against.
+    pub fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(self)
+    }
+}
+
+/// A waypoint saved as its time in nanoseconds, followed by x, y, and yaw.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WaypointRecord(pub i64, pub f64, pub f64, pub f64);
+
+impl WaypointRecord {
+    fn new(wp: &WaypointSE2) -> Self {
+        let p = wp.position.translation.vector;
+        Self(
+            wp.time.nanos_since_zero,
+            p[0],
+            p[1],
+            wp.position.rotation.angle(),
+        )
+    }
+
+    fn restore(&self) -> WaypointSE2 {
+        WaypointSE2::new(TimePoint::new(self.0), self.1, self.2, self.3)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrajectoryRecord {
+    pub waypoints: Vec<WaypointRecord>,
+    #[serde(default)]
+    pub indefinite_initial_time: bool,
+    #[serde(default)]
+    pub indefinite_finish_time: bool,
+}
+
+impl TrajectoryRecord {
+    fn new(trajectory: &Trajectory<WaypointSE2>) -> Self {
+        Self {
+            waypoints: trajectory
+                .iter()
+                .map(|wp| WaypointRecord::new(&wp))
+                .collect(),
+            indefinite_initial_time: trajectory.has_indefinite_initial_time(),
+            indefinite_finish_time: trajectory.has_indefinite_finish_time(),
+        }
+    }
+
+    fn restore(&self) -> Result<Trajectory<WaypointSE2>, CheckpointError> {
+        Ok(
+            Trajectory::from_iter(self.waypoints.iter().map(WaypointRecord::restore))
+                .map_err(|_| invalid("a trajectory has fewer than two waypoints"))?
+                .with_indefinite_initial_time(self.indefinite_initial_time)
+                .with_indefinite_finish_time(self.indefinite_finish_time),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StateRecord {
+    pub vertex: Cell,
+    pub orientation: i32,
+    pub waypoint: WaypointRecord,
+}
+
+impl StateRecord {
+    fn new(state: &StateSippSE2<Cell>) -> Self {
+        Self {
+            vertex: state.key.vertex,
+            orientation: state.key.orientation,
+            waypoint: WaypointRecord::new(&state.waypoint),
+        }
+    }
+
+    fn restore(&self) -> StateSippSE2<Cell> {
+        StateSippSE2 {
+            key: KeySE2 {
+                vertex: self.vertex,
+                orientation: self.orientation,
+            },
+            waypoint: self.waypoint.restore(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProposalRecord {
+    pub trajectory: TrajectoryRecord,
+    /// The index of each decision point within the trajectory and its state
+    pub decision_points: Vec<(usize, StateRecord)>,
+    pub initial_state: StateRecord,
+    pub final_state: StateRecord,
+    pub cost: f64,
+}
+
+impl ProposalRecord {
+    fn new(proposal: &Proposal) -> Self {
+        let meta = &proposal.meta;
+        Self {
+            trajectory: TrajectoryRecord::new(&meta.trajectory),
+            decision_points: meta
+                .decision_points
+                .iter()
+                .map(|dp| (dp.index, StateRecord::new(&dp.state)))
+                .collect(),
+            initial_state: StateRecord::new(&meta.initial_state),
+            final_state: StateRecord::new(&meta.final_state),
+            cost: proposal.cost.0,
+        }
+    }
+
+    fn restore(&self) -> Result<Proposal, CheckpointError> {
+        Ok(Proposal {
+            meta: MetaTrajectory {
+                trajectory: self.trajectory.restore()?,
+                decision_points: self
+                    .decision_points
+                    .iter()
+                    .map(|(index, state)| DecisionPoint {
+                        index: *index,
+                        state: state.restore(),
+                    })
+                    .collect(),
+                initial_state: self.initial_state.restore(),
+                final_state: self.final_state.restore(),
+            },
+            cost: Cost(self.cost),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProfileRecord {
+    pub footprint_radius: f64,
+    pub safety_buffer: f64,
+    pub follow_buffer: f64,
+}
+
+impl ProfileRecord {
+    fn new(profile: &CircularProfile) -> Self {
+        Self {
+            footprint_radius: profile.footprint_radius(),
+            safety_buffer: profile.safety_buffer(),
+            follow_buffer: profile.follow_buffer(),
+        }
+    }
+
+    fn restore(&self) -> Result<CircularProfile, CheckpointError> {
+        CircularProfile::new(
+            self.footprint_radius,
+            self.safety_buffer,
+            self.follow_buffer,
+        )
+        .map_err(|_| invalid("a profile has a negative size"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObstacleRecord {
+    pub profile: ProfileRecord,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trajectory: Option<TrajectoryRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uncertainty: Option<Uncertainty>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<usize>,
+}
+
+impl ObstacleRecord {
+    fn new(obstacle: &DynamicCircularObstacle<WaypointSE2>) -> Self {
+        Self {
+            profile: ProfileRecord::new(obstacle.profile()),
+            trajectory: obstacle.trajectory().map(TrajectoryRecord::new),
+            uncertainty: obstacle.uncertainty().cloned(),
+            level: obstacle.level(),
+        }
+    }
+
+    fn restore(&self) -> Result<DynamicCircularObstacle<WaypointSE2>, CheckpointError> {
+        let trajectory = self
+            .trajectory
+            .as_ref()
+            .map(TrajectoryRecord::restore)
+            .transpose()?;
+        Ok(DynamicCircularObstacle::new(self.profile.restore()?)
+            .with_uncertainty(self.uncertainty.clone())
+            .with_level(self.level)
+            .with_trajectory(trajectory))
+    }
+}
+
+/// A base environment. Many nodes share the same base environment, so each
+/// one is only saved once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaseEnvironmentRecord {
+    pub profile: ProfileRecord,
+    pub obstacles: Vec<ObstacleRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConstraintRecord {
+    pub from: Cell,
+    pub to: Cell,
+    pub mask: usize,
+    pub obstacle: ObstacleRecord,
+}
+
+/// A reservation of a capacity zone with its times saved in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReservationRecord {
+    pub zone: usize,
+    pub agent: usize,
+    pub begin: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
+}
+
+impl ReservationRecord {
+    fn new(reservation: &Reservation) -> Self {
+        Self {
+            zone: reservation.zone,
+            agent: reservation.agent,
+            begin: reservation.occupancy.begin.nanos_since_zero,
+            end: reservation.occupancy.end.map(|t| t.nanos_since_zero),
+        }
+    }
+
+    fn restore(&self) -> Reservation {
+        Reservation {
+            zone: self.zone,
+            agent: self.agent,
+            occupancy: Occupancy::new(TimePoint::new(self.begin), self.end.map(TimePoint::new)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentRecord {
+    /// Index of the base environment in [`NegotiationCheckpoint::bases`]
+    pub base: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay_profile: Option<ProfileRecord>,
+    pub overlay_obstacles: Vec<(usize, ObstacleRecord)>,
+    pub constraints: Vec<ConstraintRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reservations: Vec<ReservationRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeRecord {
+    pub id: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    pub participants: Vec<usize>,
+    pub proposals: Vec<(usize, ProposalRecord)>,
+    pub environment: EnvironmentRecord,
+    pub keys: Vec<NegotiationKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conceded: Option<usize>,
+    pub cost: f64,
+    pub heuristic: usize,
+    pub depth: usize,
+    pub outcome: NodeOutcome,
+}
+
+/// The serializable content of a negotiation arena and its closer.
+///
+/// Save it with [`NegotiationCheckpoint::checkpoint`] so that it is validated
+/// against the [`Scenario`] when it is restored. The conflicts of each node
+/// are not saved; they are found again when the checkpoint is restored.
+///
+/// A negotiation that was paused with
+/// [`NegotiationSettings::pause_after`](super::NegotiationSettings::pause_after)
+/// can be continued from its restored checkpoint with
+/// [`resume_negotiation`](super::resume_negotiation).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NegotiationCheckpoint {
+    pub name_map: BTreeMap<usize, String>,
+    pub bases: Vec<BaseEnvironmentRecord>,
+    pub nodes: Vec<NodeRecord>,
+    /// Every closed key with the IDs of the nodes that closed it
+    pub closer: Vec<(NegotiationKey, Vec<usize>)>,
+}
+
+/// A negotiation arena and closer that were restored from a checkpoint.
+#[derive(Debug, Clone)]
+pub struct RestoredNegotiation {
+    pub nodes: Vec<NegotiationNode>,
+    pub closer: NegotiationCloser,
+    pub name_map: HashMap<usize, String>,
+}
+
+impl NegotiationCheckpoint {
+    pub fn new(
+        nodes: &[NegotiationNode],
+        closer: &NegotiationCloser,
+        name_map: &HashMap<usize, String>,
+    ) -> Self {
+        let mut bases = Vec::new();
+        let mut base_index: HashMap<*const DynamicEnvironment<WaypointSE2>, usize> = HashMap::new();
+
+        let nodes = nodes
+            .iter()
+            .map(|node| {
+                let env = &node.environment;
+                let base = *base_index
+                    .entry(Arc::as_ptr(env.base()))
+                    .or_insert_with(|| {
+                        bases.push(BaseEnvironmentRecord {
+                            profile: ProfileRecord::new(&env.base().profile),
+                            obstacles: env
+                                .base()
+                                .obstacles
+                                .iter()
+                                .map(ObstacleRecord::new)
+                                .collect(),
+                        });
+                        bases.len() - 1
+                    });
+
+                let mut overlay_obstacles: Vec<_> = env
+                    .overlay()
+                    .obstacles
+                    .iter()
+                    .map(|(i, obs)| (*i, ObstacleRecord::new(obs)))
+                    .collect();
+                overlay_obstacles.sort_unstable_by_key(|(i, _)| *i);
+
+                let mut constraints: Vec<_> = env
+                    .constraints()
+                    .iter()
+                    .flat_map(|((from, to), constraints)| {
+                        constraints.iter().map(|c| ConstraintRecord {
+                            from: *from,
+                            to: *to,
+                            mask: c.mask,
+                            obstacle: ObstacleRecord::new(&c.obstacle),
+                        })
+                    })
+                    .collect();
+                // Constraints of the same key keep their order
+                constraints.sort_by_key(|c| (c.from.x, c.from.y, c.to.x, c.to.y));
+
+                let mut proposals: Vec<_> = node
+                    .proposals
+                    .iter()
+                    .map(|(i, p)| (*i, ProposalRecord::new(p)))
+                    .collect();
+                proposals.sort_unstable_by_key(|(i, _)| *i);
+
+                NodeRecord {
+                    id: node.id,
+                    parent: node.parent,
+                    participants: node.negotiation.participants.clone(),
+                    proposals,
+                    environment: EnvironmentRecord {
+                        base,
+                        overlay_profile: env.overlay().profile.as_ref().map(ProfileRecord::new),
+                        overlay_obstacles,
+                        constraints,
+                        mask: env.mask(),
+                        reservations: env
+                            .capacity()
+                            .reservations()
+                            .iter()
+                            .map(ReservationRecord::new)
+                            .collect(),
+                    },
+                    keys: node.keys.iter().copied().collect(),
+                    conceded: node.conceded,
+                    cost: node.cost.0,
+                    heuristic: node.heuristic,
+                    depth: node.depth,
+                    outcome: node.outcome,
+                }
+            })
+            .collect();
+
+        let closer = closer
+            .closed_set
+            .iter()
+            .map(|(key, ids)| {
+                let mut ids: Vec<usize> = ids.iter().copied().collect();
+                ids.sort_unstable();
+                (*key, ids)
+            })
+            .collect();
+
+        Self {
+            name_map: name_map.iter().map(|(i, n)| (*i, n.clone())).collect(),
+            bases,
+            nodes,
+            closer,
+        }
+    }
+
+    /// Wrap this in a [`Checkpoint`] that belongs to the scenario.
+    pub fn checkpoint(self, scenario: &Scenario) -> Result<Checkpoint<Self>, CheckpointError> {
+        Ok(Checkpoint::new(scenario.fingerprint()?, self))
+    }
+
+    /// Restore the arena and closer of a checkpoint that was made for this
+    /// scenario.
+    pub fn restore(
+        checkpoint: Checkpoint<Self>,
+        scenario: &Scenario,
+    ) -> Result<RestoredNegotiation, CheckpointError> {
+        checkpoint.validate(scenario.fingerprint()?)?;
+        let content = checkpoint.content;
+
+        // Agents are numbered in the order of the scenario when negotiating
+        if !content
+            .name_map
+            .iter()
+            .map(|(i, name)| (*i, name))
+            .eq(scenario.agents.keys().enumerate())
+        {
+            return Err(invalid("the agents are not numbered like the scenario"));
+        }
+
+        let agent_count = content.name_map.len();
+        let mut profiles = Vec::with_capacity(agent_count);
+        for i in 0..agent_count {
+            let agent = content
+                .name_map
+                .get(&i)
+                .and_then(|name| scenario.agents.get(name))
+                .ok_or_else(|| invalid(format!("agent [{i}] is not in the scenario")))?;
+            profiles.push(
+                CircularProfile::new(agent.radius, 0.0, 0.0)
+                    .map_err(|_| invalid(format!("agent [{i}] has a negative radius")))?,
+            );
+        }
+
+        let bases = content
+            .bases
+            .iter()
+            .map(|base| {
+                // Zones are not recorded in the checkpoint since they are
+                // always given by the scenario.
+                let mut env = DynamicEnvironment::new(base.profile.restore()?)
+                    .with_zones(scenario.make_blocked_zones());
+                for obs in &base.obstacles {
+                    env.obstacles.push(obs.restore()?);
+                }
+                Ok(Arc::new(env))
+            })
+            .collect::<Result<Vec<_>, CheckpointError>>()?;
+
+        let ids: HashSet<usize> = content.nodes.iter().map(|n| n.id).collect();
+        let mut nodes = Vec::with_capacity(content.nodes.len());
+        for record in &content.nodes {
+            if let Some(parent) = record.parent {
+                if !ids.contains(&parent) {
+                    return Err(CheckpointError::BrokenReference(parent));
+                }
+            }
+
+            let proposals = record
+                .proposals
+                .iter()
+                .map(|(i, p)| {
+                    if *i >= profiles.len() {
+                        return Err(invalid(format!("agent [{i}] is not in the name map")));
+                    }
+                    Ok((*i, p.restore()?))
+                })
+                .collect::<Result<HashMap<_, _>, CheckpointError>>()?;
+
+            let env_record = &record.environment;
+            let base = bases.get(env_record.base).ok_or_else(|| {
+                invalid(format!(
+                    "base environment [{}] does not exist",
+                    env_record.base
+                ))
+            })?;
+            // Like zones, capacity zones are given by the scenario
+            let mut environment = CcbsEnvironment::new(base.clone())
+                .with_capacity_zones(scenario.make_capacity_zones());
+            for r in &env_record.reservations {
+                if r.zone >= environment.capacity().zones().len() {
+                    return Err(invalid(format!(
+                        "capacity zone [{}] does not exist",
+                        r.zone
+                    )));
+                }
+                environment.reserve(r.restore());
+            }
+            if let Some(profile) = &env_record.overlay_profile {
+                environment.overlay_profile(profile.restore()?);
+            }
+            for (i, obs) in &env_record.overlay_obstacles {
+                environment.overlay_obstacle(*i, obs.restore()?).ok();
+            }
+            for c in &env_record.constraints {
+                environment.insert_constraint(
+                    (c.from, c.to),
+                    CcbsConstraint {
+                        obstacle: c.obstacle.restore()?,
+                        mask: c.mask,
+                    },
+                );
+            }
+            environment.set_mask(env_record.mask);
+
+            nodes.push(NegotiationNode {
+                negotiation: Negotiation {
+                    conflicts: reasses_conflicts(&proposals, &profiles),
+                    capacity_conflicts: find_capacity_conflicts(
+                        proposals.iter().map(|(i, p)| (*i, &p.meta)),
+                        environment.capacity().zones(),
+                    ),
+                    participants: record.participants.clone(),
+                },
+                proposals,
+                environment,
+                keys: record.keys.iter().copied().collect(),
+                conceded: record.conceded,
+                cost: Cost(record.cost),
+                heuristic: record.heuristic,
+                depth: record.depth,
+                outcome: record.outcome,
+                id: record.id,
+                parent: record.parent,
+            });
+        }
+
+        let mut closer = NegotiationCloser::new();
+        for (key, closed_by) in &content.closer {
+            if let Some(id) = closed_by.iter().find(|id| !ids.contains(id)) {
+                return Err(CheckpointError::BrokenReference(*id));
+            }
+            closer
+                .closed_set
+                .entry(*key)
+                .or_default()
+                .extend(closed_by.iter().copied());
+        }
+
+        Ok(RestoredNegotiation {
+            nodes,
+            closer,
+            name_map: content.name_map.into_iter().collect(),
+        })
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> CheckpointError {
+    CheckpointError::Invalid(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::{
+        negotiate, negotiate_with_settings, resume_negotiation, test_scenario, Agent,
+        NegotiationError, NegotiationSettings,
+    };
+
+    #[test]
+    fn test_negotiation_checkpoint() {
+        let mut scenario = test_scenario([
+            ("A", Agent::new([0, 0], [5, 0])),
+            ("B", Agent::new([5, 0], [0, 0])),
+        ]);
+        scenario.occupancy = HashMap::from_iter([(2, vec![-3, 3]), (-2, vec![-3, 3])]);
+
+        let (_, nodes, name_map) = negotiate(&scenario, None).unwrap();
+        assert!(nodes.len() > 1);
+        let mut closer = NegotiationCloser::new();
+        for node in &nodes {
+            closer.close(node);
+        }
+
+        let checkpoint = NegotiationCheckpoint::new(&nodes, &closer, &name_map)
+            .checkpoint(&scenario)
+            .unwrap();
+        let text = checkpoint.to_json().unwrap();
+        let restored =
+            NegotiationCheckpoint::restore(Checkpoint::from_json(&text).unwrap(), &scenario)
+                .unwrap();
+
+        assert_eq!(restored.name_map, name_map);
+        assert_eq!(restored.nodes.len(), nodes.len());
+        for (original, restored) in nodes.iter().zip(&restored.nodes) {
+            assert_eq!(original.id, restored.id);
+            assert_eq!(original.parent, restored.parent);
+            assert_eq!(original.keys, restored.keys);
+            assert_eq!(original.outcome, restored.outcome);
+            assert_eq!(original.cost, restored.cost);
+            assert_eq!(
+                original.environment.iter_all_obstacles().count(),
+                restored.environment.iter_all_obstacles().count(),
+            );
+            for (i, proposal) in &original.proposals {
+                let r = &restored.proposals[i];
+                assert_eq!(proposal.meta.trajectory.len(), r.meta.trajectory.len());
+                assert_eq!(
+                    proposal.meta.decision_points.len(),
+                    r.meta.decision_points.len(),
+                );
+                assert_eq!(
+                    proposal.meta.trajectory.finish_motion_time(),
+                    r.meta.trajectory.finish_motion_time(),
+                );
+            }
+        }
+
+        for node in &nodes {
+            assert_eq!(
+                closer.status(node).is_closed(),
+                restored.closer.status(node).is_closed(),
+            );
+        }
+
+        // A checkpoint cannot be restored for a different scenario
+        scenario.cell_size = 0.5;
+        assert!(matches!(
+            NegotiationCheckpoint::restore(Checkpoint::from_json(&text).unwrap(), &scenario),
+            Err(CheckpointError::Fingerprint { .. }),
+        ));
+    }
+
+    #[test]
+    fn test_paused_negotiation_resumes_to_same_solution() {
+        let mut scenario = test_scenario([
+            ("A", Agent::new([0, 0], [5, 0])),
+            ("B", Agent::new([5, 0], [0, 0])),
+            ("C", Agent::new([2, 0], [-3, 0])),
+        ]);
+        scenario.occupancy = HashMap::from_iter([(1, vec![-3, 3]), (-1, vec![-3, 3])]);
+        let (expected, expected_nodes, _) = negotiate(&scenario, None).unwrap();
+
+        for pause_after in [1, 5] {
+            let settings = NegotiationSettings::default().with_pause_after(Some(pause_after));
+            let Err(NegotiationError::Paused {
+                nodes,
+                closer,
+                name_map,
+            }) = negotiate_with_settings(&scenario, settings)
+            else {
+                panic!("The negotiation should pause after {pause_after} nodes");
+            };
+            assert!(nodes.len() < expected_nodes.len());
+
+            let text = NegotiationCheckpoint::new(&nodes, &closer, &name_map)
+                .checkpoint(&scenario)
+                .unwrap()
+                .to_json()
+                .unwrap();
+            let restored =
+                NegotiationCheckpoint::restore(Checkpoint::from_json(&text).unwrap(), &scenario)
+                    .unwrap();
+            let (solution, resumed_nodes, _) =
+                resume_negotiation(&scenario, restored, NegotiationSettings::default()).unwrap();
+
+            assert_eq!(resumed_nodes.len(), expected_nodes.len());
+            assert_eq!(solution.id, expected.id);
+            assert_eq!(solution.keys, expected.keys);
+            assert_eq!(solution.cost, expected.cost);
+            for (i, proposal) in &expected.proposals {
+                let waypoints = |proposal: &Proposal| -> Vec<_> {
+                    proposal
+                        .meta
+                        .trajectory
+                        .iter()
+                        .map(|wp| WaypointRecord::new(&wp))
+                        .collect()
+                };
+                assert_eq!(waypoints(&solution.proposals[i]), waypoints(proposal));
+            }
+        }
+    }
+}
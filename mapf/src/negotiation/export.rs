@@ -0,0 +1,278 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{NegotiationError, NegotiationKey, NegotiationNode, NodeOutcome};
+use crate::{graph::occupancy::Cell, util::escape_dot};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+/// One side of an [`ExportedKey`]: the movement from one cell to another that
+/// begins at the given time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedRange {
+    pub from: [i64; 2],
+    pub to: [i64; 2],
+    /// Seconds
+    pub time: f64,
+}
+
+impl ExportedRange {
+    fn new((from, to, time): (Cell, Cell, i64)) -> Self {
+        Self {
+            from: [from.x, from.y],
+            to: [to.x, to.y],
+            // The time of a negotiation key is kept in microseconds
+            time: time as f64 / 1e6,
+        }
+    }
+}
+
+/// A [`NegotiationKey`] with the agent given by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedKey {
+    pub concede: ExportedRange,
+    pub constraint: ExportedRange,
+    /// The agent whose motion became the constraint. For capacity keys this
+    /// is the agent that gave way instead.
+    pub constraint_agent: String,
+    /// The capacity zone that the key belongs to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity_zone: Option<usize>,
+}
+
+impl ExportedKey {
+    fn new(key: &NegotiationKey, name_map: &HashMap<usize, String>) -> Self {
+        Self {
+            concede: ExportedRange::new(key.concede),
+            constraint: ExportedRange::new(key.constraint),
+            constraint_agent: agent_name(key.mask, name_map),
+            capacity_zone: key.capacity_zone,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedNegotiationNode {
+    pub id: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    pub depth: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conceded: Option<String>,
+    pub outcome: NodeOutcome,
+    pub cost: f64,
+    pub heuristic: usize,
+    /// How many conflicts were still unresolved in this node
+    pub conflicts: usize,
+    /// Every key that was used to reach this node
+    pub keys: Vec<ExportedKey>,
+    /// The keys that this node added on top of its parent
+    pub new_keys: Vec<ExportedKey>,
+}
+
+/// A snapshot of a negotiation arena, e.g. the nodes of
+/// [`NegotiationError::PlanningFailed`], that can be written to Graphviz DOT or
+/// JSON so failures can be analyzed offline.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportedNegotiation {
+    pub nodes: Vec<ExportedNegotiationNode>,
+}
+
+impl ExportedNegotiation {
+    pub fn new(nodes: &[NegotiationNode], name_map: &HashMap<usize, String>) -> Self {
+        let node_index: HashMap<usize, usize> =
+            nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+        let empty = HashSet::new();
+
+        let nodes = nodes
+            .iter()
+            .map(|node| {
+                let parent_keys = node
+                    .parent
+                    .and_then(|p| node_index.get(&p))
+                    .map(|p| &nodes[*p].keys)
+                    .unwrap_or(&empty);
+
+                ExportedNegotiationNode {
+                    id: node.id,
+                    parent: node.parent,
+                    depth: node.depth,
+                    conceded: node.conceded.map(|i| agent_name(i, name_map)),
+                    outcome: node.outcome,
+                    cost: node.cost.0,
+                    heuristic: node.heuristic,
+                    conflicts: node.negotiation.conflicts.len()
+                        + node.negotiation.capacity_conflicts.len(),
+                    keys: export_keys(node.keys.iter(), name_map),
+                    new_keys: export_keys(node.keys.difference(parent_keys), name_map),
+                }
+            })
+            .collect();
+
+        Self { nodes }
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Write the arena in the Graphviz DOT format. Each edge goes from a parent
+    /// to a child and is labeled with the constraints that the child added.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph negotiation {\n    node [shape=box];\n");
+        for node in &self.nodes {
+            let color = match node.outcome {
+                NodeOutcome::Success => "black",
+                NodeOutcome::Impossible => "red",
+                NodeOutcome::Incomplete => "orange",
+                NodeOutcome::DeadlineMissed => "purple",
+                NodeOutcome::PredictedConflict => "brown",
+            };
+            let mut label = format!("#{} ({:?})\ncost: {:.3}", node.id, node.outcome, node.cost);
+            if let Some(conceded) = &node.conceded {
+                write!(label, "\nconceded: {conceded}").ok();
+            }
+            write!(label, "\nconflicts: {}", node.conflicts).ok();
+            writeln!(
+                dot,
+                "    n{} [label=\"{}\", color={color}];",
+                node.id,
+                escape_dot(&label),
+            )
+            .ok();
+        }
+
+        for node in &self.nodes {
+            let Some(parent) = node.parent else {
+                continue;
+            };
+
+            let label: Vec<String> = node
+                .new_keys
+                .iter()
+                .map(|key| match key.capacity_zone {
+                    Some(zone) => format!(
+                        "{} waits at {:?} @ {:.2}s for zone {zone}",
+                        key.constraint_agent, key.concede.from, key.concede.time,
+                    ),
+                    None => format!(
+                        "{:?}->{:?} @ {:.2}s yields to {}",
+                        key.concede.from, key.concede.to, key.concede.time, key.constraint_agent,
+                    ),
+                })
+                .collect();
+            writeln!(
+                dot,
+                "    n{parent} -> n{} [label=\"{}\"];",
+                node.id,
+                escape_dot(&label.join("\n")),
+            )
+            .ok();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl NegotiationError {
+    /// Export the negotiation arena that came with this error, if there is
+    /// one.
+    pub fn export(&self) -> Option<ExportedNegotiation> {
+        match self {
+            NegotiationError::PlanningFailed((nodes, name_map))
+            | NegotiationError::DeadlinesMissed {
+                nodes, name_map, ..
+            }
+            | NegotiationError::Paused {
+                nodes, name_map, ..
+            } => Some(ExportedNegotiation::new(nodes, name_map)),
+            _ => None,
+        }
+    }
+}
+
+fn agent_name(agent: usize, name_map: &HashMap<usize, String>) -> String {
+    name_map
+        .get(&agent)
+        .cloned()
+        .unwrap_or_else(|| agent.to_string())
+}
+
+/// Keys are kept in a hash set, so sort them to make the export deterministic
+fn export_keys<'a>(
+    keys: impl Iterator<Item = &'a NegotiationKey>,
+    name_map: &HashMap<usize, String>,
+) -> Vec<ExportedKey> {
+    let mut keys: Vec<_> = keys.collect();
+    keys.sort_by_key(|k| {
+        (
+            k.concede.2,
+            k.mask,
+            k.concede.0.x,
+            k.concede.0.y,
+            k.concede.1.x,
+            k.concede.1.y,
+            k.constraint.2,
+        )
+    });
+    keys.into_iter()
+        .map(|k| ExportedKey::new(k, name_map))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::{negotiate, test_scenario, Agent};
+
+    #[test]
+    fn test_export_negotiation() {
+        // Two agents that swap places need at least one constraint
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [5, 0])),
+            ("B", Agent::new([5, 0], [0, 0])),
+        ]);
+
+        let (_, nodes, name_map) = negotiate(&scenario, None).unwrap();
+        assert!(!nodes.is_empty());
+        let export = ExportedNegotiation::new(&nodes, &name_map);
+        assert_eq!(export.nodes.len(), nodes.len());
+        for node in &export.nodes {
+            if node.parent.is_some() {
+                assert!(node.conceded.is_some());
+            } else {
+                assert!(node.keys.is_empty());
+            }
+        }
+        assert!(export.nodes.iter().any(|n| !n.new_keys.is_empty()));
+
+        let reloaded: ExportedNegotiation =
+            serde_json::from_str(&export.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded, export);
+
+        let dot = export.to_dot();
+        assert!(dot.starts_with("digraph negotiation"));
+        assert_eq!(
+            dot.matches(" -> ").count(),
+            export.nodes.iter().filter(|n| n.parent.is_some()).count(),
+        );
+    }
+}
@@ -0,0 +1,764 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{
+    find_first_conflict, fixed_meta_trajectory, make_planner,
+    scenario::{LinearTrajectorySE2, Scenario},
+    PlannerSE2,
+};
+use crate::{
+    algorithm::{
+        path::{DecisionRange, MetaTrajectory},
+        SearchStatus,
+    },
+    domain::Configurable,
+    error::ThisError,
+    graph::occupancy::Cell,
+    motion::{
+        se2::{GoalSE2, Orientation, StartSE2, WaypointSE2},
+        CcbsConstraint, CcbsEnvironment, CcbsKey, CircularProfile, Duration,
+        DynamicCircularObstacle, DynamicEnvironment, TimePoint, Trajectory,
+    },
+    premade::StateSippSE2,
+    util::triangular_for,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+/// A task that becomes available to the agents at its release time.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LifelongTask {
+    /// Cell that an agent needs to reach to complete the task
+    pub goal: [i64; 2],
+    /// When the task becomes available (seconds since the start)
+    #[serde(default)]
+    pub release: f64,
+}
+
+impl LifelongTask {
+    pub fn new(goal: [i64; 2]) -> Self {
+        Self { goal, release: 0.0 }
+    }
+
+    pub fn with_release(mut self, release: f64) -> Self {
+        self.release = release;
+        self
+    }
+
+    pub fn goal_cell(&self) -> Cell {
+        self.goal.into()
+    }
+
+    pub fn release_time(&self) -> TimePoint {
+        TimePoint::from_secs_f64(self.release)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LifelongSettings {
+    /// Only the part of each new plan that falls within this horizon will be
+    /// committed. Everything past it gets replanned later.
+    pub horizon: Duration,
+    /// How often [`simulate`] calls [`Lifelong::step`]
+    pub replan_period: Duration,
+    /// Queue length limit for each individual search
+    pub queue_length_limit: Option<usize>,
+}
+
+impl LifelongSettings {
+    pub fn with_horizon(mut self, horizon: Duration) -> Self {
+        self.horizon = horizon;
+        self
+    }
+
+    pub fn with_replan_period(mut self, replan_period: Duration) -> Self {
+        self.replan_period = replan_period;
+        self
+    }
+
+    pub fn with_queue_length_limit(mut self, queue_length_limit: Option<usize>) -> Self {
+        self.queue_length_limit = queue_length_limit;
+        self
+    }
+}
+
+impl Default for LifelongSettings {
+    fn default() -> Self {
+        Self {
+            horizon: Duration::from_secs(10),
+            replan_period: Duration::from_secs(2),
+            queue_length_limit: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifelongEvent {
+    /// A released task was given to an idle agent
+    Assigned {
+        time: TimePoint,
+        agent: String,
+        task: usize,
+    },
+    /// An agent arrived at the goal of its task
+    Completed {
+        time: TimePoint,
+        agent: String,
+        task: usize,
+    },
+}
+
+impl LifelongEvent {
+    pub fn time(&self) -> TimePoint {
+        match self {
+            Self::Assigned { time, .. } | Self::Completed { time, .. } => *time,
+        }
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum LifelongError {
+    #[error("Some agents start in conflict with each other:\n{0:?}")]
+    ConflictingStarts(HashMap<String, String>),
+    #[error("The replan period must be greater than zero")]
+    InvalidReplanPeriod,
+    #[error("Unable to create a planner for {0}: {1}")]
+    PlannerSetup(String, String),
+    #[error("An error happened while planning for {0}: {1}")]
+    PlanningError(String, String),
+    #[error("Task {task} of {agent} can only be reached by passing {parked}, which is parked")]
+    BlockedByParkedAgent {
+        agent: String,
+        task: usize,
+        parked: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct AgentState {
+    /// Waypoints that the agent has committed to. This always contains at
+    /// least one waypoint.
+    committed: Vec<WaypointSE2>,
+    /// The task that the agent is currently carrying out
+    task: Option<usize>,
+    /// True when the committed waypoints bring the agent all the way to the
+    /// goal of its task.
+    arrives: bool,
+}
+
+impl AgentState {
+    fn last(&self) -> &WaypointSE2 {
+        self.committed.last().unwrap()
+    }
+}
+
+/// Plans for a team of agents that continuously receive new tasks.
+///
+/// Each agent commits to the prefix of its plan that falls within a rolling
+/// horizon. Whenever an agent is replanned, the committed prefixes of all other
+/// agents are frozen as [`CcbsConstraint`]s on each transition where its plan
+/// conflicts with them, and the agent is replanned until no conflicts are
+/// left. Replanning one agent therefore never disturbs the commitments of the
+/// rest. Only agents that received a new task or whose commitment ends inside
+/// the horizon are replanned in each step.
+///
+/// The goals of the scenario agents are ignored. Agents only move to carry out
+/// tasks, and park wherever they completed their last task. Parked agents do
+/// not move out of the way, so a task that can only be reached by passing a
+/// parked agent fails with [`LifelongError::BlockedByParkedAgent`].
+pub struct Lifelong {
+    settings: LifelongSettings,
+    cell_size: f64,
+    names: Vec<String>,
+    profiles: Vec<CircularProfile>,
+    planners: Vec<PlannerSE2>,
+    states: Vec<AgentState>,
+    tasks: Vec<LifelongTask>,
+    pending: VecDeque<usize>,
+    completed: usize,
+    events: Vec<LifelongEvent>,
+}
+
+impl Lifelong {
+    pub fn new(scenario: &Scenario, settings: LifelongSettings) -> Result<Self, LifelongError> {
+        if settings.replan_period.nanos <= 0 {
+            return Err(LifelongError::InvalidReplanPeriod);
+        }
+
+        let grid = scenario.make_grid();
+
+        let zones = scenario.make_blocked_zones();
+
+        let cs = scenario.cell_size;
+        let conflicts = start_conflicts(scenario);
+        if !conflicts.is_empty() {
+            return Err(LifelongError::ConflictingStarts(conflicts));
+        }
+
+        let mut names = Vec::new();
+        let mut profiles = Vec::new();
+        let mut planners = Vec::new();
+        let mut states = Vec::new();
+        for (name, agent) in &scenario.agents {
+            let profile = CircularProfile::new(agent.radius, 0.0, 0.0)
+                .map_err(|_| LifelongError::PlannerSetup(name.clone(), "radius".to_owned()))?;
+            let planner = make_planner(agent, profile, &grid, &zones, settings.queue_length_limit)
+                .map_err(|err| LifelongError::PlannerSetup(name.clone(), err))?;
+            let p = agent.start_cell().center_point(cs);
+
+            names.push(name.clone());
+            profiles.push(profile);
+            planners.push(planner);
+            states.push(AgentState {
+                committed: vec![WaypointSE2::new(TimePoint::zero(), p.x, p.y, agent.yaw)],
+                task: None,
+                arrives: false,
+            });
+        }
+
+        Ok(Self {
+            settings,
+            cell_size: cs,
+            names,
+            profiles,
+            planners,
+            states,
+            tasks: Vec::new(),
+            pending: VecDeque::new(),
+            completed: 0,
+            events: Vec::new(),
+        })
+    }
+
+    pub fn settings(&self) -> &LifelongSettings {
+        &self.settings
+    }
+
+    /// Add a task to the back of the queue. Tasks are handed out in the order
+    /// that they were pushed, once their release time has been reached. The
+    /// return value identifies the task in the events.
+    pub fn push_task(&mut self, task: LifelongTask) -> usize {
+        let id = self.tasks.len();
+        self.tasks.push(task);
+        self.pending.push_back(id);
+        id
+    }
+
+    pub fn tasks(&self) -> &[LifelongTask] {
+        &self.tasks
+    }
+
+    /// Every event that has happened so far, in the order that they happened.
+    pub fn events(&self) -> &[LifelongEvent] {
+        &self.events
+    }
+
+    /// Check whether every task that was pushed has been completed.
+    pub fn is_finished(&self) -> bool {
+        self.completed == self.tasks.len()
+    }
+
+    /// Get the trajectory that each agent has committed to so far. The agents
+    /// hold their final position indefinitely.
+    pub fn trajectories(&self) -> BTreeMap<String, LinearTrajectorySE2> {
+        self.names
+            .iter()
+            .zip(self.states.iter())
+            .map(|(name, state)| (name.clone(), make_trajectory(&state.committed)))
+            .collect()
+    }
+
+    /// Advance the lifelong planner to the given time. Agents that have
+    /// arrived at their goals are given the next released tasks, and then
+    /// every affected agent is replanned. The events of this step are
+    /// returned.
+    pub fn step(&mut self, now: TimePoint) -> Result<Vec<LifelongEvent>, LifelongError> {
+        let first_event = self.events.len();
+
+        for (i, state) in self.states.iter_mut().enumerate() {
+            if !state.arrives || state.last().time > now {
+                continue;
+            }
+
+            if let Some(task) = state.task.take() {
+                self.completed += 1;
+                self.events.push(LifelongEvent::Completed {
+                    time: state.last().time,
+                    agent: self.names[i].clone(),
+                    task,
+                });
+            }
+            state.arrives = false;
+        }
+
+        self.assign_tasks(now);
+
+        let horizon = now + self.settings.horizon;
+        for i in 0..self.states.len() {
+            let state = &self.states[i];
+            if state.task.is_none() || state.arrives || state.last().time >= horizon {
+                continue;
+            }
+
+            self.replan(i, now, horizon)?;
+        }
+
+        Ok(self.events[first_event..].to_vec())
+    }
+
+    fn assign_tasks(&mut self, now: TimePoint) {
+        let mut unassigned = VecDeque::new();
+        while let Some(task) = self.pending.pop_front() {
+            let goal = self.tasks[task].goal_cell().center_point(self.cell_size);
+            let idle = if self.tasks[task].release_time() <= now {
+                // Give the task to the nearest idle agent. Ties go to the agent
+                // that comes first, which keeps the assignment deterministic.
+                self.states
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, state)| state.task.is_none())
+                    .map(|(i, state)| {
+                        let p = state.last().position.translation.vector;
+                        (i, (goal.coords - p).norm())
+                    })
+                    .fold(
+                        None,
+                        |nearest: Option<(usize, f64)>, (i, dist)| match nearest {
+                            Some((_, best)) if best <= dist => nearest,
+                            _ => Some((i, dist)),
+                        },
+                    )
+                    .map(|(i, _)| i)
+            } else {
+                None
+            };
+
+            match idle {
+                Some(i) => {
+                    self.states[i].task = Some(task);
+                    self.states[i].arrives = false;
+                    self.events.push(LifelongEvent::Assigned {
+                        time: now,
+                        agent: self.names[i].clone(),
+                        task,
+                    });
+                }
+                None => unassigned.push_back(task),
+            }
+        }
+        self.pending = unassigned;
+    }
+
+    fn replan(
+        &mut self,
+        i: usize,
+        now: TimePoint,
+        horizon: TimePoint,
+    ) -> Result<(), LifelongError> {
+        let start = resume_from(&mut self.states[i].committed, now, self.cell_size);
+        let start_time = start.time;
+        let task = self.states[i].task.unwrap();
+        let goal = GoalSE2::new(self.tasks[task].goal_cell());
+
+        let meta = match self.plan_around_commitments(i, start, goal, now, |_| true)? {
+            Some(meta) => meta,
+            None => {
+                // Parked agents never move again on their own, so if one of
+                // them is what blocks the agent then the task would stall
+                // forever.
+                let moving = |j: usize| self.states[j].task.is_some();
+                if let Some(meta) = self.plan_around_commitments(i, start, goal, now, moving)? {
+                    if let Some((parked, _)) = self.find_conflict(i, &meta, now, |j| !moving(j)) {
+                        return Err(LifelongError::BlockedByParkedAgent {
+                            agent: self.names[i].clone(),
+                            task,
+                            parked: self.names[parked].clone(),
+                        });
+                    }
+                }
+
+                // The agent is blocked for now, so it keeps holding its
+                // position and tries again in the next step.
+                return Ok(());
+            }
+        };
+
+        // Commit to everything within the horizon, but always commit to at
+        // least one motion so that the agent makes progress.
+        let state = &mut self.states[i];
+        let mut remaining = meta
+            .trajectory
+            .iter()
+            .filter(|wp| wp.time > start_time)
+            .peekable();
+        if let Some(wp) = remaining.next() {
+            state.committed.push(wp);
+        }
+        while let Some(wp) = remaining.next_if(|wp| wp.time <= horizon) {
+            state.committed.push(wp);
+        }
+        state.arrives = remaining.peek().is_none();
+
+        Ok(())
+    }
+
+    /// Plan for `agent` around the committed prefixes of the other agents that
+    /// are `included`. Each time the plan conflicts with a commitment, the
+    /// commitment is frozen as a [`CcbsConstraint`] on the transition of the
+    /// conflict and the agent is planned again. Returns [`None`] if the agent
+    /// cannot get around the commitments.
+    fn plan_around_commitments(
+        &self,
+        agent: usize,
+        start: StartSE2<Cell>,
+        goal: GoalSE2<Cell>,
+        now: TimePoint,
+        included: impl Fn(usize) -> bool,
+    ) -> Result<Option<MetaTrajectory<WaypointSE2, StateSippSE2<Cell>>>, LifelongError> {
+        let name = &self.names[agent];
+        let mut constraints = Vec::new();
+        let mut constrained = HashSet::new();
+        loop {
+            let planner =
+                with_constraints(&self.planners[agent], self.profiles[agent], &constraints)
+                    .map_err(|err| LifelongError::PlannerSetup(name.clone(), err))?;
+
+            let status = planner
+                .plan(start, goal)
+                .map_err(|err| LifelongError::PlanningError(name.clone(), format!("{err:?}")))?
+                .solve()
+                .map_err(|err| LifelongError::PlanningError(name.clone(), format!("{err:?}")))?;
+
+            let solution = match status {
+                SearchStatus::Solved(solution) => solution,
+                SearchStatus::Impossible | SearchStatus::Incomplete => return Ok(None),
+            };
+
+            let meta = solution
+                .make_trajectory_or_hold::<WaypointSE2>(Duration::from_secs(1))
+                .map_err(|err| LifelongError::PlanningError(name.clone(), format!("{err:?}")))?
+                .with_indefinite_finish_time(true);
+
+            let Some((other, range)) = self.find_conflict(agent, &meta, now, &included) else {
+                return Ok(Some(meta));
+            };
+
+            let key: CcbsKey<Cell> = match range {
+                DecisionRange::Between([s0, s1]) => (s0.state.key.vertex, s1.state.key.vertex),
+                DecisionRange::Before(s, _) | DecisionRange::After(s, _) => {
+                    (s.key.vertex, s.key.vertex)
+                }
+            };
+
+            if !constrained.insert((key, other)) {
+                // The commitment was already constrained on this transition
+                // and the agent still could not get around it.
+                return Ok(None);
+            }
+
+            constraints.push((
+                key,
+                CcbsConstraint {
+                    obstacle: frozen_obstacle(
+                        self.profiles[other],
+                        &self.states[other].committed,
+                        now,
+                    ),
+                    mask: other,
+                },
+            ));
+        }
+    }
+
+    /// Find the first commitment of the agents that are `included` which
+    /// conflicts with the plan of `agent`, along with the range of the plan
+    /// where the conflict happens.
+    fn find_conflict(
+        &self,
+        agent: usize,
+        meta: &MetaTrajectory<WaypointSE2, StateSippSE2<Cell>>,
+        now: TimePoint,
+        included: impl Fn(usize) -> bool,
+    ) -> Option<(usize, DecisionRange<StateSippSE2<Cell>>)> {
+        (0..self.states.len())
+            .filter(|j| *j != agent && included(*j))
+            .find_map(|j| {
+                let committed = &self.states[j].committed;
+                let begin = committed.iter().rposition(|wp| wp.time <= now).unwrap_or(0);
+                let frozen =
+                    fixed_meta_trajectory(make_trajectory(&committed[begin..]), self.cell_size)?;
+                find_first_conflict(meta, &self.profiles[agent], &frozen, &self.profiles[j])
+                    .map(|(range, _)| (j, range))
+            })
+    }
+}
+
+/// Replay a task log until every task is completed or until `max_time` is
+/// reached. The lifelong planner is stepped once every replan period, so the
+/// outcome is fully determined by the scenario, the tasks, and the settings.
+pub fn simulate(
+    scenario: &Scenario,
+    tasks: &[LifelongTask],
+    settings: LifelongSettings,
+    max_time: TimePoint,
+) -> Result<Lifelong, LifelongError> {
+    let mut lifelong = Lifelong::new(scenario, settings)?;
+    for task in tasks {
+        lifelong.push_task(*task);
+    }
+
+    let mut now = TimePoint::zero();
+    while now <= max_time {
+        lifelong.step(now)?;
+        if lifelong.is_finished() {
+            break;
+        }
+        now += settings.replan_period;
+    }
+
+    Ok(lifelong)
+}
+
+/// Find pairs of agents whose start cells are too close together.
+pub(super) fn start_conflicts(scenario: &Scenario) -> HashMap<String, String> {
+    let cs = scenario.cell_size;
+    let mut conflicts = HashMap::new();
+    triangular_for(scenario.agents.iter(), |(n_a, a), (n_b, b)| {
+        let pa = a.start_cell().center_point(cs);
+        let pb = b.start_cell().center_point(cs);
+        if (pa - pb).norm() < a.radius + b.radius {
+            conflicts.insert(
+                (**n_a).clone().min((*n_b).clone()),
+                (**n_a).clone().max((*n_b).clone()),
+            );
+        }
+    });
+    conflicts
+}
+
+/// Get the start conditions for planning from the end of the committed
+/// waypoints. If the agent has been waiting there then it holds its position
+/// until now.
+pub(super) fn resume_from(
+    committed: &mut Vec<WaypointSE2>,
+    now: TimePoint,
+    cell_size: f64,
+) -> StartSE2<Cell> {
+    let mut from = *committed.last().unwrap();
+    if from.time < now {
+        from.time = now;
+        committed.push(from);
+    }
+
+    let p = from.position.translation.vector;
+    StartSE2 {
+        time: from.time,
+        key: Cell::from_point([p.x, p.y].into(), cell_size),
+        orientation: Orientation::new(from.position.rotation.angle()),
+    }
+}
+
+/// An obstacle for the committed waypoints of an agent from the current time
+/// onwards. The agent holds its final position indefinitely.
+pub(super) fn frozen_obstacle(
+    profile: CircularProfile,
+    committed: &[WaypointSE2],
+    now: TimePoint,
+) -> DynamicCircularObstacle<WaypointSE2> {
+    let begin = committed.iter().rposition(|wp| wp.time <= now).unwrap_or(0);
+    DynamicCircularObstacle::new(profile)
+        .with_trajectory(Some(make_trajectory(&committed[begin..])))
+}
+
+/// Get a copy of the planner whose environment contains the given constraints
+/// and no obstacles. The blocked zones of the planner's environment are kept.
+fn with_constraints(
+    planner: &PlannerSE2,
+    profile: CircularProfile,
+    constraints: &[(CcbsKey<Cell>, CcbsConstraint<WaypointSE2>)],
+) -> Result<PlannerSE2, String> {
+    planner
+        .clone()
+        .configure(|config| {
+            config.modify_environment(|previous| {
+                let base =
+                    DynamicEnvironment::new(profile).with_zones(previous.base().zones.clone());
+                let mut environment = CcbsEnvironment::new(Arc::new(base));
+                for (key, constraint) in constraints {
+                    environment.insert_constraint(*key, constraint.clone());
+                }
+                Ok(environment)
+            })
+        })
+        .map_err(|err| format!("{err:?}"))
+}
+
+/// Get a copy of the planner whose environment contains only the given
+/// obstacles. The blocked zones of the planner's environment are kept.
+pub(super) fn with_obstacles(
+    planner: &PlannerSE2,
+    profile: CircularProfile,
+    obstacles: Vec<DynamicCircularObstacle<WaypointSE2>>,
+) -> Result<PlannerSE2, String> {
+    let mut environment = DynamicEnvironment::new(profile);
+    environment.obstacles = obstacles.into();
+    planner
+        .clone()
+        .configure(|config| {
+            config.modify_environment(|previous| {
+                environment.zones = previous.base().zones.clone();
+                Ok(CcbsEnvironment::new(Arc::new(environment)))
+            })
+        })
+        .map_err(|err| format!("{err:?}"))
+}
+
+pub(super) fn make_trajectory(waypoints: &[WaypointSE2]) -> LinearTrajectorySE2 {
+    let trajectory = if waypoints.len() < 2 {
+        let wp = waypoints[0];
+        Trajectory::hold(wp, wp.time + Duration::from_secs(1))
+    } else {
+        Trajectory::from_iter(waypoints.iter().cloned())
+    };
+
+    trajectory.unwrap().with_indefinite_finish_time(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::{test_scenario, Agent};
+
+    fn make_scenario() -> Scenario {
+        test_scenario([
+            ("A", Agent::new([0, 0], [0, 0])),
+            ("B", Agent::new([0, 4], [0, 4])),
+        ])
+    }
+
+    #[test]
+    fn test_lifelong_replays_task_log() {
+        let scenario = make_scenario();
+        let tasks = [
+            LifelongTask::new([4, 0]),
+            LifelongTask::new([4, 4]),
+            LifelongTask::new([0, 2]).with_release(3.0),
+        ];
+        let settings = LifelongSettings::default()
+            .with_horizon(Duration::from_secs(4))
+            .with_replan_period(Duration::from_secs(1));
+        let max_time = TimePoint::from_secs_f64(120.0);
+
+        let lifelong = simulate(&scenario, &tasks, settings, max_time).unwrap();
+        assert!(lifelong.is_finished());
+
+        for task in 0..tasks.len() {
+            let assigned = lifelong
+                .events()
+                .iter()
+                .filter(|e| matches!(e, LifelongEvent::Assigned { task: t, .. } if *t == task))
+                .count();
+            assert_eq!(assigned, 1);
+        }
+
+        // Each agent goes to the nearest released task first.
+        assert!(lifelong.events().contains(&LifelongEvent::Assigned {
+            time: TimePoint::zero(),
+            agent: "A".to_owned(),
+            task: 0,
+        }));
+        assert!(lifelong.events().contains(&LifelongEvent::Assigned {
+            time: TimePoint::zero(),
+            agent: "B".to_owned(),
+            task: 1,
+        }));
+
+        // The third task cannot be handed out before it is released.
+        let third = lifelong
+            .events()
+            .iter()
+            .find(|e| matches!(e, LifelongEvent::Assigned { task: 2, .. }))
+            .unwrap();
+        assert!(third.time() >= TimePoint::from_secs_f64(3.0));
+
+        // Every agent ends where it completed its last task.
+        let trajectories = lifelong.trajectories();
+        for event in lifelong.events() {
+            if let LifelongEvent::Completed { agent, task, .. } = event {
+                let last_task = lifelong
+                    .events()
+                    .iter()
+                    .rev()
+                    .find_map(|e| match e {
+                        LifelongEvent::Completed { agent: a, task, .. } if a == agent => {
+                            Some(*task)
+                        }
+                        _ => None,
+                    })
+                    .unwrap();
+                if last_task != *task {
+                    continue;
+                }
+
+                let p = trajectories[agent]
+                    .finish_motion()
+                    .position
+                    .translation
+                    .vector;
+                let cell = Cell::from_point([p.x, p.y].into(), scenario.cell_size);
+                assert_eq!(cell, tasks[*task].goal_cell());
+            }
+        }
+
+        // Replaying the same log gives the same outcome.
+        let replay = simulate(&scenario, &tasks, settings, max_time).unwrap();
+        assert_eq!(lifelong.events(), replay.events());
+    }
+
+    #[test]
+    fn test_lifelong_reports_parked_agent_on_only_route() {
+        // B is parked in the bend of a closed U-shaped corridor. The task is
+        // nearer to A, so A gets it, but A has to pass B to reach it.
+        let mut scenario = test_scenario([
+            ("A", Agent::new([0, 0], [0, 0])),
+            ("B", Agent::new([4, 1], [4, 1])),
+        ]);
+        scenario.occupancy = HashMap::from_iter([
+            (3, (-1..=5).collect()),
+            (2, vec![-1, 5]),
+            (1, (-1..=3).chain([5]).collect()),
+            (0, vec![-1, 5]),
+            (-1, (-1..=5).collect()),
+        ]);
+
+        let tasks = [LifelongTask::new([0, 2])];
+        let settings = LifelongSettings::default();
+        let max_time = TimePoint::from_secs_f64(60.0);
+
+        let err = simulate(&scenario, &tasks, settings, max_time)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            LifelongError::BlockedByParkedAgent { agent, task: 0, parked }
+            if agent == "A" && parked == "B"
+        ));
+    }
+}
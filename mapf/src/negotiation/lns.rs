@@ -0,0 +1,609 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{
+    assign_goal_regions, find_first_conflict, lifelong::with_obstacles, make_planner, Agent,
+    NegotiationNode, PlannerSE2, Proposal, Scenario,
+};
+use crate::{
+    algorithm::SearchStatus,
+    domain::Cost,
+    error::ThisError,
+    motion::{
+        se2::{plan_sequence, WaypointSE2},
+        CircularProfile, Duration, DynamicCircularObstacle,
+    },
+    planner::halt::Halt,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Strategies for choosing which agents get replanned together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// Agents chosen uniformly at random
+    Random,
+    /// The agent that is delayed the most compared to its ideal plan, along
+    /// with the agents that get in the way of its ideal plan
+    AgentBased,
+    /// Agents that pass near the same location
+    IntersectionBased,
+}
+
+#[derive(Debug, Clone)]
+pub struct LnsSettings {
+    /// How many agents get replanned in each iteration
+    pub neighborhood_size: usize,
+    /// The neighborhoods are used one after another in each iteration
+    pub neighborhoods: Vec<Neighborhood>,
+    /// Seed for choosing neighborhoods and replanning orders. The same seed
+    /// will always produce the same result.
+    pub seed: u64,
+    /// Queue length limit for each individual search
+    pub queue_length_limit: Option<usize>,
+}
+
+impl LnsSettings {
+    pub fn with_neighborhood_size(mut self, neighborhood_size: usize) -> Self {
+        self.neighborhood_size = neighborhood_size;
+        self
+    }
+
+    pub fn with_neighborhoods(mut self, neighborhoods: Vec<Neighborhood>) -> Self {
+        self.neighborhoods = neighborhoods;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_queue_length_limit(mut self, queue_length_limit: Option<usize>) -> Self {
+        self.queue_length_limit = queue_length_limit;
+        self
+    }
+}
+
+impl Default for LnsSettings {
+    fn default() -> Self {
+        Self {
+            neighborhood_size: 4,
+            neighborhoods: vec![
+                Neighborhood::AgentBased,
+                Neighborhood::IntersectionBased,
+                Neighborhood::Random,
+            ],
+            seed: 0x9E37_79B9_7F4A_7C15,
+            queue_length_limit: None,
+        }
+    }
+}
+
+/// The state of the search that is given to the [`Halt`] budget before each
+/// iteration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LnsProgress {
+    /// How many iterations have been run
+    pub iterations: usize,
+    /// How many iterations produced a plan that was accepted
+    pub accepted: usize,
+    /// Sum of the costs of the current plan
+    pub sum_of_costs: f64,
+    /// Number of pairs of agents whose current trajectories collide
+    pub conflicts: usize,
+}
+
+#[derive(Debug, ThisError)]
+pub enum LnsError {
+    #[error("Unable to create a planner for {0}: {1}")]
+    PlannerSetup(String, String),
+    #[error("There is no proposal for {0}")]
+    MissingProposal(String),
+    #[error("It was impossible to find a basic plan for {0}")]
+    PlanningImpossible(String),
+    #[error("An error happened while planning for {0}: {1}")]
+    PlanningError(String, String),
+}
+
+/// Large Neighborhood Search over the trajectories of a scenario.
+///
+/// Each iteration picks a neighborhood of agents, removes their trajectories,
+/// and replans them one at a time in a random order with `SippSE2`. Every
+/// other trajectory is treated as an obstacle while replanning. Use
+/// [`Lns::improve`] to lower the sum of costs of a conflict-free plan, or
+/// [`Lns::repair`] to remove the conflicts from a plan that has collisions.
+pub struct Lns {
+    settings: LnsSettings,
+    names: Vec<String>,
+    agents: Vec<Agent>,
+    profiles: Vec<CircularProfile>,
+    planners: Vec<PlannerSE2>,
+    /// The plan of each agent when it ignores all other agents
+    ideal: Vec<Proposal>,
+    proposals: Vec<Proposal>,
+    tabu: HashSet<usize>,
+    rng: XorShift,
+    progress: LnsProgress,
+}
+
+impl Lns {
+    /// Begin from the solution of a negotiation. The name map tells which
+    /// agent of the scenario each proposal belongs to.
+    pub fn new(
+        scenario: &Scenario,
+        solution: &NegotiationNode,
+        name_map: &HashMap<usize, String>,
+        settings: LnsSettings,
+    ) -> Result<Self, LnsError> {
+        let mut proposals = Vec::new();
+        let mut agents = Vec::new();
+        for (name, agent) in &scenario.agents {
+            let proposal = name_map
+                .iter()
+                .find(|(_, n)| *n == name)
+                .and_then(|(i, _)| solution.proposals.get(i))
+                .ok_or_else(|| LnsError::MissingProposal(name.clone()))?;
+
+            // The negotiation may have chosen a goal from the goal region of
+            // the agent, so keep whichever goal it finished at.
+            let goal = proposal.meta.final_state.key.vertex;
+            let mut agent = agent.clone();
+            agent.goal = [goal.x, goal.y];
+            agent.goal_region.clear();
+
+            proposals.push(proposal.clone());
+            agents.push(agent);
+        }
+
+        Self::from_proposals(scenario, agents, Some(proposals), settings)
+    }
+
+    /// Begin from the plan that each agent would follow if it were alone. This
+    /// plan will usually have conflicts, which [`Lns::repair`] can remove.
+    pub fn from_ideal(scenario: &Scenario, settings: LnsSettings) -> Result<Self, LnsError> {
+        let grid = scenario.make_grid();
+        let zones = scenario.make_blocked_zones();
+        let mut agents: Vec<Agent> = scenario.agents.values().cloned().collect();
+        let planners = scenario
+            .agents
+            .iter()
+            .map(|(name, agent)| {
+                let profile = CircularProfile::new(agent.radius, 0.0, 0.0)
+                    .map_err(|_| LnsError::PlannerSetup(name.clone(), "radius".to_owned()))?;
+                make_planner(agent, profile, &grid, &zones, settings.queue_length_limit)
+                    .map_err(|err| LnsError::PlannerSetup(name.clone(), err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assign_goal_regions(&mut agents, &planners, &grid, scenario.cell_size);
+        for agent in &mut agents {
+            agent.goal_region.clear();
+        }
+
+        Self::from_proposals(scenario, agents, None, settings)
+    }
+
+    fn from_proposals(
+        scenario: &Scenario,
+        agents: Vec<Agent>,
+        proposals: Option<Vec<Proposal>>,
+        settings: LnsSettings,
+    ) -> Result<Self, LnsError> {
+        let grid = scenario.make_grid();
+        let zones = scenario.make_blocked_zones();
+        let names: Vec<_> = scenario.agents.keys().cloned().collect();
+        let mut profiles = Vec::new();
+        let mut planners = Vec::new();
+        for (name, agent) in names.iter().zip(agents.iter()) {
+            let profile = CircularProfile::new(agent.radius, 0.0, 0.0)
+                .map_err(|_| LnsError::PlannerSetup(name.clone(), "radius".to_owned()))?;
+            let planner = make_planner(agent, profile, &grid, &zones, settings.queue_length_limit)
+                .map_err(|err| LnsError::PlannerSetup(name.clone(), err))?;
+            profiles.push(profile);
+            planners.push(planner);
+        }
+
+        let mut lns = Self {
+            rng: XorShift::new(settings.seed),
+            settings,
+            names,
+            agents,
+            profiles,
+            planners,
+            ideal: Vec::new(),
+            proposals: Vec::new(),
+            tabu: HashSet::new(),
+            progress: LnsProgress {
+                iterations: 0,
+                accepted: 0,
+                sum_of_costs: 0.0,
+                conflicts: 0,
+            },
+        };
+
+        for i in 0..lns.agents.len() {
+            match lns.plan(i, Vec::new())? {
+                Some(ideal) => lns.ideal.push(ideal),
+                None => return Err(LnsError::PlanningImpossible(lns.names[i].clone())),
+            }
+        }
+
+        lns.proposals = proposals.unwrap_or_else(|| lns.ideal.clone());
+        lns.progress.sum_of_costs = sum_of_costs(&lns.proposals);
+        lns.progress.conflicts = lns.colliding_pairs(&lns.proposals).len();
+        Ok(lns)
+    }
+
+    pub fn progress(&self) -> LnsProgress {
+        self.progress
+    }
+
+    /// The current proposal of each agent, keyed by the same indices that
+    /// [`negotiate`](super::negotiate) uses.
+    pub fn proposals(&self) -> HashMap<usize, Proposal> {
+        self.proposals.iter().cloned().enumerate().collect()
+    }
+
+    /// Replace the proposals of a negotiation node with the current ones.
+    pub fn apply_to(&self, node: &mut NegotiationNode) {
+        node.proposals = self.proposals();
+        node.cost = Cost(self.progress.sum_of_costs);
+    }
+
+    /// Keep replanning neighborhoods until the halting condition is met,
+    /// accepting any new plan that lowers the sum of costs without adding
+    /// conflicts.
+    pub fn improve<H: Halt<LnsProgress>>(&mut self, mut halt: H) -> Result<LnsProgress, LnsError> {
+        while !halt.halt(&self.progress) {
+            let neighborhood = self.choose_neighborhood();
+            if let Some(proposals) = self.replan_neighborhood(&neighborhood, false)? {
+                let conflicts = self.colliding_pairs(&proposals).len();
+                let cost = sum_of_costs(&proposals);
+                if conflicts <= self.progress.conflicts && cost < self.progress.sum_of_costs {
+                    self.accept(proposals, cost, conflicts);
+                }
+            }
+            self.progress.iterations += 1;
+        }
+
+        Ok(self.progress)
+    }
+
+    /// Keep replanning neighborhoods until there are no conflicts left or the
+    /// halting condition is met. Neighborhoods are built around agents that
+    /// are in conflict, and a new plan is accepted when it has fewer conflicts
+    /// or the same number of conflicts at a lower cost.
+    pub fn repair<H: Halt<LnsProgress>>(&mut self, mut halt: H) -> Result<LnsProgress, LnsError> {
+        while self.progress.conflicts > 0 && !halt.halt(&self.progress) {
+            let neighborhood = self.choose_collision_neighborhood();
+            if let Some(proposals) = self.replan_neighborhood(&neighborhood, true)? {
+                let conflicts = self.colliding_pairs(&proposals).len();
+                let cost = sum_of_costs(&proposals);
+                if conflicts < self.progress.conflicts
+                    || (conflicts == self.progress.conflicts && cost < self.progress.sum_of_costs)
+                {
+                    self.accept(proposals, cost, conflicts);
+                }
+            }
+            self.progress.iterations += 1;
+        }
+
+        Ok(self.progress)
+    }
+
+    fn accept(&mut self, proposals: Vec<Proposal>, cost: f64, conflicts: usize) {
+        self.proposals = proposals;
+        self.progress.sum_of_costs = cost;
+        self.progress.conflicts = conflicts;
+        self.progress.accepted += 1;
+    }
+
+    /// Replan the agents of the neighborhood in a random order. When `soft` is
+    /// true, an agent that cannot avoid the others falls back on its ideal
+    /// plan instead of failing the whole neighborhood.
+    fn replan_neighborhood(
+        &mut self,
+        neighborhood: &[usize],
+        soft: bool,
+    ) -> Result<Option<Vec<Proposal>>, LnsError> {
+        let mut order = neighborhood.to_vec();
+        self.rng.shuffle(&mut order);
+
+        let mut proposals = self.proposals.clone();
+        let mut removed: HashSet<usize> = order.iter().copied().collect();
+        for i in order {
+            removed.remove(&i);
+            let obstacles = proposals
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i && !removed.contains(j))
+                .map(|(j, proposal)| {
+                    DynamicCircularObstacle::new(self.profiles[j])
+                        .with_trajectory(Some(proposal.meta.trajectory.clone()))
+                })
+                .collect();
+
+            match self.plan(i, obstacles)? {
+                Some(proposal) => proposals[i] = proposal,
+                None if soft => proposals[i] = self.ideal[i].clone(),
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(proposals))
+    }
+
+    fn plan(
+        &self,
+        i: usize,
+        obstacles: Vec<DynamicCircularObstacle<WaypointSE2>>,
+    ) -> Result<Option<Proposal>, LnsError> {
+        let name = &self.names[i];
+        let agent = &self.agents[i];
+        let planner = with_obstacles(&self.planners[i], self.profiles[i], obstacles)
+            .map_err(|err| LnsError::PlannerSetup(name.clone(), err))?;
+
+        let status = plan_sequence(&planner, agent.make_start(), &agent.make_sequential_goal())
+            .map_err(|err| LnsError::PlanningError(name.clone(), format!("{err:?}")))?;
+        let solution = match status {
+            SearchStatus::Solved(solution) => solution,
+            SearchStatus::Impossible | SearchStatus::Incomplete => return Ok(None),
+        };
+
+        let meta = solution
+            .make_trajectory_or_hold(Duration::from_secs(1))
+            .map_err(|err| LnsError::PlanningError(name.clone(), format!("{err:?}")))?
+            .with_indefinite_finish_time(true);
+        Ok(Some(Proposal {
+            meta,
+            cost: solution.total_cost,
+        }))
+    }
+
+    fn choose_neighborhood(&mut self) -> Vec<usize> {
+        let neighborhoods = &self.settings.neighborhoods;
+        let neighborhood = match neighborhoods.len() {
+            0 => Neighborhood::Random,
+            n => neighborhoods[self.progress.iterations % n],
+        };
+
+        let mut chosen = match neighborhood {
+            Neighborhood::Random => Vec::new(),
+            Neighborhood::AgentBased => self.agent_based_neighborhood(),
+            Neighborhood::IntersectionBased => self.intersection_based_neighborhood(),
+        };
+        self.fill_randomly(&mut chosen);
+        chosen
+    }
+
+    /// Choose the agent that is delayed the most, skipping agents that were
+    /// chosen recently, and then the agents that get in the way of its ideal
+    /// plan.
+    fn agent_based_neighborhood(&mut self) -> Vec<usize> {
+        let delays: Vec<f64> = self
+            .proposals
+            .iter()
+            .zip(self.ideal.iter())
+            .map(|(proposal, ideal)| proposal.cost.0 - ideal.cost.0)
+            .collect();
+        let most_delayed = |tabu: &HashSet<usize>| {
+            (0..delays.len())
+                .filter(|i| !tabu.contains(i) && delays[*i] > 0.0)
+                .max_by(|a, b| delays[*a].total_cmp(&delays[*b]))
+        };
+
+        let mut seed = most_delayed(&self.tabu);
+        if seed.is_none() {
+            self.tabu.clear();
+            seed = most_delayed(&self.tabu);
+        }
+
+        let Some(seed) = seed else {
+            return Vec::new();
+        };
+        self.tabu.insert(seed);
+
+        let mut chosen = vec![seed];
+        for j in 0..self.agents.len() {
+            if chosen.len() >= self.settings.neighborhood_size {
+                break;
+            }
+
+            if j != seed
+                && find_first_conflict(
+                    &self.ideal[seed].meta,
+                    &self.profiles[seed],
+                    &self.proposals[j].meta,
+                    &self.profiles[j],
+                )
+                .is_some()
+            {
+                chosen.push(j);
+            }
+        }
+
+        chosen
+    }
+
+    /// Choose a random location along the trajectory of a random agent, and
+    /// then the agents whose trajectories pass near it.
+    fn intersection_based_neighborhood(&mut self) -> Vec<usize> {
+        if self.agents.is_empty() {
+            return Vec::new();
+        }
+
+        let seed = self.rng.below(self.agents.len());
+        let waypoints: Vec<WaypointSE2> = self.proposals[seed].meta.trajectory.iter().collect();
+        let location = waypoints[self.rng.below(waypoints.len())]
+            .position
+            .translation
+            .vector;
+
+        let mut chosen = vec![seed];
+        for j in 0..self.agents.len() {
+            if chosen.len() >= self.settings.neighborhood_size {
+                break;
+            }
+
+            let nearby = 2.0
+                * (self.profiles[seed].footprint_radius() + self.profiles[j].footprint_radius());
+            if j != seed
+                && self.proposals[j]
+                    .meta
+                    .trajectory
+                    .iter()
+                    .any(|wp| (wp.position.translation.vector - location).norm() <= nearby)
+            {
+                chosen.push(j);
+            }
+        }
+
+        chosen
+    }
+
+    /// Choose a random agent that is in conflict, and then the agents that it
+    /// is in conflict with.
+    fn choose_collision_neighborhood(&mut self) -> Vec<usize> {
+        let pairs = self.colliding_pairs(&self.proposals);
+        let mut chosen = Vec::new();
+        if !pairs.is_empty() {
+            let (a, b) = pairs[self.rng.below(pairs.len())];
+            let seed = if self.rng.below(2) == 0 { a } else { b };
+            chosen.push(seed);
+            for (a, b) in pairs {
+                if chosen.len() >= self.settings.neighborhood_size {
+                    break;
+                }
+
+                let other = if a == seed {
+                    b
+                } else if b == seed {
+                    a
+                } else {
+                    continue;
+                };
+
+                if !chosen.contains(&other) {
+                    chosen.push(other);
+                }
+            }
+        }
+
+        self.fill_randomly(&mut chosen);
+        chosen
+    }
+
+    fn fill_randomly(&mut self, chosen: &mut Vec<usize>) {
+        let size = self.settings.neighborhood_size.min(self.agents.len());
+        while chosen.len() < size {
+            let i = self.rng.below(self.agents.len());
+            if !chosen.contains(&i) {
+                chosen.push(i);
+            }
+        }
+    }
+
+    fn colliding_pairs(&self, proposals: &[Proposal]) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for a in 0..proposals.len() {
+            for b in a + 1..proposals.len() {
+                if find_first_conflict(
+                    &proposals[a].meta,
+                    &self.profiles[a],
+                    &proposals[b].meta,
+                    &self.profiles[b],
+                )
+                .is_some()
+                {
+                    pairs.push((a, b));
+                }
+            }
+        }
+
+        pairs
+    }
+}
+
+fn sum_of_costs(proposals: &[Proposal]) -> f64 {
+    proposals.iter().map(|p| p.cost.0).sum()
+}
+
+/// A small deterministic random number generator so that searches can be
+/// reproduced from their seed.
+#[derive(Debug, Clone, Copy)]
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, values: &mut [T]) {
+        for i in (1..values.len()).rev() {
+            let j = self.below(i + 1);
+            values.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{negotiation::test_scenario, planner::halt::StepLimit};
+
+    fn make_scenario() -> Scenario {
+        test_scenario([
+            ("A", Agent::new([0, 0], [5, 0])),
+            ("B", Agent::new([5, 0], [0, 0])),
+            ("C", Agent::new([2, -2], [2, 2])),
+        ])
+    }
+
+    #[test]
+    fn test_repair_then_improve() {
+        let scenario = make_scenario();
+        let settings = LnsSettings::default().with_neighborhood_size(2);
+        let mut lns = Lns::from_ideal(&scenario, settings.clone()).unwrap();
+        assert!(lns.progress().conflicts > 0);
+
+        let repaired = lns.repair(StepLimit::new(Some(50))).unwrap();
+        assert_eq!(repaired.conflicts, 0);
+
+        let improved = lns.improve(StepLimit::new(Some(20))).unwrap();
+        assert_eq!(improved.conflicts, 0);
+        assert!(improved.sum_of_costs <= repaired.sum_of_costs);
+        assert_eq!(lns.proposals().len(), 3);
+
+        // The same seed reproduces the same search.
+        let mut replay = Lns::from_ideal(&scenario, settings).unwrap();
+        assert_eq!(replay.repair(StepLimit::new(Some(50))).unwrap(), repaired);
+    }
+}
@@ -0,0 +1,822 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{
+    assignment::hungarian,
+    lifelong::{frozen_obstacle, make_trajectory, resume_from, start_conflicts, with_obstacles},
+    make_planner,
+    scenario::{is_zero, LinearTrajectorySE2, Scenario},
+    PlannerSE2,
+};
+use crate::{
+    algorithm::SearchStatus,
+    error::ThisError,
+    graph::occupancy::Cell,
+    motion::{
+        se2::{plan_sequence, SequentialGoalSE2, StartSE2, StopSE2, WaypointSE2},
+        CircularProfile, Duration, TimePoint,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// A job that requires an agent to pick something up from one cell and drop it
+/// off at another.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Job {
+    /// Cell where the item gets picked up
+    pub pickup: [i64; 2],
+    /// Cell where the item gets dropped off
+    pub drop: [i64; 2],
+    /// When the job becomes available (seconds since the start)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub release: f64,
+    /// How long the agent spends at the pickup cell (seconds)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub pickup_duration: f64,
+    /// How long the agent spends at the drop cell (seconds)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub drop_duration: f64,
+}
+
+impl Job {
+    pub fn new(pickup: [i64; 2], drop: [i64; 2]) -> Self {
+        Self {
+            pickup,
+            drop,
+            release: 0.0,
+            pickup_duration: 0.0,
+            drop_duration: 0.0,
+        }
+    }
+
+    pub fn with_release(mut self, release: f64) -> Self {
+        self.release = release;
+        self
+    }
+
+    pub fn with_pickup_duration(mut self, duration: f64) -> Self {
+        self.pickup_duration = duration;
+        self
+    }
+
+    pub fn with_drop_duration(mut self, duration: f64) -> Self {
+        self.drop_duration = duration;
+        self
+    }
+
+    pub fn pickup_cell(&self) -> Cell {
+        self.pickup.into()
+    }
+
+    pub fn drop_cell(&self) -> Cell {
+        self.drop.into()
+    }
+
+    pub fn release_time(&self) -> TimePoint {
+        TimePoint::from_secs_f64(self.release)
+    }
+
+    /// A goal that visits the pickup cell and then the drop cell, staying at
+    /// each of them while the item is picked up or dropped off.
+    pub fn make_goal(&self) -> SequentialGoalSE2<Cell> {
+        SequentialGoalSE2::new(vec![StopSE2::new(self.pickup_cell())
+            .with_dwell(Duration::from_secs_f64(self.pickup_duration))])
+        .with_stop(
+            StopSE2::new(self.drop_cell()).with_dwell(Duration::from_secs_f64(self.drop_duration)),
+        )
+    }
+
+    fn is_endpoint(&self, cell: Cell) -> bool {
+        self.pickup_cell() == cell || self.drop_cell() == cell
+    }
+}
+
+/// How released jobs are given to idle agents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapdStrategy {
+    /// Idle agents take turns choosing the nearest job whose endpoints are not
+    /// the endpoint of any other agent's path. Each agent plans around the
+    /// paths of all the agents that went before it.
+    TokenPassing,
+    /// Idle agents are matched with released jobs all at once, minimizing the
+    /// sum of their individual costs. Paths are then planned in agent order.
+    Centralized,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapdSettings {
+    pub strategy: MapdStrategy,
+    /// Cells where idle agents can wait without blocking any job
+    pub parking: Vec<[i64; 2]>,
+    /// How often [`simulate`] calls [`Mapd::step`]
+    pub replan_period: Duration,
+    /// Queue length limit for each individual search
+    pub queue_length_limit: Option<usize>,
+}
+
+impl MapdSettings {
+    pub fn with_strategy(mut self, strategy: MapdStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_parking(mut self, parking: Vec<[i64; 2]>) -> Self {
+        self.parking = parking;
+        self
+    }
+
+    pub fn with_replan_period(mut self, replan_period: Duration) -> Self {
+        self.replan_period = replan_period;
+        self
+    }
+
+    pub fn with_queue_length_limit(mut self, queue_length_limit: Option<usize>) -> Self {
+        self.queue_length_limit = queue_length_limit;
+        self
+    }
+}
+
+impl Default for MapdSettings {
+    fn default() -> Self {
+        Self {
+            strategy: MapdStrategy::TokenPassing,
+            parking: Vec::new(),
+            replan_period: Duration::from_secs(1),
+            queue_length_limit: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapdEvent {
+    /// A released job was given to an idle agent
+    Assigned {
+        time: TimePoint,
+        agent: String,
+        job: usize,
+    },
+    /// An agent finished dropping off the item of its job
+    Delivered {
+        time: TimePoint,
+        agent: String,
+        job: usize,
+    },
+    /// An idle agent was sent to a parking cell
+    Parked {
+        time: TimePoint,
+        agent: String,
+        cell: [i64; 2],
+    },
+}
+
+impl MapdEvent {
+    pub fn time(&self) -> TimePoint {
+        match self {
+            Self::Assigned { time, .. }
+            | Self::Delivered { time, .. }
+            | Self::Parked { time, .. } => *time,
+        }
+    }
+}
+
+/// Which agent was given a job and when
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobRecord {
+    pub job: usize,
+    pub agent: String,
+    pub assigned: TimePoint,
+    /// When the planned path of the agent finishes dropping off the item
+    pub delivered: TimePoint,
+}
+
+#[derive(Debug, ThisError)]
+pub enum MapdError {
+    #[error("Some agents start in conflict with each other:\n{0:?}")]
+    ConflictingStarts(HashMap<String, String>),
+    #[error("The replan period must be greater than zero")]
+    InvalidReplanPeriod,
+    #[error("Unable to create a planner for {0}: {1}")]
+    PlannerSetup(String, String),
+    #[error("An error happened while planning for {0}: {1}")]
+    PlanningError(String, String),
+}
+
+#[derive(Debug, Clone)]
+struct AgentState {
+    /// Waypoints that the agent has committed to. This always contains at
+    /// least one waypoint.
+    committed: Vec<WaypointSE2>,
+    /// The job that the agent is currently carrying out
+    job: Option<usize>,
+}
+
+impl AgentState {
+    fn last(&self) -> &WaypointSE2 {
+        self.committed.last().unwrap()
+    }
+}
+
+/// Multi-agent pickup and delivery.
+///
+/// Whenever an agent receives a job it plans all the way through the pickup
+/// and the drop, and commits to that entire path. Every other agent plans
+/// around the committed paths, and agents hold the final position of their
+/// path indefinitely. To keep idle agents from blocking jobs, an idle agent
+/// that sits on the pickup or drop cell of an unfinished job is sent to the
+/// nearest free parking cell.
+pub struct Mapd {
+    settings: MapdSettings,
+    cell_size: f64,
+    names: Vec<String>,
+    profiles: Vec<CircularProfile>,
+    planners: Vec<PlannerSE2>,
+    states: Vec<AgentState>,
+    jobs: Vec<Job>,
+    pending: Vec<usize>,
+    delivered: usize,
+    records: Vec<JobRecord>,
+    events: Vec<MapdEvent>,
+}
+
+impl Mapd {
+    pub fn new(scenario: &Scenario, settings: MapdSettings) -> Result<Self, MapdError> {
+        if settings.replan_period.nanos <= 0 {
+            return Err(MapdError::InvalidReplanPeriod);
+        }
+
+        let conflicts = start_conflicts(scenario);
+        if !conflicts.is_empty() {
+            return Err(MapdError::ConflictingStarts(conflicts));
+        }
+
+        let grid = scenario.make_grid();
+
+        let zones = scenario.make_blocked_zones();
+        let cs = scenario.cell_size;
+        let mut names = Vec::new();
+        let mut profiles = Vec::new();
+        let mut planners = Vec::new();
+        let mut states = Vec::new();
+        for (name, agent) in &scenario.agents {
+            let profile = CircularProfile::new(agent.radius, 0.0, 0.0)
+                .map_err(|_| MapdError::PlannerSetup(name.clone(), "radius".to_owned()))?;
+            let planner = make_planner(agent, profile, &grid, &zones, settings.queue_length_limit)
+                .map_err(|err| MapdError::PlannerSetup(name.clone(), err))?;
+            let p = agent.start_cell().center_point(cs);
+
+            names.push(name.clone());
+            profiles.push(profile);
+            planners.push(planner);
+            states.push(AgentState {
+                committed: vec![WaypointSE2::new(TimePoint::zero(), p.x, p.y, agent.yaw)],
+                job: None,
+            });
+        }
+
+        Ok(Self {
+            settings,
+            cell_size: cs,
+            names,
+            profiles,
+            planners,
+            states,
+            jobs: Vec::new(),
+            pending: Vec::new(),
+            delivered: 0,
+            records: Vec::new(),
+            events: Vec::new(),
+        })
+    }
+
+    pub fn settings(&self) -> &MapdSettings {
+        &self.settings
+    }
+
+    /// Add a job to the back of the queue. The return value identifies the job
+    /// in the events and records.
+    pub fn push_job(&mut self, job: Job) -> usize {
+        let id = self.jobs.len();
+        self.jobs.push(job);
+        self.pending.push(id);
+        id
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Every event that has happened so far, in the order that they happened.
+    pub fn events(&self) -> &[MapdEvent] {
+        &self.events
+    }
+
+    /// Every job that has been assigned so far, in the order they were
+    /// assigned.
+    pub fn records(&self) -> &[JobRecord] {
+        &self.records
+    }
+
+    /// Check whether every job that was pushed has been delivered.
+    pub fn is_finished(&self) -> bool {
+        self.delivered == self.jobs.len()
+    }
+
+    /// Get the trajectory that each agent has committed to so far. The agents
+    /// hold their final position indefinitely.
+    pub fn trajectories(&self) -> BTreeMap<String, LinearTrajectorySE2> {
+        self.names
+            .iter()
+            .zip(self.states.iter())
+            .map(|(name, state)| (name.clone(), make_trajectory(&state.committed)))
+            .collect()
+    }
+
+    /// Compute the metrics of the jobs that have been delivered so far.
+    pub fn metrics(&self) -> MapdMetrics {
+        compute_metrics(
+            &self.jobs,
+            &self.records,
+            &self.trajectories(),
+            self.cell_size,
+        )
+    }
+
+    /// Advance to the given time. Agents that have finished their jobs become
+    /// idle, released jobs are given to idle agents, and idle agents that are
+    /// in the way get parked. The events of this step are returned.
+    pub fn step(&mut self, now: TimePoint) -> Result<Vec<MapdEvent>, MapdError> {
+        let first_event = self.events.len();
+
+        for (i, state) in self.states.iter_mut().enumerate() {
+            if state.last().time > now {
+                continue;
+            }
+
+            if let Some(job) = state.job.take() {
+                self.delivered += 1;
+                self.events.push(MapdEvent::Delivered {
+                    time: state.last().time,
+                    agent: self.names[i].clone(),
+                    job,
+                });
+            }
+        }
+
+        match self.settings.strategy {
+            MapdStrategy::TokenPassing => self.pass_token(now)?,
+            MapdStrategy::Centralized => self.assign_centrally(now)?,
+        }
+
+        self.park_idle_agents(now)?;
+
+        Ok(self.events[first_event..].to_vec())
+    }
+
+    fn pass_token(&mut self, now: TimePoint) -> Result<(), MapdError> {
+        for i in 0..self.states.len() {
+            if self.states[i].job.is_some() {
+                continue;
+            }
+
+            let p = self.states[i].last().position.translation.vector;
+            let mut candidates = self.available_jobs(i, now);
+            candidates.sort_by(|a, b| {
+                let da = (self.jobs[*a]
+                    .pickup_cell()
+                    .center_point(self.cell_size)
+                    .coords
+                    - p)
+                    .norm();
+                let db = (self.jobs[*b]
+                    .pickup_cell()
+                    .center_point(self.cell_size)
+                    .coords
+                    - p)
+                    .norm();
+                da.total_cmp(&db)
+            });
+
+            for job in candidates {
+                if self.try_assign(i, job, now)? {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn assign_centrally(&mut self, now: TimePoint) -> Result<(), MapdError> {
+        let idle: Vec<usize> = (0..self.states.len())
+            .filter(|i| self.states[*i].job.is_none())
+            .collect();
+        let released: Vec<usize> = self
+            .pending
+            .iter()
+            .copied()
+            .filter(|job| self.jobs[*job].release_time() <= now)
+            .collect();
+        if idle.is_empty() || released.is_empty() {
+            return Ok(());
+        }
+
+        // The cost of each agent doing each job if it were alone
+        let size = idle.len().max(released.len());
+        let mut costs = vec![vec![0.0; size]; size];
+        for (row, i) in idle.iter().enumerate() {
+            let available = self.available_jobs(*i, now);
+            let start = self.resume_start(*i, now);
+            for (col, job) in released.iter().enumerate() {
+                costs[row][col] = if available.contains(job) {
+                    match plan_sequence(&self.planners[*i], start, &self.jobs[*job].make_goal()) {
+                        Ok(SearchStatus::Solved(solution)) => solution.total_cost.0,
+                        _ => f64::INFINITY,
+                    }
+                } else {
+                    f64::INFINITY
+                };
+            }
+        }
+
+        let assignment = hungarian(&costs);
+        for (row, i) in idle.iter().enumerate() {
+            let col = assignment[row];
+            if col >= released.len() || costs[row][col].is_infinite() {
+                continue;
+            }
+
+            // Another assignment in this step may have claimed an endpoint of
+            // this job, in which case it waits for the next step.
+            let job = released[col];
+            if self.available_jobs(*i, now).contains(&job) {
+                self.try_assign(*i, job, now)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Released jobs that have not been taken and whose endpoints are not the
+    /// endpoint of any agent other than agent `i`.
+    fn available_jobs(&self, i: usize, now: TimePoint) -> Vec<usize> {
+        let endpoints: Vec<Cell> = self
+            .states
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, state)| self.cell_of(state.last()))
+            .collect();
+
+        self.pending
+            .iter()
+            .copied()
+            .filter(|job| {
+                let job = &self.jobs[*job];
+                job.release_time() <= now && !endpoints.iter().any(|cell| job.is_endpoint(*cell))
+            })
+            .collect()
+    }
+
+    fn try_assign(&mut self, i: usize, job: usize, now: TimePoint) -> Result<bool, MapdError> {
+        let goal = self.jobs[job].make_goal();
+        let Some(path) = self.plan(i, now, &goal)? else {
+            return Ok(false);
+        };
+
+        // The goal holds at the drop cell until the item is dropped off, so
+        // the path finishes with the delivery.
+        let delivered = path.last().unwrap_or(self.states[i].last()).time;
+        let state = &mut self.states[i];
+        state.committed.extend(path);
+        state.job = Some(job);
+        self.pending.retain(|j| *j != job);
+        self.records.push(JobRecord {
+            job,
+            agent: self.names[i].clone(),
+            assigned: now,
+            delivered,
+        });
+        self.events.push(MapdEvent::Assigned {
+            time: now,
+            agent: self.names[i].clone(),
+            job,
+        });
+        Ok(true)
+    }
+
+    fn park_idle_agents(&mut self, now: TimePoint) -> Result<(), MapdError> {
+        for i in 0..self.states.len() {
+            if self.states[i].job.is_some() {
+                continue;
+            }
+
+            let cell = self.cell_of(self.states[i].last());
+            let in_the_way = self
+                .pending
+                .iter()
+                .chain(self.states.iter().filter_map(|state| state.job.as_ref()))
+                .any(|job| self.jobs[*job].is_endpoint(cell));
+            if !in_the_way {
+                continue;
+            }
+
+            let taken: Vec<Cell> = self
+                .states
+                .iter()
+                .map(|state| self.cell_of(state.last()))
+                .collect();
+            let p = cell.center_point(self.cell_size);
+            let mut parking: Vec<Cell> = self
+                .settings
+                .parking
+                .iter()
+                .map(|cell| Cell::from(*cell))
+                .filter(|cell| !taken.contains(cell))
+                .collect();
+            parking.sort_by(|a, b| {
+                let da = (a.center_point(self.cell_size) - p).norm();
+                let db = (b.center_point(self.cell_size) - p).norm();
+                da.total_cmp(&db)
+            });
+
+            for spot in parking {
+                let goal = SequentialGoalSE2::new(vec![StopSE2::new(spot)]);
+                if let Some(path) = self.plan(i, now, &goal)? {
+                    self.states[i].committed.extend(path);
+                    self.events.push(MapdEvent::Parked {
+                        time: now,
+                        agent: self.names[i].clone(),
+                        cell: [spot.x, spot.y],
+                    });
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Plan for agent `i` around the committed paths of every other agent.
+    /// This gives back the new waypoints that come after the committed ones,
+    /// or None if no path could be found right now.
+    fn plan(
+        &mut self,
+        i: usize,
+        now: TimePoint,
+        goal: &SequentialGoalSE2<Cell>,
+    ) -> Result<Option<Vec<WaypointSE2>>, MapdError> {
+        let start = resume_from(&mut self.states[i].committed, now, self.cell_size);
+        let obstacles = self
+            .states
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(j, state)| frozen_obstacle(self.profiles[j], &state.committed, now))
+            .collect();
+
+        let name = &self.names[i];
+        let planner = with_obstacles(&self.planners[i], self.profiles[i], obstacles)
+            .map_err(|err| MapdError::PlannerSetup(name.clone(), err))?;
+
+        let status = plan_sequence(&planner, start, goal)
+            .map_err(|err| MapdError::PlanningError(name.clone(), format!("{err:?}")))?;
+        let solution = match status {
+            SearchStatus::Solved(solution) => solution,
+            SearchStatus::Impossible | SearchStatus::Incomplete => return Ok(None),
+        };
+
+        let trajectory = solution
+            .make_trajectory_or_hold::<WaypointSE2>(Duration::from_secs(1))
+            .map_err(|err| MapdError::PlanningError(name.clone(), format!("{err:?}")))?
+            .trajectory;
+
+        Ok(Some(
+            trajectory
+                .iter()
+                .filter(|wp| wp.time > start.time)
+                .collect(),
+        ))
+    }
+
+    fn resume_start(&self, i: usize, now: TimePoint) -> StartSE2<Cell> {
+        let mut committed = vec![*self.states[i].last()];
+        resume_from(&mut committed, now, self.cell_size)
+    }
+
+    fn cell_of(&self, wp: &WaypointSE2) -> Cell {
+        let p = wp.position.translation.vector;
+        Cell::from_point([p.x, p.y].into(), self.cell_size)
+    }
+}
+
+/// Replay a job log until every job is delivered or until `max_time` is
+/// reached. The outcome is fully determined by the scenario, the jobs, and the
+/// settings.
+pub fn simulate(
+    scenario: &Scenario,
+    jobs: &[Job],
+    settings: MapdSettings,
+    max_time: TimePoint,
+) -> Result<Mapd, MapdError> {
+    let replan_period = settings.replan_period;
+    let mut mapd = Mapd::new(scenario, settings)?;
+    for job in jobs {
+        mapd.push_job(*job);
+    }
+
+    let mut now = TimePoint::zero();
+    while now <= max_time {
+        mapd.step(now)?;
+        if mapd.is_finished() {
+            break;
+        }
+        now += replan_period;
+    }
+
+    Ok(mapd)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MapdMetrics {
+    /// Seconds from the release of each delivered job until its item was
+    /// dropped off
+    pub service_times: BTreeMap<usize, f64>,
+    /// Average of the service times
+    pub mean_service_time: f64,
+    /// Seconds from the start until the last item was dropped off
+    pub makespan: f64,
+    /// Delivered jobs per second over the makespan
+    pub throughput: f64,
+}
+
+/// Measure how well the jobs were served by looking at the trajectories of the
+/// agents. Each job is traced through the trajectory of the agent it was
+/// assigned to, starting from the time it was assigned. Jobs whose drop cannot
+/// be found in the trajectory are not counted as delivered. The drop-off time
+/// is where the path that was planned for the job finishes.
+pub fn compute_metrics(
+    jobs: &[Job],
+    records: &[JobRecord],
+    trajectories: &BTreeMap<String, LinearTrajectorySE2>,
+    cell_size: f64,
+) -> MapdMetrics {
+    let cell_of = |wp: &WaypointSE2| {
+        let p = wp.position.translation.vector;
+        Cell::from_point([p.x, p.y].into(), cell_size)
+    };
+
+    let mut metrics = MapdMetrics::default();
+    let mut last_delivery = TimePoint::zero();
+    for record in records {
+        let Some(trajectory) = trajectories.get(&record.agent) else {
+            continue;
+        };
+        let job = &jobs[record.job];
+
+        let picked_up = trajectory
+            .iter()
+            .filter(|wp| wp.time >= record.assigned)
+            .find(|wp| cell_of(wp) == job.pickup_cell())
+            .map(|wp| wp.time + Duration::from_secs_f64(job.pickup_duration));
+        let Some(picked_up) = picked_up else {
+            continue;
+        };
+
+        let reached_drop = trajectory
+            .iter()
+            .filter(|wp| wp.time >= picked_up)
+            .any(|wp| cell_of(&wp) == job.drop_cell());
+        if !reached_drop {
+            continue;
+        }
+        let dropped_off = record.delivered;
+
+        last_delivery = last_delivery.max(dropped_off);
+        metrics
+            .service_times
+            .insert(record.job, (dropped_off - job.release_time()).as_secs_f64());
+    }
+
+    let delivered = metrics.service_times.len();
+    if delivered > 0 {
+        metrics.mean_service_time = metrics.service_times.values().sum::<f64>() / delivered as f64;
+        metrics.makespan = last_delivery.as_secs_f64();
+        if metrics.makespan > 0.0 {
+            metrics.throughput = delivered as f64 / metrics.makespan;
+        }
+    }
+
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::{test_scenario, Agent};
+
+    fn make_scenario() -> Scenario {
+        test_scenario([
+            ("A", Agent::new([0, 0], [0, 0])),
+            ("B", Agent::new([0, 4], [0, 4])),
+            ("C", Agent::new([4, 2], [4, 2])),
+        ])
+    }
+
+    fn make_jobs() -> Vec<Job> {
+        vec![
+            Job::new([2, 0], [6, 0]).with_pickup_duration(2.0),
+            Job::new([2, 4], [6, 4]).with_drop_duration(1.0),
+            // C starts on the drop cell of this job, so it needs to get out of
+            // the way.
+            Job::new([0, 2], [4, 2]).with_release(5.0),
+        ]
+    }
+
+    fn run(strategy: MapdStrategy) -> Mapd {
+        let settings = MapdSettings::default()
+            .with_strategy(strategy)
+            .with_parking(vec![[8, 2], [8, 6]]);
+        simulate(
+            &make_scenario(),
+            &make_jobs(),
+            settings,
+            TimePoint::from_secs_f64(120.0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_token_passing_delivers_jobs() {
+        let mapd = run(MapdStrategy::TokenPassing);
+        assert!(mapd.is_finished());
+
+        assert!(mapd.events().contains(&MapdEvent::Assigned {
+            time: TimePoint::zero(),
+            agent: "A".to_owned(),
+            job: 0,
+        }));
+        assert!(mapd.events().contains(&MapdEvent::Parked {
+            time: TimePoint::zero(),
+            agent: "C".to_owned(),
+            cell: [8, 2],
+        }));
+
+        let metrics = mapd.metrics();
+        assert_eq!(metrics.service_times.len(), 3);
+        // Job 0 needs at least 4m of travel at 0.75m/s plus the pickup.
+        assert!(metrics.service_times[&0] >= 4.0 / 0.75 + 2.0);
+        assert!(metrics.service_times[&2] > 0.0);
+        assert!(metrics.throughput > 0.0);
+
+        // The agent of job 1 holds the drop cell until the drop-off finishes.
+        let record = mapd.records().iter().find(|r| r.job == 1).unwrap();
+        let drop = mapd.jobs()[1].drop_cell();
+        let trajectory = &mapd.trajectories()[&record.agent];
+        let at_drop: Vec<TimePoint> = trajectory
+            .iter()
+            .filter(|wp| {
+                let p = wp.position.translation.vector;
+                Cell::from_point([p.x, p.y].into(), 1.0) == drop
+            })
+            .map(|wp| wp.time)
+            .collect();
+        assert!(at_drop.contains(&record.delivered));
+        assert!(at_drop
+            .iter()
+            .any(|t| *t + Duration::from_secs(1) <= record.delivered));
+        assert_eq!(metrics.service_times[&1], record.delivered.as_secs_f64());
+
+        // Replaying the same log gives the same outcome.
+        assert_eq!(mapd.events(), run(MapdStrategy::TokenPassing).events());
+    }
+
+    #[test]
+    fn test_centralized_delivers_jobs() {
+        let mapd = run(MapdStrategy::Centralized);
+        assert!(mapd.is_finished());
+        assert_eq!(mapd.metrics().service_times.len(), 3);
+        for record in mapd.records() {
+            let assigned = mapd
+                .records()
+                .iter()
+                .filter(|r| r.job == record.job)
+                .count();
+            assert_eq!(assigned, 1);
+        }
+    }
+}
@@ -18,29 +18,61 @@
 pub mod scenario;
 pub use scenario::*;
 
+pub mod assignment;
+pub use assignment::{hungarian, negotiate_assignment, AssignmentError, AssignmentSolution};
+
+pub mod cardinal;
+use cardinal::CardinalityAnalysis;
+pub use cardinal::{Cardinality, ConflictSelection, HighLevelHeuristic};
+
+pub mod lifelong;
+pub use lifelong::{Lifelong, LifelongError, LifelongEvent, LifelongSettings, LifelongTask};
+
+pub mod lns;
+pub use lns::{Lns, LnsError, LnsProgress, LnsSettings, Neighborhood};
+
+pub mod mapd;
+pub use mapd::{
+    compute_metrics, Job, JobRecord, Mapd, MapdError, MapdEvent, MapdMetrics, MapdSettings,
+    MapdStrategy,
+};
+
+pub mod export;
+pub use export::{ExportedKey, ExportedNegotiation, ExportedNegotiationNode, ExportedRange};
+
+pub mod checkpoint;
+pub use checkpoint::{NegotiationCheckpoint, RestoredNegotiation};
+
+pub mod symmetry;
+use symmetry::{break_symmetry, SymmetryContext};
+pub use symmetry::{ConflictKind, ConstraintSet};
+
 use crate::{
     algorithm::{
-        path::{DecisionRange, MetaTrajectory},
-        AStarConnect, QueueLength, SearchStatus,
+        path::{DecisionPoint, DecisionRange, MetaTrajectory},
+        AStarConnect, SearchStatus,
     },
     domain::{ClosedStatus, Configurable, Cost},
     error::ThisError,
     graph::{occupancy::*, SharedGraph},
     motion::{
+        capacity::find_overflow,
         have_conflict,
         r2::{Positioned, WaypointR2},
-        se2::{DifferentialDriveLineFollow, WaypointSE2},
+        se2::{plan_sequence, KeySE2, Orientation, StartSE2, WaypointSE2},
         trajectory::TrajectoryIter,
-        BoundingBox, CcbsConstraint, CcbsEnvironment, CircularProfile, Duration,
-        DynamicCircularObstacle, DynamicEnvironment, TimePoint, Timed, TravelEffortCost,
+        BlockedZone, BoundingBox, CapacityReservations, CapacityZone, CcbsConstraint,
+        CcbsEnvironment, CircularProfile, Duration, DynamicCircularObstacle, DynamicEnvironment,
+        Occupancy, Reservation, TimePoint, Timed,
     },
     planner::{halt::QueueLengthLimit, Planner},
     premade::{SippSE2, StateSippSE2},
-    util::triangular_for,
+    util::{parallel_map, triangular_for},
 };
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::Reverse,
-    collections::{BinaryHeap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
     sync::Arc,
 };
 
@@ -50,45 +82,194 @@ pub enum NegotiationError {
     ConflictingEndpoints(HashMap<String, String>),
     #[error("It was impossible to find a basic plan for {0}")]
     PlanningImpossible(String),
+    #[error("The time windows of {0} cannot be met even without other agents")]
+    DeadlineInfeasible(String),
+    #[error("The predicted trajectory of {0} needs at least two waypoints")]
+    InvalidPrediction(String),
+    #[error(
+        "A solution could not be found because the time windows of {agents:?} could not be met"
+    )]
+    DeadlinesMissed {
+        agents: Vec<String>,
+        nodes: Vec<NegotiationNode>,
+        name_map: HashMap<usize, String>,
+    },
     #[error("A solution might have been possible, but we failed to find it")]
     PlanningFailed((Vec<NegotiationNode>, HashMap<usize, String>)),
+    #[error("The negotiation was paused after expanding the number of nodes in its settings")]
+    Paused {
+        nodes: Vec<NegotiationNode>,
+        closer: NegotiationCloser,
+        name_map: HashMap<usize, String>,
+    },
 }
 
+/// The single-agent planner that is used for each agent of a scenario
+pub type PlannerSE2 =
+    Planner<AStarConnect<SippSE2<AccessibilityGraph<SparseGrid>>>, QueueLengthLimit>;
+
+// Negotiations share the planners and environments between threads, so they
+// must remain safe to share.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<PlannerSE2>();
+    assert_send_sync::<CcbsEnvironment<WaypointSE2, Cell>>();
+};
+
+pub(crate) fn make_planner(
+    agent: &Agent,
+    profile: CircularProfile,
+    grid: &SparseGrid,
+    zones: &[BlockedZone],
+    queue_length_limit: Option<usize>,
+) -> Result<PlannerSE2, String> {
+    make_planner_on(
+        make_graph(agent, grid),
+        agent,
+        profile,
+        zones,
+        queue_length_limit,
+    )
+}
+
+/// Make the graph that an agent moves through on the given grid.
+pub(crate) fn make_graph(agent: &Agent, grid: &SparseGrid) -> AccessibilityGraph<SparseGrid> {
+    AccessibilityGraph::new(Arc::new(Accessibility::new(grid.clone(), agent.radius)))
+}
+
+/// Make the planner of an agent on a graph that was already made for it, so
+/// the graph can be shared with other users.
+pub(crate) fn make_planner_on(
+    graph: AccessibilityGraph<SparseGrid>,
+    agent: &Agent,
+    profile: CircularProfile,
+    zones: &[BlockedZone],
+    queue_length_limit: Option<usize>,
+) -> Result<PlannerSE2, String> {
+    let activity = SharedGraph::new(graph);
+    let heuristic = activity.clone();
+    let environment = Arc::new(CcbsEnvironment::new(Arc::new(
+        DynamicEnvironment::new(profile).with_zones(zones.to_vec()),
+    )));
+    let extrapolator = agent
+        .make_extrapolator()
+        .ok_or_else(|| format!("invalid speed or spin for agent: {agent:?}"))?;
+    let weight = agent.make_cost();
+
+    Ok(Planner::new(AStarConnect(
+        SippSE2::new_sipp_se2(activity, heuristic, extrapolator, environment, weight)
+            .map_err(|err| format!("{err:?}"))?,
+    ))
+    .with_halting(QueueLengthLimit(queue_length_limit)))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NegotiationSettings {
+    /// Queue length limit for each individual search
+    pub queue_length_limit: Option<usize>,
+    /// How to choose which conflict to split on
+    pub conflict_selection: ConflictSelection,
+    /// How to estimate how much the cost of a node still has to increase
+    pub heuristic: HighLevelHeuristic,
+    /// How many threads may be used to solve independent negotiations and to
+    /// replan both branches of a conflict at once. With 1 thread everything is
+    /// solved on the calling thread. The outcome is the same for any number of
+    /// threads.
+    pub threads: usize,
+    /// Pause a negotiation once it has expanded this many nodes. The paused
+    /// negotiation is returned as [`NegotiationError::Paused`] so that it can
+    /// be saved in a [`NegotiationCheckpoint`] and continued later with
+    /// [`resume_negotiation`].
+    pub pause_after: Option<usize>,
+}
+
+impl NegotiationSettings {
+    pub fn with_queue_length_limit(mut self, queue_length_limit: Option<usize>) -> Self {
+        self.queue_length_limit = queue_length_limit;
+        self
+    }
+
+    pub fn with_conflict_selection(mut self, conflict_selection: ConflictSelection) -> Self {
+        self.conflict_selection = conflict_selection;
+        self
+    }
+
+    pub fn with_heuristic(mut self, heuristic: HighLevelHeuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_pause_after(mut self, pause_after: Option<usize>) -> Self {
+        self.pause_after = pause_after;
+        self
+    }
+}
+
+impl Default for NegotiationSettings {
+    fn default() -> Self {
+        Self {
+            queue_length_limit: None,
+            conflict_selection: Default::default(),
+            heuristic: Default::default(),
+            threads: 1,
+            pause_after: None,
+        }
+    }
+}
+
+/// The solution node of a negotiation, every node that was explored, and the
+/// names of the agents.
+pub type NegotiationResult = (
+    NegotiationNode,
+    Vec<NegotiationNode>,
+    HashMap<usize, String>,
+);
+
 pub fn negotiate(
     scenario: &Scenario,
     queue_length_limit: Option<usize>,
-) -> Result<
-    (
-        NegotiationNode,
-        Vec<NegotiationNode>,
-        HashMap<usize, String>,
-    ),
-    NegotiationError,
-> {
+) -> Result<NegotiationResult, NegotiationError> {
+    negotiate_with_settings(
+        scenario,
+        NegotiationSettings::default().with_queue_length_limit(queue_length_limit),
+    )
+}
+
+pub fn negotiate_with_settings(
+    scenario: &Scenario,
+    settings: NegotiationSettings,
+) -> Result<NegotiationResult, NegotiationError> {
+    negotiate_from(scenario, settings, None)
+}
+
+/// Continue a negotiation of the scenario from the arena and closer that were
+/// restored from a [`NegotiationCheckpoint`]. Negotiations that were already
+/// solved keep their solutions, and the queue of a paused negotiation is
+/// rebuilt from its nodes that were not expanded yet.
+pub fn resume_negotiation(
+    scenario: &Scenario,
+    restored: RestoredNegotiation,
+    settings: NegotiationSettings,
+) -> Result<NegotiationResult, NegotiationError> {
+    negotiate_from(scenario, settings, Some(restored))
+}
+
+fn negotiate_from(
+    scenario: &Scenario,
+    settings: NegotiationSettings,
+    restored: Option<RestoredNegotiation>,
+) -> Result<NegotiationResult, NegotiationError> {
+    let grid = scenario.make_grid();
+    let zones = scenario.make_blocked_zones();
+    let capacity_zones = scenario.make_capacity_zones();
     let cs = scenario.cell_size;
-    let mut conflicts = HashMap::new();
-    triangular_for(scenario.agents.iter(), |(n_a, a), (n_b, b)| {
-        for (cell_a, cell_b) in [
-            (a.start_cell(), b.start_cell()),
-            (a.goal_cell(), b.goal_cell()),
-        ] {
-            let pa = cell_a.center_point(cs);
-            let pb = cell_b.center_point(cs);
-            let dist = (pa - pb).norm();
-            let min_dist = a.radius + b.radius;
-            if dist < min_dist {
-                conflicts.insert(
-                    (**n_a).clone().min((*n_b).clone()),
-                    (**n_a).clone().max((*n_b).clone()),
-                );
-            }
-        }
-    });
-    if !conflicts.is_empty() {
-        return Err(NegotiationError::ConflictingEndpoints(conflicts));
-    }
 
-    let (name_map, agents) = {
+    let (name_map, mut agents) = {
         let mut name_map = HashMap::new();
         let mut agents = Vec::new();
         for (name, agent) in &scenario.agents {
@@ -99,67 +280,83 @@ pub fn negotiate(
         (name_map, agents)
     };
 
-    let grid = {
-        let mut grid = SparseGrid::new(scenario.cell_size);
-        let changes: HashMap<_, _> = scenario
-            .occupancy
-            .iter()
-            .flat_map(|(y, row)| row.iter().map(|x| (Cell::new(*x, *y), true)))
-            .collect();
-        grid.change_cells(&changes);
-        grid
-    };
-
     let profiles: Vec<_> = agents
         .iter()
         .map(|a| CircularProfile::new(a.radius, 0.0, 0.0).unwrap())
         .collect();
 
+    let graphs: Vec<_> = agents.iter().map(|a| make_graph(a, &grid)).collect();
+
     let planners = agents
         .iter()
-        .map(|a| {
-            let profile = CircularProfile::new(a.radius, 0.0, 0.0).unwrap();
-            let accessibility = Arc::new(Accessibility::new(grid.clone(), a.radius));
-            // let visibility = Arc::new(Visibility::new(grid.clone(), a.radius));
-            // let heuristic = SharedGraph::new(VisibilityGraph::new(visibility.clone(), []));
-            // let activity = SharedGraph::new(NeighborhoodGraph::new(visibility.clone(), []));
-            let activity = SharedGraph::new(AccessibilityGraph::new(accessibility));
-            let heuristic = activity.clone();
-            let environment = Arc::new(CcbsEnvironment::new(Arc::new(DynamicEnvironment::new(
-                profile,
-            ))));
-            let extrapolator = DifferentialDriveLineFollow::new(a.speed, a.spin).unwrap();
-            let weight = TravelEffortCost::default();
-
-            Planner::new(AStarConnect(
-                SippSE2::new_sipp_se2(activity, heuristic, extrapolator, environment, weight)
-                    .unwrap(),
-            ))
-            .with_halting(QueueLengthLimit(queue_length_limit))
+        .zip(graphs.iter())
+        .zip(profiles.iter())
+        .map(|((a, graph), profile)| {
+            make_planner_on(
+                graph.clone(),
+                a,
+                *profile,
+                &zones,
+                settings.queue_length_limit,
+            )
+            .map_err(NegotiationError::PlanningImpossible)
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assign_goal_regions(&mut agents, &planners, &grid, cs);
+    let mut conflicts = HashMap::new();
+    triangular_for(agents.iter().enumerate(), |(i_a, a), (i_b, b)| {
+        for (cell_a, cell_b) in [
+            (a.start_cell(), b.start_cell()),
+            (a.goal_cell(), b.goal_cell()),
+        ] {
+            let pa = cell_a.center_point(cs);
+            let pb = cell_b.center_point(cs);
+            let dist = (pa - pb).norm();
+            let min_dist = a.radius + b.radius;
+            if dist < min_dist {
+                let (n_a, n_b) = (&name_map[i_a], &name_map[&i_b]);
+                conflicts.insert(n_a.min(n_b).clone(), n_a.max(n_b).clone());
+            }
+        }
+    });
+    if !conflicts.is_empty() {
+        return Err(NegotiationError::ConflictingEndpoints(conflicts));
+    }
 
     let mut ideal: Vec<Proposal> = Vec::new();
     for (i, (agent, planner)) in agents.iter().zip(planners.iter()).enumerate() {
+        if agent.is_predicted() {
+            // Predicted agents are not planned. They follow their prediction
+            // and the other agents make way for them.
+            let proposal = predicted_proposal(agent, cs)
+                .ok_or_else(|| NegotiationError::InvalidPrediction(name_map[&i].clone()))?;
+            ideal.push(proposal);
+            continue;
+        }
+
         let start = agent.make_start();
-        let goal = agent.make_goal();
-        let s = match match planner.plan(start, goal) {
-            Ok(search) => search,
+        let goal = agent.make_sequential_goal();
+        let s = match match plan_sequence(planner, start, &goal) {
+            Ok(status) => status,
             Err(err) => {
                 return Err(NegotiationError::PlanningImpossible(
                     format!("{err:?}").to_owned(),
                 ))
             }
         }
-        .solve()
-        .unwrap()
         .solution()
         {
             Some(s) => s,
             None => {
-                return Err(NegotiationError::PlanningImpossible(
-                    name_map.get(&i).unwrap().clone(),
-                ));
+                let name = name_map.get(&i).unwrap().clone();
+                if agent.has_time_windows() {
+                    let relaxed = agent.without_time_windows().make_sequential_goal();
+                    if let Ok(SearchStatus::Solved(_)) = plan_sequence(planner, start, &relaxed) {
+                        return Err(NegotiationError::DeadlineInfeasible(name));
+                    }
+                }
+                return Err(NegotiationError::PlanningImpossible(name));
             }
         };
         let mt = s
@@ -172,20 +369,41 @@ pub fn negotiate(
         });
     }
 
-    let (mut negotiation_of_agent, mut negotiations) = organize_negotiations(&ideal, &profiles);
+    let (mut negotiation_of_agent, mut negotiations) =
+        organize_negotiations(&ideal, &profiles, &capacity_zones);
 
+    let mut arena = Vec::new();
+    // Nodes closed in earlier rounds stay closed, the same as when the rounds
+    // were solved one node at a time.
     let mut closer = NegotiationCloser::new();
+    if let Some(restored) = restored {
+        arena = restored.nodes;
+        closer = restored.closer;
+    }
+    let mut resumable = Resumable::new(&arena, &closer);
+
+    let context = NegotiationContext {
+        agents: &agents,
+        profiles: &profiles,
+        graphs: &graphs,
+        planners: &planners,
+        name_map: &name_map,
+        cell_size: cs,
+        settings,
+        restored: arena.len(),
+    };
+
     let mut culled = 0;
     let mut count = 0;
     let mut solution_node: Option<NegotiationNode> = None;
-    let mut arena = Vec::new();
     while !negotiations.is_empty() {
         dbg!(count);
         count += 1;
 
         let base_env = {
             let mut base_env =
-                DynamicEnvironment::new(CircularProfile::new(0.0, 0.0, 0.0).unwrap());
+                DynamicEnvironment::new(CircularProfile::new(0.0, 0.0, 0.0).unwrap())
+                    .with_zones(zones.clone());
             for i in 0..agents.len() {
                 if !negotiation_of_agent.contains_key(&i) {
                     base_env.obstacles.push(
@@ -197,81 +415,435 @@ pub fn negotiate(
             Arc::new(base_env)
         };
 
-        for root in negotiations.values() {
-            let mut queue: BinaryHeap<QueueEntry> = BinaryHeap::new();
-            let root = NegotiationNode::from_root(root, &ideal, base_env.clone(), arena.len());
-            arena.push(root.clone());
-            queue.push(QueueEntry::new(root));
-
-            let mut solution = None;
-            let mut iters = 0;
-            while let Some(mut top) = queue.pop() {
-                iters += 1;
-                if iters % 10 == 0 {
-                    dbg!(iters);
+        // Agents outside of every negotiation keep their ideal plans, so the
+        // capacity zones that they pass through are reserved for them.
+        let mut base_capacity = CapacityReservations::new(capacity_zones.clone());
+        for (i, proposal) in ideal.iter().enumerate() {
+            if negotiation_of_agent.contains_key(&i) {
+                continue;
+            }
+
+            for (z, zone) in capacity_zones.iter().enumerate() {
+                for (_, occupancy) in zone_occupancies(&proposal.meta, zone) {
+                    base_capacity.reserve(Reservation {
+                        zone: z,
+                        agent: i,
+                        occupancy,
+                    });
                 }
-                if iters > 1000 {
-                    println!("Too many iterations");
+            }
+        }
 
-                    // Dump the remaining queue into the node history
-                    println!("Queue begins at {}", arena.len() + 1);
-                    while let Some(remainder) = queue.pop() {
-                        arena.push(remainder.node);
-                    }
+        // The negotiations do not depend on each other, so they are solved in
+        // parallel. Their outcomes are collected in the order of their IDs so
+        // that the result is the same for any number of threads.
+        let roots: Vec<_> = negotiations
+            .values()
+            .map(|root| (root, resumable.take(root)))
+            .collect();
+        let outcomes = parallel_map(&roots, settings.threads, |(root, resume)| {
+            if let Resume::Solved(solution) = resume {
+                return NegotiationOutcome {
+                    solution: Some(solution.as_ref().clone()),
+                    arena: Vec::new(),
+                    closer: NegotiationCloser::new(),
+                    culled: 0,
+                    paused: false,
+                };
+            }
 
-                    break;
+            let queued = match resume {
+                Resume::Queued(queued) => queued.clone(),
+                _ => Vec::new(),
+            };
+            solve_negotiation(
+                root,
+                queued,
+                &ideal,
+                base_env.clone(),
+                &base_capacity,
+                &closer,
+                &context,
+            )
+        });
+
+        let mut paused = false;
+        for outcome in outcomes {
+            culled += outcome.culled;
+            paused |= outcome.paused;
+            // New nodes were given IDs after the restored nodes, so that is
+            // where the offset starts.
+            let offset = arena.len() - context.restored;
+            closer.merge_from(outcome.closer, context.restored, offset);
+            arena.extend(
+                outcome
+                    .arena
+                    .into_iter()
+                    .map(|node| node.with_id_offset(context.restored, offset)),
+            );
+
+            if outcome.paused {
+                continue;
+            }
+
+            if let Some(solution) = outcome.solution {
+                let solution = solution.with_id_offset(context.restored, offset);
+                for (i, mt) in &solution.proposals {
+                    ideal[*i] = mt.clone();
                 }
+                solution_node = Some(solution);
+            } else {
+                // TODO(@mxgrey): Consider re-running the negotiation but
+                // without the base environment in order to identify which other
+                // agents need to be pulled into the negotiation.
+                // Even better would be to queue up those nodes as backup nodes
+                // in a lower priority queue running in parallel, then use the
+                // outcome if a solution cannot be found.
+                println!("Culled {culled}");
+                let mut missed: Vec<String> = arena
+                    .iter()
+                    .filter(|n| matches!(n.outcome, NodeOutcome::DeadlineMissed))
+                    .filter_map(|n| n.conceded)
+                    .map(|i| name_map[&i].clone())
+                    .collect();
+                if !missed.is_empty() {
+                    missed.sort_unstable();
+                    missed.dedup();
+                    return Err(NegotiationError::DeadlinesMissed {
+                        agents: missed,
+                        nodes: arena,
+                        name_map,
+                    });
+                }
+                return Err(NegotiationError::PlanningFailed((arena, name_map)));
+            }
+        }
 
-                if !closer.close(&top.node) {
-                    culled += 1;
-                    // println!("REDUNDANT NODE: {:?}", top.node.keys);
-                    continue;
+        if paused {
+            return Err(NegotiationError::Paused {
+                nodes: arena,
+                closer,
+                name_map,
+            });
+        }
+
+        (negotiation_of_agent, negotiations) = reconsider_negotiations(
+            &ideal,
+            &profiles,
+            &capacity_zones,
+            negotiation_of_agent,
+            negotiations,
+        );
+    }
+
+    if solution_node.is_none() {
+        let fake = NegotiationNode::from_root(
+            &Negotiation {
+                conflicts: vec![],
+                capacity_conflicts: vec![],
+                participants: vec![],
+            },
+            &ideal,
+            Arc::new(
+                DynamicEnvironment::new(CircularProfile::new(0.0, 0.0, 0.0).unwrap())
+                    .with_zones(zones),
+            ),
+            &CapacityReservations::new(capacity_zones.clone()),
+            0,
+        );
+
+        solution_node = Some(fake);
+    }
+
+    if let Some(node) = &mut solution_node {
+        for (i, proposal) in ideal.iter().enumerate() {
+            match node.proposals.entry(i) {
+                std::collections::hash_map::Entry::Vacant(vacant) => {
+                    vacant.insert(proposal.clone());
                 }
+                _ => {}
+            }
+        }
+    }
+
+    // dbg!(closer);
+
+    println!("Culled {culled}");
+    Ok((solution_node.unwrap(), arena, name_map))
+    // Ok(ideal.into_iter().enumerate().map(|(i, proposal)|
+    //     (name_map.get(&i).unwrap().clone(), proposal)
+    // ).collect())
+}
+
+/// Everything that stays the same while the negotiations are being solved
+struct NegotiationContext<'a> {
+    agents: &'a [Agent],
+    profiles: &'a Vec<CircularProfile>,
+    graphs: &'a [AccessibilityGraph<SparseGrid>],
+    planners: &'a [PlannerSE2],
+    name_map: &'a HashMap<usize, String>,
+    cell_size: f64,
+    settings: NegotiationSettings,
+    /// How many nodes were restored from a checkpoint
+    restored: usize,
+}
+
+/// The result of solving one [`Negotiation`]. The IDs of the new nodes in the
+/// arena and the closer start from the number of restored nodes.
+struct NegotiationOutcome {
+    solution: Option<NegotiationNode>,
+    arena: Vec<NegotiationNode>,
+    closer: NegotiationCloser,
+    culled: usize,
+    /// The negotiation reached [`NegotiationSettings::pause_after`]
+    paused: bool,
+}
+
+/// How to continue a negotiation from the nodes of a checkpoint
+#[derive(Clone)]
+enum Resume {
+    /// The negotiation was not started yet
+    Start,
+    /// The negotiation was solved by this node
+    Solved(Box<NegotiationNode>),
+    /// The negotiation was paused with these nodes in its queue
+    Queued(Vec<NegotiationNode>),
+}
+
+/// The nodes of a checkpoint that have not been used to resume a negotiation
+/// yet, grouped by the participants of their negotiation.
+struct Resumable {
+    solved: HashMap<Vec<usize>, NegotiationNode>,
+    queued: HashMap<Vec<usize>, Vec<NegotiationNode>>,
+}
+
+impl Resumable {
+    fn new(arena: &[NegotiationNode], closer: &NegotiationCloser) -> Self {
+        // A node was expanded if it has children, if it failed to produce a
+        // child, or if it closed its keys. Failed nodes are saved with the ID
+        // of the node that produced them.
+        let mut expanded: HashSet<usize> = arena.iter().filter_map(|n| n.parent).collect();
+        let mut seen = HashSet::new();
+        for node in arena {
+            if !seen.insert(node.id) {
+                expanded.insert(node.id);
+            }
+        }
+        let closed: HashSet<usize> = closer.closed_set.values().flatten().copied().collect();
+        expanded.extend(closed.iter().copied());
+
+        let mut solved = HashMap::new();
+        let mut queued: HashMap<_, Vec<_>> = HashMap::new();
+        for node in arena {
+            if node.outcome != NodeOutcome::Success {
+                continue;
+            }
+
+            let participants = sorted_participants(&node.negotiation);
+            if closed.contains(&node.id)
+                && node.negotiation.conflicts.is_empty()
+                && node.negotiation.capacity_conflicts.is_empty()
+            {
+                // Only the solution gets closed without any conflicts left
+                solved.insert(participants, node.clone());
+            } else if !expanded.contains(&node.id) {
+                queued.entry(participants).or_default().push(node.clone());
+            }
+        }
+
+        Self { solved, queued }
+    }
+
+    fn take(&mut self, root: &Negotiation) -> Resume {
+        let participants = sorted_participants(root);
+        let queued = self.queued.remove(&participants);
+        if let Some(solution) = self.solved.remove(&participants) {
+            // Nodes left in the queue of a solved negotiation are not needed
+            return Resume::Solved(Box::new(solution));
+        }
+
+        match queued {
+            Some(queued) => Resume::Queued(queued),
+            None => Resume::Start,
+        }
+    }
+}
+
+fn sorted_participants(negotiation: &Negotiation) -> Vec<usize> {
+    let mut participants = negotiation.participants.clone();
+    participants.sort_unstable();
+    participants
+}
 
-                // Sort the conflicts such that we pop the the earliest conflict.
-                // Using Reverse will put the conflicts in descending order, and
-                // then popping the last element will grab the lowest time.
+/// The result of replanning the conceding agent of one branch
+enum Replan {
+    Solved(Proposal),
+    Impossible { deadline_missed: bool },
+    Incomplete,
+}
+
+/// Solve one negotiation, starting from its root unless the queue of a paused
+/// negotiation is given.
+fn solve_negotiation(
+    root: &Negotiation,
+    queued: Vec<NegotiationNode>,
+    ideal: &Vec<Proposal>,
+    base_env: Arc<DynamicEnvironment<WaypointSE2>>,
+    base_capacity: &CapacityReservations<Cell>,
+    closed: &NegotiationCloser,
+    ctx: &NegotiationContext,
+) -> NegotiationOutcome {
+    // Nodes closed by earlier rounds are only read, so the negotiations of
+    // one round can share them while each closes its own nodes.
+    let mut closer = NegotiationCloser::new();
+    let mut cardinality = CardinalityAnalysis::new(ctx.graphs, ctx.agents, ctx.cell_size);
+    let mut culled = 0;
+    let mut arena = Vec::new();
+    let mut queue: BinaryHeap<QueueEntry> = BinaryHeap::new();
+    // New nodes are given IDs after the restored nodes
+    let next_id = |arena: &Vec<NegotiationNode>| ctx.restored + arena.len();
+    if queued.is_empty() {
+        let root =
+            NegotiationNode::from_root(root, ideal, base_env, base_capacity, next_id(&arena));
+        arena.push(root.clone());
+        queue.push(QueueEntry::new(root));
+    } else {
+        queue.extend(queued.into_iter().map(QueueEntry::new));
+    }
+
+    let mut solution = None;
+    let mut paused = false;
+    let mut iters = 0;
+    while let Some(mut top) = queue.pop() {
+        if ctx.settings.pause_after.is_some_and(|limit| iters >= limit) {
+            // Every node in the queue is already in the arena, where it will be
+            // found again when the negotiation is resumed.
+            paused = true;
+            break;
+        }
+
+        iters += 1;
+        if iters % 10 == 0 {
+            dbg!(iters);
+        }
+        if iters > 1000 {
+            println!("Too many iterations");
+
+            // Dump the remaining queue into the node history
+            println!("Queue begins at {}", arena.len() + 1);
+            while let Some(remainder) = queue.pop() {
+                arena.push(remainder.node);
+            }
+
+            break;
+        }
+
+        if closed.status(&top.node).is_closed() || !closer.close(&top.node) {
+            culled += 1;
+            // println!("REDUNDANT NODE: {:?}", top.node.keys);
+            continue;
+        }
+
+        // Sort the conflicts such that we pop the the earliest conflict.
+        // Using Reverse will put the conflicts in descending order, and
+        // then popping the last element will grab the lowest time.
+        match ctx.settings.conflict_selection {
+            ConflictSelection::Earliest => {
                 top.node
                     .negotiation
                     .conflicts
-                    .sort_unstable_by_key(|c| Reverse(c.time));
-                let next_conflict = match top.node.negotiation.conflicts.pop() {
-                    Some(c) => c,
-                    None => {
-                        // There are no conflicts left, so we have found the
-                        // solution for this negotiation.
-                        // solution = Some(top.node.proposals);
-                        solution = Some(top.node);
-
-                        // Dump the remaining queue into the node history
-                        // println!("Queue begins at {}", arena.len() + 1);
-                        // while let Some(remainder) = queue.pop() {
-                        //     arena.push(remainder.node);
-                        // }
-
-                        break;
-                    }
-                };
+                    .sort_unstable_by_key(|c| Reverse(c.order_key()));
+            }
+            ConflictSelection::Cardinal => {
+                let proposals = &top.node.proposals;
+                top.node.negotiation.conflicts.sort_by_cached_key(|c| {
+                    Reverse((cardinality.classify(c, proposals), c.order_key()))
+                });
+            }
+        }
+        // Capacity conflicts are resolved before any pairwise conflict that
+        // happens later than them.
+        let capacity_first = match (
+            top.node.negotiation.conflicts.last(),
+            top.node.negotiation.capacity_conflicts.first(),
+        ) {
+            (Some(next), Some(capacity)) => capacity.time < next.time,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+
+        let finish_time = top
+            .node
+            .proposals
+            .values()
+            .max_by_key(|t| t.meta.trajectory.finish_motion_time())
+            .unwrap()
+            .meta
+            .trajectory
+            .finish_motion_time();
+
+        let branches: Vec<_> = if capacity_first {
+            let conflict = top.node.negotiation.capacity_conflicts.remove(0);
+            capacity_branches(&conflict, &top.node, ctx)
+        } else {
+            let next_conflict = match top.node.negotiation.conflicts.pop() {
+                Some(c) => c,
+                None => {
+                    // There are no conflicts left, so we have found the
+                    // solution for this negotiation.
+                    // solution = Some(top.node.proposals);
+                    solution = Some(top.node);
+
+                    // Dump the remaining queue into the node history
+                    // println!("Queue begins at {}", arena.len() + 1);
+                    // while let Some(remainder) = queue.pop() {
+                    //     arena.push(remainder.node);
+                    // }
+
+                    break;
+                }
+            };
+
+            // Conflicts that belong to a symmetry get a whole set of
+            // constraints for each branch so the symmetric alternatives do
+            // not need to be explored one at a time.
+            let (_, symmetric_constraints) = break_symmetry(
+                &next_conflict,
+                &top.node.proposals,
+                &SymmetryContext {
+                    graphs: ctx.graphs,
+                    agents: ctx.agents,
+                    profiles: ctx.profiles,
+                    cell_size: ctx.cell_size,
+                },
+            );
+
+            let segments = next_conflict.segments;
+            [(segments[0], segments[1]), (segments[1], segments[0])]
+                .into_iter()
+                .enumerate()
+                // Predicted agents cannot concede
+                .filter(|(_, (concede, _))| !ctx.agents[concede.agent].is_predicted())
+                .map(|(branch, (concede, constraint))| {
+                    let key =
+                        NegotiationKey::new(&concede.range, &constraint.range, constraint.agent);
+                    // If the symmetry was already broken for this conflict and
+                    // the conflict came back anyway, split it the regular way
+                    // so that the branch still makes progress.
+                    let symmetric = symmetric_constraints[branch]
+                        .as_ref()
+                        .filter(|_| !top.node.keys.contains(&key.with_symmetry()));
+                    let key = if symmetric.is_some() {
+                        key.with_symmetry()
+                    } else {
+                        key
+                    };
 
-                let finish_time = top
-                    .node
-                    .proposals
-                    .values()
-                    .max_by_key(|t| t.meta.trajectory.finish_motion_time())
-                    .unwrap()
-                    .meta
-                    .trajectory
-                    .finish_motion_time();
-
-                let segments = next_conflict.segments;
-                for (concede, constraint) in
-                    [(segments[0], segments[1]), (segments[1], segments[0])]
-                {
                     let mut environment = top.node.environment.clone();
                     // Insert the new constraint on top of the previous
                     // environment
                     let env_constraint = CcbsConstraint {
-                        obstacle: DynamicCircularObstacle::new(profiles[constraint.agent])
+                        obstacle: DynamicCircularObstacle::new(ctx.profiles[constraint.agent])
                             .with_trajectory(Some(
                                 top.node
                                     .proposals
@@ -284,6 +856,11 @@ pub fn negotiate(
                     };
 
                     match concede.range {
+                        _ if symmetric.is_some() => {
+                            for (key, constraint) in symmetric.into_iter().flatten() {
+                                environment.insert_constraint(*key, constraint.clone());
+                            }
+                        }
                         DecisionRange::Before(s, _) | DecisionRange::After(s, _) => {
                             // dbg!((concede, constraint));
                             environment.insert_constraint(
@@ -318,140 +895,223 @@ pub fn negotiate(
                         }
                     };
 
-                    let key =
-                        NegotiationKey::new(&concede.range, &constraint.range, constraint.agent);
-
                     // Set the environment to be suitable for the conceding agent
-                    environment.overlay_profile(profiles[concede.agent]);
+                    environment.overlay_profile(ctx.profiles[concede.agent]);
                     environment.set_mask(Some(concede.agent));
 
-                    let agent = &agents[concede.agent];
-                    // Replan for the conceding agent with this constraint added
-                    let mut search = planners[concede.agent]
-                        .clone()
-                        .configure(|config| config.modify_environment(|_| Ok(environment.clone())))
-                        .unwrap()
-                        .plan(
-                            agent.make_start(),
-                            agent.make_goal().with_minimum_time(Some(finish_time)),
-                        )
-                        .unwrap();
-
-                    let solution = match search.solve().unwrap() {
-                        SearchStatus::Solved(solution) => solution,
-                        SearchStatus::Impossible => {
-                            println!(
-                                "The search is IMPOSSIBLE for {}! node: {}:{}, queue: {}, arena: {}",
-                                name_map[&concede.agent],
-                                top.node.id,
-                                concede.agent,
-                                search.memory().0.queue.len(),
-                                search.memory().0.arena.len(),
-                            );
-                            let mut failed_node = top.node.clone();
-                            failed_node.conceded = Some(concede.agent);
-                            failed_node.environment = environment;
-                            failed_node.outcome = NodeOutcome::Impossible;
-                            arena.push(failed_node);
-                            continue;
-                        }
-                        SearchStatus::Incomplete => {
-                            println!(
-                                "The search is INCOMPLETE for {}! node: {}:{} measure: {}, queue: {}, arena: {}",
-                                name_map[&concede.agent],
-                                top.node.id,
-                                concede.agent,
-                                search.memory().queue_length(),
-                                search.memory().0.queue.len(),
-                                search.memory().0.arena.len(),
-                            );
-                            let mut failed_node = top.node.clone();
-                            failed_node.conceded = Some(concede.agent);
-                            failed_node.environment = environment;
-                            failed_node.outcome = NodeOutcome::Incomplete;
-                            arena.push(failed_node);
-                            continue;
-                        }
-                    };
+                    (concede.agent, environment, key)
+                })
+                .collect()
+        };
+
+        if branches.is_empty() {
+            // Only predicted agents are involved in the conflict, so there is
+            // no agent that could concede to resolve it.
+            let mut failed_node = top.node.clone();
+            failed_node.outcome = NodeOutcome::PredictedConflict;
+            arena.push(failed_node);
+            continue;
+        }
+
+        // Both branches are replanned at the same time, but their nodes are
+        // always added in the same order.
+        let replans = parallel_map(
+            &branches,
+            ctx.settings.threads,
+            |(concede, environment, _)| replan(*concede, environment, finish_time, ctx),
+        );
+
+        for ((concede, environment, key), replan) in branches.into_iter().zip(replans) {
+            let proposal = match replan {
+                Replan::Solved(proposal) => proposal,
+                Replan::Impossible { deadline_missed } => {
+                    println!(
+                        "The search is IMPOSSIBLE for {}! node: {}:{}",
+                        ctx.name_map[&concede], top.node.id, concede,
+                    );
+                    let mut failed_node = top.node.clone();
+                    failed_node.conceded = Some(concede);
+                    failed_node.environment = environment;
+                    failed_node.outcome = if deadline_missed {
+                        NodeOutcome::DeadlineMissed
+                    } else {
+                        NodeOutcome::Impossible
+                    };
+                    arena.push(failed_node);
+                    continue;
+                }
+                Replan::Incomplete => {
+                    println!(
+                        "The search is INCOMPLETE for {}! node: {}:{}",
+                        ctx.name_map[&concede], top.node.id, concede,
+                    );
+                    let mut failed_node = top.node.clone();
+                    failed_node.conceded = Some(concede);
+                    failed_node.environment = environment;
+                    failed_node.outcome = NodeOutcome::Incomplete;
+                    arena.push(failed_node);
+                    continue;
+                }
+            };
+
+            let mut proposals = top.node.proposals.clone();
+            proposals.insert(concede, proposal);
+            let conflicts = reasses_conflicts(&proposals, ctx.profiles);
+            let heuristic = cardinality.heuristic(ctx.settings.heuristic, &conflicts, &proposals);
+
+            let mut node = top.node.fork(
+                conflicts,
+                proposals,
+                environment,
+                key,
+                Some(concede),
+                next_id(&arena),
+            );
+            node.heuristic = heuristic;
+            arena.push(node.clone());
+            queue.push(QueueEntry::new(node));
+        }
+    }
+
+    NegotiationOutcome {
+        solution,
+        arena,
+        closer,
+        culled,
+        paused,
+    }
+}
 
-                    let mt = solution
-                        .make_trajectory_or_hold::<WaypointSE2>(Duration::from_secs(1))
-                        .unwrap()
-                        .with_indefinite_finish_time(true);
-                    let cost = solution.total_cost;
-
-                    let mut proposals = top.node.proposals.clone();
-                    proposals.insert(concede.agent, Proposal { meta: mt, cost });
-                    let conflicts = reasses_conflicts(&proposals, &profiles);
-
-                    let node = top.node.fork(
-                        conflicts,
-                        proposals,
-                        environment,
-                        key,
-                        Some(concede.agent),
-                        arena.len(),
-                    );
-                    arena.push(node.clone());
-                    queue.push(QueueEntry::new(node));
+/// Make one branch for each occupant of an overfilled capacity zone. In each
+/// branch the other occupants reserve their place in the zone, so the
+/// conceding occupant has to wait until there is room for it.
+fn capacity_branches(
+    conflict: &CapacityConflict,
+    node: &NegotiationNode,
+    ctx: &NegotiationContext,
+) -> Vec<(usize, CcbsEnvironment<WaypointSE2, Cell>, NegotiationKey)> {
+    conflict
+        .occupants
+        .iter()
+        // Predicted agents cannot concede
+        .filter(|occupant| !ctx.agents[occupant.agent].is_predicted())
+        .map(|occupant| {
+            let mut environment = node.environment.clone();
+            for other in &conflict.occupants {
+                if other.agent == occupant.agent {
+                    continue;
                 }
-            }
 
-            if let Some(solution) = solution {
-                for (i, mt) in &solution.proposals {
-                    ideal[*i] = mt.clone();
-                }
-                solution_node = Some(solution);
-            } else {
-                // TODO(@mxgrey): Consider re-running the negotiation but
-                // without the base environment in order to identify which other
-                // agents need to be pulled into the negotiation.
-                // Even better would be to queue up those nodes as backup nodes
-                // in a lower priority queue running in parallel, then use the
-                // outcome if a solution cannot be found.
-                println!("Culled {culled}");
-                return Err(NegotiationError::PlanningFailed((arena, name_map)));
+                environment.reserve(Reservation {
+                    zone: conflict.zone,
+                    agent: other.agent,
+                    occupancy: other.occupancy,
+                });
             }
-        }
 
-        (negotiation_of_agent, negotiations) =
-            reconsider_negotiations(&ideal, &profiles, negotiation_of_agent, negotiations);
-    }
+            let key = NegotiationKey::for_capacity(conflict.zone, occupant, conflict.time);
 
-    if solution_node.is_none() {
-        let fake = NegotiationNode::from_root(
-            &Negotiation {
-                conflicts: vec![],
-                participants: vec![],
+            // Set the environment to be suitable for the conceding agent
+            environment.overlay_profile(ctx.profiles[occupant.agent]);
+            environment.set_mask(Some(occupant.agent));
+
+            (occupant.agent, environment, key)
+        })
+        .collect()
+}
+
+/// Make a proposal that follows the predicted trajectory of an agent. Every
+/// waypoint of the prediction is a decision point.
+fn predicted_proposal(agent: &Agent, cell_size: f64) -> Option<Proposal> {
+    let trajectory = agent.make_predicted_trajectory(cell_size)?;
+    let duration = trajectory.finish_motion_time() - trajectory.initial_motion_time();
+    Some(Proposal {
+        meta: fixed_meta_trajectory(trajectory, cell_size)?,
+        cost: Cost(duration.as_secs_f64()),
+    })
+}
+
+/// Describe a trajectory that is not being planned, with a decision point at
+/// each of its waypoints.
+pub(crate) fn fixed_meta_trajectory(
+    trajectory: LinearTrajectorySE2,
+    cell_size: f64,
+) -> Option<MetaTrajectory<WaypointSE2, StateSippSE2<Cell>>> {
+    let decision_points: Vec<_> = trajectory
+        .iter()
+        .enumerate()
+        .map(|(index, waypoint)| DecisionPoint {
+            index,
+            state: StateSippSE2 {
+                key: KeySE2::new(
+                    Cell::from_point(waypoint.position.translation.vector.into(), cell_size),
+                    waypoint.position.rotation.angle(),
+                ),
+                waypoint,
             },
-            &ideal,
-            Arc::new(DynamicEnvironment::new(
-                CircularProfile::new(0.0, 0.0, 0.0).unwrap(),
-            )),
-            0,
-        );
+        })
+        .collect();
 
-        solution_node = Some(fake);
-    }
+    Some(MetaTrajectory {
+        initial_state: decision_points.first()?.state,
+        final_state: decision_points.last()?.state,
+        trajectory,
+        decision_points,
+    })
+}
 
-    if let Some(node) = &mut solution_node {
-        for (i, proposal) in ideal.iter().enumerate() {
-            match node.proposals.entry(i) {
-                std::collections::hash_map::Entry::Vacant(vacant) => {
-                    vacant.insert(proposal.clone());
-                }
-                _ => {}
-            }
+/// Replan for the conceding agent with the constraints of its branch added
+fn replan(
+    agent: usize,
+    environment: &CcbsEnvironment<WaypointSE2, Cell>,
+    finish_time: TimePoint,
+    ctx: &NegotiationContext,
+) -> Replan {
+    let params = &ctx.agents[agent];
+    let planner = ctx.planners[agent]
+        .clone()
+        .configure(|config| config.modify_environment(|_| Ok(environment.clone())))
+        .unwrap();
+
+    // The agent holds at its goal until every other proposal is finished, but
+    // its time windows still apply to when it actually arrives.
+    let status = plan_sequence(
+        &planner,
+        params.make_start(),
+        &params
+            .make_sequential_goal()
+            .with_minimum_time(Some(finish_time)),
+    )
+    .unwrap();
+
+    match status {
+        SearchStatus::Solved(solution) => {
+            let meta = solution
+                .make_trajectory_or_hold::<WaypointSE2>(Duration::from_secs(1))
+                .unwrap()
+                .with_indefinite_finish_time(true);
+            Replan::Solved(Proposal {
+                meta,
+                cost: solution.total_cost,
+            })
+        }
+        SearchStatus::Impossible => {
+            // Find out whether the time windows of the agent are what made
+            // this impossible.
+            let deadline_missed = params.has_time_windows()
+                && plan_sequence(
+                    &planner,
+                    params.make_start(),
+                    &params
+                        .without_time_windows()
+                        .make_sequential_goal()
+                        .with_minimum_time(Some(finish_time)),
+                )
+                .is_ok_and(|status| status.solved());
+            Replan::Impossible { deadline_missed }
         }
+        SearchStatus::Incomplete => Replan::Incomplete,
     }
-
-    // dbg!(closer);
-
-    println!("Culled {culled}");
-    Ok((solution_node.unwrap(), arena, name_map))
-    // Ok(ideal.into_iter().enumerate().map(|(i, proposal)|
-    //     (name_map.get(&i).unwrap().clone(), proposal)
-    // ).collect())
 }
 
 #[derive(Debug, Clone)]
@@ -460,11 +1120,19 @@ pub struct Proposal {
     pub cost: Cost<f64>,
 }
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct NegotiationKey {
     pub concede: (Cell, Cell, i64),
     pub constraint: (Cell, Cell, i64),
     pub mask: usize,
+    /// The capacity zone that the conceding agent gave way in, if the key
+    /// belongs to a capacity conflict
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity_zone: Option<usize>,
+    /// True if the conceding agent was given the constraints that break the
+    /// symmetry of the conflict instead of the regular constraint
+    #[serde(default)]
+    pub symmetric: bool,
 }
 
 impl NegotiationKey {
@@ -495,6 +1163,33 @@ impl NegotiationKey {
                 constraint.2.nanos_since_zero / res,
             ),
             mask,
+            capacity_zone: None,
+            symmetric: false,
+        }
+    }
+
+    /// The key for breaking the symmetry of the same conflict
+    pub fn with_symmetry(mut self) -> Self {
+        self.symmetric = true;
+        self
+    }
+
+    /// Make a key for an occupant that gives way to the other occupants of an
+    /// overfilled capacity zone. The mask is the conceding agent since the
+    /// constraint comes from every other occupant.
+    pub fn for_capacity(zone: usize, occupant: &Occupant, time: TimePoint) -> Self {
+        let res = 1e3 as i64;
+        let entry = occupant.entry;
+        Self {
+            concede: (
+                entry,
+                entry,
+                occupant.occupancy.begin.nanos_since_zero / res,
+            ),
+            constraint: (entry, entry, time.nanos_since_zero / res),
+            mask: occupant.agent,
+            capacity_zone: Some(zone),
+            symmetric: false,
         }
     }
 }
@@ -554,6 +1249,32 @@ impl NegotiationCloser {
 
         true
     }
+
+    /// Add the nodes closed by another closer, whose node IDs are shifted by
+    /// `offset` to match where its arena was appended.
+    pub fn merge(&mut self, other: NegotiationCloser, offset: usize) {
+        self.merge_from(other, 0, offset);
+    }
+
+    /// Like [`Self::merge`], but IDs below `first` belong to restored nodes
+    /// and are not shifted.
+    fn merge_from(&mut self, other: NegotiationCloser, first: usize, offset: usize) {
+        for (key, ids) in other.closed_set {
+            self.closed_set
+                .entry(key)
+                .or_default()
+                .extend(ids.into_iter().map(|id| shift_id(id, first, offset)));
+        }
+    }
+}
+
+/// Shift a node ID by an offset, unless it belongs to a restored node
+fn shift_id(id: usize, first: usize, offset: usize) -> usize {
+    if id < first {
+        id
+    } else {
+        id + offset
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -570,17 +1291,23 @@ pub struct NegotiationNode {
     pub keys: HashSet<NegotiationKey>,
     pub conceded: Option<usize>,
     pub cost: Cost<f64>,
+    /// Value of the [`HighLevelHeuristic`] for this node
+    pub heuristic: usize,
     pub depth: usize,
     pub outcome: NodeOutcome,
     pub id: usize,
     pub parent: Option<usize>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NodeOutcome {
     Success,
     Impossible,
     Incomplete,
+    /// The conceding agent could have found a plan if not for its time windows
+    DeadlineMissed,
+    /// The conflict only involves predicted agents, so no agent can concede
+    PredictedConflict,
 }
 
 impl NegotiationNode {
@@ -588,6 +1315,7 @@ impl NegotiationNode {
         root: &Negotiation,
         ideal: &Vec<Proposal>,
         base_env: Arc<DynamicEnvironment<WaypointSE2>>,
+        base_capacity: &CapacityReservations<Cell>,
         id: usize,
     ) -> Self {
         let cost = ideal
@@ -601,7 +1329,11 @@ impl NegotiationNode {
                 .map(|i| (*i, ideal[*i].clone()))
                 .collect(),
             environment: {
-                let mut env = CcbsEnvironment::new(base_env);
+                let mut env = CcbsEnvironment::new(base_env)
+                    .with_capacity_zones(base_capacity.zones().to_vec());
+                for reservation in base_capacity.reservations() {
+                    env.reserve(*reservation);
+                }
                 for i in &root.participants {
                     env.overlay_trajectory(*i, None).ok();
                 }
@@ -610,6 +1342,7 @@ impl NegotiationNode {
             conceded: None,
             keys: HashSet::new(),
             cost,
+            heuristic: 0,
             depth: 0,
             outcome: NodeOutcome::Success,
             id,
@@ -617,6 +1350,15 @@ impl NegotiationNode {
         }
     }
 
+    /// Shift the IDs of this node and its parent, for when it gets moved into
+    /// a larger arena. IDs below `first` belong to restored nodes, which are
+    /// already in place, so they are not shifted.
+    fn with_id_offset(mut self, first: usize, offset: usize) -> Self {
+        self.id = shift_id(self.id, first, offset);
+        self.parent = self.parent.map(|parent| shift_id(parent, first, offset));
+        self
+    }
+
     fn fork(
         &self,
         conflicts: Vec<Conflict>,
@@ -626,6 +1368,10 @@ impl NegotiationNode {
         conceded: Option<usize>,
         id: usize,
     ) -> Self {
+        let capacity_conflicts = find_capacity_conflicts(
+            proposals.iter().map(|(i, p)| (*i, &p.meta)),
+            environment.capacity().zones(),
+        );
         let cost = proposals
             .values()
             .fold(Cost(0.0), |cost, proposal| cost + proposal.cost);
@@ -636,12 +1382,14 @@ impl NegotiationNode {
         NegotiationNode {
             negotiation: Negotiation {
                 conflicts,
+                capacity_conflicts,
                 participants: self.negotiation.participants.clone(),
             },
             proposals,
             environment,
             conceded,
             cost,
+            heuristic: 0,
             keys,
             depth: self.depth + 1,
             outcome: self.outcome,
@@ -658,11 +1406,7 @@ struct QueueEntry {
 
 impl PartialOrd for QueueEntry {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        if f64::abs(self.node.cost.0 - other.node.cost.0) < 0.1 {
-            Reverse(self.node.depth).partial_cmp(&Reverse(other.node.depth))
-        } else {
-            Reverse(self.node.cost).partial_cmp(&Reverse(other.node.cost))
-        }
+        Some(self.cmp(other))
     }
 }
 
@@ -674,10 +1418,17 @@ impl PartialEq for QueueEntry {
 
 impl Ord for QueueEntry {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if f64::abs(self.node.cost.0 - other.node.cost.0) < 0.1 {
-            self.node.depth.cmp(&self.node.depth)
+        let (f, other_f) = (self.evaluation(), other.evaluation());
+        if f64::abs(f - other_f) < COST_RESOLUTION {
+            if self.node.heuristic != other.node.heuristic {
+                return Reverse(self.node.heuristic).cmp(&Reverse(other.node.heuristic));
+            }
+            // The ID makes the order of tied nodes the same when the queue is
+            // rebuilt from a checkpoint.
+            Reverse((self.node.depth, self.node.id))
+                .cmp(&Reverse((other.node.depth, other.node.id)))
         } else {
-            Reverse(self.node.cost).cmp(&Reverse(other.node.cost))
+            Reverse(Cost(f)).cmp(&Reverse(Cost(other_f)))
         }
     }
 }
@@ -687,12 +1438,26 @@ impl QueueEntry {
     fn new(node: NegotiationNode) -> Self {
         Self { node }
     }
+
+    /// The cost of the node plus the least that its cost still has to
+    /// increase according to its [`HighLevelHeuristic`].
+    fn evaluation(&self) -> f64 {
+        self.node.cost.0 + self.node.heuristic as f64 * COST_RESOLUTION
+    }
 }
 
+/// Nodes whose costs are closer than this are considered tied. This is also
+/// the least that the cost of an agent is expected to increase by when it has
+/// to concede.
+const COST_RESOLUTION: f64 = 0.1;
+
 #[derive(Debug, Default, Clone)]
 pub struct Negotiation {
     /// Conflicts that were identified for this state of the negotiation
     pub conflicts: Vec<Conflict>,
+    /// Capacity zones that are overfilled for this state of the negotiation,
+    /// sorted by the time that they become overfilled
+    pub capacity_conflicts: Vec<CapacityConflict>,
     /// All agents that need to participate in the negotiation
     pub participants: Vec<usize>,
 }
@@ -712,6 +1477,32 @@ impl std::fmt::Debug for Conflict {
     }
 }
 
+/// More agents are inside of a capacity zone than the zone can hold
+#[derive(Clone)]
+pub struct CapacityConflict {
+    time: TimePoint,
+    zone: usize,
+    occupants: Vec<Occupant>,
+}
+
+impl std::fmt::Debug for CapacityConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CapacityConflict")
+            .field("time", &self.time.as_secs_f64())
+            .field("zone", &self.zone)
+            .field("occupants", &self.occupants)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Occupant {
+    agent: usize,
+    /// The cell where the agent enters the zone
+    entry: Cell,
+    occupancy: Occupancy,
+}
+
 pub type SippDecisionRange = DecisionRange<StateSippSE2<Cell>>;
 pub type DecisionRangePair = (SippDecisionRange, SippDecisionRange);
 
@@ -721,13 +1512,37 @@ pub struct Segment {
     range: SippDecisionRange,
 }
 
+impl Conflict {
+    /// A key that orders conflicts by time and then by the agents and the
+    /// trajectory segments involved, so that conflicts which happen at the
+    /// same time are always chosen in the same order.
+    fn order_key(&self) -> (TimePoint, [(usize, usize); 2]) {
+        (self.time, self.segments.map(|s| (s.agent, s.order())))
+    }
+}
+
+impl Segment {
+    /// The position of the segment along the trajectory of its agent
+    fn order(&self) -> usize {
+        match &self.range {
+            DecisionRange::Before(..) => 0,
+            DecisionRange::Between([p, _]) => p.index + 1,
+            DecisionRange::After(..) => usize::MAX,
+        }
+    }
+}
+
 fn reasses_conflicts(
     proposals: &HashMap<usize, Proposal>,
     profiles: &Vec<CircularProfile>,
 ) -> Vec<Conflict> {
+    // Visit the agents in order so that conflicts which happen at the same
+    // time are always listed in the same order.
+    let mut proposals: Vec<_> = proposals.iter().collect();
+    proposals.sort_unstable_by_key(|(i, _)| **i);
     let mut conflicts = Vec::new();
     triangular_for(
-        proposals.iter().map(|(i, p)| (i, &p.meta)),
+        proposals.into_iter().map(|(i, p)| (i, &p.meta)),
         |(i_a, mt_a), (i_b, mt_b)| {
             let profile_a = profiles.get(**i_a).unwrap();
             let profile_b = profiles.get(*i_b).unwrap();
@@ -758,13 +1573,183 @@ fn reasses_conflicts(
     conflicts
 }
 
+/// Get the spans of time that a trajectory spends inside of a capacity zone
+/// along with the cell where each span begins. An agent is inside of the zone
+/// from the start of the motion that brings it to a cell of the zone until it
+/// reaches its next decision point.
+fn zone_occupancies(
+    mt: &MetaTrajectory<WaypointSE2, StateSippSE2<Cell>>,
+    zone: &CapacityZone<Cell>,
+) -> Vec<(Cell, Occupancy)> {
+    let time_of = |index: usize| mt.trajectory.get(index).map(|wp| wp.time);
+    let mut occupancies = Vec::new();
+    let mut decision_points = mt.decision_points.iter().peekable();
+    while let Some(dp) = decision_points.next() {
+        let cell = dp.state.key.vertex;
+        if !zone.contains(&cell) {
+            continue;
+        }
+
+        let begin = match dp.index.checked_sub(1) {
+            Some(previous) => time_of(previous),
+            None => Some(mt.trajectory.initial_motion_time()),
+        };
+        let Some(begin) = begin else {
+            continue;
+        };
+
+        let end = match decision_points.peek() {
+            Some(next) => time_of(next.index),
+            None if mt.trajectory.has_indefinite_finish_time() => None,
+            None => Some(mt.trajectory.finish_motion_time()),
+        };
+
+        occupancies.push((cell, Occupancy::new(begin, end)));
+    }
+
+    occupancies
+}
+
+/// Find the first time that each capacity zone gets overfilled by a set of
+/// proposals.
+fn find_capacity_conflicts<'a>(
+    proposals: impl IntoIterator<Item = (usize, &'a MetaTrajectory<WaypointSE2, StateSippSE2<Cell>>)>,
+    zones: &[CapacityZone<Cell>],
+) -> Vec<CapacityConflict> {
+    if zones.is_empty() {
+        return Vec::new();
+    }
+
+    // Proposals may come from a map, so put them in agent order to keep the
+    // occupants of each conflict in the same order.
+    let mut proposals: Vec<_> = proposals.into_iter().collect();
+    proposals.sort_unstable_by_key(|(agent, _)| *agent);
+    let mut conflicts = Vec::new();
+    for (z, zone) in zones.iter().enumerate() {
+        let mut entries = Vec::new();
+        let mut occupancies = Vec::new();
+        for (agent, mt) in &proposals {
+            for (entry, occupancy) in zone_occupancies(mt, zone) {
+                entries.push((*agent, entry, occupancy.begin));
+                occupancies.push((*agent, occupancy));
+            }
+        }
+
+        let Some((time, occupants)) = find_overflow(zone.capacity(), &occupancies) else {
+            continue;
+        };
+
+        let occupants = occupants
+            .into_iter()
+            .map(|(agent, occupancy)| {
+                // Merged occupancies begin where the first of their spans
+                // begins, so use the cell where that span entered the zone.
+                let entry = entries
+                    .iter()
+                    .find(|(a, _, begin)| *a == agent && *begin == occupancy.begin)
+                    .map(|(_, entry, _)| *entry)
+                    .unwrap();
+                Occupant {
+                    agent,
+                    entry,
+                    occupancy,
+                }
+            })
+            .collect();
+
+        conflicts.push(CapacityConflict {
+            time,
+            zone: z,
+            occupants,
+        });
+    }
+
+    conflicts.sort_by_key(|c| c.time);
+    conflicts
+}
+
+/// Agents that have a goal region may finish in any cell of that region. Plan
+/// each of them into its region to find the cell that it can reach soonest,
+/// considering only the cells that are free of obstacles and do not collide
+/// with the start or goal of any other agent. Agents with stops are planned
+/// into their region from their last stop. Agents whose region has no such
+/// cell keep their original goal so the endpoint conflict can be reported.
+fn assign_goal_regions(
+    agents: &mut [Agent],
+    planners: &[PlannerSE2],
+    grid: &SparseGrid,
+    cell_size: f64,
+) {
+    let collides = |cell: &[i64; 2], radius: f64, other: [i64; 2], other_radius: f64| {
+        let p = Cell::from(*cell).center_point(cell_size);
+        let q = Cell::from(other).center_point(cell_size);
+        (p - q).norm() < radius + other_radius
+    };
+
+    // Agents without a goal region keep their goal, so their goals are
+    // settled from the start.
+    let mut settled: Vec<bool> = agents.iter().map(|a| a.goal_region.is_empty()).collect();
+    for i in 0..agents.len() {
+        if settled[i] {
+            continue;
+        }
+
+        let agent = &agents[i];
+        let region = agent.make_goal_region(|cell| {
+            let p = Cell::from(*cell).center_point(cell_size);
+            if grid.is_circle_occupied(p, agent.radius).is_some() {
+                return false;
+            }
+
+            agents.iter().enumerate().all(|(j, other)| {
+                if j == i {
+                    return true;
+                }
+
+                let blocks_start = collides(cell, agent.radius, other.start, other.radius);
+                let blocks_goal =
+                    settled[j] && collides(cell, agent.radius, other.goal, other.radius);
+                !blocks_start && !blocks_goal
+            })
+        });
+
+        if region.keys.is_empty() {
+            settled[i] = true;
+            continue;
+        }
+
+        let start = match agent.stops.last() {
+            Some(stop) => StartSE2 {
+                time: TimePoint::zero(),
+                key: Cell::from(stop.cell),
+                orientation: Orientation::from_angle(stop.yaw.unwrap_or(agent.yaw)),
+            },
+            None => agent.make_start(),
+        };
+
+        let reached = planners[i]
+            .plan(start, region.clone())
+            .ok()
+            .and_then(|mut search| search.solve().ok())
+            .and_then(|status| status.solution())
+            .map(|solution| solution.final_state().key.vertex);
+
+        // If the region cannot be reached, fall back on its most preferred
+        // cell and let the planner report the problem.
+        let goal = reached.unwrap_or(region.keys[0]);
+        agents[i].goal = [goal.x, goal.y];
+        settled[i] = true;
+    }
+}
+
 fn organize_negotiations(
     ideal: &Vec<Proposal>,
     profiles: &Vec<CircularProfile>,
-) -> (HashMap<usize, usize>, HashMap<usize, Negotiation>) {
+    capacity_zones: &[CapacityZone<Cell>],
+) -> (BTreeMap<usize, usize>, BTreeMap<usize, Negotiation>) {
     let mut next_conflict_id = 0;
-    let mut negotiation_of_agent: HashMap<usize, usize> = HashMap::new();
-    let mut negotiations: HashMap<usize, Negotiation> = HashMap::new();
+    let mut negotiation_of_agent: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut negotiations: BTreeMap<usize, Negotiation> = BTreeMap::new();
     triangular_for(
         ideal
             .iter()
@@ -834,11 +1819,72 @@ fn organize_negotiations(
         },
     );
 
+    // Every agent inside of an overfilled zone needs to be part of the same
+    // negotiation, so merge all the negotiations that they already belong to.
+    let capacity_conflicts =
+        find_capacity_conflicts(ideal.iter().map(|p| &p.meta).enumerate(), capacity_zones);
+    for conflict in capacity_conflicts {
+        let mut merge_ids: Vec<usize> = conflict
+            .occupants
+            .iter()
+            .filter_map(|o| negotiation_of_agent.get(&o.agent).cloned())
+            .collect();
+        merge_ids.sort_unstable();
+        merge_ids.dedup();
+
+        let conflict_id = match merge_ids.first() {
+            Some(conflict_id) => *conflict_id,
+            None => {
+                let conflict_id = next_conflict_id;
+                next_conflict_id += 1;
+                conflict_id
+            }
+        };
+
+        for acquire_id in merge_ids.iter().skip(1) {
+            let info = negotiations.remove(acquire_id).unwrap();
+            for agent in info
+                .conflicts
+                .iter()
+                .flat_map(|c| c.segments.iter().map(|s| s.agent))
+                .chain(
+                    info.capacity_conflicts
+                        .iter()
+                        .flat_map(|c| c.occupants.iter().map(|o| o.agent)),
+                )
+            {
+                negotiation_of_agent.insert(agent, conflict_id);
+            }
+
+            let negotiation = negotiations.entry(conflict_id).or_default();
+            negotiation.conflicts.extend(info.conflicts);
+            negotiation
+                .capacity_conflicts
+                .extend(info.capacity_conflicts);
+        }
+
+        for occupant in &conflict.occupants {
+            negotiation_of_agent.insert(occupant.agent, conflict_id);
+        }
+        negotiations
+            .entry(conflict_id)
+            .or_default()
+            .capacity_conflicts
+            .push(conflict);
+    }
+
     for negotiation in negotiations.values_mut() {
+        negotiation.capacity_conflicts.sort_by_key(|c| c.time);
         negotiation.participants = negotiation
             .conflicts
             .iter()
             .flat_map(|c| c.segments.iter().map(|s| s.agent))
+            .chain(
+                negotiation
+                    .capacity_conflicts
+                    .iter()
+                    .flat_map(|c| c.occupants.iter().map(|o| o.agent)),
+            )
             .collect();
         negotiation.participants.sort_unstable();
         negotiation.participants.dedup();
@@ -850,11 +1896,12 @@ fn organize_negotiations(
 fn reconsider_negotiations(
     base: &Vec<Proposal>,
     profiles: &Vec<CircularProfile>,
-    previous_negotiation_of_agent: HashMap<usize, usize>,
-    previous_negotiations: HashMap<usize, Negotiation>,
-) -> (HashMap<usize, usize>, HashMap<usize, Negotiation>) {
+    capacity_zones: &[CapacityZone<Cell>],
+    previous_negotiation_of_agent: BTreeMap<usize, usize>,
+    previous_negotiations: BTreeMap<usize, Negotiation>,
+) -> (BTreeMap<usize, usize>, BTreeMap<usize, Negotiation>) {
     let (mut new_negotiation_of_agent, mut new_negotiations) =
-        organize_negotiations(base, profiles);
+        organize_negotiations(base, profiles, capacity_zones);
 
     // Now that we've negotiated away some conflicts, check if any new conflicts
     // have been formed and pull all newly conflicting agents together into a
@@ -863,7 +1910,7 @@ fn reconsider_negotiations(
     // Key: ID of an old negotiation
     // Value: IDs of the new negotiations that ought to contain the participants
     // of the old negotiations.
-    let mut merge_old_negotiation_into_new: HashMap<usize, HashSet<usize>> = HashMap::new();
+    let mut merge_old_negotiation_into_new: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
     for (i, negotiation) in &new_negotiations {
         for agent in &negotiation.participants {
             let Some(n_prev) = previous_negotiation_of_agent.get(agent).cloned() else {
@@ -876,7 +1923,7 @@ fn reconsider_negotiations(
         }
     }
 
-    let mut merge_new_negotiation_into: HashMap<usize, usize> = HashMap::new();
+    let mut merge_new_negotiation_into: BTreeMap<usize, usize> = BTreeMap::new();
     for overlapping in merge_old_negotiation_into_new.values() {
         let merge_all_into = 'merge: {
             // Find if one of them is already supposed to merge into another
@@ -947,6 +1994,10 @@ fn reconsider_negotiations(
         let merge_from_n = new_negotiations.remove(merge_from).unwrap();
         let merge_into_n = new_negotiations.get_mut(merge_into).unwrap();
         merge_into_n.conflicts.extend(merge_from_n.conflicts);
+        merge_into_n
+            .capacity_conflicts
+            .extend(merge_from_n.capacity_conflicts);
+        merge_into_n.capacity_conflicts.sort_by_key(|c| c.time);
         merge_into_n.participants.extend(merge_from_n.participants);
         merge_into_n.participants.sort_unstable();
         merge_into_n.participants.dedup();
@@ -1202,3 +2253,371 @@ fn find_spillover_conflict(
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    /// Negotiate with the given number of threads and get the trajectory of
+    /// each agent in agent order, the number of nodes, and the solution node.
+    fn negotiate_with_threads(
+        scenario: &Scenario,
+        threads: usize,
+    ) -> (Vec<(usize, Vec<WaypointSE2>)>, usize, usize) {
+        let (solution, nodes, _) = negotiate_with_settings(
+            scenario,
+            NegotiationSettings::default().with_threads(threads),
+        )
+        .unwrap();
+        let mut trajectories: Vec<_> = solution
+            .proposals
+            .iter()
+            .map(|(i, p)| (*i, p.meta.trajectory.iter().collect::<Vec<_>>()))
+            .collect();
+        trajectories.sort_by_key(|(i, _)| *i);
+        (trajectories, nodes.len(), solution.id)
+    }
+
+    #[test]
+    fn test_parallel_negotiation_is_deterministic() {
+        // Two pairs of agents that swap places far away from each other, so
+        // they make two independent negotiations.
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [5, 0])),
+            ("B", Agent::new([5, 0], [0, 0])),
+            ("C", Agent::new([0, 30], [5, 30])),
+            ("D", Agent::new([5, 30], [0, 30])),
+        ]);
+
+        let sequential = negotiate_with_threads(&scenario, 1);
+        assert_eq!(sequential.0.len(), 4);
+        for threads in [2, 4] {
+            assert_eq!(negotiate_with_threads(&scenario, threads), sequential);
+        }
+    }
+
+    #[test]
+    fn test_equal_time_conflicts_are_deterministic() {
+        // Four agents cross each other's paths on a square. The crossings of
+        // A with D and of B with C happen at the same time, so those two
+        // conflicts are tied for the earliest one once A and B are resolved.
+        let north = std::f64::consts::FRAC_PI_2;
+        let scenario = test_scenario([
+            ("A", Agent::new([-3, 0], [4, 0])),
+            ("B", Agent::new([0, -3], [0, 4]).with_yaw(north)),
+            ("C", Agent::new([-5, 2], [4, 2])),
+            ("D", Agent::new([2, -5], [2, 4]).with_yaw(north)),
+        ]);
+
+        // Every map in the negotiation gets its own hash seed, so repeating
+        // the negotiation would expose any order that depends on hashing.
+        let sequential = negotiate_with_threads(&scenario, 1);
+        assert_eq!(sequential.0.len(), 4);
+        for threads in [1, 4, 1, 4] {
+            assert_eq!(negotiate_with_threads(&scenario, threads), sequential);
+        }
+    }
+
+    #[test]
+    fn test_merged_closer_keeps_nodes_apart() {
+        let mut scenario = test_scenario([
+            ("A", Agent::new([0, 0], [5, 0])),
+            ("B", Agent::new([5, 0], [0, 0])),
+        ]);
+        scenario.occupancy = HashMap::from_iter([(2, vec![-3, 3]), (-2, vec![-3, 3])]);
+
+        let (_, nodes, _) = negotiate(&scenario, None).unwrap();
+        let mut keyed = nodes.iter().filter(|n| n.keys.len() == 1);
+        let mut node_a = keyed.next().unwrap().clone();
+        let mut node_b = keyed.find(|n| n.keys != node_a.keys).unwrap().clone();
+
+        // Two negotiations of the same round both number their nodes from
+        // zero, so their closers must be shifted apart when they are merged.
+        node_a.id = 0;
+        node_b.id = 0;
+        let mut closer_a = NegotiationCloser::new();
+        let mut closer_b = NegotiationCloser::new();
+        assert!(closer_a.close(&node_a));
+        assert!(closer_b.close(&node_b));
+
+        let mut closer = NegotiationCloser::new();
+        closer.merge(closer_a, 0);
+        closer.merge(closer_b, 1);
+        assert!(closer.status(&node_a).is_closed());
+        assert!(closer.status(&node_b).is_closed());
+
+        // No single closed node had both keys, so a node with both is open.
+        let mut node_ab = node_a.clone();
+        node_ab.keys.extend(node_b.keys.iter().copied());
+        assert!(!closer.status(&node_ab).is_closed());
+    }
+
+    #[test]
+    fn test_heuristic_is_part_of_evaluation() {
+        let node = |id: usize, cost: f64, heuristic: usize| {
+            let mut node = NegotiationNode::from_root(
+                &Negotiation::default(),
+                &Vec::new(),
+                Arc::new(DynamicEnvironment::new(
+                    CircularProfile::new(0.0, 0.0, 0.0).unwrap(),
+                )),
+                &CapacityReservations::new(Vec::new()),
+                id,
+            );
+            node.cost = Cost(cost);
+            node.heuristic = heuristic;
+            node
+        };
+
+        // The cheaper node still has several agents that must increase their
+        // cost, so the other node is expanded first.
+        let mut queue = BinaryHeap::new();
+        queue.push(QueueEntry::new(node(0, 10.0, 0)));
+        queue.push(QueueEntry::new(node(1, 9.8, 5)));
+        assert_eq!(queue.pop().unwrap().node.id, 0);
+
+        // Without the heuristic the cheaper node is expanded first
+        let mut queue = BinaryHeap::new();
+        queue.push(QueueEntry::new(node(0, 10.0, 0)));
+        queue.push(QueueEntry::new(node(1, 9.8, 0)));
+        assert_eq!(queue.pop().unwrap().node.id, 1);
+    }
+
+    #[test]
+    fn test_predicted_agent_keeps_its_trajectory() {
+        // A human-driven vehicle crosses the path of a holonomic robot that
+        // needs to make way for it.
+        let predicted = AgentMotion::Predicted {
+            trajectory: vec![(0.0, 0, 0), (8.0, 6, 0)],
+        };
+        let scenario = test_scenario([
+            ("human", Agent::new([0, 0], [6, 0]).with_motion(predicted)),
+            (
+                "robot",
+                Agent::new([6, 0], [0, 0]).with_motion(AgentMotion::Holonomic),
+            ),
+        ]);
+
+        let (solution, _, name_map) = negotiate(&scenario, None).unwrap();
+        let human = *name_map.iter().find(|(_, n)| *n == "human").unwrap().0;
+        let expected = scenario.agents["human"]
+            .make_predicted_trajectory(scenario.cell_size)
+            .unwrap();
+        assert_eq!(
+            solution.proposals[&human]
+                .meta
+                .trajectory
+                .iter()
+                .collect::<Vec<_>>(),
+            expected.iter().collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn test_conflict_between_predicted_agents_fails() {
+        let agent = |start: [i64; 2], goal: [i64; 2], trajectory| {
+            Agent::new(start, goal).with_motion(AgentMotion::Predicted { trajectory })
+        };
+
+        // Two human-driven vehicles drive head-on into each other, and neither
+        // of them can be asked to make way.
+        let scenario = test_scenario([
+            ("A", agent([0, 0], [6, 0], vec![(0.0, 0, 0), (6.0, 6, 0)])),
+            ("B", agent([6, 0], [0, 0], vec![(0.0, 6, 0), (6.0, 0, 0)])),
+        ]);
+
+        let Err(NegotiationError::PlanningFailed((nodes, _))) = negotiate(&scenario, None) else {
+            panic!("A conflict between predicted agents cannot be resolved");
+        };
+        assert!(nodes
+            .iter()
+            .any(|n| n.outcome == NodeOutcome::PredictedConflict));
+    }
+
+    #[test]
+    fn test_holonomic_agent_does_not_turn() {
+        let yaw = 90_f64.to_radians();
+        let scenario = test_scenario([(
+            "robot",
+            Agent::new([0, 0], [4, 0])
+                .with_yaw(yaw)
+                .with_speed(1.0)
+                .with_motion(AgentMotion::Holonomic),
+        )]);
+
+        // The robot drives sideways to its goal, so it arrives without
+        // spending any time on turning and keeps facing the same way.
+        let (solution, _, _) = negotiate(&scenario, None).unwrap();
+        let trajectory = &solution.proposals[&0].meta.trajectory;
+        assert_relative_eq!(
+            trajectory.finish_motion_time().as_secs_f64(),
+            4.0,
+            max_relative = 0.01
+        );
+        for wp in trajectory.iter() {
+            assert_relative_eq!(wp.position.rotation.angle(), yaw, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_agent_fills_in_missing_fields() {
+        let agent: Agent = serde_yaml::from_str(
+            "start: [0, 0]\n\
+             yaw: 0.0\n\
+             goal: [4, 0]\n\
+             effort:\n  translation: 2.0\n",
+        )
+        .unwrap();
+
+        let expected = Agent {
+            effort: TravelEffort {
+                translation: 2.0,
+                ..Default::default()
+            },
+            ..Agent::new([0, 0], [4, 0])
+        };
+        assert_eq!(
+            serde_yaml::to_string(&agent).unwrap(),
+            serde_yaml::to_string(&expected).unwrap()
+        );
+        assert_eq!(agent.motion, AgentMotion::DifferentialDrive);
+        assert_eq!(agent.effort.time, TravelEffort::default().time);
+    }
+
+    #[test]
+    fn test_goal_region_avoids_taken_goal() {
+        // Both agents want to finish in the same cell, but B is satisfied with
+        // any cell of its staging region, so it is planned into the nearest
+        // cell of the region that is still free.
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [4, 0])),
+            (
+                "B",
+                Agent::new([0, 2], [4, 0]).with_goal_region(vec![[8, 2], [4, 2]]),
+            ),
+        ]);
+
+        let (solution, _, name_map) = negotiate(&scenario, None).unwrap();
+        let finish_cell = |name: &str| {
+            let i = *name_map.iter().find(|(_, n)| *n == name).unwrap().0;
+            solution.proposals[&i].meta.final_state.key.vertex
+        };
+        assert_eq!(finish_cell("A"), Cell::new(4, 0));
+        assert_eq!(finish_cell("B"), Cell::new(4, 2));
+    }
+
+    #[test]
+    fn test_goal_region_keeps_agent_that_starts_inside() {
+        // B already stands in a cell of its staging region, so it stays there
+        // instead of driving to the first cell of the region.
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [4, 0])),
+            (
+                "B",
+                Agent::new([4, 2], [8, 2]).with_goal_region(vec![[8, 2], [4, 2]]),
+            ),
+        ]);
+
+        let (solution, _, name_map) = negotiate(&scenario, None).unwrap();
+        let b = *name_map.iter().find(|(_, n)| *n == "B").unwrap().0;
+        assert_eq!(
+            solution.proposals[&b].meta.final_state.key.vertex,
+            Cell::new(4, 2)
+        );
+    }
+
+    #[test]
+    fn test_capacity_region_admits_one_agent_at_a_time() {
+        // Two agents drive side by side without touching each other, but the
+        // column of cells that they pass through can only hold one of them.
+        let mut scenario = test_scenario([
+            ("A", Agent::new([0, 0], [6, 0])),
+            ("B", Agent::new([0, 2], [6, 2])),
+        ]);
+        scenario.capacity_regions = vec![CapacityRegion {
+            cells: vec![[3, 0], [3, 1], [3, 2]],
+            capacity: 1,
+        }];
+
+        let zones = scenario.make_capacity_zones();
+        let (solution, _, _) = negotiate(&scenario, None).unwrap();
+        assert_eq!(solution.proposals.len(), 2);
+        assert!(find_capacity_conflicts(
+            solution.proposals.iter().map(|(i, p)| (*i, &p.meta)),
+            &zones,
+        )
+        .is_empty());
+    }
+
+    /// Make an agent that must arrive at its goal before the deadline.
+    fn agent_with_deadline(start: [i64; 2], goal: [i64; 2], deadline: f64) -> Agent {
+        let mut agent = Agent::new(start, goal);
+        agent.window.latest_arrival = Some(deadline);
+        agent
+    }
+
+    /// Make a corridor along the x axis from -2 to 8 that is one cell wide.
+    fn corridor() -> HashMap<i64, Vec<i64>> {
+        HashMap::from_iter([(1, (-2..=8).collect()), (-1, (-2..=8).collect())])
+    }
+
+    #[test]
+    fn test_deadline_is_checked_against_arrival() {
+        // A human-driven vehicle cuts across the path of A and then keeps
+        // driving until long after A's deadline. A only needs to let it pass
+        // and can still arrive on time, even though it has to stay at its goal
+        // until the vehicle is finished.
+        let predicted = AgentMotion::Predicted {
+            trajectory: vec![(0.0, 2, -3), (4.0, 2, 3), (20.0, 2, 10)],
+        };
+        let scenario = test_scenario([
+            ("A", agent_with_deadline([0, 0], [4, 0], 10.0)),
+            ("human", Agent::new([2, -3], [2, 10]).with_motion(predicted)),
+        ]);
+
+        let (solution, _, name_map) = negotiate(&scenario, None).unwrap();
+        let a = *name_map.iter().find(|(_, n)| *n == "A").unwrap().0;
+        let goal = Cell::new(4, 0).center_point(scenario.cell_size);
+        let arrival = solution.proposals[&a]
+            .meta
+            .trajectory
+            .iter()
+            .find(|wp| (wp.position.translation.vector - goal.coords).norm() < 1e-6)
+            .unwrap();
+        assert!(arrival.time <= TimePoint::from_secs_f64(10.0));
+        // A holds at its goal until the vehicle is finished.
+        let finish = solution.proposals[&a].meta.trajectory.finish_motion_time();
+        assert!(finish >= TimePoint::from_secs_f64(20.0));
+    }
+
+    #[test]
+    fn test_unreachable_deadline_is_infeasible() {
+        let scenario = test_scenario([("A", agent_with_deadline([0, 0], [20, 0], 2.0))]);
+        let Err(NegotiationError::DeadlineInfeasible(name)) = negotiate(&scenario, None) else {
+            panic!("A cannot cross 20 cells in 2 seconds");
+        };
+        assert_eq!(name, "A");
+    }
+
+    #[test]
+    fn test_blocked_corridor_misses_deadline() {
+        // A human-driven vehicle stands in the corridor that A needs to pass
+        // through until long after A's deadline, so A could only make it on
+        // time if the vehicle were not there.
+        let predicted = AgentMotion::Predicted {
+            trajectory: vec![(0.0, 4, 0), (20.0, 4, 0), (24.0, 8, 0)],
+        };
+        let mut scenario = test_scenario([
+            ("A", agent_with_deadline([0, 0], [6, 0], 10.0)),
+            ("human", Agent::new([4, 0], [8, 0]).with_motion(predicted)),
+        ]);
+        scenario.occupancy = corridor();
+
+        let Err(NegotiationError::DeadlinesMissed { agents, .. }) = negotiate(&scenario, None)
+        else {
+            panic!("A cannot get past the vehicle before its deadline");
+        };
+        assert_eq!(agents, ["A"]);
+    }
+}
@@ -16,10 +16,14 @@
 */
 
 use crate::{
-    graph::occupancy::Cell,
+    graph::occupancy::{Cell, Grid, SparseGrid},
     motion::{
-        se2::{GoalSE2, Orientation, StartSE2, WaypointSE2},
-        TimePoint, Trajectory,
+        r2::Point,
+        se2::{
+            DifferentialDriveLineFollow, GoalRegionSE2, GoalSE2, Orientation, SequentialGoalSE2,
+            StartSE2, StopSE2, WaypointSE2,
+        },
+        BlockedZone, CapacityZone, Duration, TimePoint, Trajectory, TravelEffortCost,
     },
 };
 use serde::{Deserialize, Serialize};
@@ -27,7 +31,7 @@ use std::collections::{BTreeMap, HashMap};
 
 pub type LinearTrajectorySE2 = Trajectory<WaypointSE2>;
 
-#[derive(Serialize, Deserialize, Clone, Debug, Copy)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Agent {
     /// Start cell
     pub start: [i64; 2],
@@ -35,6 +39,17 @@ pub struct Agent {
     pub yaw: f64,
     /// Goal cell
     pub goal: [i64; 2],
+    /// Other cells that are acceptable for the agent to finish in. When the
+    /// goals of agents conflict, the negotiation may assign them distinct
+    /// cells from their goal regions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub goal_region: Vec<[i64; 2]>,
+    /// Stops that must be visited in order before reaching the goal cell
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stops: Vec<AgentStop>,
+    /// When the robot may leave its start and when it must reach its goal
+    #[serde(default, skip_serializing_if = "TimeWindow::is_unbounded")]
+    pub window: TimeWindow,
     /// Radius of the robot's footprint (meters)
     #[serde(default = " default_radius")]
     pub radius: f64,
@@ -44,10 +59,64 @@ pub struct Agent {
     /// How fast the robot can spin (radians/sec)
     #[serde(default = "default_spin")]
     pub spin: f64,
-    // TODO(@mxgrey): Allow parameters for travel effort cost
+    /// How the robot moves
+    #[serde(default, skip_serializing_if = "AgentMotion::is_default")]
+    pub motion: AgentMotion,
+    /// Weights of the travel effort cost for the robot
+    #[serde(default, skip_serializing_if = "TravelEffort::is_default")]
+    pub effort: TravelEffort,
+}
+
+impl Default for Agent {
+    fn default() -> Self {
+        Self {
+            start: [0, 0],
+            yaw: 0.0,
+            goal: [0, 0],
+            goal_region: Vec::new(),
+            stops: Vec::new(),
+            window: TimeWindow::default(),
+            radius: default_radius(),
+            speed: default_speed(),
+            spin: default_spin(),
+            motion: AgentMotion::default(),
+            effort: TravelEffort::default(),
+        }
+    }
 }
 
 impl Agent {
+    /// Make an agent that drives from `start` to `goal` with the default
+    /// footprint, speeds, and travel effort, and without any stops or time
+    /// windows.
+    pub fn new(start: [i64; 2], goal: [i64; 2]) -> Self {
+        Self {
+            start,
+            goal,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_yaw(mut self, yaw: f64) -> Self {
+        self.yaw = yaw;
+        self
+    }
+
+    pub fn with_goal_region(mut self, goal_region: Vec<[i64; 2]>) -> Self {
+        self.goal_region = goal_region;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_motion(mut self, motion: AgentMotion) -> Self {
+        self.motion = motion;
+        self
+    }
+
     pub fn start_cell(&self) -> Cell {
         self.start.into()
     }
@@ -66,6 +135,219 @@ impl Agent {
 
     pub fn make_goal(&self) -> GoalSE2<Cell> {
         GoalSE2::new(Cell::from(self.goal))
+            .with_minimum_time(self.window.earliest_arrival())
+            .with_maximum_time(self.window.latest_arrival())
+    }
+
+    /// Make a goal that accepts the goal cell or any cell of the goal region,
+    /// leaving out the cells that are not accepted by the filter. The cells
+    /// keep their order of preference, starting with the goal cell.
+    pub fn make_goal_region(&self, accept: impl Fn(&[i64; 2]) -> bool) -> GoalRegionSE2<Cell> {
+        GoalRegionSE2::new(
+            [self.goal]
+                .into_iter()
+                .chain(self.goal_region.iter().cloned())
+                .filter(|cell| accept(cell))
+                .map(Cell::from),
+        )
+        .with_minimum_time(self.window.earliest_arrival())
+        .with_maximum_time(self.window.latest_arrival())
+    }
+
+    /// Make a goal that visits each stop of the agent in order before
+    /// arriving at its goal cell.
+    pub fn make_sequential_goal(&self) -> SequentialGoalSE2<Cell> {
+        SequentialGoalSE2::new(self.stops.iter().map(AgentStop::make_stop).collect())
+            .with_stop(
+                StopSE2::new(Cell::from(self.goal))
+                    .with_earliest_arrival(self.window.earliest_arrival())
+                    .with_latest_arrival(self.window.latest_arrival()),
+            )
+            .with_earliest_departure(self.window.earliest_departure())
+    }
+
+    /// Check whether the agent or any of its stops has a time window.
+    pub fn has_time_windows(&self) -> bool {
+        !self.window.is_unbounded() || self.stops.iter().any(|s| !s.window.is_unbounded())
+    }
+
+    /// Make the motion model that the planner should use for this agent.
+    /// Returns None if the speed or spin of the agent is not positive.
+    pub fn make_extrapolator(&self) -> Option<DifferentialDriveLineFollow> {
+        let mut extrapolator = DifferentialDriveLineFollow::new(self.speed, self.spin).ok()?;
+        extrapolator.set_holonomic(matches!(self.motion, AgentMotion::Holonomic));
+        Some(extrapolator)
+    }
+
+    /// Make the travel effort cost that the planner should use for this agent.
+    pub fn make_cost(&self) -> TravelEffortCost {
+        self.effort.into()
+    }
+
+    /// Check whether the trajectory of this agent is predicted instead of
+    /// planned.
+    pub fn is_predicted(&self) -> bool {
+        matches!(self.motion, AgentMotion::Predicted { .. })
+    }
+
+    /// Make the predicted trajectory of this agent. The robot faces along its
+    /// direction of travel and holds its position after the last waypoint.
+    /// This gives back None if the agent is not predicted or its prediction has
+    /// fewer than two waypoints.
+    pub fn make_predicted_trajectory(&self, cell_size: f64) -> Option<LinearTrajectorySE2> {
+        let AgentMotion::Predicted { trajectory } = &self.motion else {
+            return None;
+        };
+
+        let mut yaw = self.yaw;
+        let mut waypoints = Vec::new();
+        for (i, (t, x, y)) in trajectory.iter().enumerate() {
+            let p = Cell::new(*x, *y).center_point(cell_size);
+            if let Some((_, next_x, next_y)) = trajectory.get(i + 1) {
+                let next = Cell::new(*next_x, *next_y).center_point(cell_size);
+                let delta = next - p;
+                if delta.norm() > 1e-6 {
+                    yaw = f64::atan2(delta.y, delta.x);
+                }
+            }
+            waypoints.push(WaypointSE2::new_f64(*t, p.x, p.y, yaw));
+        }
+
+        Trajectory::from_iter(waypoints)
+            .ok()
+            .map(|t| t.with_indefinite_finish_time(true))
+    }
+
+    /// Get a copy of this agent with all of its time windows removed.
+    pub fn without_time_windows(&self) -> Agent {
+        let mut agent = self.clone();
+        agent.window = TimeWindow::default();
+        for stop in &mut agent.stops {
+            stop.window = TimeWindow::default();
+        }
+        agent
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentMotion {
+    /// The robot turns in place to face its direction of travel and then
+    /// drives straight.
+    #[default]
+    DifferentialDrive,
+    /// The robot can drive in any direction without turning first. It only
+    /// spins to reach the orientation that a goal asks for.
+    Holonomic,
+    /// The robot is not controlled by the planner, e.g. a human-driven
+    /// vehicle. It is expected to follow this trajectory, given as
+    /// (time (s), x cell, y cell), and the other agents make way for it.
+    Predicted { trajectory: Vec<(f64, i64, i64)> },
+}
+
+impl AgentMotion {
+    pub fn is_default(&self) -> bool {
+        *self == AgentMotion::DifferentialDrive
+    }
+}
+
+/// Weights of the [`TravelEffortCost`] for an agent. Weights that are left
+/// out of a scenario file keep their default values.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct TravelEffort {
+    /// Cost per second of travel
+    pub time: f64,
+    /// Cost per meter of translation
+    pub translation: f64,
+    /// Cost per radian of rotation
+    pub rotation: f64,
+}
+
+impl TravelEffort {
+    pub fn is_default(&self) -> bool {
+        *self == TravelEffort::default()
+    }
+}
+
+impl Default for TravelEffort {
+    fn default() -> Self {
+        let cost = TravelEffortCost::default();
+        Self {
+            time: cost.time,
+            translation: cost.translation,
+            rotation: cost.rotation,
+        }
+    }
+}
+
+impl From<TravelEffort> for TravelEffortCost {
+    fn from(value: TravelEffort) -> Self {
+        TravelEffortCost {
+            time: value.time,
+            translation: value.translation,
+            rotation: value.rotation,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AgentStop {
+    /// Cell of the stop
+    pub cell: [i64; 2],
+    /// Yaw that the robot must have when it arrives at the stop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yaw: Option<f64>,
+    /// How long the robot must remain at the stop (seconds)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub dwell: f64,
+    /// When the robot may arrive at and leave the stop
+    #[serde(default, skip_serializing_if = "TimeWindow::is_unbounded")]
+    pub window: TimeWindow,
+}
+
+impl AgentStop {
+    pub fn make_stop(&self) -> StopSE2<Cell> {
+        StopSE2::new(Cell::from(self.cell))
+            .with_orientation(self.yaw.map(Orientation::from_angle))
+            .with_dwell(Duration::from_secs_f64(self.dwell))
+            .with_earliest_arrival(self.window.earliest_arrival())
+            .with_latest_arrival(self.window.latest_arrival())
+            .with_earliest_departure(self.window.earliest_departure())
+    }
+}
+
+/// Times are given in seconds since the start of the scenario.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimeWindow {
+    /// The robot may not leave before this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earliest_departure: Option<f64>,
+    /// The robot may not arrive before this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earliest_arrival: Option<f64>,
+    /// The robot must arrive no later than this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_arrival: Option<f64>,
+}
+
+impl TimeWindow {
+    pub fn is_unbounded(&self) -> bool {
+        self.earliest_departure.is_none()
+            && self.earliest_arrival.is_none()
+            && self.latest_arrival.is_none()
+    }
+
+    pub fn earliest_departure(&self) -> Option<TimePoint> {
+        self.earliest_departure.map(TimePoint::from_secs_f64)
+    }
+
+    pub fn earliest_arrival(&self) -> Option<TimePoint> {
+        self.earliest_arrival.map(TimePoint::from_secs_f64)
+    }
+
+    pub fn latest_arrival(&self) -> Option<TimePoint> {
+        self.latest_arrival.map(TimePoint::from_secs_f64)
     }
 }
 
@@ -99,10 +381,82 @@ impl Obstacle {
     }
 }
 
+/// A region that agents must stay out of while it is closed. A zone is given
+/// either as a polygon or as a set of cells.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Zone {
+    /// Corners of the zone polygon (meters)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub polygon: Vec<[f64; 2]>,
+    /// Cells covered by the zone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cells: Vec<[i64; 2]>,
+    /// When the zone closes (seconds). If omitted, the zone is closed from
+    /// the start of the scenario.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_from: Option<f64>,
+    /// When the zone opens again (seconds). If omitted, the zone never opens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_until: Option<f64>,
+}
+
+impl Zone {
+    /// Make the blocked zones described by this zone. A zone that has both a
+    /// polygon and cells becomes two blocked zones with the same closing time.
+    pub fn make_blocked_zones(&self, cell_size: f64) -> Vec<BlockedZone> {
+        let mut zones = Vec::new();
+        if !self.polygon.is_empty() {
+            zones.push(BlockedZone::polygon(
+                self.polygon
+                    .iter()
+                    .map(|[x, y]| Point::new(*x, *y))
+                    .collect(),
+            ));
+        }
+
+        if !self.cells.is_empty() {
+            zones.push(BlockedZone::cells(
+                cell_size,
+                self.cells.iter().map(|c| Cell::from(*c)).collect(),
+            ));
+        }
+
+        zones
+            .into_iter()
+            .map(|zone| {
+                zone.with_closing_time(self.closed_from.map(TimePoint::from_secs_f64))
+                    .with_opening_time(self.closed_until.map(TimePoint::from_secs_f64))
+            })
+            .collect()
+    }
+}
+
+/// A set of cells that can hold at most a limited number of agents at once,
+/// e.g. a narrow doorway, a single-lane bridge, or a lift lobby.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CapacityRegion {
+    /// Cells that belong to the region
+    pub cells: Vec<[i64; 2]>,
+    /// How many agents may be inside of the region at the same time
+    pub capacity: usize,
+}
+
+impl CapacityRegion {
+    pub fn make_capacity_zone(&self) -> CapacityZone<Cell> {
+        CapacityZone::new(self.capacity, self.cells.iter().map(|c| Cell::from(*c)))
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Scenario {
     pub agents: BTreeMap<String, Agent>,
     pub obstacles: Vec<Obstacle>,
+    /// Regions that agents must stay out of while they are closed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<Zone>,
+    /// Regions that may only hold a limited number of agents at once
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capacity_regions: Vec<CapacityRegion>,
     // y -> [..x..]
     pub occupancy: HashMap<i64, Vec<i64>>,
     #[serde(default = "default_cell_size")]
@@ -111,6 +465,37 @@ pub struct Scenario {
     pub camera_bounds: Option<[[f32; 2]; 2]>,
 }
 
+impl Scenario {
+    /// Make the occupancy grid described by this scenario.
+    pub fn make_grid(&self) -> SparseGrid {
+        let mut grid = SparseGrid::new(self.cell_size);
+        let changes: HashMap<_, _> = self
+            .occupancy
+            .iter()
+            .flat_map(|(y, row)| row.iter().map(|x| (Cell::new(*x, *y), true)))
+            .collect();
+        grid.change_cells(&changes);
+        grid
+    }
+
+    /// Make the blocked zones described by this scenario.
+    pub fn make_blocked_zones(&self) -> Vec<BlockedZone> {
+        self.zones
+            .iter()
+            .flat_map(|zone| zone.make_blocked_zones(self.cell_size))
+            .collect()
+    }
+
+    /// Make the capacity zones described by this scenario. The index of each
+    /// zone matches the index of its region.
+    pub fn make_capacity_zones(&self) -> Vec<CapacityZone<Cell>> {
+        self.capacity_regions
+            .iter()
+            .map(CapacityRegion::make_capacity_zone)
+            .collect()
+    }
+}
+
 pub fn default_radius() -> f64 {
     0.45
 }
@@ -134,3 +519,24 @@ pub fn bool_false() -> bool {
 pub fn is_false(b: &bool) -> bool {
     !b
 }
+
+pub fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+/// Make a scenario with the given agents on an empty grid of 1 meter cells.
+#[cfg(test)]
+pub(crate) fn test_scenario<'a>(agents: impl IntoIterator<Item = (&'a str, Agent)>) -> Scenario {
+    Scenario {
+        agents: agents
+            .into_iter()
+            .map(|(name, agent)| (name.to_owned(), agent))
+            .collect(),
+        obstacles: Vec::new(),
+        zones: Vec::new(),
+        capacity_regions: Vec::new(),
+        occupancy: HashMap::new(),
+        cell_size: 1.0,
+        camera_bounds: None,
+    }
+}
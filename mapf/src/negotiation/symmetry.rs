@@ -0,0 +1,652 @@
+/*
+ * Copyright (C) 2023 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{Agent, Conflict, Proposal};
+use crate::{
+    algorithm::path::{DecisionPoint, DecisionRange},
+    graph::{
+        occupancy::{AccessibilityGraph, Cell, SparseGrid},
+        Graph,
+    },
+    motion::{
+        se2::WaypointSE2, CcbsConstraint, CcbsKey, CircularProfile, Duration,
+        DynamicCircularObstacle, TimePoint, Trajectory,
+    },
+    premade::StateSippSE2,
+};
+use std::collections::HashMap;
+
+/// Corridors longer than this many cells are not followed any further.
+const MAX_CORRIDOR_LENGTH: usize = 1000;
+
+/// The kinds of pairwise symmetry that can be recognized in a conflict.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictKind {
+    /// No symmetry was recognized, so the conflict is split with the usual
+    /// constraint on the edges that the agents were moving along.
+    Regular,
+    /// The agents meet head-on inside a corridor. One of them has to let the
+    /// other through the whole corridor before it can leave the corridor.
+    Corridor { exits: [Cell; 2], length: f64 },
+    /// The agents cross a rectangle where every pair of their shortest paths
+    /// collides. One of them has to reach the far border of the rectangle
+    /// later than it could have.
+    Rectangle {
+        start_corner: Cell,
+        goal_corner: Cell,
+    },
+    /// One agent runs into the goal of the other after it has finished. Either
+    /// the finished agent arrives later, or the other agent stays off that goal
+    /// from then on.
+    Target { goal: Cell },
+}
+
+/// A set of constraints that should be inserted together
+pub type ConstraintSet = Vec<(CcbsKey<Cell>, CcbsConstraint<WaypointSE2>)>;
+
+/// What is needed to recognize the symmetries of a conflict
+pub(super) struct SymmetryContext<'a> {
+    pub graphs: &'a [AccessibilityGraph<SparseGrid>],
+    pub agents: &'a [Agent],
+    pub profiles: &'a [CircularProfile],
+    pub cell_size: f64,
+}
+
+/// Classify the conflict and find the constraints that break its symmetry.
+/// Element `k` of the constraints should be inserted for the branch where
+/// agent `conflict.segments[k]` concedes. When it is None, that branch should
+/// use the regular constraint.
+pub(super) fn break_symmetry(
+    conflict: &Conflict,
+    proposals: &HashMap<usize, Proposal>,
+    ctx: &SymmetryContext,
+) -> (ConflictKind, [Option<ConstraintSet>; 2]) {
+    let [seg_a, seg_b] = conflict.segments;
+    let (Some(proposal_a), Some(proposal_b)) =
+        (proposals.get(&seg_a.agent), proposals.get(&seg_b.agent))
+    else {
+        return (ConflictKind::Regular, [None, None]);
+    };
+
+    if let Some(result) = target_symmetry(conflict, ctx) {
+        return result;
+    }
+
+    if let (DecisionRange::Between(range_a), DecisionRange::Between(range_b)) =
+        (&seg_a.range, &seg_b.range)
+    {
+        let a = Mover {
+            agent: seg_a.agent,
+            range: range_a,
+            proposal: proposal_a,
+        };
+        let b = Mover {
+            agent: seg_b.agent,
+            range: range_b,
+            proposal: proposal_b,
+        };
+
+        if let Some(result) = corridor_symmetry(&a, &b, ctx) {
+            return result;
+        }
+
+        if let Some(result) = rectangle_symmetry(&a, &b, ctx) {
+            return result;
+        }
+    }
+
+    (ConflictKind::Regular, [None, None])
+}
+
+/// One side of a conflict where the agent is moving between decision points
+struct Mover<'a> {
+    agent: usize,
+    range: &'a [DecisionPoint<StateSippSE2<Cell>>; 2],
+    proposal: &'a Proposal,
+}
+
+impl<'a> Mover<'a> {
+    fn from(&self) -> Cell {
+        self.range[0].state.key.vertex
+    }
+
+    fn to(&self) -> Cell {
+        self.range[1].state.key.vertex
+    }
+
+    fn start_time(&self) -> TimePoint {
+        self.range[0].state.waypoint.time
+    }
+
+    fn speed(&self, ctx: &SymmetryContext) -> f64 {
+        ctx.agents[self.agent].speed
+    }
+
+    /// The first time at or after the start of this segment when the agent is
+    /// at the cell.
+    fn arrival_at(&self, cell: Cell) -> Option<TimePoint> {
+        self.proposal
+            .meta
+            .decision_points
+            .iter()
+            .map(|dp| &dp.state)
+            .find(|s| s.waypoint.time >= self.start_time() && s.key.vertex == cell)
+            .map(|s| s.waypoint.time)
+    }
+
+    /// Extend the segment backwards and forwards along the decision points for
+    /// as long as the agent keeps moving in the same quadrant. Gives back the
+    /// first and last state of that run.
+    fn monotone_run(&self) -> Option<([StateSippSE2<Cell>; 2], [i64; 2])> {
+        let points = &self.proposal.meta.decision_points;
+        let i0 = points
+            .iter()
+            .position(|dp| dp.index == self.range[0].index)?;
+        let step = direction(self.from(), self.to());
+        let mut signs = step;
+
+        let compatible = |signs: &mut [i64; 2], step: [i64; 2]| {
+            for (sign, step) in signs.iter_mut().zip(step) {
+                if step == 0 {
+                    continue;
+                }
+                if *sign == 0 {
+                    *sign = step;
+                } else if *sign != step {
+                    return false;
+                }
+            }
+            true
+        };
+
+        let mut first = i0;
+        while first > 0 {
+            let step = direction(
+                points[first - 1].state.key.vertex,
+                points[first].state.key.vertex,
+            );
+            if !compatible(&mut signs, step) {
+                break;
+            }
+            first -= 1;
+        }
+
+        let mut last = i0;
+        while last + 1 < points.len() {
+            let step = direction(
+                points[last].state.key.vertex,
+                points[last + 1].state.key.vertex,
+            );
+            if !compatible(&mut signs, step) {
+                break;
+            }
+            last += 1;
+        }
+
+        Some(([points[first].state, points[last].state], signs))
+    }
+}
+
+fn target_symmetry(
+    conflict: &Conflict,
+    ctx: &SymmetryContext,
+) -> Option<(ConflictKind, [Option<ConstraintSet>; 2])> {
+    for (k, (finished, other)) in [
+        (conflict.segments[0], conflict.segments[1]),
+        (conflict.segments[1], conflict.segments[0]),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let DecisionRange::After(state, t) = finished.range else {
+            continue;
+        };
+        if matches!(other.range, DecisionRange::After(..)) {
+            continue;
+        }
+
+        // The agent that runs into the goal has to stay off of it from the
+        // time of the conflict onwards, no matter which way it approaches.
+        let goal = state.key.vertex;
+        let barrier = barrier(
+            goal,
+            t,
+            None,
+            ctx.profiles[finished.agent],
+            finished.agent,
+            ctx.cell_size,
+        );
+
+        let mut constraints = [None, None];
+        constraints[1 - k] = Some(barrier);
+        return Some((ConflictKind::Target { goal }, constraints));
+    }
+
+    None
+}
+
+fn corridor_symmetry(
+    a: &Mover,
+    b: &Mover,
+    ctx: &SymmetryContext,
+) -> Option<(ConflictKind, [Option<ConstraintSet>; 2])> {
+    let dir_a = direction(a.from(), a.to());
+    let dir_b = direction(b.from(), b.to());
+    if dir_a == [0, 0] || dir_a != [-dir_b[0], -dir_b[1]] {
+        return None;
+    }
+
+    let chain = corridor(&ctx.graphs[a.agent], a.from(), a.to())?;
+    if !chain.contains(&b.from()) || !chain.contains(&b.to()) {
+        return None;
+    }
+
+    // Agent a is headed towards the end of the chain and agent b towards the
+    // beginning.
+    let exit_a = *chain.last()?;
+    let exit_b = *chain.first()?;
+    let arrival_a = a.arrival_at(exit_a)?;
+    let arrival_b = b.arrival_at(exit_b)?;
+    let length: f64 = chain
+        .windows(2)
+        .map(|w| (w[1].center_point(ctx.cell_size) - w[0].center_point(ctx.cell_size)).norm())
+        .sum();
+
+    // Whichever agent concedes cannot leave the corridor until the other has
+    // gotten all the way through it and then it has crossed the corridor.
+    let yield_a = barrier(
+        exit_a,
+        TimePoint::zero(),
+        Some(arrival_b + Duration::from_secs_f64(length / a.speed(ctx))),
+        ctx.profiles[b.agent],
+        b.agent,
+        ctx.cell_size,
+    );
+    let yield_b = barrier(
+        exit_b,
+        TimePoint::zero(),
+        Some(arrival_a + Duration::from_secs_f64(length / b.speed(ctx))),
+        ctx.profiles[a.agent],
+        a.agent,
+        ctx.cell_size,
+    );
+
+    if yield_a.is_empty() || yield_b.is_empty() {
+        return None;
+    }
+
+    Some((
+        ConflictKind::Corridor {
+            exits: [exit_b, exit_a],
+            length,
+        },
+        [Some(yield_a), Some(yield_b)],
+    ))
+}
+
+fn rectangle_symmetry(
+    a: &Mover,
+    b: &Mover,
+    ctx: &SymmetryContext,
+) -> Option<(ConflictKind, [Option<ConstraintSet>; 2])> {
+    let ([start_a, goal_a], signs_a) = a.monotone_run()?;
+    let ([start_b, goal_b], signs_b) = b.monotone_run()?;
+    if signs_a != signs_b || signs_a[0] == 0 || signs_a[1] == 0 {
+        return None;
+    }
+
+    let (s1, g1) = (start_a.key.vertex, goal_a.key.vertex);
+    let (s2, g2) = (start_b.key.vertex, goal_b.key.vertex);
+    let inner = |s1: i64, s2: i64, g1: i64| {
+        if (s1 - s2) * (s2 - g1) >= 0 {
+            s2
+        } else {
+            s1
+        }
+    };
+    let start_corner = Cell::new(inner(s1.x, s2.x, g1.x), inner(s1.y, s2.y, g1.y));
+    let goal_corner = Cell::new(inner(g2.x, g1.x, s1.x), inner(g2.y, g1.y, s1.y));
+    let extent = [
+        (goal_corner.x - start_corner.x) * signs_a[0],
+        (goal_corner.y - start_corner.y) * signs_a[1],
+    ];
+    if extent[0] <= 0 || extent[1] <= 0 {
+        return None;
+    }
+
+    // An agent whose start lines up with the start corner in x enters the
+    // rectangle from the y side, so it must cross the far border along x.
+    let crosses_x_border_a = s1.x == start_corner.x;
+    let crosses_x_border_b = s2.x == start_corner.x && s1.x != s2.x;
+    if crosses_x_border_a == crosses_x_border_b {
+        return None;
+    }
+
+    let yield_a = rectangle_barrier(
+        a,
+        b,
+        start_a,
+        crosses_x_border_a,
+        start_corner,
+        goal_corner,
+        ctx,
+    )?;
+    let yield_b = rectangle_barrier(
+        b,
+        a,
+        start_b,
+        crosses_x_border_b,
+        start_corner,
+        goal_corner,
+        ctx,
+    )?;
+
+    Some((
+        ConflictKind::Rectangle {
+            start_corner,
+            goal_corner,
+        },
+        [Some(yield_a), Some(yield_b)],
+    ))
+}
+
+/// The agent may not reach any cell of its far border of the rectangle as
+/// early as its current run would bring it there. The barrier stands in for
+/// the other agent, so it is masked for that agent instead of the mover.
+fn rectangle_barrier(
+    mover: &Mover,
+    other: &Mover,
+    start: StateSippSE2<Cell>,
+    along_x: bool,
+    start_corner: Cell,
+    goal_corner: Cell,
+    ctx: &SymmetryContext,
+) -> Option<ConstraintSet> {
+    let border: Vec<Cell> = if along_x {
+        span(start_corner.x, goal_corner.x)
+            .map(|x| Cell::new(x, goal_corner.y))
+            .collect()
+    } else {
+        span(start_corner.y, goal_corner.y)
+            .map(|y| Cell::new(goal_corner.x, y))
+            .collect()
+    };
+
+    let cell_time = ctx.cell_size / mover.speed(ctx);
+    let earliest = |cell: Cell| {
+        start.waypoint.time + Duration::from_secs_f64(octile(start.key.vertex, cell) * cell_time)
+    };
+
+    // Whatever time the current plan loses to turning also gets added to the
+    // rest of the border.
+    let (crossing, crossed_at) = border
+        .iter()
+        .find_map(|cell| mover.arrival_at(*cell).map(|t| (*cell, t)))?;
+    let overhead = crossed_at - earliest(crossing);
+
+    let mut constraints = Vec::new();
+    for cell in border {
+        let until = earliest(cell) + overhead + Duration::from_secs_f64(cell_time);
+        constraints.extend(barrier(
+            cell,
+            TimePoint::zero(),
+            Some(until),
+            ctx.profiles[other.agent],
+            other.agent,
+            ctx.cell_size,
+        ));
+    }
+
+    Some(constraints)
+}
+
+/// Keep an agent off of a cell for a range of time. If `until` is None then the
+/// agent is kept off of the cell indefinitely. The constraint is applied to
+/// every edge that enters, leaves, or waits on the cell.
+fn barrier(
+    cell: Cell,
+    from: TimePoint,
+    until: Option<TimePoint>,
+    profile: CircularProfile,
+    mask: usize,
+    cell_size: f64,
+) -> ConstraintSet {
+    let p = cell.center_point(cell_size);
+    let wp = WaypointSE2::new(from, p.x, p.y, 0.0);
+    let trajectory = match until {
+        Some(until) if until > from => Trajectory::hold(wp, until),
+        Some(_) => return Vec::new(),
+        None => Trajectory::hold(wp, from + Duration::from_secs(1))
+            .map(|t| t.with_indefinite_finish_time(true)),
+    };
+    let Ok(trajectory) = trajectory else {
+        return Vec::new();
+    };
+
+    let constraint = CcbsConstraint {
+        obstacle: DynamicCircularObstacle::new(profile).with_trajectory(Some(trajectory)),
+        mask,
+    };
+
+    let mut constraints = vec![((cell, cell), constraint.clone())];
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+
+            let neighbor = cell.shifted(dx, dy);
+            constraints.push(((neighbor, cell), constraint.clone()));
+            constraints.push(((cell, neighbor), constraint.clone()));
+        }
+    }
+
+    constraints
+}
+
+/// Find the corridor that contains the edge from `u` to `v`. The corridor is
+/// returned as a chain of cells ordered from behind `u` to ahead of `v`, where
+/// the first and last cells are the exits of the corridor.
+fn corridor(graph: &AccessibilityGraph<SparseGrid>, u: Cell, v: Cell) -> Option<Vec<Cell>> {
+    let neighbors = |cell: Cell| -> Vec<Cell> {
+        graph
+            .edges_from_vertex(&cell)
+            .map(|(_, to)| to)
+            .collect()
+    };
+    let in_corridor = |cell: Cell| neighbors(cell).len() == 2;
+    if !in_corridor(u) && !in_corridor(v) {
+        return None;
+    }
+
+    let walk = |prev: Cell, start: Cell| -> Option<Vec<Cell>> {
+        let (mut prev, mut current) = (prev, start);
+        let mut cells = vec![current];
+        while in_corridor(current) {
+            let next = neighbors(current).into_iter().find(|n| *n != prev)?;
+            prev = current;
+            current = next;
+            cells.push(current);
+            if cells.len() > MAX_CORRIDOR_LENGTH {
+                return None;
+            }
+        }
+        Some(cells)
+    };
+
+    let mut chain = walk(v, u)?;
+    chain.reverse();
+    chain.extend(walk(u, v)?);
+    Some(chain)
+}
+
+fn direction(from: Cell, to: Cell) -> [i64; 2] {
+    [(to.x - from.x).signum(), (to.y - from.y).signum()]
+}
+
+fn span(from: i64, to: i64) -> impl Iterator<Item = i64> {
+    from.min(to)..=from.max(to)
+}
+
+/// The length of the shortest path between two cells on an 8-connected grid,
+/// in units of cells.
+fn octile(from: Cell, to: Cell) -> f64 {
+    let dx = (to.x - from.x).abs() as f64;
+    let dy = (to.y - from.y).abs() as f64;
+    dx.max(dy) + (2_f64.sqrt() - 1.0) * dx.min(dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        graph::occupancy::{Accessibility, Grid},
+        negotiation::{make_graph, negotiate, test_scenario, Scenario},
+    };
+    use std::sync::Arc;
+
+    fn corridor_graph() -> AccessibilityGraph<SparseGrid> {
+        // A horizontal corridor from x=0 to x=6 along y=0, walled in above
+        // and below, which opens up at both ends.
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        for x in 0..=6 {
+            changes.insert(Cell::new(x, 1), true);
+            changes.insert(Cell::new(x, -1), true);
+        }
+        grid.change_cells(&changes);
+        AccessibilityGraph::new(Arc::new(Accessibility::new(grid, 0.45)))
+    }
+
+    #[test]
+    fn test_corridor_chain() {
+        let graph = corridor_graph();
+        let chain = corridor(&graph, Cell::new(2, 0), Cell::new(3, 0)).unwrap();
+        assert_eq!(chain.first(), Some(&Cell::new(-1, 0)));
+        assert_eq!(chain.last(), Some(&Cell::new(7, 0)));
+        assert_eq!(chain.len(), 9);
+
+        // There is no corridor out in the open
+        assert!(corridor(&graph, Cell::new(2, 5), Cell::new(3, 5)).is_none());
+    }
+
+    #[test]
+    fn test_barrier_covers_every_approach() {
+        let profile = CircularProfile::new(0.45, 0.0, 0.0).unwrap();
+        let cell = Cell::new(2, 2);
+        let constraints = barrier(cell, TimePoint::zero(), None, profile, 0, 1.0);
+        // Waiting on the cell plus entering and leaving from 8 neighbors
+        assert_eq!(constraints.len(), 17);
+        assert!(constraints
+            .iter()
+            .all(|(key, _)| key.0 == cell || key.1 == cell));
+        assert!(constraints.iter().all(|(_, c)| c
+            .obstacle
+            .trajectory()
+            .unwrap()
+            .has_indefinite_finish_time()));
+
+        let until = TimePoint::from_secs_f64(3.0);
+        assert!(barrier(cell, until, Some(until), profile, 0, 1.0).is_empty());
+    }
+
+    /// Negotiate a scenario and check that the conflicts between the ideal
+    /// plans include one with the expected kind of symmetry. Each branch of
+    /// that conflict must use constraints that its conceding agent can see,
+    /// otherwise the agent would replan the same path.
+    fn assert_negotiates_with_symmetry(scenario: &Scenario, expected: fn(&ConflictKind) -> bool) {
+        let (_, nodes, _) = negotiate(scenario, None).unwrap();
+        let root = &nodes[0];
+
+        let agents: Vec<Agent> = scenario.agents.values().cloned().collect();
+        let grid = scenario.make_grid();
+        let graphs: Vec<_> = agents.iter().map(|a| make_graph(a, &grid)).collect();
+        let profiles: Vec<_> = agents
+            .iter()
+            .map(|a| CircularProfile::new(a.radius, 0.0, 0.0).unwrap())
+            .collect();
+        let ctx = SymmetryContext {
+            graphs: &graphs,
+            agents: &agents,
+            profiles: &profiles,
+            cell_size: scenario.cell_size,
+        };
+
+        let (conflict, constraints) = root
+            .negotiation
+            .conflicts
+            .iter()
+            .find_map(|conflict| {
+                let (kind, constraints) = break_symmetry(conflict, &root.proposals, &ctx);
+                expected(&kind).then_some((conflict, constraints))
+            })
+            .expect("The symmetry should be recognized");
+
+        for (segment, constraints) in conflict.segments.iter().zip(&constraints) {
+            for (_, constraint) in constraints.iter().flatten() {
+                assert_ne!(constraint.mask, segment.agent);
+            }
+        }
+
+        // The negotiation must have branched on the symmetry
+        assert!(nodes.iter().any(|n| n.keys.iter().any(|k| k.symmetric)));
+    }
+
+    #[test]
+    fn test_negotiate_corridor() {
+        // The agents meet head-on in the corridor of corridor_graph
+        let mut scenario = test_scenario([
+            ("A", Agent::new([-3, 0], [9, 0])),
+            (
+                "B",
+                Agent::new([9, 0], [-3, 0]).with_yaw(std::f64::consts::PI),
+            ),
+        ]);
+        scenario.occupancy = HashMap::from_iter([(1, (0..=6).collect()), (-1, (0..=6).collect())]);
+        assert_negotiates_with_symmetry(&scenario, |kind| {
+            matches!(kind, ConflictKind::Corridor { .. })
+        });
+    }
+
+    #[test]
+    fn test_negotiate_rectangle() {
+        // A drives right and B drives up, and their straight runs cross at
+        // the start corner of a rectangle that both of them pass through.
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 2], [5, 4])),
+            (
+                "B",
+                Agent::new([2, 0], [4, 5]).with_yaw(std::f64::consts::FRAC_PI_2),
+            ),
+        ]);
+        assert_negotiates_with_symmetry(&scenario, |kind| {
+            matches!(kind, ConflictKind::Rectangle { .. })
+        });
+    }
+
+    #[test]
+    fn test_negotiate_target() {
+        // B drives through the goal of A after A has already parked there.
+        let scenario = test_scenario([
+            ("A", Agent::new([0, 0], [2, 0])),
+            ("B", Agent::new([-3, 0], [6, 0])),
+        ]);
+        assert_negotiates_with_symmetry(&scenario, |kind| {
+            matches!(kind, ConflictKind::Target { .. })
+        });
+    }
+}
@@ -15,7 +15,7 @@
  *
 */
 
-use crate::algorithm::{MinimumCostBound, QueueLength};
+use crate::algorithm::{MemoryFootprint, MinimumCostBound, QueueLength};
 use std::{ops::Fn, sync::Arc};
 
 /// A trait to define conditions in which a search should be halted. The
@@ -118,6 +118,26 @@ where
     }
 }
 
+/// The maximum number of bytes that the Memory is estimated to occupy before
+/// the solve attempt quits. See [`MemoryFootprint`] for how the estimate is
+/// made. Unlike [`QueueLengthLimit`], this accounts for every node that the
+/// search is holding onto, not only the ones waiting in the queue.
+#[derive(Default, Clone)]
+pub struct MemoryLimit(pub Option<usize>);
+
+impl<Mem> Halt<Mem> for MemoryLimit
+where
+    Mem: MemoryFootprint,
+{
+    fn halt(&mut self, memory: &Mem) -> bool {
+        if let Some(limit) = self.0 {
+            return memory.memory_footprint() > limit;
+        }
+
+        false
+    }
+}
+
 /// If the lower cost bound of the search exceeds this cost limit then the
 /// search will halt.
 #[derive(Default, Clone)]
@@ -180,6 +200,14 @@ mod tests {
         }
     }
 
+    struct FakeFootprint(usize);
+
+    impl MemoryFootprint for FakeFootprint {
+        fn memory_footprint(&self) -> usize {
+            self.0
+        }
+    }
+
     #[test]
     fn test_tuple_options() {
         let mut halting = (
@@ -200,4 +228,15 @@ mod tests {
         }
         assert!(halting.halt(&FakeMem));
     }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut halting = MemoryLimit(Some(1024));
+        assert!(!halting.halt(&FakeFootprint(512)));
+        assert!(!halting.halt(&FakeFootprint(1024)));
+        assert!(halting.halt(&FakeFootprint(1025)));
+
+        let mut halting = MemoryLimit(None);
+        assert!(!halting.halt(&FakeFootprint(usize::MAX)));
+    }
 }
@@ -22,7 +22,7 @@ pub mod halt;
 pub use halt::Halt;
 
 use crate::{
-    algorithm::{Coherent, Solvable},
+    algorithm::{Checkpoint, CheckpointError, Checkpointable, Coherent, Solvable},
     domain::Configurable,
     error::Anyhow,
 };
@@ -128,6 +128,32 @@ impl<Algo, Halting> Planner<Algo, Halting> {
         Ok(Search::new(memory, self.algorithm.clone(), goal, halt))
     }
 
+    /// Continue a search from a checkpoint that was made by
+    /// [`Search::checkpoint`]. The checkpoint is rejected if it was made with a
+    /// different version of this library or if its fingerprint does not match
+    /// the fingerprint of this planner's domain configuration.
+    ///
+    /// The search will use the default halting of this planner.
+    pub fn resume<Goal>(
+        &self,
+        checkpoint: Checkpoint<Algo::Snapshot>,
+        goal: Goal,
+    ) -> Result<Search<Algo, Goal, Halting>, CheckpointError>
+    where
+        Algo: Checkpointable + Clone,
+        Halting: Halt<Algo::Memory> + Clone,
+    {
+        checkpoint.validate(self.algorithm.fingerprint()?)?;
+        let memory = self.algorithm.restore(checkpoint.content)?;
+
+        Ok(Search::new(
+            memory,
+            self.algorithm.clone(),
+            goal,
+            self.default_halting.clone(),
+        ))
+    }
+
     /// Convert the planner into a single [`Search`] instance. This can be used
     /// for Algorithms that don't implement the [`Clone`] trait.
     ///
@@ -19,13 +19,20 @@ use anyhow;
 use std::cell::RefCell;
 
 use crate::{
-    algorithm::{Algorithm, SearchStatus, Solvable},
+    algorithm::{
+        Algorithm, Checkpoint, CheckpointError, Checkpointable, SearchObserver, SearchStatus,
+        Solvable,
+    },
     error::Anyhow,
     planner::Halt,
 };
 
 /// Search manages the progress of a planning effort.
-pub struct Search<Algo: Algorithm, Goal, Halting> {
+///
+/// An observer can be given to the search with [`Search::with_observer`] to
+/// watch the events that happen while the algorithm is stepping. By default
+/// the observer is an empty tuple, which observes nothing.
+pub struct Search<Algo: Algorithm, Goal, Halting, Observer = ()> {
     /// Storage container for the progress of the search algorithm
     memory: Algo::Memory,
 
@@ -37,6 +44,9 @@ pub struct Search<Algo: Algorithm, Goal, Halting> {
 
     /// The options that moderate the progress of the solving
     halting: Halting,
+
+    /// Watches the events of the search algorithm
+    observer: Observer,
 }
 
 impl<Algo: Algorithm, Goal, Halting> Search<Algo, Goal, Halting> {
@@ -46,9 +56,12 @@ impl<Algo: Algorithm, Goal, Halting> Search<Algo, Goal, Halting> {
             algorithm,
             goal,
             halting,
+            observer: (),
         }
     }
+}
 
+impl<Algo: Algorithm, Goal, Halting, Observer> Search<Algo, Goal, Halting, Observer> {
     /// Tell the planner to attempt to solve the problem. This will run the
     /// step() function until a solution is found, the progress gets
     /// interrupted, or the algorithm determines that the problem is impossible
@@ -57,6 +70,7 @@ impl<Algo: Algorithm, Goal, Halting> Search<Algo, Goal, Halting> {
     where
         Algo: Solvable<Goal>,
         Halting: Halt<Algo::Memory>,
+        Observer: SearchObserver<Algo::Memory>,
     {
         loop {
             if self.halting.halt(&self.memory) {
@@ -75,8 +89,10 @@ impl<Algo: Algorithm, Goal, Halting> Search<Algo, Goal, Halting> {
     pub fn step(&mut self) -> Result<SearchStatus<Algo::Solution>, Algo::StepError>
     where
         Algo: Solvable<Goal>,
+        Observer: SearchObserver<Algo::Memory>,
     {
-        self.algorithm.step(&mut self.memory, &self.goal)
+        self.algorithm
+            .step_observed(&mut self.memory, &self.goal, &mut self.observer)
     }
 
     pub fn memory(&self) -> &Algo::Memory {
@@ -95,16 +111,56 @@ impl<Algo: Algorithm, Goal, Halting> Search<Algo, Goal, Halting> {
         &mut self.algorithm
     }
 
+    pub fn observer(&self) -> &Observer {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut Observer {
+        &mut self.observer
+    }
+
     /// Change the halting behavior for this progress.
-    pub fn with_halting<NewHalt>(self, halting: NewHalt) -> Search<Algo, Goal, NewHalt> {
+    pub fn with_halting<NewHalt>(self, halting: NewHalt) -> Search<Algo, Goal, NewHalt, Observer> {
         Search {
             memory: self.memory,
             algorithm: self.algorithm,
             goal: self.goal,
             halting,
+            observer: self.observer,
+        }
+    }
+
+    /// Change the observer for this progress. Only the events that happen
+    /// after this is set will be observed.
+    pub fn with_observer<NewObserver>(
+        self,
+        observer: NewObserver,
+    ) -> Search<Algo, Goal, Halting, NewObserver> {
+        Search {
+            memory: self.memory,
+            algorithm: self.algorithm,
+            goal: self.goal,
+            halting: self.halting,
+            observer,
         }
     }
 
+    /// Save the progress of this search so that it can be continued later
+    /// with [`Planner::resume`](crate::Planner::resume). The checkpoint
+    /// carries the fingerprint of the domain configuration that the search is
+    /// using.
+    ///
+    /// The goal, halting, and observer are not saved.
+    pub fn checkpoint(&self) -> Result<Checkpoint<Algo::Snapshot>, CheckpointError>
+    where
+        Algo: Checkpointable,
+    {
+        Ok(Checkpoint::new(
+            self.algorithm.fingerprint()?,
+            self.algorithm.snapshot(&self.memory),
+        ))
+    }
+
     /// Modify the existing options and return the progress
     pub fn tweak_halting<F: FnOnce(&mut Halting)>(mut self, tweak: F) -> Self {
         tweak(&mut self.halting);
@@ -118,10 +174,11 @@ pub trait SearchInterface<Solution> {
     fn step(&mut self) -> anyhow::Result<SearchStatus<Solution>>;
 }
 
-impl<A, G, H> SearchInterface<A::Solution> for Search<A, G, H>
+impl<A, G, H, O> SearchInterface<A::Solution> for Search<A, G, H, O>
 where
     A: Solvable<G>,
     H: Halt<A::Memory>,
+    O: SearchObserver<A::Memory>,
     A::StepError: Into<Anyhow>,
 {
     fn solve(&mut self) -> anyhow::Result<SearchStatus<A::Solution>> {
@@ -140,7 +197,7 @@ pub trait GetHalting<H> {
     fn halting_mut(&mut self) -> &mut H;
 }
 
-impl<A: Algorithm, G, H: Halt<A::Memory>> GetHalting<H> for Search<A, G, H> {
+impl<A: Algorithm, G, H: Halt<A::Memory>, O> GetHalting<H> for Search<A, G, H, O> {
     fn halting(&self) -> &H {
         &self.halting
     }
@@ -165,7 +222,7 @@ pub trait WithHalting<H> {
     fn update_halting<T, F: FnOnce(&mut H) -> T>(&mut self, f: F) -> T;
 }
 
-impl<A: Algorithm, G, H: Halt<A::Memory>> WithHalting<H> for Search<A, G, H> {
+impl<A: Algorithm, G, H: Halt<A::Memory>, O> WithHalting<H> for Search<A, G, H, O> {
     fn view_halting<T, F: FnOnce(&H) -> T>(&self, f: F) -> T {
         f(self.halting())
     }
@@ -191,14 +248,15 @@ pub struct AbstractSearch<Solution> {
     implementation: Box<RefCell<dyn SearchInterface<Solution>>>,
 }
 
-impl<A, G, H> From<Search<A, G, H>> for AbstractSearch<A::Solution>
+impl<A, G, H, O> From<Search<A, G, H, O>> for AbstractSearch<A::Solution>
 where
     A: Solvable<G> + 'static,
     A::StepError: Into<Anyhow>,
     H: Halt<A::Memory> + 'static,
+    O: SearchObserver<A::Memory> + 'static,
     G: 'static,
 {
-    fn from(value: Search<A, G, H>) -> Self {
+    fn from(value: Search<A, G, H, O>) -> Self {
         AbstractSearch {
             implementation: Box::new(RefCell::new(value)),
         }
@@ -219,14 +277,15 @@ pub struct AbstractSearchWithHalting<Solution, Halting> {
     implementation: Box<RefCell<dyn SearchInterfaceWithHalting<Solution, Halting>>>,
 }
 
-impl<A, G, H> From<Search<A, G, H>> for AbstractSearchWithHalting<A::Solution, H>
+impl<A, G, H, O> From<Search<A, G, H, O>> for AbstractSearchWithHalting<A::Solution, H>
 where
     A: Solvable<G> + 'static,
     H: Halt<A::Memory> + 'static,
+    O: SearchObserver<A::Memory> + 'static,
     A::StepError: Into<Anyhow>,
     G: 'static,
 {
-    fn from(value: Search<A, G, H>) -> Self {
+    fn from(value: Search<A, G, H, O>) -> Self {
         AbstractSearchWithHalting {
             implementation: Box::new(RefCell::new(value)),
         }
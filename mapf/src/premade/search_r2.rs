@@ -128,6 +128,36 @@ mod tests {
         assert!(solution.solved());
     }
 
+    #[test]
+    fn test_export_r2_tree() {
+        use crate::algorithm::export::{ExportedTree, TreeNodeStatus};
+
+        let planner = Planner::new(AStar(InformedSearch::new_r2(
+            SharedGraph::new(make_test_graph()),
+            LineFollow::new(2.0).unwrap(),
+        )));
+
+        let mut search = planner.plan(0usize, 8usize).unwrap();
+        assert!(search.solve().unwrap().solved());
+
+        let tree = search.memory().export();
+        assert!(!tree.nodes.is_empty());
+        assert!(tree.nodes[0].parent.is_none());
+        assert!(tree.nodes.iter().skip(1).all(|n| n.parent.is_some()));
+        assert!(tree
+            .nodes
+            .iter()
+            .any(|n| n.status == TreeNodeStatus::Closed));
+        assert!(tree.nodes.iter().all(|n| n.heuristic.is_some()));
+
+        let reloaded: ExportedTree = serde_json::from_str(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded, tree);
+
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.matches(" -> ").count() >= tree.nodes.len() - 1);
+    }
+
     use crate::graph::occupancy::{Cell, NeighborhoodGraph, SparseGrid, Visibility};
 
     #[test]
@@ -208,4 +208,56 @@ mod tests {
         // println!("{solution:#?}");
         assert!(solution.solved());
     }
+
+    #[test]
+    fn test_observed_se2() {
+        use crate::algorithm::observer::{SearchCounters, SearchEventKind, SearchTrace};
+
+        let mut visibility = Visibility::new(SparseGrid::new(0.5), 1.25);
+        visibility.change_cells(
+            &(0..=5)
+                .flat_map(|i| (0..=5).map(move |j| (Cell::new(i, j), true)))
+                .collect(),
+        );
+
+        let graph = NeighborhoodGraph::new(Arc::new(visibility), []);
+        let planner = Planner::new(AStarConnect(InformedSearch::new_se2(
+            SharedGraph::new(graph),
+            DifferentialDriveLineFollow::new(2.0, 1.0).unwrap(),
+        )));
+        let start = (Cell::new(-3, -3), 20_f64.to_radians());
+        let goal = GoalSE2::new(Cell::new(10, 10));
+
+        let unobserved = planner
+            .plan(start, goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+
+        let mut search = planner
+            .plan(start, goal)
+            .unwrap()
+            .with_observer(SearchCounters::default());
+        let observed = search.solve().unwrap().solution().unwrap();
+        assert_eq!(observed.total_cost, unobserved.total_cost);
+
+        let counters = *search.observer();
+        assert_eq!(counters.solutions, 1);
+        assert!(counters.popped > 0);
+        assert!(counters.pushed > 0);
+
+        let mut search = planner
+            .plan(start, goal)
+            .unwrap()
+            .with_observer(SearchTrace::new(4));
+        assert!(search.solve().unwrap().solved());
+        let trace = search.observer();
+        assert_eq!(trace.len(), 4);
+        assert_eq!(
+            trace.events().last().unwrap().kind,
+            SearchEventKind::Solution
+        );
+    }
 }
@@ -393,7 +393,7 @@ where
 mod tests {
     use super::*;
     use crate::{
-        algorithm::AStarConnect,
+        algorithm::{AStarConnect, CheckpointError},
         graph::{
             occupancy::{Cell, NeighborhoodGraph, SparseGrid, Visibility, VisibilityGraph},
             SimpleGraph,
@@ -401,6 +401,7 @@ mod tests {
         motion::{
             CircularProfile, DynamicCircularObstacle, DynamicEnvironment, TimePoint, Trajectory,
         },
+        planner::halt::StepLimit,
         Planner,
     };
     use approx::assert_relative_eq;
@@ -505,6 +506,53 @@ mod tests {
         assert!(trajectory.len() >= 11);
     }
 
+    #[test]
+    fn test_sipp_se2_checkpoint() {
+        let planner = Planner::new(AStarConnect(make_simple_sipp_se2_domain()));
+        let (start, goal) = ((0usize, 20_f64.to_radians()), 8usize);
+
+        let mut search = planner
+            .plan_with_halting(start, goal, StepLimit::new(Some(3)))
+            .unwrap();
+        assert!(search.solve().unwrap().incomplete());
+        let checkpoint = search.checkpoint().unwrap();
+
+        let resumed = planner
+            .resume(checkpoint.clone(), goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        let uninterrupted = planner
+            .plan(start, goal)
+            .unwrap()
+            .solve()
+            .unwrap()
+            .solution()
+            .unwrap();
+        assert_relative_eq!(resumed.total_cost.0, uninterrupted.total_cost.0);
+        assert_eq!(
+            resumed.sequence.last().unwrap().1.key.vertex,
+            uninterrupted.sequence.last().unwrap().1.key.vertex,
+        );
+
+        // Removing the obstacle changes the environment, so the checkpoint
+        // does not fit it anymore.
+        let changed = planner
+            .configure(|domain| {
+                domain.modify_environment(|mut env| {
+                    env.overlay_trajectory(0, None).unwrap();
+                    Ok(env)
+                })
+            })
+            .unwrap();
+        assert!(matches!(
+            changed.resume(checkpoint, goal),
+            Err(CheckpointError::Fingerprint { .. }),
+        ));
+    }
+
     #[test]
     fn test_sipp_se2_configure() {
         let domain = make_simple_sipp_se2_domain();
@@ -15,7 +15,10 @@
  *
 */
 
-use crate::domain::{ConflictAvoider, Extrapolator};
+use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{ConflictAvoider, Extrapolator, Fingerprinted},
+};
 use std::sync::Arc;
 
 #[derive(Debug)]
@@ -33,6 +36,16 @@ impl<Avoider: Clone, Environment> Clone for ConflictAvoidance<Avoider, Environme
     }
 }
 
+impl<Avoider, Environment> Fingerprinted for ConflictAvoidance<Avoider, Environment>
+where
+    Avoider: Fingerprinted,
+    Environment: Fingerprinted,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[self.avoider.fingerprint()?, self.environment.fingerprint()?])
+    }
+}
+
 impl<Avoider, Env, State, Target, Guidance, Key> Extrapolator<State, Target, Guidance, Key>
     for ConflictAvoidance<Avoider, Env>
 where
@@ -16,9 +16,10 @@
 */
 
 use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
     domain::{
-        Activity, Backtrack, Domain, Extrapolator, Keyed, KeyedSpace, Keyring, PartialKeyed,
-        Reversible,
+        Activity, Backtrack, Domain, Extrapolator, Fingerprinted, Keyed, KeyedSpace, Keyring,
+        PartialKeyed, Reversible,
     },
     error::{StdError, ThisError},
     graph::{Edge, Graph},
@@ -174,6 +175,21 @@ where
     }
 }
 
+impl<S, G, E> Fingerprinted for GraphMotion<S, G, E>
+where
+    S: Fingerprinted,
+    G: Fingerprinted,
+    E: Fingerprinted,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[
+            self.space.fingerprint()?,
+            self.graph.fingerprint()?,
+            self.extrapolator.fingerprint()?,
+        ])
+    }
+}
+
 impl<S, G, E> Reversible for GraphMotion<S, G, E>
 where
     S: Reversible,
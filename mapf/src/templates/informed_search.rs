@@ -16,10 +16,11 @@
 */
 
 use crate::{
+    algorithm::checkpoint::{fingerprint, CheckpointError},
     domain::{
         Activity, ArrivalKeyring, AsTimeInvariant, AsTimeVariant, Backtrack, Chained, Closable,
-        Connectable, Domain, Informed, Initializable, Keyed, Keyring, PartialKeyed, Reversible,
-        Satisfiable, Weighted,
+        Connectable, Domain, Fingerprinted, Informed, Initializable, Keyed, Keyring, PartialKeyed,
+        Reversible, Satisfiable, Weighted,
     },
     error::{Anyhow, ThisError},
 };
@@ -288,6 +289,29 @@ where
     }
 }
 
+impl<A, W, H, X, I, S, C> Fingerprinted for InformedSearch<A, W, H, X, I, S, C>
+where
+    A: Fingerprinted,
+    W: Fingerprinted,
+    H: Fingerprinted,
+    X: Fingerprinted,
+    I: Fingerprinted,
+    S: Fingerprinted,
+    C: Fingerprinted,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[
+            self.activity.fingerprint()?,
+            self.weight.fingerprint()?,
+            self.heuristic.fingerprint()?,
+            self.closer.fingerprint()?,
+            self.initializer.fingerprint()?,
+            self.satisfier.fingerprint()?,
+            self.connector.fingerprint()?,
+        ])
+    }
+}
+
 impl<A, W, H, X, I, S, C> Activity<A::State> for InformedSearch<A, W, H, X, I, S, C>
 where
     A: Domain + Activity<A::State>,
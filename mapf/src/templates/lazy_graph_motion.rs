@@ -16,7 +16,8 @@
 */
 
 use crate::{
-    domain::{ArrivalKeyring, Connectable, Extrapolator, KeyedSpace, Reversible},
+    algorithm::checkpoint::{fingerprint, CheckpointError},
+    domain::{ArrivalKeyring, Connectable, Extrapolator, Fingerprinted, KeyedSpace, Reversible},
     error::ThisError,
     graph::{Edge, Graph},
     templates::GraphMotion,
@@ -147,6 +148,21 @@ where
     }
 }
 
+impl<S, G, E, R, C> Fingerprinted for LazyGraphMotion<S, G, E, R, C>
+where
+    GraphMotion<S, G, E>: Fingerprinted,
+    R: Fingerprinted,
+    C: Fingerprinted,
+{
+    fn fingerprint(&self) -> Result<u64, CheckpointError> {
+        fingerprint(&[
+            self.motion.fingerprint()?,
+            self.keyring.fingerprint()?,
+            self.chain.fingerprint()?,
+        ])
+    }
+}
+
 impl<S, G, E, R, C> Reversible for LazyGraphMotion<S, G, E, R, C>
 where
     S: Reversible,
@@ -15,6 +15,15 @@
  *
 */
 
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
 pub(crate) fn triangular_for<Item>(
     iterable: impl Iterator<Item = Item> + Clone,
     mut f: impl FnMut(&Item, Item),
@@ -153,3 +162,111 @@ pub fn wrap_to_pi(mut value: f64) -> f64 {
 
     value
 }
+
+/// Get the value of `key` from a cache that is shared between threads, or
+/// compute and store it if it is missing. The cache is not locked while the
+/// value is computed, so another thread may store the same key first. In that
+/// case the value that was stored first is kept and given back, so every user
+/// of the cache sees the same value.
+pub(crate) fn get_or_compute<K: Hash + Eq, V: Clone, E>(
+    cache: &RwLock<HashMap<K, V>>,
+    key: K,
+    poisoned: impl Fn() -> E,
+    compute: impl FnOnce() -> Result<V, E>,
+) -> Result<V, E> {
+    match cache.read() {
+        Ok(cache) => {
+            if let Some(value) = cache.get(&key) {
+                return Ok(value.clone());
+            }
+        }
+        Err(_) => return Err(poisoned()),
+    }
+
+    let value = compute()?;
+    match cache.write() {
+        Ok(mut cache) => Ok(cache.entry(key).or_insert(value).clone()),
+        Err(_) => Err(poisoned()),
+    }
+}
+
+/// Apply `f` to every item using up to `threads` scoped threads. The results
+/// are given back in the same order as the items, no matter how the work ended
+/// up being divided between the threads. With one thread (or one item) the work
+/// is done on the calling thread.
+pub(crate) fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let finished: Vec<Vec<(usize, R)>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut finished = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            return finished;
+                        };
+                        finished.push((i, f(item)));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|err| std::panic::resume_unwind(err))
+            })
+            .collect()
+    });
+
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+    for (i, result) in finished.into_iter().flatten() {
+        results[i] = Some(result);
+    }
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every item is taken by one of the workers"))
+        .collect()
+}
+
+/// Escape text so it can be used inside a double-quoted Graphviz DOT string.
+/// Line breaks become `\n` so labels keep their lines.
+pub(crate) fn escape_dot(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<u64> = (0..100).collect();
+        let expected: Vec<u64> = items.iter().map(|x| x * x).collect();
+        for threads in [0, 1, 3, 8, 200] {
+            assert_eq!(parallel_map(&items, threads, |x| x * x), expected);
+        }
+    }
+}